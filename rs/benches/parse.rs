@@ -0,0 +1,47 @@
+// Benchmarks for parsing messages.
+//
+// Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ichen_openprotocol::*;
+
+// A typical `ControllersList` with a number of controllers, with or without escape
+// sequences in the text fields that can hold them.
+fn controllers_list(count: u32, escaped: bool) -> String {
+    let name = if escaped { r#"Machine \"A\""# } else { "Machine A" };
+
+    let controllers: Vec<_> = (1..=count)
+        .map(|id| {
+            format!(
+                r#""{id}":{{"controllerId":{id},"displayName":"{name} #{id}","controllerType":"Ai12","version":"1.0.0","model":"JM128-MK6","IP":"192.168.1.{id}:12345","geoLatitude":23.0,"geoLongitude":-121.0,"opMode":"Automatic","jobMode":"ID02","jobCardId":"{name}","lastCycleData":{{"Z_QDGODCNT":8567,"Z_QDCYCTIM":979,"Z_QDPLSTIM":4.4}},"variables":{{"Z_QDGODCNT":8567}},"lastConnectionTime":"2016-03-06T23:11:27.1442177+08:00","operatorId":123,"operatorName":"{name}","moldId":"{name}"}}"#,
+                id = id,
+                name = name
+            )
+        })
+        .collect();
+
+    format!(
+        r#"{{"$type":"ControllersList","data":{{{}}},"sequence":68568}}"#,
+        controllers.join(",")
+    )
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let json = controllers_list(10, false);
+    c.bench_function("parse ControllersList (10)", |b| {
+        b.iter(|| Message::parse_from_json_str(black_box(&json)).unwrap())
+    });
+
+    let json = controllers_list(10, true);
+    c.bench_function("parse ControllersList (10, escaped)", |b| {
+        b.iter(|| Message::parse_from_json_str(black_box(&json)).unwrap())
+    });
+
+    let json = r#"{"$type":"Alive","sequence":42}"#;
+    c.bench_function("parse Alive", |b| {
+        b.iter(|| Message::parse_from_json_str(black_box(json)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
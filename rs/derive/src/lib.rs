@@ -0,0 +1,103 @@
+//! Internal derive macros for the `ichen-openprotocol` crate.
+//!
+//! The code generated refers to items in the `ichen-openprotocol` crate via `crate::` paths,
+//! so these macros can only be used inside that crate.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Token};
+
+/// Derive `crate::validate::OpValidate` for a struct with named fields.
+///
+/// The generated `check_fields` method checks each field marked with an `op` attribute,
+/// in the order the fields are declared, and returns the first error:
+///
+/// * `#[op(non_empty)]` - the field (or every item in it) must not be empty or
+///   all whitespace, otherwise `OpenProtocolError::EmptyField`.
+///
+/// * `#[op(range(min, max))]` - the field (if present) must be between `min` and `max`
+///   (inclusive), otherwise `OpenProtocolError::ConstraintViolated`.
+///
+#[proc_macro_derive(OpValidate, attributes(op))]
+pub fn derive_op_validate(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "OpValidate requires named fields")),
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "OpValidate can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut checks = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let name = ident.to_string();
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("op")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("non_empty") {
+                    checks.push(quote! {
+                        if !crate::validate::NonEmpty::is_non_empty(&self.#ident) {
+                            return Err(crate::Error::EmptyField(#name));
+                        }
+                    });
+                    Ok(())
+                } else if meta.path.is_ident("range") {
+                    let content;
+                    syn::parenthesized!(content in meta.input);
+                    let min: Expr = content.parse()?;
+                    content.parse::<Token![,]>()?;
+                    let max: Expr = content.parse()?;
+
+                    checks.push(quote! {
+                        if let Some(value) =
+                            crate::validate::InRange::out_of_range(&self.#ident, #min, #max)
+                        {
+                            return Err(crate::Error::ConstraintViolated(
+                                format!(
+                                    "invalid {}: {} (must be between {} and {})",
+                                    #name, value, #min, #max
+                                )
+                                .into(),
+                            ));
+                        }
+                    });
+                    Ok(())
+                } else {
+                    Err(meta
+                        .error("unsupported op attribute; expected non_empty or range(min, max)"))
+                }
+            })?;
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics crate::validate::OpValidate for #ident #type_generics #where_clause {
+            fn check_fields(&self) -> crate::Result<'static, ()> {
+                #(#checks)*
+                Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,16 @@
+#![no_main]
+
+use ichen_openprotocol::Message;
+use libfuzzer_sys::fuzz_target;
+
+// Feed arbitrary bytes into the parser - it must never panic.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = Message::parse_from_json_slice(data) {
+        let _ = msg.to_json_str();
+    }
+
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = Message::parse_from_json_str(text);
+        let _ = Message::parse_ndjson(text).count();
+    }
+});
@@ -0,0 +1,211 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use chrono::{FixedOffset, TimeZone};
+use ichen_openprotocol::*;
+use indexmap::IndexMap;
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary inputs from which to build a `Message`.
+#[derive(Debug, Arbitrary)]
+enum Input<'a> {
+    Alive,
+    ControllerAction {
+        controller_id: u32,
+        action_id: i32,
+        timestamp: i64,
+        offset: i32,
+    },
+    ActionResult {
+        controller_id: u32,
+        action_id: i32,
+        success: bool,
+        reason: Option<&'a str>,
+    },
+    RequestControllersList {
+        controller_id: Option<u32>,
+    },
+    CycleData {
+        controller_id: u32,
+        data: Vec<(&'a str, f32)>,
+        timestamp: i64,
+        offset: i32,
+    },
+    Join {
+        password: &'a str,
+        org_id: Option<&'a str>,
+        filter: u16,
+    },
+    JoinResponse {
+        result: u32,
+        level: Option<u32>,
+        message: Option<&'a str>,
+        version: Option<&'a str>,
+    },
+    ReadMoldData {
+        controller_id: u32,
+        field: Option<&'a str>,
+    },
+    MoldDataValue {
+        controller_id: u32,
+        field: &'a str,
+        value: f32,
+    },
+    LoginOperator {
+        controller_id: u32,
+        password: &'a str,
+    },
+    OperatorInfo {
+        controller_id: u32,
+        operator_id: Option<u32>,
+        name: &'a str,
+        password: &'a str,
+        level: u8,
+    },
+}
+
+// Build a `Message` from the inputs, or `None` if the inputs do not make a valid `Message`.
+fn build<'a>(input: &Input<'a>) -> Option<Message<'a>> {
+    // RFC3339 time zone offsets are in whole minutes (up to ±14 hours)
+    let timestamp = |secs: i64, offset: i32| {
+        FixedOffset::east_opt(offset % (14 * 60) * 60)?.timestamp_opt(secs, 0).single()
+    };
+
+    let id = |id: u32| ID::new(id);
+
+    // Only named filters can be represented on the wire
+    let filters = |bits: u16| {
+        const NAMED: [Filters; 10] = [
+            Filters::Status,
+            Filters::Cycle,
+            Filters::Mold,
+            Filters::Actions,
+            Filters::Alarms,
+            Filters::Audit,
+            Filters::All,
+            Filters::JobCards,
+            Filters::Operators,
+            Filters::OPCUA,
+        ];
+
+        NAMED
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| bits & (1 << n) != 0)
+            .fold(Filters::None, |f, (_, &x)| f + x)
+    };
+
+    // A `TextID` borrows from the JSON text, so it cannot hold characters that JSON escapes
+    let text_id = |text: &'a str| {
+        if text.chars().any(|c| c == '"' || c == '\\' || c.is_control()) {
+            None
+        } else {
+            TextID::new(text)
+        }
+    };
+
+    Some(match *input {
+        Input::Alive => Message::new_alive(),
+        Input::ControllerAction { controller_id, action_id, timestamp: secs, offset } => {
+            Message::ControllerAction {
+                controller_id: id(controller_id)?,
+                action_id: ActionID::new(action_id),
+                timestamp: timestamp(secs, offset)?,
+                idempotency_key: None,
+                options: Default::default(),
+            }
+        }
+        Input::ActionResult { controller_id, action_id, success, reason } => {
+            Message::try_new_action_result(
+                id(controller_id)?,
+                ActionID::new(action_id),
+                success,
+                reason,
+            )
+            .ok()?
+        }
+        Input::RequestControllersList { controller_id } => Message::RequestControllersList {
+            controller_id: controller_id.and_then(id),
+            options: Default::default(),
+        },
+        Input::CycleData { controller_id, ref data, timestamp: secs, offset } => {
+            let mut map = IndexMap::new();
+            for &(key, value) in data {
+                map.insert(text_id(key)?, R32::try_new(value)?);
+            }
+
+            Message::CycleData {
+                controller_id: id(controller_id)?,
+                data: map,
+                timestamp: timestamp(secs, offset)?,
+                state: Default::default(),
+                options: Default::default(),
+            }
+        }
+        Input::Join { password, org_id: None, filter } => {
+            Message::try_new_join(password, filters(filter)).ok()?
+        }
+        Input::Join { password, org_id: Some(org_id), filter } => {
+            text_id(org_id)?;
+            Message::try_new_join_with_org(password, filters(filter), org_id).ok()?
+        }
+        Input::JoinResponse { result, level, message, version } => Message::JoinResponse {
+            result,
+            level,
+            message: message.map(|m| Box::new(m.into())),
+            version: match version {
+                Some(version) => Some(text_id(version)?),
+                None => None,
+            },
+            options: Default::default(),
+        },
+        Input::ReadMoldData { controller_id, field } => Message::ReadMoldData {
+            controller_id: id(controller_id)?,
+            field: match field {
+                Some(field) => Some(text_id(field)?),
+                None => None,
+            },
+            options: Default::default(),
+        },
+        Input::MoldDataValue { controller_id, field, value } => Message::MoldDataValue {
+            controller_id: id(controller_id)?,
+            field: text_id(field)?,
+            value: R32::try_new(value)?,
+            options: Default::default(),
+        },
+        Input::LoginOperator { controller_id, password } => Message::LoginOperator {
+            controller_id: id(controller_id)?,
+            password: Password::new(password),
+            options: Default::default(),
+        },
+        Input::OperatorInfo { controller_id, operator_id, name, password, level } => {
+            Message::OperatorInfo {
+                controller_id: id(controller_id)?,
+                operator_id: operator_id.and_then(id),
+                name: TextName::new_from_str(name)?,
+                password: Password::new(password),
+                level: AccessLevel::new(level)?,
+                options: Default::default(),
+            }
+        }
+    })
+}
+
+// Build an arbitrary `Message`, serialize it, re-parse it, and check that nothing is lost.
+fuzz_target!(|input: Input| {
+    let msg = match build(&input) {
+        Some(msg) => msg,
+        None => return,
+    };
+
+    // Messages that fail validation cannot be serialized
+    let json = match msg.to_json_str() {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+
+    let parsed = Message::parse_from_json_str(&json)
+        .unwrap_or_else(|err| panic!("cannot re-parse {}: {}", json, err));
+
+    assert_eq!(json, parsed.to_json_str().unwrap(), "round-trip mismatch for {:?}", msg);
+});
@@ -1,10 +1,10 @@
-use super::TextID;
+use super::{Error, TextID};
 use derive_more::*;
 use lazy_static::*;
 use regex::Regex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::{TryFrom, TryInto};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6};
 use std::num::{NonZeroU16, NonZeroU8};
 use std::str::FromStr;
 
@@ -26,6 +26,10 @@ pub enum Address<'a> {
     #[display(fmt = "{}:{}", _0, _1)]
     IPv4(Ipv4Addr, NonZeroU16),
     //
+    /// An IP v.6 address plus port, written as `[address]:port`.
+    #[display(fmt = "[{}]:{}", _0, _1)]
+    IPv6(Ipv6Addr, NonZeroU16),
+    //
     /// A Windows COM port.
     #[display(fmt = "COM{}", _0)]
     ComPort(NonZeroU8),
@@ -83,6 +87,53 @@ impl<'a> Address<'a> {
         }
     }
 
+    /// Create a new `Address::IPv6` from an IP address string (without brackets) and port number.
+    ///
+    /// The IP address cannot be unspecified (i.e. `::`).
+    /// The IP port cannot be zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if:
+    /// * The IP address string is invalid,
+    /// * The IP address is unspecified (i.e. `::`),
+    /// * The IP port is zero.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Err("invalid IP address: [hello]".into()), Address::new_ipv6("hello", 123));
+    /// assert_eq!(Err("IP port cannot be zero".into()), Address::new_ipv6("2001:db8::1", 0));
+    /// assert_eq!(Err("invalid null IP address".into()), Address::new_ipv6("::", 123));
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::str::FromStr;
+    /// # use std::net::Ipv6Addr;
+    /// # use std::num::NonZeroU16;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// assert_eq!(
+    ///     Address::IPv6(Ipv6Addr::from_str("2001:db8::1").unwrap(), NonZeroU16::new(5).unwrap()),
+    ///     Address::new_ipv6("2001:0db8:0:0::1", 5)?
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn new_ipv6(addr: &str, port: u16) -> Result<Self, String> {
+        let addr =
+            Ipv6Addr::from_str(addr).map_err(|_| format!("invalid IP address: [{}]", addr))?;
+
+        if !addr.is_unspecified() {
+            Ok(Self::IPv6(addr, NonZeroU16::new(port).ok_or("IP port cannot be zero")?))
+        } else {
+            Err("invalid null IP address".into())
+        }
+    }
+
     /// Create a new `Address::ComPort` from a Windows serial port number.
     ///
     /// The COM port number cannot be zero.
@@ -150,6 +201,141 @@ impl<'a> Address<'a> {
             Err(format!("invalid tty device: [{}]", device))
         }
     }
+
+    /// Returns true if this is a serial port (i.e. a COM port or a tty device).
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// assert!(Address::new_com_port(1)?.is_serial());
+    /// assert!(Address::new_tty_device("ttyS0")?.is_serial());
+    /// assert!(!Address::new_ipv4("1.2.3.4", 5678)?.is_serial());
+    /// assert!(!Address::new_ipv6("2001:db8::1", 5678)?.is_serial());
+    /// assert!(!Address::Unknown.is_serial());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn is_serial(&self) -> bool {
+        matches!(self, Self::ComPort(_) | Self::TtyDevice(_))
+    }
+
+    /// Get the port number of a Windows COM port, which is never zero.
+    ///
+    /// Returns `None` if this is not a COM port.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// assert_eq!(Some(123), Address::try_from("COM123")?.com_port());
+    /// assert_eq!(None, Address::try_from("ttyS0")?.com_port());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn com_port(&self) -> Option<u8> {
+        match self {
+            Self::ComPort(port) => Some(port.get()),
+            _ => None,
+        }
+    }
+
+    /// Get the device name of a UNIX-style tty serial port (e.g. `ttyUSB0`).
+    ///
+    /// Returns `None` if this is not a tty device.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// assert_eq!(Some("ttyUSB0"), Address::try_from("ttyUSB0")?.tty_device());
+    /// assert_eq!(None, Address::try_from("COM1")?.tty_device());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn tty_device(&self) -> Option<&str> {
+        match self {
+            Self::TtyDevice(device) => Some(device.get()),
+            _ => None,
+        }
+    }
+
+    /// Convert into an `Address` that owns its data, copying the tty device name if borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let text = String::from("ttyUSB0");
+    /// let addr = Address::try_from(text.as_str())?.into_owned();
+    /// drop(text);
+    /// assert_eq!(Some("ttyUSB0"), addr.tty_device());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn into_owned(self) -> Address<'static> {
+        match self {
+            Self::Unknown => Address::Unknown,
+            Self::IPv4(ip, port) => Address::IPv4(ip, port),
+            Self::IPv6(ip, port) => Address::IPv6(ip, port),
+            Self::ComPort(port) => Address::ComPort(port),
+            Self::TtyDevice(device) => Address::TtyDevice(device.into_owned()),
+        }
+    }
+}
+
+/// Validate a controller address text string without constructing an [`Address`].
+///
+/// The following address forms are accepted:
+///
+/// * IP v.4 address plus port (e.g. `1.2.3.4:5678`), or `0.0.0.0:0` for an unknown address
+/// * IP v.6 address in brackets plus port (e.g. `[2001:db8::1]:5678`)
+/// * Windows COM port (e.g. `COM1`)
+/// * UNIX-style tty serial port device (e.g. `ttyS0`)
+///
+/// [`Address`]: enum.Address.html
+///
+/// # Errors
+///
+/// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if the text string is not a valid address.
+///
+/// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+///
+/// ## Error Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// assert!(validate_address("1.2.3.4").is_err());
+/// assert!(validate_address("COM0").is_err());
+/// assert!(validate_address("hello").is_err());
+/// assert!(validate_address("2001:db8::1").is_err());
+/// ~~~
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// validate_address("1.2.3.4:5678")?;
+/// validate_address("[2001:db8::1]:5678")?;
+/// validate_address("COM1")?;
+/// validate_address("ttyS0")?;
+/// # Ok(())
+/// # }
+/// ~~~
+pub fn validate_address(addr: &str) -> crate::Result<'_, ()> {
+    Address::try_from(addr).map(|_| ()).map_err(|err| Error::InvalidField {
+        field: "address",
+        value: addr.into(),
+        description: err.into(),
+    })
 }
 
 impl<'a> TryFrom<&'a str> for Address<'a> {
@@ -187,7 +373,7 @@ impl<'a> TryFrom<&'a str> for Address<'a> {
     /// # use std::borrow::Cow;
     /// # use std::str::FromStr;
     /// # use std::num::{NonZeroU16, NonZeroU8};
-    /// # use std::net::Ipv4Addr;
+    /// # use std::net::{Ipv4Addr, Ipv6Addr};
     /// # fn main() -> std::result::Result<(), String> {
     /// assert_eq!(
     ///     Address::IPv4(Ipv4Addr::from_str("1.2.3.4").unwrap(), NonZeroU16::new(5).unwrap()),
@@ -198,6 +384,11 @@ impl<'a> TryFrom<&'a str> for Address<'a> {
     /// assert_eq!(Address::Unknown, Address::try_from("0.0.0.0:0")?);
     ///
     /// assert_eq!(
+    ///     Address::IPv6(Ipv6Addr::from_str("2001:db8::1").unwrap(), NonZeroU16::new(5).unwrap()),
+    ///     Address::try_from("[2001:db8::1]:5")?
+    /// );
+    ///
+    /// assert_eq!(
     ///     Address::ComPort(NonZeroU8::new(123).unwrap()),
     ///     Address::try_from("COM123")?
     /// );
@@ -255,6 +446,25 @@ impl<'a> TryFrom<&'a str> for Address<'a> {
                     Err(_) => return Err(format!("invalid IP port: [{}]", port)),
                 }
             }
+            // Match [IPv6]:port syntax
+            text if text.starts_with('[') => {
+                let address = SocketAddrV6::from_str(text)
+                    .map_err(|_| format!("invalid IP address: [{}]", text))?;
+
+                // Scope IDs (e.g. `%eth0`) are local to the host and cannot be kept
+                if address.scope_id() != 0 {
+                    return Err(format!("invalid IP address: [{}]", text));
+                }
+
+                if address.ip().is_unspecified() {
+                    return Err("invalid null IP address".into());
+                }
+
+                let port = NonZeroU16::new(address.port()).ok_or("IP port cannot be zero")?;
+
+                Address::IPv6(*address.ip(), port)
+            }
+            //
             // Failed to match any address type
             _ => return Err(format!("invalid address: [{}]", item)),
         })
@@ -273,3 +483,118 @@ impl<'a, 'de: 'a> Deserialize<'de> for Address<'a> {
         Address::try_from(s).map_err(|err| serde::de::Error::custom(format!("{}: [{}]", err, s)))
     }
 }
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_address_ipv4() {
+        assert_eq!(Ok(()), validate_address("1.2.3.4:5678"));
+        assert_eq!(Ok(()), validate_address("255.255.255.255:65535"));
+        assert_eq!(Ok(()), validate_address("0.0.0.0:0"));
+    }
+
+    #[test]
+    fn test_validate_address_ipv6() {
+        assert_eq!(Ok(()), validate_address("[2001:db8::1]:8080"));
+        assert_eq!(Ok(()), validate_address("[::1]:1"));
+        assert_eq!(Ok(()), validate_address("[::ffff:1.2.3.4]:65535"));
+    }
+
+    #[test]
+    fn test_address_ipv6_round_trip() {
+        let addr = Address::try_from("[2001:0db8:0000::0001]:8080").unwrap();
+        assert_eq!(Address::new_ipv6("2001:db8::1", 8080).unwrap(), addr);
+        assert!(!addr.is_serial());
+        assert_eq!("[2001:db8::1]:8080", addr.to_string());
+
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(r#""[2001:db8::1]:8080""#, json);
+        assert_eq!(addr, serde_json::from_str::<Address>(&json).unwrap());
+
+        assert_eq!(Err("IP port cannot be zero".into()), Address::try_from("[2001:db8::1]:0"));
+        assert_eq!(Err("invalid null IP address".into()), Address::try_from("[::]:123"));
+    }
+
+    #[test]
+    fn test_validate_address_com_port() {
+        assert_eq!(Ok(()), validate_address("COM1"));
+        assert_eq!(Ok(()), validate_address("COM255"));
+    }
+
+    #[test]
+    fn test_validate_address_tty_device() {
+        assert_eq!(Ok(()), validate_address("ttyS0"));
+        assert_eq!(Ok(()), validate_address("ttyUSB1"));
+    }
+
+    #[test]
+    fn test_address_serial_port_details() {
+        assert_eq!(Err("COM port cannot be zero".into()), Address::try_from("COM0").map(|_| ()));
+        assert_eq!(Err("COM port cannot be zero".into()), Address::new_com_port(0).map(|_| ()));
+
+        let com = Address::try_from("COM123").unwrap();
+        assert!(com.is_serial());
+        assert_eq!(Some(123), com.com_port());
+        assert_eq!(None, com.tty_device());
+
+        let tty = Address::try_from("ttyUSB0").unwrap();
+        assert!(tty.is_serial());
+        assert_eq!(Some("ttyUSB0"), tty.tty_device());
+        assert_eq!(None, tty.com_port());
+
+        let ip = Address::try_from("1.2.3.4:5678").unwrap();
+        assert!(!ip.is_serial());
+        assert_eq!(None, ip.com_port());
+        assert_eq!(None, ip.tty_device());
+    }
+
+    #[test]
+    fn test_address_tty_device_serializes_device_name() {
+        let addr = Address::try_from("ttyUSB1").unwrap();
+        assert_eq!("ttyUSB1", addr.to_string());
+
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(r#""ttyUSB1""#, json);
+        assert_eq!(addr, serde_json::from_str::<Address>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_validate_address_malformed() {
+        for addr in &[
+            "",
+            "hello",
+            "1.2.3.4",
+            "1.2.3.4:",
+            "1.2.3.4:0",
+            "0.0.0.0:123",
+            "256.1.1.1:123",
+            "1.2.3.4:65536",
+            "COM",
+            "COM0",
+            "COM256",
+            "tty",
+            "tty-S0",
+            "2001:db8::1",
+            "[2001:db8::1]",
+            "[2001:db8::1]:",
+            "2001:db8::1:8080",
+            "[2001:db8::g]:8080",
+            "[2001:db8::1]:65536",
+            "[2001:db8::1%eth0]:8080",
+            "[1.2.3.4]:8080",
+            "[::]:0",
+        ] {
+            match validate_address(addr) {
+                Err(Error::InvalidField { field, value, .. }) => {
+                    assert_eq!("address", field);
+                    assert_eq!(*addr, value);
+                }
+                result => panic!("expected error for [{}], got {:?}", addr, result),
+            }
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! `proptest::arbitrary::Arbitrary` implementations (enable the `proptest` feature).
+//!
+//! All values generated are valid by construction, i.e. every generated `Message` passes
+//! [`validate`](../enum.Message.html#method.validate) and can be serialized.
+//!
+//! Fields holding a `TextID` (which borrows from the JSON text and so cannot hold escape
+//! sequences) are drawn from a fixed set of identifiers.  Other text fields are arbitrary
+//! strings, except where the public constructors require a `&'static str`.
+
+use super::*;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use indexmap::IndexMap;
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::sample::select;
+use std::borrow::Cow;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::num::{NonZeroU16, NonZeroU8};
+use Message::*;
+
+// Identifiers for `TextID` fields.
+const IDENTIFIERS: &[&str] = &[
+    "Z_QDGODCNT",
+    "Z_QDCYCTIM",
+    "Z_QDINJTIM",
+    "Z_QDPLSTIM",
+    "Hello",
+    "a",
+    "A.B-c_1",
+    "with space",
+    "~!@#$%^&*()",
+];
+
+// Names for `TextName` fields that can only be created from a `&'static str`.
+const NAMES: &[&str] = &["John", "JC001", "Mold#001", "注塑機", "J\"o\\hn", "  padded  ", "x"];
+
+// Serial device names.
+const TTY_DEVICES: &[&str] = &["ttyS0", "ttyUSB1", "tty_ACM12"];
+
+// Only named filters can be represented on the wire.
+const FILTERS: &[Filters] = &[
+    Filters::Status,
+    Filters::Cycle,
+    Filters::Mold,
+    Filters::Actions,
+    Filters::Alarms,
+    Filters::Audit,
+    Filters::All,
+    Filters::JobCards,
+    Filters::Operators,
+    Filters::OPCUA,
+];
+
+macro_rules! impl_arbitrary {
+    ($type:ty, $strategy:expr) => {
+        impl Arbitrary for $type {
+            type Parameters = ();
+            type Strategy = BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+                $strategy.boxed()
+            }
+        }
+    };
+}
+
+fn text_id() -> impl Strategy<Value = TextID<'static>> {
+    select(IDENTIFIERS).prop_map(|text| TextID::new(text).unwrap())
+}
+
+fn name() -> impl Strategy<Value = &'static str> {
+    select(NAMES)
+}
+
+fn text() -> impl Strategy<Value = String> {
+    any::<String>().prop_filter("empty text", |text| !text.trim().is_empty())
+}
+
+fn text_name() -> impl Strategy<Value = TextName<'static>> {
+    text().prop_map(|text| TextName::new_from_str(text).unwrap())
+}
+
+fn r32() -> impl Strategy<Value = R32> {
+    any::<f32>().prop_filter_map("non-finite value", R32::try_new)
+}
+
+fn data() -> impl Strategy<Value = IndexMap<TextID<'static>, R32>> {
+    vec((text_id(), r32()), 0..5).prop_map(|items| items.into_iter().collect())
+}
+
+// Time-stamps up to year 9999 with whole-minute offsets (as in RFC3339).
+fn timestamp() -> impl Strategy<Value = DateTime<FixedOffset>> {
+    (0_i64..=253_402_200_000, 0_u32..1_000_000_000, -14 * 60..=14 * 60_i32).prop_map(
+        |(secs, nanos, minutes)| {
+            FixedOffset::east_opt(minutes * 60).unwrap().timestamp_opt(secs, nanos).unwrap()
+        },
+    )
+}
+
+// Copy a `StateValues` text field into a message field.
+fn state_text(text: Option<&str>) -> Option<Box<TextName<'static>>> {
+    text.map(|text| Box::new(TextName::new_from_str(text.to_string()).unwrap()))
+}
+
+fn non_empty_filters() -> impl Strategy<Value = Filters> {
+    proptest::sample::subsequence(FILTERS, 1..=FILTERS.len())
+        .prop_map(|list| list.into_iter().fold(Filters::None, |f, x| f + x))
+}
+
+impl_arbitrary!(ID, (1..=u32::MAX).prop_map(ID::from_u32));
+
+impl_arbitrary!(ActionID, any::<i32>().prop_map(ActionID::new));
+
+impl_arbitrary!(
+    OpMode,
+    select(
+        &[
+            OpMode::Unknown,
+            OpMode::Manual,
+            OpMode::SemiAutomatic,
+            OpMode::Automatic,
+            OpMode::Others,
+            OpMode::Offline,
+        ][..]
+    )
+);
+
+impl_arbitrary!(
+    JobMode,
+    select(
+        &[
+            JobMode::Unknown,
+            JobMode::ID01,
+            JobMode::ID02,
+            JobMode::ID03,
+            JobMode::ID04,
+            JobMode::ID05,
+            JobMode::ID06,
+            JobMode::ID07,
+            JobMode::ID08,
+            JobMode::ID09,
+            JobMode::ID10,
+            JobMode::ID11,
+            JobMode::ID12,
+            JobMode::ID13,
+            JobMode::ID14,
+            JobMode::ID15,
+            JobMode::Offline,
+        ][..]
+    )
+);
+
+impl_arbitrary!(
+    Language,
+    select(
+        &[
+            Language::Unknown,
+            Language::EN,
+            Language::B5,
+            Language::GB,
+            Language::FR,
+            Language::DE,
+            Language::IT,
+            Language::ES,
+            Language::PT,
+            Language::JA,
+        ][..]
+    )
+);
+
+impl_arbitrary!(Filters, prop_oneof![Just(Filters::None), non_empty_filters()]);
+
+impl_arbitrary!(
+    GeoLocation,
+    (-90.0_f32..=90.0, -180.0_f32..=180.0)
+        .prop_map(|(lat, lng)| GeoLocation::new(lat, lng).unwrap())
+);
+
+impl_arbitrary!(
+    Address<'static>,
+    prop_oneof![
+        Just(Address::Unknown),
+        (1..=u32::MAX, 1..=u16::MAX).prop_map(|(ip, port)| Address::IPv4(
+            Ipv4Addr::from(ip),
+            NonZeroU16::new(port).unwrap()
+        )),
+        (1..=u128::MAX, 1..=u16::MAX).prop_map(|(ip, port)| Address::IPv6(
+            Ipv6Addr::from(ip),
+            NonZeroU16::new(port).unwrap()
+        )),
+        (1..=u8::MAX).prop_map(|port| Address::ComPort(NonZeroU8::new(port).unwrap())),
+        select(TTY_DEVICES).prop_map(|tty| Address::TtyDevice(TextID::new(tty).unwrap())),
+    ]
+);
+
+impl_arbitrary!(
+    Operator<'static>,
+    (any::<ID>(), option::of(name())).prop_map(|(id, name)| match name {
+        Some(name) => Operator::try_new_with_name(id, name).unwrap(),
+        None => Operator::new(id),
+    })
+);
+
+impl_arbitrary!(
+    StateValues<'static>,
+    (
+        any::<OpMode>(),
+        any::<JobMode>(),
+        option::of(any::<ID>()),
+        option::of(name()),
+        option::of(name())
+    )
+        .prop_map(|(op, job, operator_id, job_card_id, mold_id)| {
+            StateValues::try_new_with_all(op, job, operator_id, job_card_id, mold_id).unwrap()
+        })
+);
+
+impl_arbitrary!(
+    JobCard<'static>,
+    (name(), name(), any::<u32>(), any::<u32>())
+        .prop_map(|(id, mold, a, b)| { JobCard::try_new(id, mold, a.min(b), a.max(b)).unwrap() })
+);
+
+impl_arbitrary!(
+    Controller<'static>,
+    (
+        (any::<ID>(), text_name(), text_id(), text_id(), text_id()),
+        (any::<Address>(), option::of(any::<GeoLocation>()), any::<OpMode>(), any::<JobMode>()),
+        (data(), data(), option::of(connection_time())),
+        (option::of(any::<Operator>()), option::of(text()), option::of(text())),
+        option::of(vec(select(IDENTIFIERS).prop_map(Cow::Borrowed), 0..4)),
+    )
+        .prop_map(|(identity, config, telemetry, states, capabilities)| {
+            let (controller_id, display_name, controller_type, version, model) = identity;
+            let (address, geo_location, op_mode, job_mode) = config;
+            let (last_cycle_data, variables, last_connection_time) = telemetry;
+            let (operator, job_card_id, mold_id) = states;
+
+            Controller {
+                controller_id,
+                display_name,
+                controller_type,
+                version,
+                model,
+                address,
+                geo_location,
+                op_mode,
+                job_mode,
+                last_cycle_data,
+                variables,
+                last_connection_time,
+                operator,
+                job_card_id: job_card_id.map(|id| Box::new(Cow::Owned(id))),
+                mold_id: mold_id.map(|id| Box::new(Cow::Owned(id))),
+                capabilities,
+            }
+        })
+);
+
+impl_arbitrary!(
+    MessageOptions<'static>,
+    (option::of(select(IDENTIFIERS)), any::<i32>()).prop_map(|(id, priority)| {
+        let mut options = MessageOptions::new_with_priority(priority);
+        if let Some(id) = id {
+            options.set_id(id).unwrap();
+        }
+        options
+    })
+);
+
+// Time of last connection of a `Controller`.
+#[cfg(not(feature = "epoch-millis"))]
+fn connection_time() -> impl Strategy<Value = DateTime<FixedOffset>> {
+    timestamp()
+}
+
+// Time of last connection of a `Controller`, in whole milliseconds and UTC
+// (as serialized with the `epoch-millis` feature).
+#[cfg(feature = "epoch-millis")]
+fn connection_time() -> impl Strategy<Value = DateTime<FixedOffset>> {
+    (0_i64..=253_402_200_000_000)
+        .prop_map(|millis| FixedOffset::east_opt(0).unwrap().timestamp_millis_opt(millis).unwrap())
+}
+
+// A `ControllerStatus` message carrying a full `Controller`.
+fn controller_status_with_controller() -> impl Strategy<Value = Message<'static>> {
+    (any::<Controller>(), any::<[bool; 7]>(), any::<MessageOptions>()).prop_map(
+        |(controller, include, options)| {
+            let state = StateValues::from_controller(&controller);
+
+            ControllerStatus {
+                controller_id: controller.controller_id,
+                display_name: if include[0] {
+                    Some(Box::new(controller.display_name.clone()))
+                } else {
+                    None
+                },
+                is_disconnected: None,
+                op_mode: if include[1] { Some(controller.op_mode) } else { None },
+                job_mode: if include[2] { Some(controller.job_mode) } else { None },
+                alarm: None,
+                audit: None,
+                variable: None,
+                operator_id: if include[3] { Some(state.operator_id()) } else { None },
+                operator_name: if include[4] {
+                    Some(
+                        controller.operator.as_ref().and_then(|user| user.name()).map(|name| {
+                            Box::new(TextName::new_from_str(name.to_string()).unwrap())
+                        }),
+                    )
+                } else {
+                    None
+                },
+                job_card_id: if include[5] { Some(state_text(state.job_card_id())) } else { None },
+                mold_id: if include[6] { Some(state_text(state.mold_id())) } else { None },
+                state,
+                controller: Some(Box::new(controller)),
+                options,
+            }
+        },
+    )
+}
+
+// A `ControllerStatus` message carrying individual updates.
+fn controller_status_with_updates() -> impl Strategy<Value = Message<'static>> {
+    (
+        (any::<ID>(), any::<StateValues>(), any::<[bool; 5]>()),
+        (option::of(text_name()), option::of(any::<bool>()), option::of(option::of(text_name()))),
+        (
+            option::of((text_id(), any::<bool>())),
+            option::of((text_id(), r32())),
+            option::of((text_id(), r32())),
+        ),
+        any::<MessageOptions>(),
+    )
+        .prop_map(|((controller_id, state, include), texts, updates, options)| {
+            let (display_name, is_disconnected, operator_name) = texts;
+            let (alarm, audit, variable) = updates;
+            let kv = |(key, value)| Box::new(KeyValuePair::new(key, value));
+
+            ControllerStatus {
+                controller_id,
+                display_name: display_name.map(Box::new),
+                is_disconnected,
+                op_mode: if include[0] { Some(state.op_mode()) } else { None },
+                job_mode: if include[1] { Some(state.job_mode()) } else { None },
+                alarm: alarm.map(|(key, value)| Box::new(KeyValuePair::new(key, value))),
+                audit: audit.map(kv),
+                variable: variable.map(kv),
+                operator_id: if include[2] { Some(state.operator_id()) } else { None },
+                operator_name: operator_name.map(|name| name.map(Box::new)),
+                job_card_id: if include[3] { Some(state_text(state.job_card_id())) } else { None },
+                mold_id: if include[4] { Some(state_text(state.mold_id())) } else { None },
+                state,
+                controller: None,
+                options,
+            }
+        })
+}
+
+impl_arbitrary!(
+    Message<'static>,
+    prop_oneof![
+        any::<MessageOptions>().prop_map(|options| Alive { options }),
+        (any::<ID>(), any::<ActionID>(), timestamp(), option::of(text()), any::<MessageOptions>())
+            .prop_map(|(controller_id, action_id, timestamp, idempotency_key, options)| {
+                ControllerAction {
+                    controller_id,
+                    action_id,
+                    timestamp,
+                    idempotency_key: idempotency_key.map(|key| Box::new(Cow::Owned(key))),
+                    options,
+                }
+            }),
+        (
+            any::<ID>(),
+            any::<ActionID>(),
+            any::<bool>(),
+            option::of(text()),
+            any::<MessageOptions>()
+        )
+            .prop_filter("rejected action without reason", |(_, _, success, reason, _)| {
+                *success || reason.is_some()
+            })
+            .prop_map(|(controller_id, action_id, success, reason, options)| {
+                ActionResult {
+                    controller_id,
+                    action_id,
+                    success,
+                    reason: reason.map(|r| Box::new(Cow::Owned(r))),
+                    options,
+                }
+            }),
+        (option::of(any::<ID>()), any::<MessageOptions>())
+            .prop_map(|(controller_id, options)| RequestControllersList { controller_id, options }),
+        (vec(any::<Controller>(), 0..3), any::<MessageOptions>()).prop_map(
+            |(controllers, options)| ControllersList {
+                data: controllers.into_iter().map(|c| (c.controller_id, c)).collect(),
+                options,
+            }
+        ),
+        controller_status_with_controller(),
+        controller_status_with_updates(),
+        (any::<ID>(), data(), timestamp(), any::<StateValues>(), any::<MessageOptions>()).prop_map(
+            |(controller_id, data, timestamp, state, options)| CycleData {
+                controller_id,
+                data,
+                timestamp,
+                state,
+                options
+            }
+        ),
+        (any::<ID>(), any::<MessageOptions>())
+            .prop_map(|(controller_id, options)| RequestJobCardsList { controller_id, options }),
+        (any::<ID>(), vec(any::<JobCard>(), 0..3), any::<MessageOptions>()).prop_map(
+            |(controller_id, jobs, options)| JobCardsList {
+                controller_id,
+                data: jobs
+                    .into_iter()
+                    .map(|job| (
+                        TextName::new_from_str(job.job_card_id().to_string()).unwrap(),
+                        job
+                    ))
+                    .collect(),
+                options,
+            }
+        ),
+        (
+            option::of(text_id()),
+            text_id(),
+            any::<String>(),
+            any::<Language>().prop_filter("unknown language", |lang| !lang.is_unknown()),
+            non_empty_filters(),
+            any::<MessageOptions>()
+        )
+            .prop_map(|(org_id, version, password, language, filter, options)| Join {
+                org_id,
+                version,
+                password: Password::new(password),
+                language,
+                filter,
+                options,
+            }),
+        (
+            any::<u32>(),
+            option::of(any::<u32>()),
+            option::of(any::<String>()),
+            option::of(text_id()),
+            any::<MessageOptions>()
+        )
+            .prop_map(|(result, level, message, version, options)| JoinResponse {
+                result,
+                level,
+                message: message.map(|m| Box::new(Cow::Owned(m))),
+                version,
+                options,
+            }),
+        (any::<ID>(), any::<MessageOptions>())
+            .prop_map(|(controller_id, options)| RequestMoldData { controller_id, options }),
+        (any::<ID>(), data(), timestamp(), any::<StateValues>(), any::<MessageOptions>()).prop_map(
+            |(controller_id, data, timestamp, state, options)| MoldData {
+                controller_id,
+                data,
+                timestamp,
+                state,
+                options
+            }
+        ),
+        (any::<ID>(), option::of(text_id()), any::<MessageOptions>()).prop_map(
+            |(controller_id, field, options)| ReadMoldData { controller_id, field, options }
+        ),
+        (any::<ID>(), text_id(), r32(), any::<MessageOptions>()).prop_map(
+            |(controller_id, field, value, options)| MoldDataValue {
+                controller_id,
+                field,
+                value,
+                options
+            }
+        ),
+        (any::<ID>(), any::<String>(), any::<MessageOptions>()).prop_map(
+            |(controller_id, password, options)| LoginOperator {
+                controller_id,
+                password: Password::new(password),
+                options,
+            }
+        ),
+        (
+            any::<ID>(),
+            option::of(any::<ID>()),
+            text_name(),
+            text(),
+            (0..=Message::MAX_OPERATOR_LEVEL).prop_map(|level| AccessLevel::new(level).unwrap()),
+            any::<MessageOptions>()
+        )
+            .prop_map(|(controller_id, operator_id, name, password, level, options)| {
+                OperatorInfo {
+                    controller_id,
+                    operator_id,
+                    name,
+                    password: Password::new(password),
+                    level,
+                    options,
+                }
+            }),
+    ]
+);
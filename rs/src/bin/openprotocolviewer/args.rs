@@ -0,0 +1,173 @@
+use ichen_openprotocol::{Filters, Password};
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: openprotocolviewer [OPTIONS]
+
+Options:
+    --url <URL>             WebSocket URL of the iChen Server (e.g. ws://x.x.x.x:5788)
+    --password <PASSWORD>   Login password
+    --filters <FILTERS>     Comma-delimited filters (default: All,JobCards,Operators)
+    --non-interactive       Never prompt; fail if --url or --password is missing
+    -h, --help              Print this help message
+
+Options not specified on the command line are prompted for interactively.";
+
+/// Command-line arguments of the viewer.
+///
+/// The password is kept as a `Password` so that it is redacted when `Args` is printed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Args {
+    pub url: Option<String>,
+    pub password: Option<Password<'static>>,
+    pub filters: Option<Filters>,
+    pub non_interactive: bool,
+    pub help: bool,
+}
+
+// Parse a comma-delimited list of filters, rejecting unrecognized names
+// (which `Filters::from_str` silently discards).
+fn parse_filters(text: &str) -> Result<Filters, String> {
+    let mut filters = Filters::None;
+
+    for name in text.split(',').map(str::trim) {
+        match Filters::from_str(name)? {
+            Filters::None if name != "None" => {
+                return Err(format!("invalid filter: [{}]", name));
+            }
+            f => filters += f,
+        }
+    }
+
+    Ok(filters)
+}
+
+/// Parse command-line arguments (excluding the program name).
+///
+/// Options taking a value accept both `--option value` and `--option=value`.
+///
+/// # Errors
+///
+/// Returns `Err(String)` with a description of the error for an unknown option,
+/// a missing or empty value, an option specified more than once, or invalid filters.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut result = Args::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (&arg[..index], Some(&arg[index + 1..])),
+            _ => (arg.as_str(), None),
+        };
+
+        match name {
+            "-h" | "--help" | "--non-interactive" => {
+                if inline_value.is_some() {
+                    return Err(format!("{} does not take a value", name));
+                }
+                if name == "--non-interactive" {
+                    result.non_interactive = true;
+                } else {
+                    result.help = true;
+                }
+                continue;
+            }
+            "--url" | "--password" | "--filters" => (),
+            _ => return Err(format!("unknown option: {}", name)),
+        }
+
+        let value = match inline_value {
+            Some(value) => value.to_string(),
+            None => args.next().ok_or_else(|| format!("{} requires a value", name))?,
+        };
+
+        let value = value.trim();
+
+        if value.is_empty() {
+            return Err(format!("{} cannot be empty", name));
+        }
+
+        let duplicated = match name {
+            "--url" => result.url.replace(value.to_string()).is_some(),
+            "--password" => result.password.replace(Password::new(value.to_string())).is_some(),
+            "--filters" => result.filters.replace(parse_filters(value)?).is_some(),
+            _ => unreachable!(),
+        };
+
+        if duplicated {
+            return Err(format!("{} is specified more than once", name));
+        }
+    }
+
+    Ok(result)
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|&a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args_empty() -> Result<(), String> {
+        assert_eq!(Args::default(), parse(&[])?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_args_all_options() -> Result<(), String> {
+        let args = parse(&[
+            "--url",
+            "ws://localhost:5788",
+            "--password=secret",
+            "--filters",
+            "Cycle, Mold,Operators",
+            "--non-interactive",
+        ])?;
+
+        assert_eq!(Some("ws://localhost:5788"), args.url.as_deref());
+        assert_eq!("secret", args.password.as_ref().unwrap().get());
+        assert_eq!(Some(Filters::Cycle + Filters::Mold + Filters::Operators), args.filters);
+        assert!(args.non_interactive);
+        assert!(!args.help);
+
+        assert!(parse(&["-h"])?.help);
+        assert!(parse(&["--help"])?.help);
+        assert_eq!(Some(Filters::None), parse(&["--filters=None"])?.filters);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_args_redacts_password() -> Result<(), String> {
+        let args = parse(&["--password", "secret"])?;
+        let text = format!("{:?}", args);
+
+        assert!(!text.contains("secret"), "{}", text);
+        assert!(text.contains(Password::REDACTED), "{}", text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(Err("unknown option: --foo".into()), parse(&["--foo"]));
+        assert_eq!(Err("unknown option: ws://localhost".into()), parse(&["ws://localhost"]));
+        assert_eq!(Err("--url requires a value".into()), parse(&["--url"]));
+        assert_eq!(Err("--password cannot be empty".into()), parse(&["--password="]));
+        assert_eq!(Err("--password cannot be empty".into()), parse(&["--password", "  "]));
+        assert_eq!(Err("invalid filter: [Foo]".into()), parse(&["--filters", "Cycle,Foo"]));
+        assert_eq!(
+            Err("--non-interactive does not take a value".into()),
+            parse(&["--non-interactive=yes"])
+        );
+        assert_eq!(
+            Err("--url is specified more than once".into()),
+            parse(&["--url", "ws://a:1", "--url=ws://b:2"])
+        );
+    }
+}
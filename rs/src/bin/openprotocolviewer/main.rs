@@ -39,9 +39,28 @@
 //!
 //! _Warning: If you do not enter a password of a user account that has the appropriate
 //! access rights, you'll fail to see all Open Protocol™ messages._
+//!
+//! Command-Line Options
+//! --------------------
+//!
+//! The URL and password can also be specified on the command line, so that the program
+//! can be scripted:
+//!
+//! ~~~text
+//! openprotocolviewer --url ws://x.x.x.x:5788 --password chenhsong --filters Cycle,Mold
+//! ~~~
+//!
+//! **`--url`**, **`--password`** : Information that is otherwise prompted for.
+//!
+//! **`--filters`** : Comma-delimited list of filters to JOIN with
+//! (default: `All,JobCards,Operators`).
+//!
+//! **`--non-interactive`** : Never prompt; fail if the URL or password is missing.
+
+mod args;
 
+use args::{parse_args, USAGE};
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::io::{stdin, Write};
 
 // This program uses the `websocket` crate for connection.
@@ -54,49 +73,53 @@ type WebSocketClient = Client<Box<dyn NetworkStream + Send>>;
 // Pull in the `ichen_openprotocol` namespace.
 // Beware that `ichen_openprotocol::Message` will conflict with `websocket::Message`
 // so you'll need to alias on of them if you pull both into scope.
-use ichen_openprotocol::{Filters, JobCard, Message};
+use ichen_openprotocol::{
+    validate_ws_url, AccessLevel, Filters, JobCard, JobProvider, JoinResult, Message, Protocol,
+    UserDirectory, ID,
+};
 
 struct Constants {
-    users: HashMap<&'static str, (u8, String)>,
+    users: HashMap<&'static str, (AccessLevel, String)>,
     jobs: Vec<JobCard<'static>>,
 }
 
-// Format common messages nicely for display
-fn display_message(prefix: &str, msg: &Message) {
-    print!("{}", prefix);
-
-    match msg {
-        Message::Alive { options, .. } => println!("Alive({})", options.sequence()),
-        Message::RequestControllersList { controller_id: None, options, .. } => {
-            println!("RequestControllersList({})", options.sequence())
-        }
-        Message::RequestControllersList { controller_id: Some(id), options, .. } => {
-            println!("RequestControllersList({}, {})", id, options.sequence())
-        }
-        Message::RequestJobCardsList { controller_id, options, .. } => {
-            println!("RequestJobCardsList({}, {})", controller_id, options.sequence())
-        }
-        Message::RequestMoldData { controller_id, options, .. } => {
-            println!("RequestMoldData({}, {})", controller_id, options.sequence())
-        }
-        Message::ReadMoldData { controller_id, field: None, options, .. } => {
-            println!("RequestMoldData({}, ALL, {})", controller_id, options.sequence())
-        }
-        Message::ReadMoldData { controller_id, field: Some(fld), options, .. } => {
-            println!("RequestMoldData({}, [{}], {})", controller_id, fld, options.sequence())
-        }
-        Message::ControllerAction { controller_id, action_id, options, .. } => {
-            println!("ControllerAction({}, [{}], {})", controller_id, action_id, options.sequence())
+// Mock users database
+// Replace this with a real user database in production
+impl UserDirectory for Constants {
+    fn lookup(&self, password: &str) -> Option<(AccessLevel, String)> {
+        match self.users.get(password) {
+            Some((level, name)) => {
+                println!("User found: name=[{}], access level={}.", name, level);
+                Some((*level, name.clone()))
+            }
+            None => {
+                println!("No user found with the password.");
+                None
+            }
         }
-        m if prefix.is_empty() => println!("{:#?}", m),
-        m => println!("\n{:#?}", m),
     }
 }
 
-// Parse an Open Protocol message, act on it, and generate a response (if appropriate)
+// Mock job scheduling system
+// Replace this with a real job scheduling system in production
+impl JobProvider for Constants {
+    fn jobs_for(&self, _controller_id: ID) -> Vec<JobCard<'_>> {
+        self.jobs.clone()
+    }
+}
+
+// Display a one-line description of a message
+fn display_message(prefix: &str, msg: &Message) {
+    println!("{}{}", prefix, msg.describe());
+}
+
+// Parse an Open Protocol message, act on it, and generate the responses (if any)
 // to send back to the server.
 //
-fn process_incoming_message<'a>(json: &'a str, builtin: &'a Constants) -> Option<Message<'a>> {
+fn process_incoming_message<'a, 'p: 'a>(
+    json: &'a str,
+    protocol: &mut Protocol<'p>,
+) -> Vec<Message<'a>> {
     // Parse message
     let message = match Message::parse_from_json_str(json) {
         // Valid Open Protocol message.
@@ -107,74 +130,39 @@ fn process_incoming_message<'a>(json: &'a str, builtin: &'a Constants) -> Option
         // Invalid message for Open Protocol!
         Err(err) => {
             eprintln!("Error parsing message: {}", err);
-            return None;
+            return Vec::new();
         }
     };
 
-    match message {
-        // Send an `ALIVE` when received an `ALIVE` from the server
-        Message::Alive { .. } => Some(Message::new_alive()),
-        //
-        // Response of the `JOIN`
-        // Result < 100 indicates failure
-        Message::JoinResponse { result, .. } if result < 100 => {
-            eprintln!("Failed to JOIN: error code = {}", result);
-            None
-        }
-        // Result >= 100 indicates success
-        // When the `JOIN` is successful, send `RequestControllersList`
-        Message::JoinResponse { .. } => Some(Message::RequestControllersList {
-            controller_id: None,
-            options: Default::default(),
-        }),
-        //
-        // MIS/MES integration - User login
-        // Find password in built-in list
-        Message::LoginOperator { controller_id, password, .. } => match builtin.users.get(password)
-        {
-            Some((level, name)) => {
-                println!("User found: password=[{}], access level={}.", password, level);
-
-                // Return access level
-                Some(Message::OperatorInfo {
-                    controller_id,
-                    // Cheap: Use the access level as the operator's ID
-                    operator_id: Some((u32::from(*level) + 1).try_into().unwrap()),
-                    name: name[..].try_into().unwrap(),
-                    password: password.try_into().unwrap(),
-                    level: *level,
-                    options: Default::default(),
-                })
-            }
-            None => {
-                println!("No user found with password: [{}].", password);
-
-                // Return no access
-                Some(Message::OperatorInfo {
-                    controller_id,
-                    operator_id: None,
-                    name: "Not Allowed".try_into().unwrap(),
-                    password: password.try_into().unwrap(),
-                    level: 0,
-                    options: Default::default(),
-                })
+    // `ALIVE`, the response of the `JOIN`, and MIS/MES integration (user login and
+    // request list of jobs) are all handled by the protocol state machine
+    match protocol.handle_input(&message) {
+        Ok(replies) => {
+            if let Message::JoinResponse { .. } = message {
+                if let Some(JoinResult::Failed(code)) = protocol.join_result() {
+                    eprintln!("Failed to JOIN: error code = {}", code);
+                }
             }
-        },
-        //
-        // MIS/MES integration - request list of jobs
-        Message::RequestJobCardsList { controller_id, .. } => Some(Message::JobCardsList {
-            controller_id,
-            data: builtin
-                .jobs
-                .iter()
-                .map(|jc| (jc.job_card_id().try_into().unwrap(), jc.clone()))
-                .collect(), // Load jobs list
-            options: Default::default(),
-        }),
-        //
-        // Other messages - Nothing to process
-        _ => None,
+            replies
+        }
+        Err(err) => {
+            eprintln!("Error processing message: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+// Serialize a message to JSON and send it to the server
+fn send_message(client: &mut WebSocketClient, msg: &Message) -> WebSocketResult<()> {
+    match msg.to_json_str() {
+        Ok(json) => {
+            send(client, &OwnedMessage::Text(json))?;
+            display_message("<<< ", msg);
+        }
+        Err(err) => eprintln!("Error serializing message: {}", err),
     }
+
+    Ok(())
 }
 
 fn send(client: &mut WebSocketClient, message: &OwnedMessage) -> WebSocketResult<()> {
@@ -200,7 +188,7 @@ fn send(client: &mut WebSocketClient, message: &OwnedMessage) -> WebSocketResult
     Ok(())
 }
 
-fn run(mut client: WebSocketClient, builtin: &Constants) -> WebSocketResult<()> {
+fn run(mut client: WebSocketClient, protocol: &mut Protocol) -> WebSocketResult<()> {
     loop {
         let message = match client.recv_message() {
             Ok(msg) => msg,
@@ -234,16 +222,9 @@ fn run(mut client: WebSocketClient, builtin: &Constants) -> WebSocketResult<()>
             OwnedMessage::Text(json) => {
                 println!("Received [{}]: {}", json.len(), json);
 
-                // Process the message, get reply message (if any)
-                if let Some(msg) = process_incoming_message(&json, &builtin) {
-                    // Serialize reply message to JSON and send it to the send loop
-                    match msg.to_json_str() {
-                        Ok(resp) => {
-                            send(&mut client, &OwnedMessage::Text(resp))?;
-                            display_message("<<< ", &msg);
-                        }
-                        Err(err) => eprintln!("Error serializing message: {}", err),
-                    }
+                // Process the message, send reply messages (if any)
+                for msg in process_incoming_message(&json, protocol) {
+                    send_message(&mut client, &msg)?;
                 }
             }
             // Display info if binary data received
@@ -254,35 +235,62 @@ fn run(mut client: WebSocketClient, builtin: &Constants) -> WebSocketResult<()>
     }
 }
 
-fn main() {
-    println!("iChen 4 Open Protocol Viewer");
-    println!();
-
-    // Read URL and password
-    print!("WebSocket URL (example: ws://x.x.x.x:port or wss://x.x.x.x:port): ");
+// Prompt for a line of input from stdin
+fn prompt(text: &str) -> String {
+    print!("{}", text);
     std::io::stdout().flush().expect("Failed to flush stdout.");
 
     let mut input = String::new();
     stdin().read_line(&mut input).expect("Failed to read line from stdin.");
-    let conn = input.trim();
+    input.trim().to_string()
+}
 
-    if conn.is_empty() {
-        eprintln!("URL cannot be empty.");
-        return;
-    } else if !conn.starts_with("ws://") && !conn.starts_with("wss://") {
-        eprintln!(
-            "Invalid WebSocket URL format.  \
-             Should be: ws://x.x.x.x:port or wss://x.x.x.x:port"
-        );
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!();
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if args.help {
+        println!("{}", USAGE);
         return;
     }
 
-    print!("Password: ");
-    std::io::stdout().flush().expect("Failed to flush stdout.");
+    println!("iChen 4 Open Protocol Viewer");
+    println!();
 
-    let mut input = String::new();
-    stdin().read_line(&mut input).expect("Failed to read line from stdin.");
-    let password = input.trim();
+    // Read URL and password, unless specified on the command line
+    let conn = match args.url {
+        Some(url) => url,
+        None if args.non_interactive => {
+            eprintln!("--url is required with --non-interactive.");
+            std::process::exit(2);
+        }
+        None => prompt("WebSocket URL (example: ws://x.x.x.x:port or wss://x.x.x.x:port): "),
+    };
+
+    let url = match validate_ws_url(&conn) {
+        Ok(url) => url,
+        Err(err) => {
+            eprintln!("Invalid WebSocket URL: {}", err);
+            eprintln!("Should be: ws://x.x.x.x:port or wss://x.x.x.x:port");
+            return;
+        }
+    };
+
+    let password = match args.password {
+        Some(password) => password.get().to_string(),
+        None if args.non_interactive => {
+            eprintln!("--password is required with --non-interactive.");
+            std::process::exit(2);
+        }
+        None => prompt("Password: "),
+    };
 
     if password.is_empty() {
         eprintln!("Password cannot be empty.");
@@ -292,13 +300,7 @@ fn main() {
     // Build connection to WebSocket server
     println!("Connecting to iChen Server at {}...", conn);
 
-    let mut ws_builder = match ClientBuilder::new(conn) {
-        Ok(b) => b,
-        Err(err) => {
-            eprintln!("Invalid URL: {}", err);
-            return;
-        }
-    };
+    let mut ws_builder = ClientBuilder::from_url(&url);
 
     // Attempt to connect
     let mut client = match ws_builder.connect(None) {
@@ -321,7 +323,7 @@ fn main() {
         ]
         .iter()
         .enumerate()
-        .map(|(i, &v)| (v, (i as u8, format!("MISUser{}", i))))
+        .map(|(i, &v)| (v, (AccessLevel::new(i as u8).unwrap(), format!("MISUser{}", i))))
         .collect(),
         //
         // Mock job scheduling system
@@ -370,21 +372,21 @@ fn main() {
     //     Filters::Status | Filters::Cycle | Filters::Mold | Filters::Actions | Filters::Alarms |
     //     Filters::Audit | Filters::JobCards | Filters::Operators
     //
-    let msg = Message::new_join(password, Filters::All + Filters::JobCards + Filters::Operators);
+    // Filters specified with `--filters` replace these defaults.
+    //
+    let filters = args.filters.unwrap_or(Filters::All + Filters::JobCards + Filters::Operators);
+    let mut protocol = Protocol::new_with_mis(&password, filters, &builtin, &builtin);
 
-    match msg.to_json_str() {
-        Ok(m) => {
-            if let Err(err) = send(&mut client, &OwnedMessage::Text(m)) {
-                eprintln!("Error when sending JOIN message: {}", err);
-            }
+    while let Some(msg) = protocol.poll_output() {
+        if let Err(err) = send_message(&mut client, &msg) {
+            eprintln!("Error when sending JOIN message: {}", err);
         }
-        Err(err) => eprintln!("Error in JOIN message: {}", err),
     }
 
     // After sending the `JOIN` message, start processing messages...
     println!("Process loop started...");
 
-    match run(client, &builtin) {
+    match run(client, &mut protocol) {
         Ok(_) => println!("Process loop stopped."),
         Err(err) => eprintln!("Error in process loop: {}", err),
     }
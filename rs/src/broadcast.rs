@@ -0,0 +1,336 @@
+use super::{Error, Message, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// What happens when a [`Subscription`] falls behind and its queue is full.
+///
+/// [`Subscription`]: struct.Subscription.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LagPolicy {
+    /// The oldest queued message is dropped to make room for the new one.
+    ///
+    /// The next call to [`recv`] returns `Err(`[`OpenProtocolError::Lagged`]`)` with the
+    /// number of messages dropped, after which messages are received as normal.
+    ///
+    /// [`recv`]: struct.Subscription.html#method.recv
+    /// [`OpenProtocolError::Lagged`]: enum.OpenProtocolError.html#variant.Lagged
+    DropOldest,
+    //
+    /// The subscription is closed and receives no more messages.
+    ///
+    /// The next call to [`recv`] returns `Err(`[`OpenProtocolError::Lagged`]`)`, followed by
+    /// the messages already queued, after which the subscription is closed.
+    ///
+    /// [`recv`]: struct.Subscription.html#method.recv
+    /// [`OpenProtocolError::Lagged`]: enum.OpenProtocolError.html#variant.Lagged
+    Disconnect,
+}
+
+/// Configuration for a [`Subscription`] to inbound messages.
+///
+/// [`Subscription`]: struct.Subscription.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SubscriptionConfig {
+    /// Maximum number of messages queued for the subscriber, which is at least 1.
+    pub capacity: usize,
+    //
+    /// What to do when the queue is full.
+    pub policy: LagPolicy,
+}
+
+impl SubscriptionConfig {
+    /// Default capacity of the queue of a subscriber: 256 messages.
+    pub const DEFAULT_CAPACITY: usize = 256;
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self { capacity: Self::DEFAULT_CAPACITY, policy: LagPolicy::DropOldest }
+    }
+}
+
+// The queue of a subscriber.
+#[derive(Debug, Default)]
+struct Queue {
+    messages: VecDeque<Arc<str>>,
+    // Number of messages dropped and not yet reported.
+    dropped: u64,
+    // Reason (if any) why no more messages will be queued.
+    closed: Option<&'static str>,
+}
+
+#[derive(Debug)]
+struct Channel {
+    config: SubscriptionConfig,
+    queue: Mutex<Queue>,
+    ready: Condvar,
+}
+
+impl Channel {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // Queue a message.  Returns false if the subscription is closed.
+    fn push(&self, json: &Arc<str>) -> bool {
+        let mut queue = self.lock();
+
+        if queue.closed.is_some() {
+            return false;
+        }
+
+        if queue.messages.len() >= self.config.capacity.max(1) {
+            queue.dropped += 1;
+
+            match self.config.policy {
+                LagPolicy::DropOldest => {
+                    queue.messages.pop_front();
+                }
+                LagPolicy::Disconnect => {
+                    queue.closed = Some("subscription closed");
+                    self.ready.notify_all();
+                    return false;
+                }
+            }
+        }
+
+        queue.messages.push_back(json.clone());
+        self.ready.notify_all();
+        true
+    }
+
+    fn close(&self, reason: &'static str) {
+        let mut queue = self.lock();
+
+        if queue.closed.is_none() {
+            queue.closed = Some(reason);
+        }
+        self.ready.notify_all();
+    }
+}
+
+// Fans out the JSON text of each inbound message to all subscribers.
+#[derive(Debug, Default)]
+pub(crate) struct Broadcaster(Mutex<(bool, Vec<Arc<Channel>>)>);
+
+impl Broadcaster {
+    // Add a subscriber.  A subscriber added after the broadcaster is closed is closed already.
+    pub(crate) fn subscribe(
+        &self,
+        config: SubscriptionConfig,
+        max_message_size: Arc<AtomicUsize>,
+    ) -> Subscription {
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let channel =
+            Arc::new(Channel { config, queue: Default::default(), ready: Default::default() });
+
+        if inner.0 {
+            channel.close("connection closed");
+        } else {
+            inner.1.push(channel.clone());
+        }
+
+        Subscription { channel, buffer: "".into(), max_message_size }
+    }
+
+    // Queue a message for all subscribers, removing those that are closed or dropped.
+    pub(crate) fn publish(&self, json: &str) {
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+
+        if inner.1.is_empty() {
+            return;
+        }
+
+        let json: Arc<str> = json.into();
+        inner.1.retain(|channel| Arc::strong_count(channel) > 1 && channel.push(&json));
+    }
+
+    // Close all subscriptions after the messages already queued.
+    pub(crate) fn close(&self) {
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        inner.0 = true;
+
+        for channel in inner.1.drain(..) {
+            channel.close("connection closed");
+        }
+    }
+}
+
+/// A subscription to all inbound messages of a [`BlockingConnection`].
+///
+/// Each subscription receives its own copy of every message that arrives from the server
+/// after it is created, independently of [`BlockingConnection::recv`] and of other
+/// subscriptions, so multiple consumers can share one connection.  Messages are queued for
+/// each subscriber (see [`SubscriptionConfig`]) until they are received.
+///
+/// A `Subscription` can be moved to another thread.  Dropping it unsubscribes.
+///
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+/// [`BlockingConnection::recv`]: struct.BlockingConnection.html#method.recv
+/// [`SubscriptionConfig`]: struct.SubscriptionConfig.html
+///
+#[derive(Debug)]
+pub struct Subscription {
+    channel: Arc<Channel>,
+    //
+    // The JSON text of the last message received, which the last `Message` borrows from.
+    buffer: Arc<str>,
+    //
+    // Maximum size of an inbound message (`usize::MAX` for no limit), shared with the
+    // connection.
+    max_message_size: Arc<AtomicUsize>,
+}
+
+impl Subscription {
+    /// Block until the next `Message` arrives from the server.
+    ///
+    /// The `Message` returned borrows from an internal buffer, so it must be dropped
+    /// before `recv` can be called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Lagged`]`)` if messages were dropped because
+    /// this subscriber has fallen behind (see [`LagPolicy`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::MessageTooLarge`]`)` if the message is larger than
+    /// the maximum message size of the connection.
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the message cannot be parsed, or if all
+    /// queued messages have been received and the subscription or the connection is closed.
+    ///
+    /// [`LagPolicy`]: enum.LagPolicy.html
+    /// [`OpenProtocolError::Lagged`]: enum.OpenProtocolError.html#variant.Lagged
+    /// [`OpenProtocolError::MessageTooLarge`]: enum.OpenProtocolError.html#variant.MessageTooLarge
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    pub fn recv(&mut self) -> Result<'_, Message<'_>> {
+        let mut queue = self.channel.lock();
+
+        loop {
+            if queue.dropped > 0 {
+                let dropped = queue.dropped;
+                queue.dropped = 0;
+                return Err(Error::Lagged(dropped));
+            }
+
+            if let Some(json) = queue.messages.pop_front() {
+                self.buffer = json;
+                break;
+            }
+
+            if let Some(reason) = queue.closed {
+                return Err(Error::SystemError(reason.into()));
+            }
+
+            queue = self.channel.ready.wait(queue).unwrap_or_else(|err| err.into_inner());
+        }
+
+        drop(queue);
+
+        let limit = match self.max_message_size.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            limit => Some(limit),
+        };
+
+        Message::parse_from_json_str_with_limit(&self.buffer, limit)
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn alive(sequence: u64) -> String {
+        format!(r#"{{"$type":"Alive","sequence":{}}}"#, sequence)
+    }
+
+    fn subscribe(broadcaster: &Broadcaster, capacity: usize, policy: LagPolicy) -> Subscription {
+        let config = SubscriptionConfig { capacity, policy };
+        broadcaster.subscribe(config, Arc::new(AtomicUsize::new(usize::MAX)))
+    }
+
+    fn sequence(subscription: &mut Subscription) -> std::result::Result<u64, String> {
+        Ok(subscription.recv()?.sequence())
+    }
+
+    fn is_closed(subscription: &mut Subscription, reason: &str) -> bool {
+        match subscription.recv() {
+            Err(Error::SystemError(err)) => err == reason,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_broadcast_drop_oldest() -> std::result::Result<(), String> {
+        let broadcaster = Broadcaster::default();
+        let mut fast = subscribe(&broadcaster, 8, LagPolicy::DropOldest);
+        let mut slow = subscribe(&broadcaster, 2, LagPolicy::DropOldest);
+
+        for n in 1..=2 {
+            broadcaster.publish(&alive(n));
+            assert_eq!(n, sequence(&mut fast)?);
+        }
+        for n in 3..=5 {
+            broadcaster.publish(&alive(n));
+        }
+        assert_eq!(3, sequence(&mut fast)?);
+
+        // The slow subscriber missed the oldest three messages
+        assert_eq!(Err(Error::Lagged(3)), slow.recv().map(|_| ()));
+        assert_eq!(4, sequence(&mut slow)?);
+        assert_eq!(5, sequence(&mut slow)?);
+
+        broadcaster.close();
+
+        assert_eq!(4, sequence(&mut fast)?);
+        assert_eq!(5, sequence(&mut fast)?);
+        assert!(is_closed(&mut fast, "connection closed"));
+        assert!(is_closed(&mut slow, "connection closed"));
+
+        // Subscribing to a closed broadcaster
+        assert!(is_closed(
+            &mut subscribe(&broadcaster, 2, LagPolicy::DropOldest),
+            "connection closed"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_disconnect() -> std::result::Result<(), String> {
+        let broadcaster = Broadcaster::default();
+        let mut slow = subscribe(&broadcaster, 2, LagPolicy::Disconnect);
+
+        for n in 1..=4 {
+            broadcaster.publish(&alive(n));
+        }
+
+        assert_eq!(Err(Error::Lagged(1)), slow.recv().map(|_| ()));
+        assert_eq!(1, sequence(&mut slow)?);
+        assert_eq!(2, sequence(&mut slow)?);
+        assert!(is_closed(&mut slow, "subscription closed"));
+
+        // The closed subscription has been removed
+        assert!(broadcaster.0.lock().unwrap().1.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_unsubscribe_on_drop() {
+        let broadcaster = Broadcaster::default();
+        let subscription = subscribe(&broadcaster, 2, LagPolicy::DropOldest);
+
+        broadcaster.publish(&alive(1));
+        assert_eq!(1, broadcaster.0.lock().unwrap().1.len());
+
+        drop(subscription);
+        broadcaster.publish(&alive(2));
+        assert!(broadcaster.0.lock().unwrap().1.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use std::time::Instant;
+
+/// A source of the current time.
+///
+/// [`Keepalive`] and [`RateLimiter`] get the current time from a `Clock`, so that a test can
+/// substitute a clock that it controls (e.g. [`MockClock`], with the `test-util` feature) for
+/// the [`SystemClock`].
+///
+/// [`BlockingConnection`] always uses the time of the system, because its timeouts wait on
+/// real sockets and threads.
+///
+/// [`Keepalive`]: struct.Keepalive.html
+/// [`RateLimiter`]: struct.RateLimiter.html
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+/// [`MockClock`]: testing/struct.MockClock.html
+/// [`SystemClock`]: struct.SystemClock.html
+///
+pub trait Clock {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+}
+
+/// A [`Clock`] returning the current time of the system (i.e. `Instant::now()`).
+///
+/// [`Clock`]: trait.Clock.html
+///
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
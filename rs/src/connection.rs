@@ -0,0 +1,2447 @@
+use super::broadcast::Broadcaster;
+use super::deflate::{DeflateParams, Deflater, MessageAssembler};
+use super::{
+    Error, JoinResult, Message, RateLimitConfig, RateLimiter, Result, Subscription,
+    SubscriptionConfig, TimestampedMessage, ID,
+};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use websocket::dataframe::DataFrame;
+use websocket::sync::{Reader, Writer};
+use websocket::url::Url;
+use websocket::ws::util::header::read_header;
+use websocket::{ClientBuilder, CloseData, OwnedMessage, WebSocketError};
+
+/// What a [`BlockingConnection`] does when its outbound queue is full.
+///
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum QueueFullPolicy {
+    /// [`send`] blocks until there is room in the queue.
+    ///
+    /// [`send`]: struct.BlockingConnection.html#method.send
+    Block,
+    //
+    /// [`send`] silently drops low-priority status messages (i.e. `ControllerStatus` and
+    /// `CycleData` messages with a `priority` of zero or below) and blocks for all
+    /// other messages.
+    ///
+    /// [`send`]: struct.BlockingConnection.html#method.send
+    DropLowPriority,
+}
+
+/// State of a [`BlockingConnection`].
+///
+/// The transitions are:
+///
+/// | From         | To        | When                                                          |
+/// |:-------------|:----------|:--------------------------------------------------------------|
+/// | `Connecting` | `Joined`  | A `JoinResponse` indicating success arrives                   |
+/// | `Connecting` | `Closed`  | A `JoinResponse` indicating failure arrives (the connection is then closed) |
+/// | any          | `Closed`  | The connection is closed by either side, times out or fails   |
+///
+/// `Closed` is final.  A `BlockingConnection` never reconnects by itself; use [`rejoin`] to
+/// connect again, which creates a new `BlockingConnection` starting from `Connecting`.
+///
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+/// [`rejoin`]: struct.BlockingConnection.html#method.rejoin
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ConnectionState {
+    /// The WebSocket connection is open, but no `JoinResponse` indicating success has arrived yet.
+    Connecting,
+    //
+    /// A `JoinResponse` indicating success has arrived.
+    Joined,
+    //
+    /// The connection is closed.
+    Closed,
+}
+
+// The state of a connection, shared with the receive thread, together with the senders
+// of all channels watching it.
+#[derive(Debug)]
+struct SharedState(Mutex<(ConnectionState, Vec<Sender<ConnectionState>>)>);
+
+impl SharedState {
+    fn new(state: ConnectionState) -> Self {
+        Self(Mutex::new((state, Vec::new())))
+    }
+
+    fn get(&self) -> ConnectionState {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).0
+    }
+
+    // Create a channel that receives the current state, then all transitions.
+    fn watch(&self) -> Receiver<ConnectionState> {
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let (sender, receiver) = channel();
+        let _ = sender.send(inner.0);
+        inner.1.push(sender);
+        receiver
+    }
+
+    // Change the state, notifying all watchers.  `Closed` is final.
+    fn set(&self, state: ConnectionState) {
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let (current, watchers) = &mut *inner;
+
+        if *current == state || *current == ConnectionState::Closed {
+            return;
+        }
+
+        *current = state;
+        watchers.retain(|watcher| watcher.send(state).is_ok());
+    }
+}
+
+/// Configuration for the outbound queue of a [`BlockingConnection`].
+///
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct QueueConfig {
+    /// Maximum number of messages waiting to be sent.
+    ///
+    /// With a capacity of zero, each message is handed directly to the sending thread.
+    pub capacity: usize,
+    //
+    /// What to do when the queue is full.
+    pub policy: QueueFullPolicy,
+}
+
+impl QueueConfig {
+    /// Default capacity of the outbound queue: 1024 messages.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self { capacity: Self::DEFAULT_CAPACITY, policy: QueueFullPolicy::Block }
+    }
+}
+
+/// Configuration for a [`BlockingConnection`].
+///
+/// [`BlockingConnection`]: struct.BlockingConnection.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConnectionConfig {
+    /// Configuration for the outbound queue.
+    pub queue: QueueConfig,
+    //
+    /// Request compression of messages with the `permessage-deflate` WebSocket extension
+    /// (default `false`).
+    ///
+    /// Compression only takes effect if the server also supports it (see
+    /// [`is_compressed`]); otherwise messages are sent and received uncompressed.
+    ///
+    /// Large messages compress very well.  For example, a `ControllersList` of 10
+    /// controllers shrinks from about 3.8KB to about 460 bytes (around 12%), and a
+    /// 200-byte `CycleData` repeated with slightly different values compresses to
+    /// around 10 bytes because the compression context is kept between messages.
+    ///
+    /// [`is_compressed`]: struct.BlockingConnection.html#method.is_compressed
+    pub compression: bool,
+    //
+    /// Close the connection if nothing (not even an `ALIVE` message) arrives from the server
+    /// within this period of time (default `None`, meaning wait forever).
+    ///
+    /// A TCP connection can stay open long after the server has silently gone away.
+    /// As the iChen® Server sends `ALIVE` messages regularly, a period of silence longer
+    /// than the `ALIVE` interval means that the connection is dead.  When this happens,
+    /// the connection is closed and [`recv`] returns
+    /// `Err(`[`OpenProtocolError::Timeout`]`)` instead of blocking forever.
+    ///
+    /// The timeout must not be zero.
+    ///
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    /// [`OpenProtocolError::Timeout`]: enum.OpenProtocolError.html#variant.Timeout
+    pub idle_timeout: Option<Duration>,
+    //
+    /// Maximum size (in bytes) of a message as received from the server, before
+    /// decompression (default [`DEFAULT_MAX_FRAME_SIZE`]).
+    ///
+    /// This guards against a server sending a huge message that exhausts memory.  The size
+    /// of each WebSocket frame is checked before the frame is read into memory, so a message
+    /// over this size is never allocated.  [`recv`] then returns
+    /// `Err(`[`OpenProtocolError::MessageTooLarge`]`)` and the connection is closed.
+    ///
+    /// For a message fragmented into multiple frames, the reported `size` is the size of
+    /// the frames received so far.
+    ///
+    /// Smaller messages are still checked against the [maximum message size], which can
+    /// be changed at any time.
+    ///
+    /// [`DEFAULT_MAX_FRAME_SIZE`]: struct.ConnectionConfig.html#associatedconstant.DEFAULT_MAX_FRAME_SIZE
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    /// [`OpenProtocolError::MessageTooLarge`]: enum.OpenProtocolError.html#variant.MessageTooLarge
+    /// [maximum message size]: struct.BlockingConnection.html#method.set_max_message_size
+    pub max_frame_size: usize,
+    //
+    /// Limit the rate of messages sent to the server (default `None`, meaning no limit).
+    ///
+    /// When the limit is exceeded, [`send`] blocks until the message can be sent, while
+    /// [`try_send`] returns `Err(`[`OpenProtocolError::RateLimited`]`)`.  `Alive` messages
+    /// are never limited (see [`RateLimiter`]).
+    ///
+    /// [`send`]: struct.BlockingConnection.html#method.send
+    /// [`try_send`]: struct.BlockingConnection.html#method.try_send
+    /// [`OpenProtocolError::RateLimited`]: enum.OpenProtocolError.html#variant.RateLimited
+    /// [`RateLimiter`]: struct.RateLimiter.html
+    pub rate_limit: Option<RateLimitConfig>,
+    //
+    /// Send all queued messages when the connection is closed with [`close`], waiting up to
+    /// this period of time (default `None`, meaning that queued messages may be dropped).
+    ///
+    /// When set, [`close`] behaves like [`shutdown`] with this timeout, except that a timeout
+    /// is not reported as an error.  Use this to make sure that a final message (e.g. an
+    /// `OperatorInfo` reply) is not lost when the connection is closed.
+    ///
+    /// The timeout must not be zero.
+    ///
+    /// [`close`]: struct.BlockingConnection.html#method.close
+    /// [`shutdown`]: struct.BlockingConnection.html#method.shutdown
+    pub drain_on_close: Option<Duration>,
+}
+
+impl ConnectionConfig {
+    /// Default maximum size of a message as received from the server: 16 MiB.
+    pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+            compression: false,
+            idle_timeout: None,
+            max_frame_size: Self::DEFAULT_MAX_FRAME_SIZE,
+            rate_limit: None,
+            drain_on_close: None,
+        }
+    }
+}
+
+/// Validate a WebSocket URL (e.g. `ws://1.2.3.4:5788`) for connecting to an iChen® Server.
+///
+/// The URL must have a `ws` or `wss` scheme, a non-empty host, and an explicit port.
+///
+/// # Errors
+///
+/// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` with `field` set to `"url"` if the
+/// URL cannot be parsed, or if any of the above conditions is not met.
+///
+/// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+///
+/// ## Error Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// assert_eq!(
+///     "value [http://1.2.3.4:5788] is invalid for the field url - scheme must be ws or wss",
+///     validate_ws_url("http://1.2.3.4:5788").unwrap_err().to_string()
+/// );
+/// assert_eq!(
+///     "value [ws://1.2.3.4] is invalid for the field url - port must be specified",
+///     validate_ws_url("ws://1.2.3.4").unwrap_err().to_string()
+/// );
+/// ~~~
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let url = validate_ws_url("ws://1.2.3.4:5788")?;
+/// assert_eq!(Some("1.2.3.4"), url.host_str());
+/// assert_eq!(Some(5788), url.port());
+/// # Ok(())
+/// # }
+/// ~~~
+pub fn validate_ws_url(url: &str) -> Result<'static, Url> {
+    let error = |description: String| Error::InvalidField {
+        field: "url",
+        value: url.to_string().into(),
+        description: description.into(),
+    };
+
+    let parsed = Url::parse(url.trim()).map_err(|err| error(format!("invalid URL: {}", err)))?;
+
+    match parsed.scheme() {
+        "ws" | "wss" => (),
+        _ => return Err(error("scheme must be ws or wss".into())),
+    }
+
+    match parsed.host_str() {
+        Some(host) if !host.is_empty() => (),
+        _ => return Err(error("host cannot be empty".into())),
+    }
+
+    // The port of a URL is dropped when it is the default port of the scheme,
+    // so check the original text for an explicit port
+    if !has_explicit_port(url.trim()) {
+        return Err(error("port must be specified".into()));
+    }
+
+    Ok(parsed)
+}
+
+// Does the authority part of a URL text string end with a `:port`?
+fn has_explicit_port(url: &str) -> bool {
+    let authority = url.split_once("://").map(|(_, rest)| rest).unwrap_or_default();
+    let authority = authority.split(&['/', '?', '#'][..]).next().unwrap_or_default();
+    let host_port = authority.rsplit('@').next().unwrap_or_default();
+
+    match host_port.rfind(':') {
+        // A colon inside the brackets of an IP v.6 address is not a port separator
+        Some(index) if !host_port[index..].contains(']') => {
+            let port = &host_port[index + 1..];
+            !port.is_empty() && port.chars().all(|c| c.is_ascii_digit())
+        }
+        _ => false,
+    }
+}
+
+// The sending end of the outbound queue.
+#[derive(Debug, Clone)]
+struct Outbox {
+    sender: SyncSender<OwnedMessage>,
+    policy: QueueFullPolicy,
+}
+
+impl Outbox {
+    // Queue a message, applying the queue-full policy.
+    //
+    // Returns `false` if the message is dropped.
+    fn send(&self, msg: OwnedMessage, low_priority: bool) -> Result<'static, bool> {
+        match self.policy {
+            QueueFullPolicy::DropLowPriority if low_priority => match self.try_send(msg) {
+                Ok(()) => Ok(true),
+                Err(Error::QueueFull) => Ok(false),
+                Err(err) => Err(err),
+            },
+            _ => self
+                .sender
+                .send(msg)
+                .map(|_| true)
+                .map_err(|_| Error::SystemError("connection closed".into())),
+        }
+    }
+
+    // Queue a message without blocking.
+    fn try_send(&self, msg: OwnedMessage) -> Result<'static, ()> {
+        self.sender.try_send(msg).map_err(|err| match err {
+            TrySendError::Full(_) => Error::QueueFull,
+            TrySendError::Disconnected(_) => Error::SystemError("connection closed".into()),
+        })
+    }
+}
+
+// Is a message a low-priority status message that may be dropped?
+fn is_low_priority(message: &Message) -> bool {
+    match message {
+        Message::ControllerStatus { .. } | Message::CycleData { .. } => message.priority() <= 0,
+        _ => false,
+    }
+}
+
+/// A synchronous (blocking) connection to an iChen® Server via WebSocket.
+///
+/// The connection internally manages two background threads: one receiving messages from
+/// the WebSocket and one sending messages to it.
+///
+/// `ALIVE` messages from the server are automatically replied to; they are still
+/// delivered via [`recv`] but there is no need to respond to them manually.
+///
+/// Messages fragmented by the server into multiple WebSocket frames (e.g. a large
+/// `ControllersList`) are reassembled into a single complete JSON text before parsing.
+///
+/// Outbound messages are queued in a bounded queue (see [`QueueConfig`]), so a stalled
+/// connection cannot grow memory without limit.
+///
+/// Inbound messages larger than [`DEFAULT_MAX_MESSAGE_SIZE`] are rejected without being
+/// parsed (see [`set_max_message_size`]).
+/// Messages larger than the [maximum frame size] (16 MiB by default) are rejected before
+/// being read into memory, closing the connection.
+///
+/// Only plain-text (i.e. `ws://`) connections are supported.  Messages can be compressed
+/// with the `permessage-deflate` WebSocket extension (see [`ConnectionConfig`]).
+///
+/// With the `tracing` feature enabled, each connection is wrapped in a `connection` span,
+/// and every inbound and outbound message is logged at `DEBUG` level.  Only a summary of each
+/// message (see [`Message::describe`]) is logged, never the JSON text, which may contain
+/// passwords.
+///
+/// [`recv`]: struct.BlockingConnection.html#method.recv
+/// [`Message::describe`]: enum.Message.html#method.describe
+/// [`QueueConfig`]: struct.QueueConfig.html
+/// [`ConnectionConfig`]: struct.ConnectionConfig.html
+/// [`DEFAULT_MAX_MESSAGE_SIZE`]: enum.Message.html#associatedconstant.DEFAULT_MAX_MESSAGE_SIZE
+/// [`set_max_message_size`]: struct.BlockingConnection.html#method.set_max_message_size
+/// [maximum frame size]: struct.ConnectionConfig.html#structfield.max_frame_size
+///
+/// # Examples
+///
+/// ~~~no_run
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let mut conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+///
+/// conn.send(&Message::new_join("mypassword", Filters::All + Filters::JobCards))?;
+///
+/// loop {
+///     let msg = conn.recv()?;
+///
+///     match msg.join_result() {
+///         Some(JoinResult::Failed(_)) => break,
+///         Some(JoinResult::Succeeded(_)) => println!("Joined!"),
+///         None => println!("{:?}", msg),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ~~~
+#[derive(Debug)]
+pub struct BlockingConnection {
+    // Outbound messages to the send thread.
+    outgoing: Outbox,
+    //
+    // Inbound JSON text, with the time it arrived (or an error), from the receive thread.
+    incoming: Receiver<Result<'static, (Instant, String)>>,
+    //
+    // Buffer holding the last JSON text received, which the last `Message` borrows from.
+    buffer: String,
+    //
+    // Maximum size of an inbound message (`usize::MAX` for no limit), shared with the
+    // receive thread.
+    max_message_size: Arc<AtomicUsize>,
+    //
+    // Whether messages are compressed with the `permessage-deflate` extension.
+    compressed: bool,
+    //
+    // State of the connection, shared with the receive thread.
+    state: Arc<SharedState>,
+    //
+    // Subscribers to inbound messages, shared with the receive thread.
+    subscribers: Arc<Broadcaster>,
+    //
+    // Controllers watched (`None` for all controllers), shared with the receive thread.
+    watched: Arc<Mutex<Option<HashSet<ID>>>>,
+    //
+    // Whether a `JOIN` has been sent on this connection.
+    join_sent: AtomicBool,
+    //
+    // Rate limiter for outbound messages (if any).
+    rate_limiter: Option<Mutex<RateLimiter>>,
+    //
+    // URL and configuration of the connection, used to re-establish it.
+    url: String,
+    config: ConnectionConfig,
+    //
+    // The underlying TCP stream, used to force a shutdown.
+    stream: TcpStream,
+    //
+    // Background threads.
+    threads: Vec<JoinHandle<()>>,
+    //
+    // Span covering all events on this connection.
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl BlockingConnection {
+    /// Connect to an iChen® Server at a WebSocket URL (e.g. `ws://1.2.3.4:5788`), with the
+    /// default [`QueueConfig`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if the URL is invalid (see
+    /// [`validate_ws_url`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Transport`]`)` if the connection cannot be
+    /// established.
+    ///
+    /// [`QueueConfig`]: struct.QueueConfig.html
+    /// [`validate_ws_url`]: fn.validate_ws_url.html
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError::Transport`]: enum.OpenProtocolError.html#variant.Transport
+    ///
+    pub fn connect(url: &str) -> Result<'static, Self> {
+        Self::connect_with_queue(url, Default::default())
+    }
+
+    /// Connect to an iChen® Server at a WebSocket URL (e.g. `ws://1.2.3.4:5788`), with a
+    /// particular [`QueueConfig`] for outbound messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if the URL is invalid (see
+    /// [`validate_ws_url`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Transport`]`)` if the connection cannot be
+    /// established.
+    ///
+    /// [`QueueConfig`]: struct.QueueConfig.html
+    /// [`validate_ws_url`]: fn.validate_ws_url.html
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError::Transport`]: enum.OpenProtocolError.html#variant.Transport
+    ///
+    pub fn connect_with_queue(url: &str, queue: QueueConfig) -> Result<'static, Self> {
+        Self::connect_with_config(url, ConnectionConfig { queue, ..Default::default() })
+    }
+
+    /// Connect to an iChen® Server at a WebSocket URL (e.g. `ws://1.2.3.4:5788`), with a
+    /// particular [`ConnectionConfig`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if the URL is invalid (see
+    /// [`validate_ws_url`]), if the idle timeout is zero, or if the rate limit allows no
+    /// messages or has a zero period.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Transport`]`)` if the connection cannot be
+    /// established.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::SystemError`]`)` if compression is requested and
+    /// the server responds with unsupported `permessage-deflate` parameters.
+    ///
+    /// [`ConnectionConfig`]: struct.ConnectionConfig.html
+    /// [`validate_ws_url`]: fn.validate_ws_url.html
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError::Transport`]: enum.OpenProtocolError.html#variant.Transport
+    /// [`OpenProtocolError::SystemError`]: enum.OpenProtocolError.html#variant.SystemError
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let config = ConnectionConfig { compression: true, ..Default::default() };
+    /// let conn = BlockingConnection::connect_with_config("ws://1.2.3.4:5788", config)?;
+    ///
+    /// println!("Compressed: {}", conn.is_compressed());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn connect_with_config(url: &str, config: ConnectionConfig) -> Result<'static, Self> {
+        let queue = config.queue;
+
+        if config.idle_timeout == Some(Duration::from_secs(0)) {
+            return Err(Error::InvalidField {
+                field: "idle_timeout",
+                value: "0s".into(),
+                description: "idle timeout cannot be zero".into(),
+            });
+        }
+
+        if config.drain_on_close == Some(Duration::from_secs(0)) {
+            return Err(Error::InvalidField {
+                field: "drain_on_close",
+                value: "0s".into(),
+                description: "drain timeout cannot be zero".into(),
+            });
+        }
+
+        if let Some(limit) = config.rate_limit {
+            if limit.max_messages == 0 || limit.per == Duration::from_secs(0) {
+                return Err(Error::InvalidField {
+                    field: "rate_limit",
+                    value: format!("{} per {:?}", limit.max_messages, limit.per).into(),
+                    description: "rate limit must allow at least one message per non-zero period"
+                        .into(),
+                });
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!("connection", url);
+        #[cfg(feature = "tracing")]
+        let entered = span.enter();
+
+        let address = url.to_string();
+        let url = validate_ws_url(url)?;
+        let mut builder = ClientBuilder::from_url(&url);
+
+        if config.compression {
+            builder = builder.add_extension(DeflateParams::offer());
+        }
+
+        let client =
+            builder.connect_insecure().map_err(|err| Error::transport("cannot connect", err))?;
+
+        let deflate = if config.compression {
+            DeflateParams::negotiated(client.extensions()).map_err(|err| {
+                Error::SystemError(format!("cannot negotiate compression: {}", err).into())
+            })?
+        } else {
+            None
+        };
+
+        let stream = client
+            .stream_ref()
+            .try_clone()
+            .map_err(|err| Error::transport("cannot access TCP stream", err))?;
+
+        stream
+            .set_read_timeout(config.idle_timeout)
+            .map_err(|err| Error::transport("cannot set idle timeout", err))?;
+
+        let (reader, writer) =
+            client.split().map_err(|err| Error::transport("cannot split WebSocket", err))?;
+
+        let (sender, outbox) = sync_channel(queue.capacity);
+        let outgoing = Outbox { sender, policy: queue.policy };
+        let (inbox, incoming) = channel();
+
+        #[cfg(feature = "tracing")]
+        let (send_span, recv_span) = (span.clone(), span.clone());
+
+        let deflater = deflate.map(|params| Deflater::new(params.client_no_context_takeover));
+        let assembler = MessageAssembler::new(match deflate {
+            Some(params) => params.server_no_context_takeover,
+            None => false,
+        });
+
+        let send_thread = std::thread::spawn(move || {
+            #[cfg(feature = "tracing")]
+            let _guard = send_span.enter();
+            send_loop(writer, outbox, deflater)
+        });
+
+        let reply = outgoing.sender.clone();
+        let max_message_size = Arc::new(AtomicUsize::new(Message::DEFAULT_MAX_MESSAGE_SIZE));
+        let limit = max_message_size.clone();
+        let state = Arc::new(SharedState::new(ConnectionState::Connecting));
+        let recv_state = state.clone();
+        let subscribers = Arc::new(Broadcaster::default());
+        let recv_subscribers = subscribers.clone();
+        let watched = Arc::new(Mutex::new(None));
+        let recv_watched = watched.clone();
+
+        let recv_thread = std::thread::spawn(move || {
+            #[cfg(feature = "tracing")]
+            let _guard = recv_span.enter();
+            recv_loop(
+                reader,
+                inbox,
+                reply,
+                limit,
+                assembler,
+                &config,
+                &recv_state,
+                &recv_subscribers,
+                &recv_watched,
+            )
+        });
+
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!(compressed = deflate.is_some(), "connected");
+            drop(entered);
+        }
+
+        Ok(Self {
+            outgoing,
+            incoming,
+            buffer: String::new(),
+            max_message_size,
+            compressed: deflate.is_some(),
+            state,
+            subscribers,
+            watched,
+            join_sent: AtomicBool::new(false),
+            rate_limiter: config.rate_limit.map(|limit| Mutex::new(RateLimiter::new(limit))),
+            url: address,
+            config,
+            stream,
+            threads: vec![send_thread, recv_thread],
+            #[cfg(feature = "tracing")]
+            span,
+        })
+    }
+
+    /// Validate a `Message`, serialize it into JSON and queue it for sending.
+    ///
+    /// If the outbound queue is full, this either blocks until there is room or drops the
+    /// message, depending on the [`QueueFullPolicy`].
+    ///
+    /// If a [rate limit] is configured, this blocks until the message can be sent within it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if the message is a `JOIN`
+    /// and a `JOIN` has already been sent on this connection (see [`rejoin`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the message fails validation, or if the
+    /// connection is already closed.
+    ///
+    /// [`QueueFullPolicy`]: enum.QueueFullPolicy.html
+    /// [rate limit]: struct.ConnectionConfig.html#structfield.rate_limit
+    /// [`rejoin`]: struct.BlockingConnection.html#method.rejoin
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    pub fn send<'a>(&self, message: &'a Message) -> Result<'a, ()> {
+        let json = message.to_json_str()?;
+        let is_join = self.check_join(message)?;
+
+        while let Err(wait) = self.acquire(message) {
+            std::thread::sleep(wait);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &self.span,
+            message = %message.describe(),
+            size = json.len(),
+            "sending message"
+        );
+
+        let _sent = self
+            .outgoing
+            .send(OwnedMessage::Text(json), is_low_priority(message))
+            .map_err(|err| self.join_failed(is_join, err))?;
+
+        #[cfg(feature = "tracing")]
+        {
+            if !_sent {
+                tracing::debug!(parent: &self.span, "outbound queue full, message dropped");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate a `Message`, serialize it into JSON and queue it for sending without
+    /// blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::QueueFull`]`)` if the outbound queue is full
+    /// (regardless of the [`QueueFullPolicy`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::RateLimited`]`)` if the message exceeds the
+    /// [rate limit].
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if the message is a `JOIN`
+    /// and a `JOIN` has already been sent on this connection (see [`rejoin`]).
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the message fails validation, or if the
+    /// connection is already closed.
+    ///
+    /// [`QueueFullPolicy`]: enum.QueueFullPolicy.html
+    /// [`rejoin`]: struct.BlockingConnection.html#method.rejoin
+    /// [`OpenProtocolError::QueueFull`]: enum.OpenProtocolError.html#variant.QueueFull
+    /// [`OpenProtocolError::RateLimited`]: enum.OpenProtocolError.html#variant.RateLimited
+    /// [rate limit]: struct.ConnectionConfig.html#structfield.rate_limit
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    pub fn try_send<'a>(&self, message: &'a Message) -> Result<'a, ()> {
+        let json = message.to_json_str()?;
+        let is_join = self.check_join(message)?;
+
+        self.acquire(message)
+            .map_err(|wait| self.join_failed(is_join, Error::RateLimited(wait)))?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &self.span,
+            message = %message.describe(),
+            size = json.len(),
+            "sending message"
+        );
+
+        // A message that is not queued does not count against the rate limit
+        self.outgoing.try_send(OwnedMessage::Text(json)).map_err(|err| {
+            self.release(message);
+            self.join_failed(is_join, err)
+        })
+    }
+
+    // Check whether a message can be sent without sending a second `JOIN` on this connection,
+    // which would duplicate all subscriptions.
+    //
+    // Returns `true` if the message is a `JOIN`.
+    fn check_join(&self, message: &Message) -> Result<'static, bool> {
+        match message {
+            Message::Join { .. } if self.join_sent.swap(true, Ordering::SeqCst) => {
+                Err(Error::ConstraintViolated(
+                    "a JOIN has already been sent on this connection; use rejoin to join again."
+                        .into(),
+                ))
+            }
+            Message::Join { .. } => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    // Count a message against the rate limit (if any).
+    //
+    // Returns `Err` with the period of time to wait if the message exceeds the limit.
+    fn acquire(&self, message: &Message) -> std::result::Result<(), Duration> {
+        match &self.rate_limiter {
+            Some(limiter) => {
+                limiter.lock().unwrap_or_else(|err| err.into_inner()).try_acquire(message)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Return the rate limit allowance (if any) counted for a message that cannot be queued.
+    fn release(&self, message: &Message) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.lock().unwrap_or_else(|err| err.into_inner()).release(message);
+        }
+    }
+
+    // Allow another `JOIN` to be sent if a `JOIN` cannot be queued.
+    fn join_failed(&self, is_join: bool, err: Error<'static>) -> Error<'static> {
+        if is_join {
+            self.join_sent.store(false, Ordering::SeqCst);
+        }
+        err
+    }
+
+    /// Set the maximum size (in bytes) of a message received from the server.
+    ///
+    /// The default is [`DEFAULT_MAX_MESSAGE_SIZE`].  A `limit` of `None` disables the check.
+    ///
+    /// Compressed messages are checked against the limit in effect when they arrive (rather
+    /// than when they are delivered via [`recv`]), so that they can be discarded while being
+    /// decompressed.
+    ///
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`]: enum.Message.html#associatedconstant.DEFAULT_MAX_MESSAGE_SIZE
+    ///
+    pub fn set_max_message_size(&mut self, limit: Option<usize>) {
+        self.max_message_size.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// Get the maximum size (in bytes) of a message received from the server, if any.
+    pub fn max_message_size(&self) -> Option<usize> {
+        match self.max_message_size.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Returns true if messages are compressed with the `permessage-deflate` WebSocket
+    /// extension.
+    ///
+    /// This is only the case if compression is requested in the [`ConnectionConfig`] and
+    /// the server supports it.
+    ///
+    /// [`ConnectionConfig`]: struct.ConnectionConfig.html
+    ///
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Get the current [`ConnectionState`].
+    ///
+    /// The state changes as soon as the relevant message arrives (e.g. it is `Joined` once
+    /// a successful `JoinResponse` arrives, even before that message is delivered via [`recv`]).
+    ///
+    /// [`ConnectionState`]: enum.ConnectionState.html
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    ///
+    pub fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// Returns true if a `JoinResponse` indicating success has arrived (i.e. the
+    /// [`ConnectionState`] is `Joined`).
+    ///
+    /// [`ConnectionState`]: enum.ConnectionState.html
+    ///
+    pub fn is_joined(&self) -> bool {
+        self.state() == ConnectionState::Joined
+    }
+
+    /// Watch the [`ConnectionState`] of this connection.
+    ///
+    /// The channel receives the current state immediately, then each new state as the
+    /// connection changes state.  It is disconnected after the connection is closed and the
+    /// `BlockingConnection` is dropped.
+    ///
+    /// [`ConnectionState`]: enum.ConnectionState.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    /// let states = conn.watch_state();
+    ///
+    /// std::thread::spawn(move || {
+    ///     for state in states {
+    ///         println!("Connection state: {:?}", state);
+    ///     }
+    /// });
+    ///
+    /// conn.send(&Message::new_join("mypassword", Filters::All))?;
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn watch_state(&self) -> Receiver<ConnectionState> {
+        self.state.watch()
+    }
+
+    /// Subscribe to all messages that arrive from the server from now on.
+    ///
+    /// Each [`Subscription`] receives its own copy of every inbound message, in addition to
+    /// [`recv`], so that multiple consumers (e.g. on different threads) can share one
+    /// connection.  A subscriber that falls behind has messages dropped according to the
+    /// [`LagPolicy`] in `config`, without affecting [`recv`] or other subscribers.
+    ///
+    /// The subscription is closed, after all queued messages are received, when the
+    /// connection is closed.
+    ///
+    /// [`Subscription`]: struct.Subscription.html
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    /// [`LagPolicy`]: enum.LagPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    ///
+    /// let mut dashboard = conn.subscribe(Default::default());
+    /// let mut audit = conn.subscribe(SubscriptionConfig { capacity: 16, policy: LagPolicy::Disconnect });
+    ///
+    /// std::thread::spawn(move || loop {
+    ///     match dashboard.recv() {
+    ///         Ok(msg) => println!("Dashboard: {}", msg.describe()),
+    ///         Err(Error::Lagged(count)) => println!("Dashboard missed {} message(s)", count),
+    ///         Err(_) => break,
+    ///     }
+    /// });
+    ///
+    /// std::thread::spawn(move || while let Ok(msg) = audit.recv() {
+    ///     println!("Audit: {}", msg.describe());
+    /// });
+    ///
+    /// conn.send(&Message::new_join("mypassword", Filters::All))?;
+    ///
+    /// loop {
+    ///     conn.recv()?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn subscribe(&self, config: SubscriptionConfig) -> Subscription {
+        self.subscribers.subscribe(config, self.max_message_size.clone())
+    }
+
+    /// Only receive messages for the specified controllers from now on.
+    ///
+    /// Messages that refer to any other controller (see [`Message::controller_id`]) are
+    /// dropped as they arrive, and are delivered neither via [`recv`] nor to subscribers.
+    /// Messages that do not refer to a particular controller (e.g. `Alive`, `JoinResponse`
+    /// and `ControllersList`) always pass through, as do messages that cannot be parsed.
+    /// Responses (see [`MessageKind::is_response`], e.g. `JobCardsList`) also pass through
+    /// even if they refer to another controller, so replies to requests are never lost.
+    ///
+    /// This replaces any controllers watched previously.  Use [`watch_all`] to receive
+    /// messages for all controllers again.
+    ///
+    /// This filtering is done on the client side; use [`Filters`] when joining to limit
+    /// the categories of messages sent by the server.
+    ///
+    /// [`Message::controller_id`]: enum.Message.html#method.controller_id
+    /// [`MessageKind::is_response`]: enum.MessageKind.html#method.is_response
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    /// [`watch_all`]: struct.BlockingConnection.html#method.watch_all
+    /// [`Filters`]: struct.Filters.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    /// conn.watch_only(&[ID::from_u32(42)]);
+    ///
+    /// conn.send(&Message::new_join("mypassword", Filters::All))?;
+    ///
+    /// loop {
+    ///     let msg = conn.recv()?;
+    ///     assert!(msg.kind().is_response() || msg.controller_id().map_or(true, |id| id == 42));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn watch_only(&self, controllers: &[ID]) {
+        let mut watched = self.watched.lock().unwrap_or_else(|err| err.into_inner());
+        *watched = Some(controllers.iter().copied().collect());
+    }
+
+    /// Receive messages for all controllers (the default), undoing [`watch_only`].
+    ///
+    /// [`watch_only`]: struct.BlockingConnection.html#method.watch_only
+    ///
+    pub fn watch_all(&self) {
+        *self.watched.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    }
+
+    /// Block until the next `Message` arrives from the server.
+    ///
+    /// The `Message` returned borrows from an internal buffer, so it must be dropped
+    /// before `recv` can be called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::MessageTooLarge`]`)` if the message (after
+    /// decompression, if compressed) is larger than the [maximum message size].
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Timeout`]`)` if nothing arrives from the server
+    /// within the [idle timeout]; the connection is then closed.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Transport`]`)` if there is an error receiving from
+    /// the WebSocket.
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the message cannot be parsed, or if the
+    /// connection is closed.
+    ///
+    /// [maximum message size]: struct.BlockingConnection.html#method.set_max_message_size
+    /// [idle timeout]: struct.ConnectionConfig.html#structfield.idle_timeout
+    /// [`OpenProtocolError::MessageTooLarge`]: enum.OpenProtocolError.html#variant.MessageTooLarge
+    /// [`OpenProtocolError::Timeout`]: enum.OpenProtocolError.html#variant.Timeout
+    /// [`OpenProtocolError::Transport`]: enum.OpenProtocolError.html#variant.Transport
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    pub fn recv(&mut self) -> Result<'_, Message<'_>> {
+        self.recv_timestamped().map(|received| received.message)
+    }
+
+    /// Block until the next `Message` arrives from the server, returning it together with
+    /// the time it arrived.
+    ///
+    /// The time is recorded when the message arrives from the server, so it does not
+    /// include the time the message spends queued before it is delivered.  Otherwise this
+    /// is the same as [`recv`].
+    ///
+    /// [`recv`]: struct.BlockingConnection.html#method.recv
+    ///
+    /// # Errors
+    ///
+    /// Same as [`recv`].
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    /// conn.send(&Message::new_join("mypassword", Filters::All))?;
+    ///
+    /// let mut last: Option<MessageStamp> = None;
+    ///
+    /// loop {
+    ///     let stamp = conn.recv_timestamped()?.stamp();
+    ///
+    ///     if let Some(last) = last {
+    ///         let missing = stamp.sequence_gap(last).saturating_sub(1);
+    ///         println!("+{:?}, {} message(s) missing", stamp.elapsed_since(last), missing);
+    ///     }
+    ///     last = Some(stamp);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn recv_timestamped(&mut self) -> Result<'_, TimestampedMessage<'_>> {
+        match self.incoming.recv() {
+            Ok(Ok((received_at, json))) => {
+                self.buffer = json;
+
+                #[cfg(feature = "tracing")]
+                let _guard = self.span.enter();
+
+                let message =
+                    Message::parse_from_json_str_with_limit(&self.buffer, self.max_message_size())?;
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    message = %message.describe(),
+                    size = self.buffer.len(),
+                    "received message"
+                );
+
+                Ok(TimestampedMessage { received_at, message })
+            }
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(Error::SystemError("connection closed".into())),
+        }
+    }
+
+    /// Close the connection, waiting for the background threads to terminate.
+    ///
+    /// Messages still queued may be dropped, unless [`drain_on_close`] is set.
+    /// Use [`shutdown`] instead to make sure that all queued messages are sent.
+    ///
+    /// [`drain_on_close`]: struct.ConnectionConfig.html#structfield.drain_on_close
+    /// [`shutdown`]: struct.BlockingConnection.html#method.shutdown
+    pub fn close(mut self) {
+        if let Some(timeout) = self.config.drain_on_close {
+            // The connection is closed (forcibly, if necessary) even if draining times out
+            let _ = self.shutdown(timeout);
+            return;
+        }
+
+        self.shutdown_stream();
+        self.join_threads();
+    }
+
+    /// Gracefully close the connection.
+    ///
+    /// All messages already queued are sent, followed by a WebSocket close frame
+    /// (status `1000`, normal closure).  This then waits for the iChen® Server to close its end
+    /// of the connection, so that no queued message (e.g. a final `OperatorInfo` reply) is lost.
+    ///
+    /// Messages received in the meantime are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::Timeout`]`)` if the queued messages are not sent,
+    /// or the server does not close the connection, within `timeout`.  The connection is then
+    /// forcibly closed.
+    ///
+    /// [`OpenProtocolError::Timeout`]: enum.OpenProtocolError.html#variant.Timeout
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # use std::time::Duration;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    ///
+    /// conn.send(&Message::new_join("mypassword", Filters::All + Filters::JobCards))?;
+    ///
+    /// conn.shutdown(Duration::from_secs(5))?;
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn shutdown(mut self, timeout: Duration) -> Result<'static, ()> {
+        let deadline = Instant::now() + timeout;
+        let state = self.state.watch();
+
+        // The close frame is queued after all pending messages
+        let mut close = OwnedMessage::Close(Some(CloseData::new(1000, "client shutdown".into())));
+
+        loop {
+            match self.outgoing.sender.try_send(close) {
+                // The send thread has already terminated
+                Ok(()) | Err(TrySendError::Disconnected(_)) => break,
+                Err(TrySendError::Full(msg)) if Instant::now() < deadline => {
+                    close = msg;
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(TrySendError::Full(_)) => return self.abort(timeout),
+            }
+        }
+
+        // Wait for the server to close the connection
+        loop {
+            match state.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(ConnectionState::Closed) | Err(RecvTimeoutError::Disconnected) => break,
+                Ok(_) => (),
+                Err(RecvTimeoutError::Timeout) => return self.abort(timeout),
+            }
+        }
+
+        self.join_threads();
+        Ok(())
+    }
+
+    /// Gracefully close the connection (see [`shutdown`]), then connect to the same URL again
+    /// with the same configuration and send a `JOIN` message.
+    ///
+    /// Only one `JOIN` may be sent on a connection, so this is the way to join again (e.g.
+    /// with a different password or filters).  The [maximum message size] and the
+    /// [controllers watched] are kept.
+    ///
+    /// Subscriptions are not carried over.  Each [`Subscription`] of this connection
+    /// receives the messages already queued, then is closed with the connection; call
+    /// [`subscribe`] on the new connection to receive its messages.
+    ///
+    /// [`shutdown`]: struct.BlockingConnection.html#method.shutdown
+    /// [`Subscription`]: struct.Subscription.html
+    /// [`subscribe`]: struct.BlockingConnection.html#method.subscribe
+    /// [maximum message size]: struct.BlockingConnection.html#method.set_max_message_size
+    /// [controllers watched]: struct.BlockingConnection.html#method.watch_only
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `join` is not a `JOIN` message.
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the connection cannot be established again,
+    /// or if `join` cannot be sent (see [`send`]).  The existing connection is closed in
+    /// either case.
+    ///
+    /// [`send`]: struct.BlockingConnection.html#method.send
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~no_run
+    /// # use ichen_openprotocol::*;
+    /// # use std::time::Duration;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let conn = BlockingConnection::connect("ws://1.2.3.4:5788")?;
+    /// conn.send(&Message::new_join("mypassword", Filters::All))?;
+    ///
+    /// // A second `JOIN` is rejected...
+    /// assert!(conn.send(&Message::new_join("mypassword", Filters::Cycle)).is_err());
+    ///
+    /// // ... so reconnect instead
+    /// let join = Message::new_join("mypassword", Filters::Cycle);
+    /// let conn = conn.rejoin(&join, Duration::from_secs(5))?;
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn rejoin<'a>(self, join: &'a Message, timeout: Duration) -> Result<'a, Self> {
+        if !matches!(join, Message::Join { .. }) {
+            return Err(Error::InvalidField {
+                field: "join",
+                value: join.kind().as_str().into(),
+                description: "a JOIN message is required to join again".into(),
+            });
+        }
+
+        let url = self.url.clone();
+        let config = self.config;
+        let limit = self.max_message_size();
+        let watched = self.watched.lock().unwrap_or_else(|err| err.into_inner()).clone();
+
+        // The connection is closed (forcibly, if necessary) even if the shutdown times out
+        let _ = self.shutdown(timeout);
+
+        let mut conn = Self::connect_with_config(&url, config)?;
+        conn.set_max_message_size(limit);
+        *conn.watched.lock().unwrap_or_else(|err| err.into_inner()) = watched;
+        conn.send(join)?;
+
+        Ok(conn)
+    }
+
+    // Forcibly close the connection after a graceful shutdown times out.
+    fn abort(mut self, timeout: Duration) -> Result<'static, ()> {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(parent: &self.span, ?timeout, "shutdown timed out, closing connection");
+
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.join_threads();
+        Err(Error::Timeout(timeout))
+    }
+
+    // Wait for the background threads to terminate.
+    fn join_threads(&mut self) {
+        for handle in self.threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+
+    // Send a WebSocket close frame and shut down the underlying TCP stream.
+    //
+    // If the close frame cannot be queued (e.g. the connection is stalled), the stream is
+    // shut down in both directions so that the send thread does not block forever.
+    fn shutdown_stream(&mut self) {
+        let how = match self.outgoing.try_send(OwnedMessage::Close(None)) {
+            Err(Error::QueueFull) => Shutdown::Both,
+            _ => Shutdown::Read,
+        };
+        let _ = self.stream.shutdown(how);
+    }
+}
+
+impl Drop for BlockingConnection {
+    fn drop(&mut self) {
+        self.shutdown_stream();
+    }
+}
+
+// Send loop - sends all queued messages until a close frame is sent, compressing text
+// messages if a `Deflater` is provided.
+fn send_loop(
+    mut writer: Writer<TcpStream>,
+    outbox: Receiver<OwnedMessage>,
+    mut deflater: Option<Deflater>,
+) {
+    for msg in outbox {
+        let is_close = matches!(msg, OwnedMessage::Close(_));
+
+        let sent = match (&msg, deflater.as_mut()) {
+            (OwnedMessage::Text(text), Some(deflater)) => match deflater.text_frame(text) {
+                Ok(frame) => writer.send_dataframe(&frame),
+                Err(_) => break,
+            },
+            _ => writer.send_message(&msg),
+        };
+
+        if sent.is_err() || is_close {
+            break;
+        }
+    }
+
+    let _ = writer.shutdown();
+}
+
+// Receive loop - forwards all text messages, replying to `ALIVE` and ping's along the way.
+//
+// Messages are reassembled (and decompressed if compressed) by the `MessageAssembler`.
+//
+// If an idle timeout is set (as the read timeout of the stream) and it expires, or if a
+// message is larger than the maximum frame size, the connection is closed.
+//
+// The connection state is updated according to the `JoinResponse` and set to `Closed`
+// when the loop ends.
+//
+// Each text message is also published to all subscribers, which are closed when the
+// loop ends.
+//
+// Messages for controllers not in the `watched` set (if any) are dropped, except responses.
+#[allow(clippy::too_many_arguments)]
+fn recv_loop(
+    mut reader: Reader<TcpStream>,
+    inbox: Sender<Result<'static, (Instant, String)>>,
+    reply: SyncSender<OwnedMessage>,
+    max_message_size: Arc<AtomicUsize>,
+    mut assembler: MessageAssembler,
+    config: &ConnectionConfig,
+    state: &SharedState,
+    subscribers: &Broadcaster,
+    watched: &Mutex<Option<HashSet<ID>>>,
+) {
+    loop {
+        let frame_limit = config.max_frame_size.saturating_sub(assembler.buffered());
+
+        let received = match read_frame(&mut reader, frame_limit, config.idle_timeout) {
+            Ok(frame) => match assembler.push(frame, max_message_size.load(Ordering::Relaxed)) {
+                Ok(Some(msg)) => Ok(msg),
+                Ok(None) => continue,
+                // Oversized messages are reported, but the connection is still usable
+                Err(err @ Error::MessageTooLarge { .. }) => {
+                    if inbox.send(Err(err)).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    Err(Error::SystemError(format!("error receiving message: {}", err).into()))
+                }
+            },
+            // The frame is not read, so the connection cannot be used any more
+            Err(Error::MessageTooLarge { size, .. }) => Err(Error::MessageTooLarge {
+                size: assembler.buffered().saturating_add(size),
+                limit: config.max_frame_size,
+            }),
+            Err(err) => Err(err),
+        };
+
+        let json = match received {
+            Ok(OwnedMessage::Text(json)) => json,
+            Ok(OwnedMessage::Ping(data)) => {
+                send_reply(&reply, OwnedMessage::Pong(data));
+                continue;
+            }
+            Ok(OwnedMessage::Close(_)) => {
+                send_reply(&reply, OwnedMessage::Close(None));
+                break;
+            }
+            Ok(_) => continue,
+            Err(err) => {
+                // Shut down a dead connection so that the send thread also terminates
+                match err {
+                    Error::Timeout(_timeout) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(timeout = ?_timeout, "idle timeout, closing connection");
+
+                        let _ = reader.shutdown_all();
+                    }
+                    Error::MessageTooLarge { size: _size, limit: _limit } => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(
+                            size = _size,
+                            limit = _limit,
+                            "message too large, closing connection"
+                        );
+
+                        let _ = reader.shutdown_all();
+                    }
+                    _ => (),
+                }
+
+                state.set(ConnectionState::Closed);
+                let _ = inbox.send(Err(err));
+                break;
+            }
+        };
+
+        let received_at = Instant::now();
+
+        // Oversized messages and parse failures are reported when the message is delivered
+        // via `recv`
+        let mut join_failed = false;
+
+        if json.len() <= max_message_size.load(Ordering::Relaxed) {
+            let msg = serde_json::from_str::<Message>(&json);
+
+            if let (Ok(msg), Some(watched)) =
+                (&msg, &*watched.lock().unwrap_or_else(|err| err.into_inner()))
+            {
+                if !msg.kind().is_response()
+                    && matches!(msg.controller_id(), Some(id) if !watched.contains(&id))
+                {
+                    continue;
+                }
+            }
+
+            match msg {
+                Ok(Message::Alive { .. }) => {
+                    if let Ok(alive) = Message::new_alive().to_json_str() {
+                        send_reply(&reply, OwnedMessage::Text(alive));
+                    }
+                }
+                Ok(Message::JoinResponse { result, .. }) => match JoinResult::from_code(result) {
+                    JoinResult::Succeeded(_) => state.set(ConnectionState::Joined),
+                    JoinResult::Failed(_) => {
+                        join_failed = true;
+                        state.set(ConnectionState::Closed);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        subscribers.publish(&json);
+
+        if inbox.send(Ok((received_at, json))).is_err() {
+            break;
+        }
+
+        // The server does not serve a client that fails to join
+        if join_failed {
+            send_reply(&reply, OwnedMessage::Close(None));
+            break;
+        }
+    }
+
+    state.set(ConnectionState::Closed);
+    subscribers.close();
+}
+
+// Queue a reply (e.g. `Pong` or `Alive`) to the server without blocking.
+//
+// The reply is dropped if the outbox is full, because blocking the receive thread on a
+// stalled send thread would also stop all messages from being received.
+fn send_reply(reply: &SyncSender<OwnedMessage>, msg: OwnedMessage) {
+    if let Err(TrySendError::Full(_)) = reply.try_send(msg) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("outbox full, dropping reply");
+    }
+}
+
+// Read a data frame from the WebSocket.
+//
+// The size of the frame is checked against `limit` before its payload is read into memory.
+fn read_frame(
+    reader: &mut Reader<TcpStream>,
+    limit: usize,
+    idle_timeout: Option<Duration>,
+) -> Result<'static, DataFrame> {
+    let header = read_header(&mut reader.stream).map_err(|err| receive_error(err, idle_timeout))?;
+
+    if header.len > limit as u64 {
+        return Err(Error::MessageTooLarge {
+            size: usize::try_from(header.len).unwrap_or(usize::MAX),
+            limit,
+        });
+    }
+
+    let mut data = Vec::with_capacity(header.len as usize);
+
+    match (&mut reader.stream).take(header.len).read_to_end(&mut data) {
+        Ok(read) if read as u64 == header.len => (),
+        Ok(_) => {
+            let err = std::io::Error::new(ErrorKind::UnexpectedEof, "incomplete payload");
+            return Err(Error::transport("error receiving message", err));
+        }
+        Err(err) => return Err(receive_error(err.into(), idle_timeout)),
+    }
+
+    DataFrame::read_dataframe_body(header, data, false)
+        .map_err(|err| receive_error(err, idle_timeout))
+}
+
+// Convert an error receiving from the WebSocket into an `Error`.
+//
+// With an idle timeout, the stream has a read timeout which fails the read when it expires.
+fn receive_error(err: WebSocketError, idle_timeout: Option<Duration>) -> Error<'static> {
+    match (&err, idle_timeout) {
+        (WebSocketError::IoError(io), Some(timeout))
+            if matches!(io.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+            Error::Timeout(timeout)
+        }
+        _ => Error::transport("error receiving message", err),
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockServer, Script};
+    use crate::LagPolicy;
+    use crate::{Filters, JoinResult, MessageKind};
+
+    #[test]
+    fn test_blocking_connection_with_mock_server() -> std::result::Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#)
+                .respond_json(r#"{"$type":"Alive","sequence":2}"#)
+                .expect("Alive")
+                .expect("RequestControllersList")
+                .respond_json(r#"{"$type":"ControllersList","data":{},"sequence":3}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        match conn.recv()? {
+            Message::JoinResponse { result, .. } => assert_eq!(100, result),
+            msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+        }
+
+        // The `ALIVE` is automatically replied to
+        match conn.recv()? {
+            Message::Alive { options } => assert_eq!(2, options.sequence()),
+            msg => return Err(format!("expected Alive, got {:?}", msg)),
+        }
+
+        conn.send(&Message::new_request_all_controllers())?;
+
+        match conn.recv()? {
+            Message::ControllersList { data, .. } => assert!(data.is_empty()),
+            msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+        }
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_state() -> std::result::Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#)
+                .expect("RequestControllersList")
+                .respond_json(r#"{"$type":"ControllersList","data":{},"sequence":2}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        let states = conn.watch_state();
+        assert_eq!(ConnectionState::Connecting, conn.state());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        match conn.recv()? {
+            Message::JoinResponse { result, .. } => assert_eq!(100, result),
+            msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+        }
+        assert_eq!(ConnectionState::Joined, conn.state());
+
+        conn.send(&Message::new_request_all_controllers())?;
+
+        match conn.recv()? {
+            Message::ControllersList { .. } => (),
+            msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+        }
+
+        // The server closes the connection at the end of the script
+        server.finish()?;
+        assert!(conn.recv().is_err());
+        assert_eq!(ConnectionState::Closed, conn.state());
+
+        conn.close();
+
+        assert_eq!(
+            vec![ConnectionState::Connecting, ConnectionState::Joined, ConnectionState::Closed],
+            states.iter().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_state_join_failed() -> std::result::Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":1,"sequence":1}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        let states = conn.watch_state();
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // The `JoinResponse` is still delivered, then the connection is closed
+        match conn.recv()? {
+            Message::JoinResponse { result, .. } => assert_eq!(1, result),
+            msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+        }
+        assert_eq!(ConnectionState::Closed, conn.state());
+        assert!(conn.recv().is_err());
+
+        server.finish()?;
+        conn.close();
+
+        assert_eq!(
+            vec![ConnectionState::Connecting, ConnectionState::Closed],
+            states.iter().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_second_join() -> std::result::Result<(), String> {
+        let join_response = r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#;
+
+        let server = MockServer::start_sequence(vec![
+            Script::new().expect("Join").respond_json(join_response).expect("Alive"),
+            Script::new().expect("Join").respond_json(join_response).expect("Alive"),
+        ])
+        .map_err(|x| x.to_string())?;
+
+        let join = Message::new_join("hello", Filters::All);
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        assert!(!conn.is_joined());
+
+        conn.send(&join)?;
+        assert!(conn.recv()?.join_result().is_some());
+        assert!(conn.is_joined());
+
+        for _ in 0..2 {
+            match conn.send(&join) {
+                Err(Error::ConstraintViolated(_)) => (),
+                result => return Err(format!("expected ConstraintViolated, got {:?}", result)),
+            }
+            match conn.try_send(&join) {
+                Err(Error::ConstraintViolated(_)) => (),
+                result => return Err(format!("expected ConstraintViolated, got {:?}", result)),
+            }
+        }
+
+        conn.send(&Message::new_alive())?;
+
+        // Join again over a new connection
+        let mut conn = conn.rejoin(&join, Duration::from_secs(5))?;
+        assert!(conn.recv()?.join_result().is_some());
+        assert!(conn.is_joined());
+
+        match conn.send(&join) {
+            Err(Error::ConstraintViolated(_)) => (),
+            result => return Err(format!("expected ConstraintViolated, got {:?}", result)),
+        }
+
+        conn.send(&Message::new_alive())?;
+        conn.shutdown(Duration::from_secs(5))?;
+
+        server.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejoin_closes_subscriptions() -> std::result::Result<(), String> {
+        let script = |sequence| {
+            Script::new()
+                .expect("Join")
+                .respond_json(format!(
+                    r#"{{"$type":"JoinResponse","result":100,"sequence":{}}}"#,
+                    sequence
+                ))
+                .expect("Alive")
+        };
+
+        let server = MockServer::start_sequence(vec![
+            script(1),
+            script(2).respond_json(r#"{"$type":"Alive","sequence":3}"#),
+        ])
+        .map_err(|x| x.to_string())?;
+
+        let join = Message::new_join("hello", Filters::All);
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        let mut old = conn.subscribe(Default::default());
+
+        conn.send(&join)?;
+        assert_eq!(1, conn.recv()?.sequence());
+        conn.send(&Message::new_alive())?;
+
+        let mut conn = conn.rejoin(&join, Duration::from_secs(5))?;
+        assert_eq!(2, conn.recv()?.sequence());
+
+        // The old subscription receives the messages already queued, then is closed
+        assert_eq!(1, old.recv()?.sequence());
+        assert!(match old.recv() {
+            Err(Error::SystemError(err)) => err == "connection closed",
+            _ => false,
+        });
+
+        // Subscribe again on the new connection
+        let mut new = conn.subscribe(Default::default());
+        conn.send(&Message::new_alive())?;
+        assert_eq!(3, new.recv()?.sequence());
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    // Queue messages behind a `JOIN` too large for the socket buffers, while the server is
+    // slow to start reading, then close the connection with `close`.
+    //
+    // Returns whether the server received all the messages.
+    fn close_with_queued_messages<F>(
+        config: ConnectionConfig,
+        close: F,
+    ) -> std::result::Result<bool, String>
+    where
+        F: FnOnce(BlockingConnection) -> Result<'static, ()>,
+    {
+        let server = MockServer::start(
+            Script::new()
+                .pause(Duration::from_secs(2))
+                .expect("Join")
+                .expect("Alive")
+                .expect("RequestControllersList"),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig {
+            queue: QueueConfig { capacity: 2, policy: QueueFullPolicy::Block },
+            ..config
+        };
+        let conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+
+        // The send thread is stuck on the `JOIN`, so the queue is full
+        conn.send(&Message::new_join(&"x".repeat(8 * 1024 * 1024), Filters::All))?;
+        conn.send(&Message::new_alive())?;
+        conn.send(&Message::new_request_all_controllers())?;
+
+        close(conn)?;
+
+        Ok(server.finish().is_ok())
+    }
+
+    #[test]
+    fn test_blocking_connection_shutdown_sends_queued_messages() -> std::result::Result<(), String>
+    {
+        assert!(close_with_queued_messages(Default::default(), |conn| {
+            conn.shutdown(Duration::from_secs(5))
+        })?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_subscribe() -> std::result::Result<(), String> {
+        let mut script = Script::new()
+            .expect("Join")
+            .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#);
+
+        for sequence in 2..=5 {
+            script = script.respond_json(format!(
+                r#"{{"$type":"RequestJobCardsList","controllerId":1,"sequence":{}}}"#,
+                sequence
+            ));
+        }
+
+        let server = MockServer::start(script).map_err(|x| x.to_string())?;
+        let mut conn = BlockingConnection::connect(&server.url())?;
+
+        let mut dashboard = conn.subscribe(Default::default());
+        let mut audit = conn.subscribe(Default::default());
+        let mut lagging =
+            conn.subscribe(SubscriptionConfig { capacity: 2, policy: LagPolicy::DropOldest });
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // All subscribers, as well as `recv`, receive the same sequence of messages
+        let (mut received, mut first, mut second) = (Vec::new(), Vec::new(), Vec::new());
+        for _ in 1..=5 {
+            received.push(conn.recv()?.sequence());
+            first.push(dashboard.recv()?.sequence());
+            second.push(audit.recv()?.sequence());
+        }
+
+        assert_eq!(vec![1, 2, 3, 4, 5], received);
+        assert_eq!(received, first);
+        assert_eq!(received, second);
+
+        // The lagging subscriber only keeps the last two messages
+        assert_eq!(Err(Error::Lagged(3)), lagging.recv().map(|_| ()));
+        assert_eq!(4, lagging.recv()?.sequence());
+        assert_eq!(5, lagging.recv()?.sequence());
+
+        server.finish()?;
+        conn.close();
+
+        // Subscriptions are closed together with the connection
+        assert!(matches!(dashboard.recv(), Err(Error::SystemError(_))));
+        assert!(matches!(lagging.recv(), Err(Error::SystemError(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_watch_only() -> std::result::Result<(), String> {
+        let mut script = Script::new()
+            .expect("Join")
+            .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#);
+
+        for (controller, sequence) in &[(1, 2), (2, 3), (3, 4), (2, 5)] {
+            script = script.respond_json(format!(
+                r#"{{"$type":"RequestJobCardsList","controllerId":{},"sequence":{}}}"#,
+                controller, sequence
+            ));
+        }
+
+        let script = script
+            .respond_json(r#"{"$type":"ControllersList","data":{},"sequence":6}"#)
+            .respond_json(r#"{"$type":"JobCardsList","controllerId":1,"data":{},"sequence":7}"#)
+            .respond_json(r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":8}"#);
+
+        let server = MockServer::start(script).map_err(|x| x.to_string())?;
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        conn.watch_only(&[ID::from_u32(2), ID::from_u32(3)]);
+
+        let mut subscription = conn.subscribe(Default::default());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // Messages for controller 1 are dropped, except responses; the rest pass through
+        let (mut received, mut subscribed) = (Vec::new(), Vec::new());
+        for _ in 1..=6 {
+            received.push(conn.recv()?.sequence());
+            subscribed.push(subscription.recv()?.sequence());
+        }
+
+        assert_eq!(vec![1, 3, 4, 5, 6, 7], received);
+        assert_eq!(received, subscribed);
+
+        server.finish()?;
+        conn.close();
+
+        assert!(matches!(subscription.recv(), Err(Error::SystemError(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_recv_timestamped() -> std::result::Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)
+                .respond_json(r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":3}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        let start = Instant::now();
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        let (first_at, first_seq) = {
+            let received = conn.recv_timestamped()?;
+            assert_eq!(MessageKind::JoinResponse, received.message.kind());
+            (received.received_at, received.message.sequence())
+        };
+
+        let second = conn.recv_timestamped()?;
+        let end = Instant::now();
+
+        assert_eq!(MessageKind::RequestJobCardsList, second.message.kind());
+        assert_eq!(Some(ID::from_u32(1)), second.message.controller_id());
+        assert_eq!((1, 3), (first_seq, second.message.sequence()));
+
+        assert!(start <= first_at);
+        assert!(first_at <= second.received_at);
+        assert!(second.received_at <= end);
+
+        drop(second);
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_drain_on_close() -> std::result::Result<(), String> {
+        let close = |conn: BlockingConnection| {
+            conn.close();
+            Ok(())
+        };
+
+        // Queued messages are sent
+        let config =
+            ConnectionConfig { drain_on_close: Some(Duration::from_secs(5)), ..Default::default() };
+        assert!(close_with_queued_messages(config, close)?);
+
+        // Queued messages are dropped
+        let config = ConnectionConfig { drain_on_close: None, ..Default::default() };
+        assert!(!close_with_queued_messages(config, close)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_zero_drain_on_close() {
+        let config =
+            ConnectionConfig { drain_on_close: Some(Duration::from_secs(0)), ..Default::default() };
+
+        assert!(match BlockingConnection::connect_with_config("ws://127.0.0.1:1", config) {
+            Err(Error::InvalidField { field, .. }) => field == "drain_on_close",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_blocking_connection_shutdown_timeout() -> std::result::Result<(), String> {
+        // The server does not close the connection in time
+        let server =
+            MockServer::start(Script::new().expect("Join").pause(Duration::from_millis(500)))
+                .map_err(|x| x.to_string())?;
+
+        let conn = BlockingConnection::connect(&server.url())?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        assert_eq!(
+            Err(Error::Timeout(Duration::from_millis(100))),
+            conn.shutdown(Duration::from_millis(100))
+        );
+
+        server.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_reassembles_fragmented_message() -> std::result::Result<(), String>
+    {
+        // Split in the middle of the multi-byte UTF-8 characters in the display name
+        let json = r#"{"$type":"ControllersList","data":{"1":{"controllerId":1,"displayName":"注塑機","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"}},"sequence":1}"#;
+        let split = json.find("注").unwrap() + 1;
+
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json_fragmented(json, split)
+                .respond_json_fragmented(r#"{"$type":"Alive","sequence":2}"#, 7)
+                .expect("Alive"),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        match conn.recv()? {
+            Message::ControllersList { data, .. } => {
+                assert_eq!(1, data.len());
+                assert_eq!("注塑機", data.values().next().unwrap().display_name.get());
+            }
+            msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+        }
+
+        match conn.recv()? {
+            Message::Alive { options } => assert_eq!(2, options.sequence()),
+            msg => return Err(format!("expected Alive, got {:?}", msg)),
+        }
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    fn status(priority: i32) -> Message<'static> {
+        let options = crate::MessageOptions::new_with_priority(priority);
+
+        Message::CycleData {
+            controller_id: crate::ID::from_u32(1),
+            data: Default::default(),
+            timestamp: chrono::DateTime::parse_from_rfc3339("2019-01-01T00:00:00Z").unwrap(),
+            state: Default::default(),
+            options,
+        }
+    }
+
+    #[test]
+    fn test_outbox_try_send_full_at_capacity() {
+        let (sender, outbox) = sync_channel(2);
+        let outgoing = Outbox { sender, policy: QueueFullPolicy::Block };
+
+        assert_eq!(Ok(()), outgoing.try_send(OwnedMessage::Text("1".into())));
+        assert_eq!(Ok(()), outgoing.try_send(OwnedMessage::Text("2".into())));
+        assert_eq!(Err(Error::QueueFull), outgoing.try_send(OwnedMessage::Text("3".into())));
+
+        // Room is made once the send thread takes a message
+        outbox.recv().unwrap();
+        assert_eq!(Ok(()), outgoing.try_send(OwnedMessage::Text("3".into())));
+
+        drop(outbox);
+        assert!(match outgoing.try_send(OwnedMessage::Text("4".into())) {
+            Err(Error::SystemError(err)) => err == "connection closed",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_send_reply_does_not_block_when_full() {
+        let (reply, outbox) = sync_channel(1);
+
+        send_reply(&reply, OwnedMessage::Pong(vec![1]));
+        send_reply(&reply, OwnedMessage::Pong(vec![2]));
+        send_reply(&reply, OwnedMessage::Close(None));
+
+        assert_eq!(OwnedMessage::Pong(vec![1]), outbox.recv().unwrap());
+        assert!(outbox.try_recv().is_err());
+
+        drop(outbox);
+        send_reply(&reply, OwnedMessage::Close(None));
+    }
+
+    #[test]
+    fn test_outbox_drop_low_priority_when_full() {
+        let (sender, outbox) = sync_channel(1);
+        let outgoing = Outbox { sender, policy: QueueFullPolicy::DropLowPriority };
+
+        assert!(is_low_priority(&status(0)));
+        assert!(is_low_priority(&status(-10)));
+        assert!(!is_low_priority(&status(10)));
+        assert!(!is_low_priority(&Message::new_alive()));
+
+        assert_eq!(Ok(true), outgoing.send(OwnedMessage::Text("1".into()), true));
+        assert_eq!(Ok(false), outgoing.send(OwnedMessage::Text("2".into()), true));
+        assert_eq!(Ok(false), outgoing.send(OwnedMessage::Text("3".into()), true));
+
+        assert_eq!(OwnedMessage::Text("1".into()), outbox.recv().unwrap());
+        assert!(outbox.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_blocking_connection_try_send() -> std::result::Result<(), String> {
+        let server = MockServer::start(Script::new().expect("Join").expect("Alive"))
+            .map_err(|x| x.to_string())?;
+
+        let queue = QueueConfig { capacity: 4, policy: QueueFullPolicy::DropLowPriority };
+        let conn = BlockingConnection::connect_with_queue(&server.url(), queue)?;
+
+        conn.try_send(&Message::new_join("hello", Filters::All))?;
+        conn.send(&Message::new_alive())?;
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_oversized_message() -> std::result::Result<(), String> {
+        let json = r#"{"$type":"Alive","sequence":2}"#;
+
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(json)
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":3}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        assert_eq!(Some(Message::DEFAULT_MAX_MESSAGE_SIZE), conn.max_message_size());
+
+        conn.set_max_message_size(Some(json.len() - 1));
+        assert_eq!(Some(json.len() - 1), conn.max_message_size());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // The oversized `ALIVE` is rejected and not replied to
+        assert_eq!(
+            Err(Error::MessageTooLarge { size: json.len(), limit: json.len() - 1 }),
+            conn.recv().map(|_| ())
+        );
+
+        conn.set_max_message_size(None);
+        assert_eq!(None, conn.max_message_size());
+
+        // Subsequent messages are still delivered
+        match conn.recv()? {
+            Message::JoinResponse { result, .. } => assert_eq!(100, result),
+            msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+        }
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_oversized_frame() -> std::result::Result<(), String> {
+        let json =
+            r#"{"$type":"ControllersList","data":{},"sequence":2,"priority":100,"id":"ID001"}"#;
+
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)
+                .respond_json(json),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig { max_frame_size: 64, ..Default::default() };
+        assert!(json.len() > config.max_frame_size);
+
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+        assert_eq!(Some(JoinResult::Succeeded(100)), conn.recv()?.join_result());
+
+        // The oversized message is rejected without being read, closing the connection
+        assert_eq!(
+            Err(Error::MessageTooLarge { size: json.len(), limit: 64 }),
+            conn.recv().map(|_| ())
+        );
+        assert_eq!(ConnectionState::Closed, conn.state());
+        assert!(conn.recv().is_err());
+
+        let _ = server.finish();
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_oversized_fragmented_frames(
+    ) -> std::result::Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{},"sequence":2}"#;
+
+        // Each fragment is within the limit, but not the whole message
+        let server =
+            MockServer::start(Script::new().expect("Join").respond_json_fragmented(json, 20))
+                .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig { max_frame_size: 32, ..Default::default() };
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // Two fragments of 20 bytes are too large
+        assert_eq!(Err(Error::MessageTooLarge { size: 40, limit: 32 }), conn.recv().map(|_| ()));
+        assert_eq!(ConnectionState::Closed, conn.state());
+
+        let _ = server.finish();
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_with_compression() -> std::result::Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{"1":{"controllerId":1,"displayName":"注塑機","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"}},"sequence":1}"#;
+
+        let server = MockServer::start_with_compression(
+            Script::new()
+                .expect("Join")
+                .respond_json(json)
+                .respond_json_fragmented(json, 10)
+                .respond_json(r#"{"$type":"Alive","sequence":2}"#)
+                .expect("Alive"),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig { compression: true, ..Default::default() };
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+        assert!(conn.is_compressed());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        for _ in 0..2 {
+            match conn.recv()? {
+                Message::ControllersList { data, .. } => {
+                    assert_eq!("注塑機", data.values().next().unwrap().display_name.get());
+                }
+                msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+            }
+        }
+
+        // The `ALIVE` is automatically replied to, compressed
+        match conn.recv()? {
+            Message::Alive { options } => assert_eq!(2, options.sequence()),
+            msg => return Err(format!("expected Alive, got {:?}", msg)),
+        }
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_compression_not_negotiated() -> std::result::Result<(), String> {
+        let script = || {
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)
+        };
+
+        // The server does not support compression
+        let server = MockServer::start(script()).map_err(|x| x.to_string())?;
+        let config = ConnectionConfig { compression: true, ..Default::default() };
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+        assert!(!conn.is_compressed());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+        assert_eq!(Some(JoinResult::Succeeded(100)), conn.recv()?.join_result());
+
+        server.finish()?;
+        conn.close();
+
+        // The client does not request compression
+        let server = MockServer::start_with_compression(script()).map_err(|x| x.to_string())?;
+        let mut conn = BlockingConnection::connect(&server.url())?;
+        assert!(!conn.is_compressed());
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+        assert_eq!(Some(JoinResult::Succeeded(100)), conn.recv()?.join_result());
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_oversized_compressed_message(
+    ) -> std::result::Result<(), String> {
+        let json = r#"{"$type":"Alive","sequence":2}"#;
+
+        let server = MockServer::start_with_compression(
+            Script::new()
+                .expect("Join")
+                .respond_json(json)
+                .expect("RequestControllersList")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":3}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig { compression: true, ..Default::default() };
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+        assert!(conn.is_compressed());
+
+        conn.set_max_message_size(Some(json.len() - 1));
+        conn.send(&Message::new_join("hello", Filters::All))?;
+
+        // The size limit applies to the decompressed message
+        assert_eq!(
+            Err(Error::MessageTooLarge { size: json.len(), limit: json.len() - 1 }),
+            conn.recv().map(|_| ())
+        );
+
+        // The limit of compressed messages is checked as soon as they arrive, so lift it
+        // before the server sends the next message
+        conn.set_max_message_size(None);
+        conn.send(&Message::new_request_all_controllers())?;
+        assert_eq!(Some(JoinResult::Succeeded(100)), conn.recv()?.join_result());
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_idle_timeout() -> std::result::Result<(), String> {
+        let timeout = Duration::from_millis(200);
+
+        // The server goes silent after the `JOIN`
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)
+                .pause(timeout * 5),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig { idle_timeout: Some(timeout), ..Default::default() };
+        let mut conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+
+        conn.send(&Message::new_join("hello", Filters::All))?;
+        assert_eq!(Some(JoinResult::Succeeded(100)), conn.recv()?.join_result());
+
+        let start = std::time::Instant::now();
+        assert_eq!(Err(Error::Timeout(timeout)), conn.recv().map(|_| ()));
+        assert!(start.elapsed() >= timeout);
+        assert!(start.elapsed() < timeout * 5);
+
+        // The connection is closed
+        assert!(match conn.recv() {
+            Err(Error::SystemError(err)) => err == "connection closed",
+            _ => false,
+        });
+
+        conn.close();
+        server.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_zero_idle_timeout() {
+        let config =
+            ConnectionConfig { idle_timeout: Some(Duration::from_secs(0)), ..Default::default() };
+
+        assert!(match BlockingConnection::connect_with_config("ws://127.0.0.1:1", config) {
+            Err(Error::InvalidField { field, .. }) => field == "idle_timeout",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn test_blocking_connection_rate_limit() -> std::result::Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .expect("RequestControllersList")
+                .expect("Alive")
+                .expect("RequestControllersList"),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let per = Duration::from_millis(400);
+        let config = ConnectionConfig {
+            rate_limit: Some(RateLimitConfig { max_messages: 2, per }),
+            ..Default::default()
+        };
+        let conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+        let request = Message::new_request_all_controllers();
+
+        // A burst of two messages is allowed
+        let start = std::time::Instant::now();
+        conn.send(&Message::new_join("hello", Filters::All))?;
+        conn.try_send(&request)?;
+
+        // The burst is throttled, except for `ALIVE`
+        match conn.try_send(&request) {
+            Err(Error::RateLimited(wait)) => assert!(wait <= per / 2),
+            result => return Err(format!("expected RateLimited, got {:?}", result)),
+        }
+        conn.try_send(&Message::new_alive())?;
+
+        conn.send(&request)?;
+        assert!(start.elapsed() >= per / 4);
+
+        server.finish()?;
+        conn.close();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rate_limit_queue_full() -> std::result::Result<(), String> {
+        // The server is slow to start reading, so the queue stays full
+        let server = MockServer::start(Script::new().pause(Duration::from_secs(2)))
+            .map_err(|x| x.to_string())?;
+
+        let config = ConnectionConfig {
+            queue: QueueConfig { capacity: 1, policy: QueueFullPolicy::Block },
+            rate_limit: Some(RateLimitConfig { max_messages: 3, per: Duration::from_secs(3600) }),
+            ..Default::default()
+        };
+        let conn = BlockingConnection::connect_with_config(&server.url(), config)?;
+
+        // The send thread is stuck on the `JOIN`, so the queue is full
+        conn.send(&Message::new_join(&"x".repeat(8 * 1024 * 1024), Filters::All))?;
+        conn.send(&Message::new_alive())?;
+
+        // Messages rejected because the queue is full do not use up the allowance
+        for _ in 0..5 {
+            match conn.try_send(&Message::new_request_all_controllers()) {
+                Err(Error::QueueFull) => (),
+                result => return Err(format!("expected QueueFull, got {:?}", result)),
+            }
+        }
+
+        conn.close();
+        server.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_connection_rejects_zero_rate_limit() {
+        for limit in &[
+            RateLimitConfig { max_messages: 0, per: Duration::from_secs(1) },
+            RateLimitConfig { max_messages: 10, per: Duration::from_secs(0) },
+        ] {
+            let config = ConnectionConfig { rate_limit: Some(*limit), ..Default::default() };
+
+            assert!(match BlockingConnection::connect_with_config("ws://127.0.0.1:1", config) {
+                Err(Error::InvalidField { field, .. }) => field == "rate_limit",
+                _ => false,
+            });
+        }
+    }
+
+    #[test]
+    fn test_validate_ws_url() {
+        for url in &["ws://1.2.3.4:5788", "wss://example.com:443/path", " ws://[::1]:80 "] {
+            assert!(validate_ws_url(url).is_ok(), "{}", url);
+        }
+
+        let description = |url| match validate_ws_url(url) {
+            Err(Error::InvalidField { field: "url", description, .. }) => description.into_owned(),
+            result => panic!("expected InvalidField for {}, got {:?}", url, result),
+        };
+
+        assert_eq!("scheme must be ws or wss", description("http://1.2.3.4:5788"));
+        assert_eq!("port must be specified", description("ws://1.2.3.4"));
+        assert_eq!("port must be specified", description("ws://1.2.3.4:/"));
+        assert_eq!("port must be specified", description("ws://[::1]"));
+        assert!(description("ws://:5788").starts_with("invalid URL"));
+        assert_eq!("invalid URL: relative URL without a base", description("1.2.3.4:5788"));
+    }
+}
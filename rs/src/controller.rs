@@ -1,5 +1,10 @@
-use super::{Address, GeoLocation, JobMode, OpMode, Operator, TextID, TextName, ID, R32};
-use chrono::{DateTime, FixedOffset};
+use super::utils::{check_f32, deserialize_optional_cow_str, deserialize_optional_cow_str_list};
+use super::validate::OpValidate;
+use super::{
+    Address, Error, GeoLocation, JobMode, Message, OpMode, Operator, Result, TextID, TextName, ID,
+    R32,
+};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
@@ -7,13 +12,28 @@ use std::convert::TryInto;
 
 /// A data structure containing the current known status of a controller.
 ///
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Older controller firmware may omit some fields.  When deserializing, the following fields
+/// default to the same values as [`Controller::default`] if missing:
+///
+/// * `controller_type`, `version` and `model` default to `Unknown`,
+/// * `address` defaults to [`Address::Unknown`] (i.e. `0.0.0.0:0`),
+/// * `op_mode` and `job_mode` default to `Unknown`.
+///
+/// `controller_id` and `display_name` are always required.  All other fields are optional.
+///
+/// [`Controller::default`]: struct.Controller.html#method.default
+/// [`Address::Unknown`]: enum.Address.html#variant.Unknown
+///
+#[derive(Debug, Clone, Serialize, Deserialize, OpValidate)]
 #[serde(rename_all = "camelCase")]
 pub struct Controller<'a> {
     /// Unique ID of the controller, which cannot be zero.
+    #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
     pub controller_id: ID,
     //
-    /// User-specified human-friendly name for the machine.
+    /// User-specified human-friendly name for the machine, which cannot be empty or all
+    /// whitespace.
+    #[cfg_attr(feature = "legacy", serde(alias = "display_name"))]
     pub display_name: TextName<'a>,
     //
     /// Controller type.
@@ -24,45 +44,85 @@ pub struct Controller<'a> {
     /// * `Ai12`
     /// * `CDC2000WIN`
     /// * `MPC7`
+    #[serde(default = "unknown_text_id")]
+    #[cfg_attr(feature = "legacy", serde(alias = "controller_type"))]
     pub controller_type: TextID<'a>,
     //
     /// Version of the controller's firmware.
+    #[serde(default = "unknown_text_id")]
     pub version: TextID<'a>,
     //
     /// Machine model.
+    #[serde(default = "unknown_text_id")]
     pub model: TextID<'a>,
     //
     /// Address of the controller.
     ///
-    /// For a network-connected controller, this is usually the IP address and port, in the format `x.x.x.x:port`.
+    /// For a network-connected controller, this is usually the IP address and port, in the format `x.x.x.x:port`
+    /// (or `[address]:port` for IP v.6).
     ///
     /// For a serial-connected controller, this is usually the serial port device name, such as `COM1`, `ttyS0`.
     #[serde(rename = "IP")]
+    #[serde(default = "unknown_address")]
     pub address: Address<'a>,
     //
     /// Physical geo-location of the controller (if any).
+    ///
+    /// On the wire, this is flattened into the top-level `geoLatitude` and `geoLongitude`
+    /// fields.  Use [`to_nested_geo_location`] to nest it under a `geoLocation` object instead.
+    ///
+    /// [`to_nested_geo_location`]: struct.Controller.html#method.to_nested_geo_location
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(flatten)]
     pub geo_location: Option<GeoLocation>,
     //
     /// Current operating mode of the controller.
+    #[serde(default)]
+    #[cfg_attr(feature = "legacy", serde(alias = "op_mode"))]
     pub op_mode: OpMode,
     //
     /// Current job mode of the controller.
+    #[serde(default)]
+    #[cfg_attr(feature = "legacy", serde(alias = "job_mode"))]
     pub job_mode: JobMode,
     //
     /// Last set of cycle data (if any) received from the controller.
+    ///
+    /// Serialized in insertion order (see [`Message::to_canonical_json`] for sorted keys).
+    ///
+    /// [`Message::to_canonical_json`]: enum.Message.html#method.to_canonical_json
     #[serde(skip_serializing_if = "IndexMap::is_empty")]
     #[serde(default)]
+    #[cfg_attr(feature = "legacy", serde(alias = "last_cycle_data"))]
     pub last_cycle_data: IndexMap<TextID<'a>, R32>,
     //
     /// Last-known states (if any) of controller variables.
+    ///
+    /// Serialized in insertion order (see [`Message::to_canonical_json`] for sorted keys).
+    ///
+    /// [`Message::to_canonical_json`]: enum.Message.html#method.to_canonical_json
     #[serde(skip_serializing_if = "IndexMap::is_empty")]
     #[serde(default)]
     pub variables: IndexMap<TextID<'a>, R32>,
     //
     /// Time of last connection.
+    ///
+    /// On the wire, this is an RFC3339 date-time string, with the time-zone specified either
+    /// as `Z` (UTC) or as a numeric offset (e.g. `2016-03-06T23:11:27.1442177+08:00`).
+    ///
+    /// Use [`last_connection_utc`] to get this value normalized to UTC, or
+    /// [`last_connection_epoch_ms`] to get it as Unix epoch milliseconds.
+    ///
+    /// With the `epoch-millis` feature, this is serialized as Unix epoch milliseconds instead.
+    ///
+    /// [`last_connection_utc`]: struct.Controller.html#method.last_connection_utc
+    /// [`last_connection_epoch_ms`]: struct.Controller.html#method.last_connection_epoch_ms
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "epoch-millis",
+        serde(with = "super::utils::epoch_millis_option", default)
+    )]
+    #[cfg_attr(feature = "legacy", serde(alias = "last_connection_time"))]
     pub last_connection_time: Option<DateTime<FixedOffset>>,
     //
     /// Current logged-in user (if any) on the controller
@@ -72,13 +132,791 @@ pub struct Controller<'a> {
     //
     /// Active job ID (if any) on the controller.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_optional_cow_str")]
+    #[serde(default)]
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "job_card_id"))]
     pub job_card_id: Option<Box<Cow<'a, str>>>,
     //
     /// ID of the set of mold data currently loaded (if any) on the controller.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_optional_cow_str")]
+    #[serde(default)]
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "mold_id"))]
     pub mold_id: Option<Box<Cow<'a, str>>>,
+    //
+    /// Capabilities (if known) supported by the firmware or model of the controller,
+    /// e.g. the actions that it can perform.
+    ///
+    /// Use [`supports`] to check for a particular capability.
+    ///
+    /// [`supports`]: struct.Controller.html#method.supports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(deserialize_with = "deserialize_optional_cow_str_list")]
+    #[serde(default)]
+    #[serde(borrow)]
+    #[op(non_empty)]
+    pub capabilities: Option<Vec<Cow<'a, str>>>,
+}
+
+impl<'a> Controller<'a> {
+    /// Get the time of last connection (if any), normalized to UTC.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use chrono::DateTime;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let c = Controller {
+    ///     last_connection_time: Some(
+    ///         DateTime::parse_from_rfc3339("2019-02-26T10:03:04+08:00").map_err(|x| x.to_string())?
+    ///     ),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!("2019-02-26T02:03:04+00:00", c.last_connection_utc().unwrap().to_rfc3339());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn last_connection_utc(&self) -> Option<DateTime<Utc>> {
+        self.last_connection_time.map(|t| t.with_timezone(&Utc))
+    }
+
+    /// Get the time of last connection (if any), as the number of milliseconds since the
+    /// Unix epoch.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use chrono::DateTime;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let c = Controller {
+    ///     last_connection_time: Some(
+    ///         DateTime::parse_from_rfc3339("2016-03-06T23:11:27.144+08:00").map_err(|x| x.to_string())?
+    ///     ),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(Some(1457277087144), c.last_connection_epoch_ms());
+    /// assert_eq!(None, Controller::default().last_connection_epoch_ms());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn last_connection_epoch_ms(&self) -> Option<i64> {
+        self.last_connection_time.map(|t| t.timestamp_millis())
+    }
+
+    /// Returns true if the controller has not connected within `max_age` before `now`.
+    ///
+    /// A controller without a time of last connection is always stale.
+    /// A time of last connection later than `now` (e.g. due to clock skew) is not stale.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use chrono::{DateTime, Duration};
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let now = DateTime::parse_from_rfc3339("2019-02-26T10:30:00+08:00").map_err(|x| x.to_string())?;
+    ///
+    /// let c = Controller {
+    ///     last_connection_time: Some(
+    ///         DateTime::parse_from_rfc3339("2019-02-26T10:03:04+08:00").map_err(|x| x.to_string())?
+    ///     ),
+    ///     ..Default::default()
+    /// };
+    /// assert!(!c.is_stale(now, Duration::minutes(30)));
+    /// assert!(c.is_stale(now, Duration::minutes(15)));
+    ///
+    /// assert!(Controller::default().is_stale(now, Duration::minutes(30)));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn is_stale(&self, now: DateTime<FixedOffset>, max_age: Duration) -> bool {
+        match self.last_connection_time {
+            Some(time) => now.signed_duration_since(time) > max_age,
+            None => true,
+        }
+    }
+
+    /// Returns true if the configuration of two controllers is the same.
+    ///
+    /// All fields are compared except volatile telemetry that changes during normal
+    /// operation: `last_cycle_data`, `variables` and `last_connection_time`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let c1 = Controller { model: TextID::new("JM138Ai").unwrap(), ..Default::default() };
+    ///
+    /// let mut c2 = c1.clone();
+    /// c2.last_cycle_data.insert(TextID::new("Z_QDGODCNT").unwrap(), R32::new(123.0));
+    /// assert!(c1.config_eq(&c2));
+    ///
+    /// c2.model = TextID::new("JM238Ai").unwrap();
+    /// assert!(!c1.config_eq(&c2));
+    /// ~~~
+    pub fn config_eq(&self, other: &Controller) -> bool {
+        self.controller_id == other.controller_id
+            && self.display_name == other.display_name
+            && self.controller_type == other.controller_type
+            && self.version == other.version
+            && self.model == other.model
+            && self.address == other.address
+            && self.geo_location == other.geo_location
+            && self.op_mode == other.op_mode
+            && self.job_mode == other.job_mode
+            && self.operator == other.operator
+            && self.job_card_id == other.job_card_id
+            && self.mold_id == other.mold_id
+            && self.capabilities == other.capabilities
+    }
+
+    /// Get the controller variables that have been added or changed since a previous snapshot.
+    ///
+    /// Each item is `(name, previous value, current value)`, with `None` as the previous value
+    /// for a newly-appearing variable.  Items are in the order of `variables` in `self`.
+    /// Variables that exist only in `previous` are not reported.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut previous = Controller::default();
+    /// previous.variables.insert(TextID::new("Temp").unwrap(), R32::new(200.0));
+    /// previous.variables.insert(TextID::new("Speed").unwrap(), R32::new(50.0));
+    ///
+    /// let mut current = previous.clone();
+    /// current.variables.insert(TextID::new("Temp").unwrap(), R32::new(210.0));
+    /// current.variables.insert(TextID::new("Pressure").unwrap(), R32::new(1.5));
+    ///
+    /// assert_eq!(
+    ///     vec![("Temp", Some(200.0), 210.0), ("Pressure", None, 1.5)],
+    ///     current.variable_changes(&previous)
+    /// );
+    /// ~~~
+    pub fn variable_changes(&self, previous: &Controller) -> Vec<(&str, Option<f64>, f64)> {
+        self.variables
+            .iter()
+            .filter_map(|(name, value)| match previous.variables.get(name.get()) {
+                Some(old) if old == value => None,
+                old => Some((name.get(), old.map(|v| f64::from(v.raw())), f64::from(value.raw()))),
+            })
+            .collect()
+    }
+
+    /// Get the ID of the operator currently logged onto the controller, if any.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller::default();
+    /// assert_eq!(None, c.operator_id());
+    ///
+    /// c.operator = Some(Operator::new(ID::from_u32(42)));
+    /// assert_eq!(Some(ID::from_u32(42)), c.operator_id());
+    /// ~~~
+    pub fn operator_id(&self) -> Option<ID> {
+        self.operator.as_ref().map(Operator::id)
+    }
+
+    /// Merge a partial update, in the form of a `ControllerStatus` message, into this controller.
+    ///
+    /// In a `ControllerStatus` message, a field that is `None` is _absent_ and means that the
+    /// value is unchanged, while `Some(None)` means that the value is _explicitly cleared_:
+    ///
+    /// | Field in `ControllerStatus` | `None` (absent) | `Some(None)` (cleared) | `Some(value)` |
+    /// |:----------------------------|:----------------|:-----------------------|:--------------|
+    /// | `display_name`              | unchanged       | --                     | replaced      |
+    /// | `is_disconnected`           | unchanged       | --                     | `true` calls [`set_offline`] |
+    /// | `op_mode`, `job_mode`       | unchanged       | --                     | replaced      |
+    /// | `variable`                  | unchanged       | --                     | inserted into `variables` |
+    /// | `operator_id`               | unchanged       | `operator` cleared     | `operator` replaced (name kept if same ID) |
+    /// | `operator_name`             | unchanged       | name cleared           | name replaced |
+    /// | `job_card_id`, `mold_id`    | unchanged       | cleared                | replaced      |
+    /// | `controller`                | unchanged       | --                     | replaces the whole controller |
+    ///
+    /// The `controller` field (if any) is merged first, then all the other fields.
+    /// An `operator_name` without a logged-on operator is ignored.
+    /// `alarm`, `audit` and the `state` snapshot are not part of `Controller` and are ignored.
+    ///
+    /// Returns `false`, leaving this controller unchanged, if `status` is not a
+    /// `ControllerStatus` message for this controller (by `controller_id`).
+    ///
+    /// [`set_offline`]: struct.Controller.html#method.set_offline
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut c = Controller {
+    ///     op_mode: OpMode::Manual,
+    ///     job_mode: JobMode::ID01,
+    ///     operator: Some(Operator::try_new_with_name(ID::from_u32(42), "John")?),
+    ///     job_card_id: Some(Box::new("JOB_CARD_1".into())),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// // Change the op mode, clear the job card, leave everything else untouched
+    /// let status = Message::parse_from_json_str(
+    ///     r#"{"$type":"ControllerStatus","controllerId":1,"opMode":"Automatic","jobCardId":null,"state":{"opMode":"Automatic"},"sequence":1}"#
+    /// )?;
+    ///
+    /// assert!(c.merge(&status));
+    /// assert_eq!(OpMode::Automatic, c.op_mode);
+    /// assert_eq!(JobMode::ID01, c.job_mode);
+    /// assert_eq!(Some("John"), c.operator.as_ref().and_then(Operator::name));
+    /// assert_eq!(None, c.job_card_id);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn merge(&mut self, status: &Message<'_>) -> bool {
+        match status {
+            Message::ControllerStatus {
+                controller_id,
+                display_name,
+                is_disconnected,
+                op_mode,
+                job_mode,
+                variable,
+                operator_id,
+                operator_name,
+                job_card_id,
+                mold_id,
+                controller,
+                ..
+            } if *controller_id == self.controller_id => {
+                if let Some(controller) = controller {
+                    *self = Controller::clone(controller).into_owned();
+                }
+
+                if let Some(name) = display_name {
+                    self.display_name = TextName::clone(name).into_owned();
+                }
+
+                if let Some(mode) = op_mode {
+                    self.op_mode = *mode;
+                }
+
+                if let Some(mode) = job_mode {
+                    self.job_mode = *mode;
+                }
+
+                if *is_disconnected == Some(true) {
+                    self.set_offline();
+                }
+
+                if let Some(kv) = variable {
+                    self.variables.insert(kv.key_ref().clone().into_owned(), kv.value());
+                }
+
+                match operator_id {
+                    None => (),
+                    Some(None) => self.operator = None,
+                    Some(Some(id)) if self.operator_id() == Some(*id) => (),
+                    Some(Some(id)) => self.operator = Some(Operator::new(*id)),
+                }
+
+                if let (Some(name), Some(operator)) = (operator_name, &self.operator) {
+                    let name = name.as_deref().cloned().map(TextName::into_owned);
+                    self.operator = Some(Operator::new_with_name(operator.id(), name));
+                }
+
+                let to_cow = |text: &TextName| Box::new(Cow::Owned(text.get().to_string()));
+
+                if let Some(id) = job_card_id {
+                    self.job_card_id = id.as_deref().map(to_cow);
+                }
+
+                if let Some(id) = mold_id {
+                    self.mold_id = id.as_deref().map(to_cow);
+                }
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Convert into a `Controller` that owns all its data, copying any borrowed text.
+    ///
+    /// Use this to keep a controller after the JSON text it is parsed from is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = String::from(
+    ///     r#"{"controllerId":1,"displayName":"Hello","controllerType":"Ai12","version":"1.0","model":"JM128-Ai","IP":"192.168.1.1:5000","opMode":"Manual","jobMode":"ID01","jobCardId":"XYZ"}"#
+    /// );
+    /// let c: Controller = serde_json::from_str(&json).map_err(|x| x.to_string())?;
+    /// let c = c.into_owned();
+    /// drop(json);
+    ///
+    /// assert_eq!("Hello", c.display_name.get());
+    /// assert_eq!(Some("XYZ"), c.job_card_id.as_deref().map(|id| id.as_ref()));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn into_owned(self) -> Controller<'static> {
+        let owned_keys = |map: IndexMap<TextID, R32>| {
+            map.into_iter().map(|(key, value)| (key.into_owned(), value)).collect()
+        };
+        let owned_text = |text: Box<Cow<str>>| Box::new(Cow::Owned((*text).into_owned()));
+
+        Controller {
+            controller_id: self.controller_id,
+            display_name: self.display_name.into_owned(),
+            controller_type: self.controller_type.into_owned(),
+            version: self.version.into_owned(),
+            model: self.model.into_owned(),
+            address: self.address.into_owned(),
+            geo_location: self.geo_location,
+            op_mode: self.op_mode,
+            job_mode: self.job_mode,
+            last_cycle_data: owned_keys(self.last_cycle_data),
+            variables: owned_keys(self.variables),
+            last_connection_time: self.last_connection_time,
+            operator: self.operator.map(Operator::into_owned),
+            job_card_id: self.job_card_id.map(owned_text),
+            mold_id: self.mold_id.map(owned_text),
+            capabilities: self
+                .capabilities
+                .map(|list| list.into_iter().map(|c| Cow::Owned(c.into_owned())).collect()),
+        }
+    }
+
+    /// Get the last set of cycle data as `(name, value)` pairs sorted by name.
+    ///
+    /// Returns an empty `Vec` if there is no cycle data.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller::default();
+    /// assert!(c.cycle_data_sorted().is_empty());
+    ///
+    /// c.last_cycle_data.insert(TextID::new("Z_QDGODCNT").unwrap(), R32::new(123.0));
+    /// c.last_cycle_data.insert(TextID::new("Z_QDCYCTIM").unwrap(), R32::new(12.5));
+    ///
+    /// assert_eq!(vec![("Z_QDCYCTIM", 12.5), ("Z_QDGODCNT", 123.0)], c.cycle_data_sorted());
+    /// ~~~
+    pub fn cycle_data_sorted(&self) -> Vec<(&str, f64)> {
+        sorted_values(&self.last_cycle_data)
+    }
+
+    /// Get the last-known states of controller variables as `(name, value)` pairs sorted by name.
+    ///
+    /// Returns an empty `Vec` if there are no variables.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller::default();
+    /// assert!(c.variables_sorted().is_empty());
+    ///
+    /// c.variables.insert(TextID::new("Temp").unwrap(), R32::new(210.0));
+    /// c.variables.insert(TextID::new("Pressure").unwrap(), R32::new(1.5));
+    ///
+    /// assert_eq!(vec![("Pressure", 1.5), ("Temp", 210.0)], c.variables_sorted());
+    /// ~~~
+    pub fn variables_sorted(&self) -> Vec<(&str, f64)> {
+        sorted_values(&self.variables)
+    }
+
+    /// Insert (or replace) a value in the last set of cycle data.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `key` is empty or not all ASCII,
+    /// or if `value` is not a normal number (e.g. `NaN`, `Infinity`) or is out of range.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller::default();
+    ///
+    /// assert_eq!(
+    ///     Err(Error::InvalidField {
+    ///         field: "last_cycle_data",
+    ///         value: "NaN".into(),
+    ///         description: "NaN is not a supported value for Z_QDGODCNT".into()
+    ///     }),
+    ///     c.insert_cycle_metric("Z_QDGODCNT", f64::NAN)
+    /// );
+    /// assert!(c.last_cycle_data.is_empty());
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut c = Controller::default();
+    /// c.insert_cycle_metric("Z_QDGODCNT", 123.0)?;
+    /// c.insert_cycle_metric("Z_QDCYCTIM", 12.5)?;
+    ///
+    /// assert_eq!(vec![("Z_QDCYCTIM", 12.5), ("Z_QDGODCNT", 123.0)], c.cycle_data_sorted());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn insert_cycle_metric(&mut self, key: &'a str, value: f64) -> Result<'a, ()> {
+        insert_value(&mut self.last_cycle_data, "last_cycle_data", key, value)
+    }
+
+    /// Insert (or replace) the last-known state of a controller variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `key` is empty or not all ASCII,
+    /// or if `value` is not a normal number (e.g. `NaN`, `Infinity`) or is out of range.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut c = Controller::default();
+    /// c.insert_variable("Temp", 210.0)?;
+    ///
+    /// assert_eq!(vec![("Temp", 210.0)], c.variables_sorted());
+    /// assert!(c.insert_variable("", 1.0).is_err());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn insert_variable(&mut self, key: &'a str, value: f64) -> Result<'a, ()> {
+        insert_value(&mut self.variables, "variables", key, value)
+    }
+
+    /// Add a set of cycle data to the controller, consuming and returning it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if any key or value is invalid
+    /// (see [`insert_cycle_metric`]).
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`insert_cycle_metric`]: struct.Controller.html#method.insert_cycle_metric
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let c = Controller::default().with_cycle_data(vec![("Z_QDGODCNT", 123.0), ("Z_QDCYCTIM", 12.5)])?;
+    ///
+    /// assert_eq!(vec![("Z_QDCYCTIM", 12.5), ("Z_QDGODCNT", 123.0)], c.cycle_data_sorted());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn with_cycle_data(
+        mut self,
+        data: impl IntoIterator<Item = (&'a str, f64)>,
+    ) -> Result<'a, Self> {
+        for (key, value) in data {
+            self.insert_cycle_metric(key, value)?;
+        }
+        Ok(self)
+    }
+
+    /// Get a compact one-line summary of the controller's status, suitable for logging.
+    ///
+    /// The format is `#<id> <model> [<op mode>/<job mode>] op:<operator> job:<job card>`.
+    /// The operator is shown by name if available, otherwise by ID.
+    /// Missing values are shown as `-`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let c = Controller {
+    ///     model: TextID::new("MPC7").unwrap(),
+    ///     op_mode: OpMode::Automatic,
+    ///     job_mode: JobMode::ID02,
+    ///     operator: Some(Operator::try_new_with_name(ID::from_u32(42), "John").unwrap()),
+    ///     job_card_id: Some(Box::new("JOB_CARD_1".into())),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!("#1 MPC7 [Automatic/ID02] op:John job:JOB_CARD_1", c.summary());
+    ///
+    /// let c = Controller { operator: Some(Operator::new(ID::from_u32(42))), ..Default::default() };
+    /// assert_eq!("#1 Unknown [Unknown/Unknown] op:42 job:-", c.summary());
+    /// ~~~
+    pub fn summary(&self) -> String {
+        let operator = match &self.operator {
+            Some(op) => op.name().map(str::to_string).unwrap_or_else(|| op.id().to_string()),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "#{} {} [{}/{}] op:{} job:{}",
+            self.controller_id,
+            self.model,
+            self.op_mode,
+            self.job_mode,
+            operator,
+            self.job_card_id.as_deref().map(AsRef::as_ref).unwrap_or("-")
+        )
+    }
+
+    /// Get a copy of the controller that serializes its geo-location as a nested `geoLocation`
+    /// object instead of the top-level `geoLatitude` and `geoLongitude` fields.
+    ///
+    /// This format is for API consumers only.  Always serialize the `Controller` itself for
+    /// messages sent to the iChen® Server.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let c = Controller { geo_location: Some(GeoLocation::new(88.0, 123.0)?), ..Default::default() };
+    ///
+    /// let json = serde_json::to_string(&c.to_nested_geo_location()).map_err(|x| x.to_string())?;
+    /// # #[cfg(not(feature = "lowercase-enums"))]
+    /// assert_eq!(
+    ///     r#"{"controllerId":1,"displayName":"Unknown","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"0.0.0.0:0","opMode":"Unknown","jobMode":"Unknown","geoLocation":{"geoLatitude":88.0,"geoLongitude":123.0}}"#,
+    ///     json
+    /// );
+    /// # #[cfg(feature = "lowercase-enums")]
+    /// # assert_eq!(r#"{"controllerId":1,"displayName":"Unknown","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"0.0.0.0:0","opMode":"unknown","jobMode":"unknown","geoLocation":{"geoLatitude":88.0,"geoLongitude":123.0}}"#, json);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn to_nested_geo_location(&self) -> NestedGeoController<'a> {
+        self.clone().into()
+    }
+
+    /// Mark the controller as off-line, setting both `op_mode` and `job_mode` to `Offline`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller { op_mode: OpMode::Automatic, job_mode: JobMode::ID02, ..Default::default() };
+    /// c.set_offline();
+    /// assert_eq!(OpMode::Offline, c.op_mode);
+    /// assert_eq!(JobMode::Offline, c.job_mode);
+    /// assert_eq!(Ok(()), c.check());
+    /// ~~~
+    pub fn set_offline(&mut self) {
+        self.op_mode = OpMode::Offline;
+        self.job_mode = JobMode::Offline;
+    }
+
+    /// Returns true if the controller is known to support a capability (e.g. an action),
+    /// i.e. if it is listed in [`capabilities`].
+    ///
+    /// Returns false if the capabilities of the controller are unknown.
+    ///
+    /// [`capabilities`]: struct.Controller.html#structfield.capabilities
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let mut c = Controller {
+    ///     capabilities: Some(vec!["MoldChange".into(), "Reset".into()]),
+    ///     ..Default::default()
+    /// };
+    /// assert!(c.supports("MoldChange"));
+    /// assert!(!c.supports("moldchange"));
+    /// assert!(!c.supports("Purge"));
+    ///
+    /// c.capabilities = None;
+    /// assert!(!c.supports("MoldChange"));
+    /// ~~~
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().flatten().any(|c| c == capability)
+    }
+
+    /// Check the `Controller` data structure for consistency.
+    ///
+    /// When a controller is off-line, both `op_mode` and `job_mode` must be `Offline`.
+    ///
+    /// This is not checked when parsing messages, as some iChen® Servers send controllers
+    /// with only one of the two set to `Offline`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if only one of `op_mode`
+    /// and `job_mode` is `Offline`.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::EmptyField`]`)` if any of the `capabilities` is
+    /// empty or all whitespace.
+    ///
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    /// [`OpenProtocolError::EmptyField`]: enum.OpenProtocolError.html#variant.EmptyField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let c = Controller { op_mode: OpMode::Offline, job_mode: JobMode::ID02, ..Default::default() };
+    /// assert_eq!(
+    ///     Err(Error::ConstraintViolated("job_mode must be Offline when op_mode is Offline.".into())),
+    ///     c.check()
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let c = Controller { op_mode: OpMode::Automatic, job_mode: JobMode::ID02, ..Default::default() };
+    /// assert_eq!(Ok(()), c.check());
+    /// ~~~
+    pub fn check(&self) -> Result<'static, ()> {
+        match (self.op_mode.is_offline(), self.job_mode.is_offline()) {
+            (true, false) => Err(Error::ConstraintViolated(
+                "job_mode must be Offline when op_mode is Offline.".into(),
+            )),
+            (false, true) => Err(Error::ConstraintViolated(
+                "op_mode must be Offline when job_mode is Offline.".into(),
+            )),
+            // Fields marked with `op` attributes
+            _ => self.check_fields(),
+        }
+    }
+}
+
+fn insert_value<'a>(
+    map: &mut IndexMap<TextID<'a>, R32>,
+    field: &'static str,
+    key: &'a str,
+    value: f64,
+) -> Result<'a, ()> {
+    let id = TextID::new(key).ok_or_else(|| Error::InvalidField {
+        field,
+        value: key.into(),
+        description: "key must be a non-empty, all-ASCII string".into(),
+    })?;
+
+    let invalid = |description: String| Error::InvalidField {
+        field,
+        value: value.to_string().into(),
+        description: format!("{} for {}", description, key).into(),
+    };
+
+    let num = value as f32;
+
+    if value.is_finite() && num.is_infinite() {
+        return Err(invalid("value out of range".into()));
+    }
+    check_f32(num).map_err(|e| invalid(e.into()))?;
+
+    map.insert(id, R32::new(num));
+    Ok(())
+}
+
+/// A change of the operator logged onto a controller between two snapshots
+/// (see [`detect_login_transition`]).
+///
+/// [`detect_login_transition`]: fn.detect_login_transition.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LoginTransition {
+    /// An operator has logged on.
+    LoggedIn(ID),
+    //
+    /// An operator has logged off.
+    LoggedOut(ID),
+    //
+    /// One operator has been replaced by another.
+    Switched {
+        /// The operator logged off.
+        from: ID,
+        /// The operator logged on.
+        to: ID,
+    },
+}
+
+/// Detect the operator login/logout (if any) between two successive snapshots of a
+/// controller.
+///
+/// Returns `None` if the operator has not changed.
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// let prev = Controller::default();
+/// let next = Controller { operator: Some(Operator::new(ID::from_u32(42))), ..Default::default() };
+///
+/// assert_eq!(Some(LoginTransition::LoggedIn(ID::from_u32(42))), detect_login_transition(&prev, &next));
+/// assert_eq!(Some(LoginTransition::LoggedOut(ID::from_u32(42))), detect_login_transition(&next, &prev));
+/// assert_eq!(None, detect_login_transition(&next, &next));
+/// ~~~
+pub fn detect_login_transition(prev: &Controller, next: &Controller) -> Option<LoginTransition> {
+    match (prev.operator_id(), next.operator_id()) {
+        (None, Some(id)) => Some(LoginTransition::LoggedIn(id)),
+        (Some(id), None) => Some(LoginTransition::LoggedOut(id)),
+        (Some(from), Some(to)) if from != to => Some(LoginTransition::Switched { from, to }),
+        _ => None,
+    }
+}
+
+fn sorted_values<'a>(map: &'a IndexMap<TextID<'_>, R32>) -> Vec<(&'a str, f64)> {
+    let mut list: Vec<_> = map.iter().map(|(k, v)| (k.get(), f64::from(v.raw()))).collect();
+    list.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    list
+}
+
+// Default values of fields that older controller firmware may omit.
+fn unknown_text_id<'a>() -> TextID<'a> {
+    TextID::new("Unknown").unwrap()
+}
+
+fn unknown_address<'a>() -> Address<'a> {
+    Address::Unknown
+}
+
+/// A [`Controller`] that serializes its geo-location (if any) as a nested `geoLocation` object
+/// instead of the top-level `geoLatitude` and `geoLongitude` fields.
+///
+/// All other fields are serialized exactly as in [`Controller`].
+///
+/// [`Controller`]: struct.Controller.html
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NestedGeoController<'a> {
+    /// The controller, without its geo-location.
+    #[serde(flatten)]
+    #[serde(borrow)]
+    pub controller: Controller<'a>,
+    //
+    /// Physical geo-location of the controller (if any).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub geo_location: Option<GeoLocation>,
+}
+
+impl<'a> From<Controller<'a>> for NestedGeoController<'a> {
+    fn from(mut controller: Controller<'a>) -> Self {
+        let geo_location = controller.geo_location.take();
+        Self { controller, geo_location }
+    }
+}
+
+impl<'a> From<NestedGeoController<'a>> for Controller<'a> {
+    fn from(value: NestedGeoController<'a>) -> Self {
+        let mut controller = value.controller;
+        controller.geo_location = value.geo_location.or(controller.geo_location);
+        controller
+    }
 }
 
 impl Default for Controller<'_> {
@@ -104,6 +942,7 @@ impl Default for Controller<'_> {
             last_connection_time: None,
             operator: None,
             mold_id: None,
+            capabilities: None,
         }
     }
 }
@@ -115,6 +954,8 @@ mod test {
     use super::*;
     use std::result::Result;
 
+    // Checks the standard names of enum values
+    #[cfg(not(feature = "lowercase-enums"))]
     #[test]
     fn test_controller_to_json() -> Result<(), String> {
         let c = Controller {
@@ -134,15 +975,612 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_controller_to_json_geo_location_shapes() -> Result<(), String> {
+        let c =
+            Controller { geo_location: Some(GeoLocation::new(88.0, 123.0)?), ..Default::default() };
+
+        // Default flattened form
+        let json = serde_json::to_string(&c).map_err(|x| x.to_string())?;
+        assert!(json.contains(r#""geoLatitude":88.0,"geoLongitude":123.0"#), "{}", json);
+        assert!(!json.contains("geoLocation"), "{}", json);
+
+        // Nested form
+        let json = serde_json::to_string(&c.to_nested_geo_location()).map_err(|x| x.to_string())?;
+        assert!(
+            json.ends_with(r#","geoLocation":{"geoLatitude":88.0,"geoLongitude":123.0}}"#),
+            "{}",
+            json
+        );
+        assert_eq!(1, json.matches("geoLatitude").count(), "{}", json);
+
+        let nested: NestedGeoController = serde_json::from_str(&json).map_err(|x| x.to_string())?;
+        let c2: Controller = nested.into();
+        assert_eq!(c.geo_location, c2.geo_location);
+        assert!(c.config_eq(&c2));
+
+        // No geo-location
+        let json = serde_json::to_string(&Controller::default().to_nested_geo_location())
+            .map_err(|x| x.to_string())?;
+        assert!(!json.contains("geo"), "{}", json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_to_json_map_order_is_stable() -> Result<(), String> {
+        let make = || -> Result<Controller, String> {
+            let mut c = Controller::default();
+            c.last_cycle_data.insert("Z_QDGODCNT".try_into()?, R32::new(8567.0));
+            c.last_cycle_data.insert("Z_QDCYCTIM".try_into()?, R32::new(11.5));
+            c.last_cycle_data.insert("Z_QDINJTIM".try_into()?, R32::new(4.0));
+            c.variables.insert("b".try_into()?, R32::new(2.0));
+            c.variables.insert("a".try_into()?, R32::new(1.0));
+            c.variables.insert("c".try_into()?, R32::new(3.0));
+            Ok(c)
+        };
+
+        let json = serde_json::to_string(&make()?).map_err(|x| x.to_string())?;
+
+        assert!(json.contains(
+            r#""lastCycleData":{"Z_QDGODCNT":8567.0,"Z_QDCYCTIM":11.5,"Z_QDINJTIM":4.0},"variables":{"b":2.0,"a":1.0,"c":3.0}"#
+        ), "{}", json);
+
+        for _ in 0..10 {
+            assert_eq!(json, serde_json::to_string(&make()?).map_err(|x| x.to_string())?);
+        }
+
+        // Round-trips with the same order
+        let c: Controller = serde_json::from_str(&json).map_err(|x| x.to_string())?;
+        assert_eq!(json, serde_json::to_string(&c).map_err(|x| x.to_string())?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_controller_from_json() -> Result<(), String> {
         let c: Controller = serde_json::from_str(r#"{"controllerId":1,"geoLatitude":88,"geoLongitude":-123,"displayName":"Hello","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"127.0.0.1:123","opMode":"Automatic","jobMode":"ID02","operatorId":123,"operatorName":"John"}"#).map_err(|x| x.to_string())?;
 
         assert_eq!(
-            r#"Controller { controller_id: 1, display_name: "Hello", controller_type: "Unknown", version: "Unknown", model: "Unknown", address: IPv4(127.0.0.1, 123), geo_location: Some((88,-123)), op_mode: Automatic, job_mode: ID02, last_cycle_data: {}, variables: {}, last_connection_time: None, operator: Some(Operator { operator_id: 123, operator_name: Some("John") }), job_card_id: None, mold_id: None }"#,
+            r#"Controller { controller_id: 1, display_name: "Hello", controller_type: "Unknown", version: "Unknown", model: "Unknown", address: IPv4(127.0.0.1, 123), geo_location: Some((88,-123)), op_mode: Automatic, job_mode: ID02, last_cycle_data: {}, variables: {}, last_connection_time: None, operator: Some(Operator { operator_id: 123, operator_name: Some("John") }), job_card_id: None, mold_id: None, capabilities: None }"#,
             format!("{:?}", &c)
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_controller_from_partial_json() -> Result<(), String> {
+        let c: Controller = serde_json::from_str(r#"{"controllerId":1,"displayName":"Hello"}"#)
+            .map_err(|x| x.to_string())?;
+
+        assert_eq!(OpMode::Unknown, c.op_mode);
+        assert_eq!(JobMode::Unknown, c.job_mode);
+        assert_eq!("Unknown", c.controller_type.get());
+        assert_eq!("Unknown", c.version.get());
+        assert_eq!("Unknown", c.model.get());
+        assert_eq!(Address::Unknown, c.address);
+        assert!(c.operator.is_none());
+        assert!(c.last_cycle_data.is_empty());
+
+        // Identity fields are still required
+        assert!(serde_json::from_str::<Controller>(r#"{"controllerId":1}"#).is_err());
+        assert!(serde_json::from_str::<Controller>(r#"{"displayName":"Hello"}"#).is_err());
+
+        // Present fields are not affected
+        let c: Controller = serde_json::from_str(
+            r#"{"controllerId":1,"displayName":"Hello","model":"JM128","opMode":"Automatic"}"#,
+        )
+        .map_err(|x| x.to_string())?;
+
+        assert_eq!(OpMode::Automatic, c.op_mode);
+        assert_eq!(JobMode::Unknown, c.job_mode);
+        assert_eq!("JM128", c.model.get());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_display_name_cannot_be_empty() -> Result<(), String> {
+        // `display_name` is a `TextName`, which is never empty, so `check` need not test it
+        let c: Controller = serde_json::from_str(r#"{"controllerId":1,"displayName":"Machine A"}"#)
+            .map_err(|x| x.to_string())?;
+        assert_eq!("Machine A", c.display_name.get());
+        assert_eq!(Ok(()), c.check());
+
+        for name in &["", "   ", " \t "] {
+            let json = format!(r#"{{"controllerId":1,"displayName":"{}"}}"#, name);
+            assert!(serde_json::from_str::<Controller>(&json).is_err(), "{:?}", name);
+            assert!(TextName::new_from_str(*name).is_none(), "{:?}", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_check_same_as_hand_written() {
+        // `check` before the field rules were generated by `#[derive(OpValidate)]`
+        fn hand_written_check(c: &Controller) -> crate::Result<'static, ()> {
+            match (c.op_mode.is_offline(), c.job_mode.is_offline()) {
+                (true, false) => Err(Error::ConstraintViolated(
+                    "job_mode must be Offline when op_mode is Offline.".into(),
+                )),
+                (false, true) => Err(Error::ConstraintViolated(
+                    "op_mode must be Offline when job_mode is Offline.".into(),
+                )),
+                _ if c.capabilities.iter().flatten().any(|c| c.trim().is_empty()) => {
+                    Err(Error::EmptyField("capabilities"))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        let modes = [
+            (OpMode::Automatic, JobMode::ID02),
+            (OpMode::Offline, JobMode::Offline),
+            (OpMode::Offline, JobMode::ID02),
+            (OpMode::Automatic, JobMode::Offline),
+            (OpMode::Unknown, JobMode::Unknown),
+        ];
+        let capabilities: [Option<Vec<Cow<str>>>; 5] = [
+            None,
+            Some(vec![]),
+            Some(vec!["MoldChange".into(), "Reset".into()]),
+            Some(vec!["".into()]),
+            Some(vec!["MoldChange".into(), " \t ".into()]),
+        ];
+
+        for &(op_mode, job_mode) in &modes {
+            for capabilities in &capabilities {
+                let c = Controller {
+                    op_mode,
+                    job_mode,
+                    capabilities: capabilities.clone(),
+                    ..Default::default()
+                };
+                assert_eq!(hand_written_check(&c), c.check(), "{:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_controller_capabilities() -> Result<(), String> {
+        let json = r#"{"controllerId":1,"displayName":"Hello","controllerType":"Ai12","version":"1.0","model":"JM128","IP":"127.0.0.1:123","opMode":"Automatic","jobMode":"ID02","capabilities":["MoldChange","Reset"]}"#;
+
+        let c: Controller = serde_json::from_str(json).map_err(|x| x.to_string())?;
+        assert_eq!(Some(vec![Cow::from("MoldChange"), Cow::from("Reset")]), c.capabilities);
+        assert!(c.capabilities.iter().flatten().all(|c| matches!(c, Cow::Borrowed(_))));
+        assert!(c.supports("MoldChange"));
+        assert!(c.supports("Reset"));
+        assert!(!c.supports("Purge"));
+        assert!(!c.supports(""));
+        assert_eq!(Ok(()), c.check());
+
+        // Round-trip
+        let text = serde_json::to_string(&c).map_err(|x| x.to_string())?;
+        assert!(text.ends_with(r#","capabilities":["MoldChange","Reset"]}"#), "{}", text);
+        let c2: Controller = serde_json::from_str(&text).map_err(|x| x.to_string())?;
+        assert!(c.config_eq(&c2));
+
+        // Escaped strings
+        let escaped = json.replace(r#""Reset""#, r#""Reset\u0020All""#);
+        let c2: Controller = serde_json::from_str(&escaped).map_err(|x| x.to_string())?;
+        assert!(c2.supports("Reset All"));
+
+        // Not serialized when unknown
+        let c = Controller { capabilities: None, ..c };
+        assert!(!c.supports("MoldChange"));
+        assert!(!serde_json::to_string(&c).map_err(|x| x.to_string())?.contains("capabilities"));
+
+        // Empty list
+        let c = Controller { capabilities: Some(vec![]), ..c };
+        assert!(!c.supports("MoldChange"));
+        assert_eq!(Ok(()), c.check());
+
+        // Empty capability
+        let c = Controller { capabilities: Some(vec!["Reset".into(), "  ".into()]), ..c };
+        assert_eq!(Err(Error::EmptyField("capabilities")), c.check());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_last_connection_utc() -> Result<(), String> {
+        let c1: Controller = serde_json::from_str(r#"{"controllerId":1,"displayName":"Hello","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"127.0.0.1:123","opMode":"Automatic","jobMode":"ID02","lastConnectionTime":"2016-03-06T23:11:27.144+08:00"}"#).map_err(|x| x.to_string())?;
+        let c2: Controller = serde_json::from_str(r#"{"controllerId":1,"displayName":"Hello","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"127.0.0.1:123","opMode":"Automatic","jobMode":"ID02","lastConnectionTime":"2016-03-06T15:11:27.144Z"}"#).map_err(|x| x.to_string())?;
+
+        assert!(c1.last_connection_utc().is_some());
+        assert_eq!(c1.last_connection_utc(), c2.last_connection_utc());
+        assert_eq!(c1.last_connection_time, c2.last_connection_time);
+        assert_eq!(None, Controller::default().last_connection_utc());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_last_connection_epoch_ms() -> Result<(), String> {
+        let c: Controller = serde_json::from_str(r#"{"controllerId":1,"displayName":"Hello","controllerType":"Unknown","version":"Unknown","model":"Unknown","IP":"127.0.0.1:123","opMode":"Automatic","jobMode":"ID02","lastConnectionTime":"2016-03-06T23:11:27.144+08:00"}"#).map_err(|x| x.to_string())?;
+
+        assert_eq!(Some(1_457_277_087_144), c.last_connection_epoch_ms());
+        assert_eq!(None, Controller::default().last_connection_epoch_ms());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "epoch-millis")]
+    #[test]
+    fn test_controller_epoch_millis_to_json() -> Result<(), String> {
+        let c = Controller {
+            last_connection_time: Some(
+                DateTime::parse_from_rfc3339("2016-03-06T23:11:27.144+08:00")
+                    .map_err(|x| x.to_string())?,
+            ),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&c).map_err(|x| x.to_string())?;
+        assert!(json.ends_with(r#","lastConnectionTime":1457277087144}"#), "{}", json);
+
+        let c2: Controller = serde_json::from_str(&json).map_err(|x| x.to_string())?;
+        assert_eq!("2016-03-06T15:11:27.144+00:00", c2.last_connection_time.unwrap().to_rfc3339());
+        assert_eq!(c.last_connection_utc(), c2.last_connection_utc());
+
+        // RFC3339 is still accepted
+        let rfc3339 = json.replace("1457277087144", r#""2016-03-06T23:11:27.144+08:00""#);
+        let c3: Controller = serde_json::from_str(&rfc3339).map_err(|x| x.to_string())?;
+        assert_eq!(c.last_connection_time, c3.last_connection_time);
+
+        // No time of last connection
+        let json = serde_json::to_string(&Controller::default()).map_err(|x| x.to_string())?;
+        assert!(!json.contains("lastConnectionTime"), "{}", json);
+
+        let c4: Controller = serde_json::from_str(&json).map_err(|x| x.to_string())?;
+        assert_eq!(None, c4.last_connection_time);
+
+        let null = json.replace("}", r#","lastConnectionTime":null}"#);
+        let c5: Controller = serde_json::from_str(&null).map_err(|x| x.to_string())?;
+        assert_eq!(None, c5.last_connection_time);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_config_eq_ignores_telemetry() -> Result<(), String> {
+        let c1 = Controller {
+            op_mode: OpMode::Automatic,
+            job_mode: JobMode::ID02,
+            operator: Some(Operator::try_new_with_name(ID::from_u32(123), "John")?),
+            ..Default::default()
+        };
+
+        let mut c2 = c1.clone();
+        c2.last_cycle_data.insert("Z_QDGODCNT".try_into()?, R32::new(8567.0));
+        c2.variables.insert("Z_QDCYCTIM".try_into()?, R32::new(11.475));
+        c2.last_connection_time = Some(
+            DateTime::parse_from_rfc3339("2016-03-06T23:11:27.144+08:00")
+                .map_err(|x| x.to_string())?,
+        );
+
+        assert!(c1.config_eq(&c2));
+        assert!(c2.config_eq(&c1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_config_eq_detects_changes() -> Result<(), String> {
+        let c1 = Controller {
+            model: "JM138Ai".try_into()?,
+            job_card_id: Some(Box::new("JC001".into())),
+            ..Default::default()
+        };
+
+        let c2 = Controller { model: "JM238Ai".try_into()?, ..c1.clone() };
+        assert!(!c1.config_eq(&c2));
+
+        let c2 = Controller { op_mode: OpMode::Manual, ..c1.clone() };
+        assert!(!c1.config_eq(&c2));
+
+        let c2 = Controller { job_card_id: None, ..c1.clone() };
+        assert!(!c1.config_eq(&c2));
+
+        let c2 = Controller { operator: Some(Operator::new(ID::from_u32(1))), ..c1.clone() };
+        assert!(!c1.config_eq(&c2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_variable_changes() -> Result<(), String> {
+        let mut previous = Controller::default();
+        previous.variables.insert("A".try_into()?, R32::new(1.0));
+        previous.variables.insert("B".try_into()?, R32::new(2.0));
+        previous.variables.insert("C".try_into()?, R32::new(3.0));
+
+        assert!(previous.variable_changes(&previous).is_empty());
+
+        let mut current = previous.clone();
+        current.variables.insert("B".try_into()?, R32::new(-2.5));
+        current.variables.insert("D".try_into()?, R32::new(4.0));
+        current.variables.shift_remove("C");
+
+        assert_eq!(
+            vec![("B", Some(2.0), -2.5), ("D", None, 4.0)],
+            current.variable_changes(&previous)
+        );
+
+        // All variables are new compared to an empty snapshot
+        assert_eq!(
+            vec![("A", None, 1.0), ("B", None, -2.5), ("D", None, 4.0)],
+            current.variable_changes(&Controller::default())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_sorted_values() -> Result<(), String> {
+        let mut c = Controller::default();
+        assert!(c.cycle_data_sorted().is_empty());
+        assert!(c.variables_sorted().is_empty());
+
+        c.last_cycle_data.insert("Z_QDGODCNT".try_into()?, R32::new(8567.0));
+        c.last_cycle_data.insert("Z_QDCYCTIM".try_into()?, R32::new(11.5));
+        c.last_cycle_data.insert("Z_QDINJTIM".try_into()?, R32::new(1.25));
+        c.variables.insert("b".try_into()?, R32::new(2.0));
+        c.variables.insert("a".try_into()?, R32::new(1.0));
+        c.variables.insert("B".try_into()?, R32::new(3.0));
+
+        assert_eq!(
+            vec![("Z_QDCYCTIM", 11.5), ("Z_QDGODCNT", 8567.0), ("Z_QDINJTIM", 1.25)],
+            c.cycle_data_sorted()
+        );
+        assert_eq!(vec![("B", 3.0), ("a", 1.0), ("b", 2.0)], c.variables_sorted());
+
+        // Insertion order of the maps is untouched
+        assert_eq!(Some("Z_QDGODCNT"), c.last_cycle_data.keys().next().map(TextID::get));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_offline() {
+        let mut c = Controller {
+            op_mode: OpMode::Automatic,
+            job_mode: JobMode::ID02,
+            ..Default::default()
+        };
+        assert_eq!(Ok(()), c.check());
+
+        c.set_offline();
+        assert_eq!(OpMode::Offline, c.op_mode);
+        assert_eq!(JobMode::Offline, c.job_mode);
+        assert_eq!(Ok(()), c.check());
+
+        let c =
+            Controller { op_mode: OpMode::Offline, job_mode: JobMode::ID02, ..Default::default() };
+        assert_eq!(
+            Err(Error::ConstraintViolated(
+                "job_mode must be Offline when op_mode is Offline.".into()
+            )),
+            c.check()
+        );
+
+        let c = Controller {
+            op_mode: OpMode::Automatic,
+            job_mode: JobMode::Offline,
+            ..Default::default()
+        };
+        assert_eq!(
+            Err(Error::ConstraintViolated(
+                "op_mode must be Offline when job_mode is Offline.".into()
+            )),
+            c.check()
+        );
+    }
+
+    #[test]
+    fn test_controller_summary() -> Result<(), String> {
+        let c = Controller {
+            controller_id: ID::from_u32(1),
+            display_name: "Hello".try_into()?,
+            model: "MPC7".try_into()?,
+            op_mode: OpMode::Automatic,
+            job_mode: JobMode::ID02,
+            operator: Some(Operator::try_new_with_name(ID::from_u32(123), "John")?),
+            job_card_id: Some(Box::new("JOB_CARD_1".into())),
+            mold_id: Some(Box::new("MOLD_1".into())),
+            ..Default::default()
+        };
+
+        assert_eq!("#1 MPC7 [Automatic/ID02] op:John job:JOB_CARD_1", c.summary());
+
+        let c = Controller { controller_id: ID::from_u32(99), ..Default::default() };
+
+        assert_eq!("#99 Unknown [Unknown/Unknown] op:- job:-", c.summary());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_insert_values() -> Result<(), String> {
+        let mut c = Controller::default();
+        assert!(c.last_cycle_data.is_empty());
+        assert!(c.variables.is_empty());
+
+        c.insert_cycle_metric("Z_QDGODCNT", 8567.0)?;
+        c.insert_variable("Temp", 210.5)?;
+
+        assert_eq!(vec![("Z_QDGODCNT", 8567.0)], c.cycle_data_sorted());
+        assert_eq!(vec![("Temp", 210.5)], c.variables_sorted());
+
+        // Replaces the existing value
+        c.insert_cycle_metric("Z_QDGODCNT", 8568.0)?;
+        assert_eq!(vec![("Z_QDGODCNT", 8568.0)], c.cycle_data_sorted());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_insert_invalid_values() {
+        let mut c = Controller::default();
+
+        assert_eq!(
+            Err(Error::InvalidField {
+                field: "variables",
+                value: "NaN".into(),
+                description: "NaN is not a supported value for Temp".into()
+            }),
+            c.insert_variable("Temp", f64::NAN)
+        );
+        assert_eq!(
+            Err(Error::InvalidField {
+                field: "last_cycle_data",
+                value: "inf".into(),
+                description: "Infinity is not a supported value for Z_QDGODCNT".into()
+            }),
+            c.insert_cycle_metric("Z_QDGODCNT", f64::INFINITY)
+        );
+        match c.insert_cycle_metric("Z_QDGODCNT", 1e300) {
+            Err(Error::InvalidField { field: "last_cycle_data", description, .. }) => {
+                assert_eq!("value out of range for Z_QDGODCNT", description)
+            }
+            r => panic!("expected InvalidField, got {:?}", r),
+        }
+        assert!(c.insert_cycle_metric("", 1.0).is_err());
+
+        assert!(c.last_cycle_data.is_empty());
+        assert!(c.variables.is_empty());
+
+        assert!(Controller::default().with_cycle_data(vec![("A", 1.0), ("B", f64::NAN)]).is_err());
+    }
+
+    #[test]
+    fn test_detect_login_transition() {
+        let op = |id| Some(Operator::new(ID::from_u32(id)));
+        let nobody = Controller::default();
+        let john = Controller { operator: op(1), ..Default::default() };
+        let mary = Controller { operator: op(2), ..Default::default() };
+
+        assert_eq!(Some(ID::from_u32(1)), john.operator_id());
+        assert_eq!(None, nobody.operator_id());
+
+        assert_eq!(
+            Some(LoginTransition::LoggedIn(ID::from_u32(1))),
+            detect_login_transition(&nobody, &john)
+        );
+        assert_eq!(
+            Some(LoginTransition::LoggedOut(ID::from_u32(2))),
+            detect_login_transition(&mary, &nobody)
+        );
+        assert_eq!(
+            Some(LoginTransition::Switched { from: ID::from_u32(1), to: ID::from_u32(2) }),
+            detect_login_transition(&john, &mary)
+        );
+
+        // No change
+        assert_eq!(None, detect_login_transition(&nobody, &nobody));
+        assert_eq!(None, detect_login_transition(&john, &john));
+
+        // A change of name only is not a new login
+        let renamed = Controller {
+            operator: Some(Operator::try_new_with_name(ID::from_u32(1), "John").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(None, detect_login_transition(&john, &renamed));
+    }
+
+    #[test]
+    fn test_controller_is_stale() -> Result<(), String> {
+        let time = |text| DateTime::parse_from_rfc3339(text).map_err(|x| x.to_string());
+        let now = time("2019-02-26T10:30:00+08:00")?;
+        let max_age = Duration::minutes(5);
+
+        // Recent, in a different time zone
+        let c = Controller {
+            last_connection_time: Some(time("2019-02-26T02:28:00+00:00")?),
+            ..Default::default()
+        };
+        assert!(!c.is_stale(now, max_age));
+
+        // Exactly at the maximum age
+        let c = Controller { last_connection_time: Some(now - max_age), ..Default::default() };
+        assert!(!c.is_stale(now, max_age));
+
+        // Old
+        let c = Controller {
+            last_connection_time: Some(time("2019-02-26T10:24:59+08:00")?),
+            ..Default::default()
+        };
+        assert!(c.is_stale(now, max_age));
+
+        // In the future
+        let c = Controller { last_connection_time: Some(now + max_age), ..Default::default() };
+        assert!(!c.is_stale(now, max_age));
+
+        // Never connected
+        assert!(Controller::default().is_stale(now, max_age));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_merge_status() -> Result<(), String> {
+        let original = Controller {
+            op_mode: OpMode::Manual,
+            job_mode: JobMode::ID01,
+            operator: Some(Operator::try_new_with_name(ID::from_u32(42), "John")?),
+            job_card_id: Some(Box::new("JOB_CARD_1".into())),
+            mold_id: Some(Box::new("MOLD_1".into())),
+            ..Default::default()
+        };
+
+        // Update `op_mode` only, leaving `operator` untouched
+        let mut c = original.clone();
+        assert!(c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"opMode":"Automatic","state":{"opMode":"Automatic"},"sequence":1}"#
+        )?));
+
+        assert_eq!(OpMode::Automatic, c.op_mode);
+        assert_eq!(original.operator, c.operator);
+        let json = |c: &Controller| serde_json::to_string(c).map_err(|x| x.to_string());
+        assert_eq!(json(&original)?, json(&Controller { op_mode: OpMode::Manual, ..c.clone() })?);
+
+        // Explicitly clear the job card and the operator name, keep the mold
+        assert!(c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"jobCardId":null,"operatorName":null,"state":{},"sequence":2}"#
+        )?));
+
+        assert_eq!(None, c.job_card_id);
+        assert_eq!(Some("MOLD_1"), c.mold_id.as_deref().map(|id| id.as_ref()));
+        assert_eq!(Some(Operator::new(ID::from_u32(42))), c.operator);
+
+        // A new operator logs in, then logs out
+        assert!(c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"operatorId":99,"operatorName":"Mary","state":{"operatorId":99},"sequence":3}"#
+        )?));
+        assert_eq!(Some(Operator::try_new_with_name(ID::from_u32(99), "Mary")?), c.operator);
+
+        assert!(c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"operatorId":0,"state":{},"sequence":4}"#
+        )?));
+        assert_eq!(None, c.operator);
+
+        // Disconnection
+        assert!(c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"isDisconnected":true,"state":{"opMode":"Offline","jobMode":"Offline"},"sequence":5}"#
+        )?));
+        assert_eq!(OpMode::Offline, c.op_mode);
+        assert_eq!(JobMode::Offline, c.job_mode);
+
+        // Another controller, or another message, is ignored
+        let mut c = original.clone();
+        assert!(!c.merge(&Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":2,"opMode":"Automatic","state":{"opMode":"Automatic"},"sequence":6}"#
+        )?));
+        assert!(!c.merge(&Message::new_alive()));
+        assert_eq!(json(&original)?, json(&c)?);
+
+        Ok(())
+    }
 }
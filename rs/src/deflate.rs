@@ -0,0 +1,468 @@
+use super::{Error, Result};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use websocket::dataframe::{DataFrame, Opcode};
+use websocket::header::extensions::{Extension, Parameter};
+use websocket::OwnedMessage;
+
+/// Name of the extension in the `Sec-WebSocket-Extensions` header.
+pub(crate) const EXTENSION_NAME: &str = "permessage-deflate";
+
+// Empty stored block that ends every compressed message; it is stripped before sending
+// and appended again before decompressing (RFC 7692 section 7.2).
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+// Size of each chunk of output buffer while compressing or decompressing.
+const CHUNK_SIZE: usize = 4096;
+
+/// Parameters of a negotiated `permessage-deflate` extension.
+///
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub(crate) struct DeflateParams {
+    /// The server resets its compression context after each message.
+    pub server_no_context_takeover: bool,
+    //
+    /// The client must reset its compression context after each message.
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateParams {
+    /// The extension offered by a client during the handshake.
+    pub fn offer() -> Extension {
+        Extension::new(EXTENSION_NAME.to_string())
+    }
+
+    /// Find the `permessage-deflate` extension (if any) accepted by the server in the
+    /// handshake response.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the server responds with parameters that are not supported.
+    ///
+    pub fn negotiated(extensions: &[Extension]) -> std::result::Result<Option<Self>, String> {
+        let extension = match extensions.iter().find(|ext| ext.name == EXTENSION_NAME) {
+            Some(ext) => ext,
+            None => return Ok(None),
+        };
+
+        let mut params = Self::default();
+
+        for param in &extension.params {
+            match split_param(param) {
+                ("server_no_context_takeover", None) => params.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => params.client_no_context_takeover = true,
+                // A smaller window used by the server can always be decompressed
+                ("server_max_window_bits", Some(bits)) if is_window_bits(bits) => (),
+                // The client always compresses with the maximum window
+                ("client_max_window_bits", None) | ("client_max_window_bits", Some("15")) => (),
+                _ => return Err(format!("unsupported {} parameter: {}", EXTENSION_NAME, param)),
+            }
+        }
+
+        Ok(Some(params))
+    }
+
+    /// The extension accepted by a server in response to a client's offer, if any.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn accept(offered: &[Extension]) -> Option<Extension> {
+        offered.iter().find(|ext| ext.name == EXTENSION_NAME).map(|_| Self::offer())
+    }
+}
+
+// Split a parameter into its name and value.
+//
+// The `websocket` crate does not split `name=value` pairs when parsing the header,
+// so the value may still be part of the name.
+fn split_param(param: &Parameter) -> (&str, Option<&str>) {
+    let (name, value) = match (param.name.split_once('='), &param.value) {
+        (Some((name, value)), None) => (name, Some(value)),
+        (_, value) => (param.name.as_str(), value.as_deref()),
+    };
+
+    (name.trim(), value.map(|v| v.trim().trim_matches('"')))
+}
+
+fn is_window_bits(bits: &str) -> bool {
+    matches!(bits.parse::<u8>(), Ok(8..=15))
+}
+
+/// Compresses messages to be sent.
+///
+pub(crate) struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    /// Create a new `Deflater`, optionally resetting the compression context after each message.
+    pub fn new(no_context_takeover: bool) -> Self {
+        Self { compress: Compress::new(Compression::default(), false), no_context_takeover }
+    }
+
+    /// Compress the payload of a message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if compression fails.
+    ///
+    pub fn deflate(&mut self, data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+        let start = self.compress.total_in();
+        let mut output = Vec::with_capacity(CHUNK_SIZE);
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(CHUNK_SIZE);
+            }
+
+            let consumed = (self.compress.total_in() - start) as usize;
+
+            self.compress
+                .compress_vec(&data[consumed..], &mut output, FlushCompress::Sync)
+                .map_err(|err| format!("cannot compress message: {}", err))?;
+
+            // The flush is complete when all input is consumed with room to spare
+            if (self.compress.total_in() - start) as usize == data.len()
+                && output.len() < output.capacity()
+            {
+                break;
+            }
+        }
+
+        if output.ends_with(&TRAILER) {
+            output.truncate(output.len() - TRAILER.len());
+        }
+
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+
+        Ok(output)
+    }
+
+    /// Compress a text message into a single data frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if compression fails.
+    ///
+    pub fn text_frame(&mut self, text: &str) -> std::result::Result<DataFrame, String> {
+        let mut frame = DataFrame::new(true, Opcode::Text, self.deflate(text.as_bytes())?);
+        frame.reserved[0] = true;
+        Ok(frame)
+    }
+}
+
+/// Decompresses messages received.
+///
+pub(crate) struct Inflater {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    /// Create a new `Inflater`, optionally resetting the compression context after each message.
+    pub fn new(no_context_takeover: bool) -> Self {
+        Self { decompress: Decompress::new(false), no_context_takeover }
+    }
+
+    /// Decompress the payload of a message, returning the decompressed data together with
+    /// its total size.
+    ///
+    /// If the decompressed data is larger than `limit` bytes, it is discarded (but still
+    /// decompressed in order to keep the compression context intact) and empty data is
+    /// returned with the total size.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the data is corrupted.
+    ///
+    pub fn inflate(
+        &mut self,
+        data: &[u8],
+        limit: usize,
+    ) -> std::result::Result<(Vec<u8>, usize), String> {
+        let input = [data, &TRAILER[..]].concat();
+        let (start_in, start_out) = (self.decompress.total_in(), self.decompress.total_out());
+        let mut output = Vec::with_capacity(CHUNK_SIZE.min(limit.saturating_add(1)));
+        let mut stream_end = false;
+
+        loop {
+            if output.len() == output.capacity() {
+                if output.len() > limit {
+                    output.clear();
+                }
+                output.reserve(CHUNK_SIZE);
+            }
+
+            let (total_in, total_out) = (self.decompress.total_in(), self.decompress.total_out());
+            let consumed = (total_in - start_in) as usize;
+
+            let status = self
+                .decompress
+                .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+                .map_err(|err| format!("cannot decompress message: {}", err))?;
+
+            // The final block ends the compressed stream, so the next message starts afresh
+            if status == Status::StreamEnd {
+                stream_end = true;
+                break;
+            }
+
+            if (self.decompress.total_in() - start_in) as usize == input.len()
+                && output.len() < output.capacity()
+            {
+                break;
+            }
+
+            if self.decompress.total_in() == total_in && self.decompress.total_out() == total_out {
+                return Err("cannot decompress message: truncated data".to_string());
+            }
+        }
+
+        let size = (self.decompress.total_out() - start_out) as usize;
+
+        if stream_end || self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        if size > limit {
+            output = Vec::new();
+        }
+
+        Ok((output, size))
+    }
+}
+
+/// Reassembles (possibly compressed) messages from WebSocket data frames.
+///
+pub(crate) struct MessageAssembler {
+    inflater: Inflater,
+    opcode: Option<Opcode>,
+    compressed: bool,
+    payload: Vec<u8>,
+}
+
+impl MessageAssembler {
+    /// Create a new `MessageAssembler` for a connection with negotiated extension parameters.
+    pub fn new(no_context_takeover: bool) -> Self {
+        Self {
+            inflater: Inflater::new(no_context_takeover),
+            opcode: None,
+            compressed: false,
+            payload: Vec::new(),
+        }
+    }
+
+    /// Get the number of bytes buffered for an incomplete fragmented message.
+    pub fn buffered(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Add a data frame, returning the complete message (if any).
+    ///
+    /// Control frames are returned immediately, even in the middle of a fragmented message.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::MessageTooLarge`]`)` if a compressed message is larger
+    /// than `limit` bytes after decompression.  The connection can still be used.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::SystemError`]`)` if the message cannot be
+    /// decompressed, or if it is not a valid WebSocket message.
+    ///
+    /// [`OpenProtocolError::MessageTooLarge`]: enum.OpenProtocolError.html#variant.MessageTooLarge
+    /// [`OpenProtocolError::SystemError`]: enum.OpenProtocolError.html#variant.SystemError
+    ///
+    pub fn push(
+        &mut self,
+        frame: DataFrame,
+        limit: usize,
+    ) -> Result<'static, Option<OwnedMessage>> {
+        let system_error = |err: String| Error::SystemError(err.into());
+
+        match frame.opcode {
+            Opcode::Close => return Ok(Some(OwnedMessage::Close(None))),
+            Opcode::Ping => return Ok(Some(OwnedMessage::Ping(frame.data))),
+            Opcode::Pong => return Ok(Some(OwnedMessage::Pong(frame.data))),
+            Opcode::Continuation if self.opcode.is_none() => {
+                return Err(system_error("unexpected continuation frame".to_string()))
+            }
+            Opcode::Continuation => (),
+            _ if self.opcode.is_some() => {
+                return Err(system_error("expected continuation frame".to_string()))
+            }
+            opcode => {
+                self.opcode = Some(opcode);
+                self.compressed = frame.reserved[0];
+                self.payload.clear();
+            }
+        }
+
+        self.payload.extend_from_slice(&frame.data);
+
+        if !frame.finished {
+            return Ok(None);
+        }
+
+        let opcode = self.opcode.take();
+        let payload = std::mem::take(&mut self.payload);
+
+        let data = if self.compressed {
+            let (data, size) = self.inflater.inflate(&payload, limit).map_err(system_error)?;
+
+            if size > limit {
+                return Err(Error::MessageTooLarge { size, limit });
+            }
+            data
+        } else {
+            payload
+        };
+
+        match opcode {
+            Some(Opcode::Text) => String::from_utf8(data)
+                .map(|text| Some(OwnedMessage::Text(text)))
+                .map_err(|_| system_error("invalid UTF-8 text in message".to_string())),
+            Some(Opcode::Binary) => Ok(Some(OwnedMessage::Binary(data))),
+            _ => Ok(None),
+        }
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Message;
+
+    // A representative `ControllersList` with a number of controllers.
+    fn controllers_list(count: u32) -> String {
+        let controllers: Vec<_> = (1..=count)
+            .map(|id| {
+                format!(
+                    r#""{id}":{{"controllerId":{id},"displayName":"Machine #{id}","controllerType":"Ai12","version":"1.0.0","model":"JM128-MK6","IP":"192.168.1.{id}:12345","opMode":"Automatic","jobMode":"ID02","jobCardId":"JC{id}","lastCycleData":{{"Z_QDGODCNT":8567,"Z_QDCYCTIM":979,"Z_QDPLSTIM":4.4}},"lastConnectionTime":"2016-03-06T23:11:27.1442177+08:00","operatorId":123,"operatorName":"John","moldId":"Mold-{id}"}}"#,
+                    id = id
+                )
+            })
+            .collect();
+
+        format!(
+            r#"{{"$type":"ControllersList","data":{{{}}},"sequence":68568}}"#,
+            controllers.join(",")
+        )
+    }
+
+    #[test]
+    fn test_deflate_negotiated_params() {
+        let parse = |header: &str| -> std::result::Result<Option<DeflateParams>, String> {
+            let extensions: Vec<Extension> =
+                header.split(',').map(|ext| ext.parse().unwrap()).collect();
+            DeflateParams::negotiated(&extensions)
+        };
+
+        assert_eq!(Ok(None), parse("foo"));
+        assert_eq!(Ok(Some(DeflateParams::default())), parse("permessage-deflate"));
+        assert_eq!(
+            Ok(Some(DeflateParams {
+                server_no_context_takeover: true,
+                client_no_context_takeover: true
+            })),
+            parse("permessage-deflate; server_no_context_takeover; client_no_context_takeover")
+        );
+        assert_eq!(
+            Ok(Some(DeflateParams::default())),
+            parse("permessage-deflate; server_max_window_bits=10")
+        );
+        assert!(parse("permessage-deflate; server_max_window_bits=16").is_err());
+        assert!(parse("permessage-deflate; client_max_window_bits=10").is_err());
+        assert!(parse("permessage-deflate; foo").is_err());
+    }
+
+    #[test]
+    fn test_deflate_round_trip() -> std::result::Result<(), String> {
+        let json = controllers_list(10);
+
+        for &no_context_takeover in &[false, true] {
+            let mut deflater = Deflater::new(no_context_takeover);
+            let mut inflater = Inflater::new(no_context_takeover);
+
+            // Later messages may refer back to earlier ones when the context is kept
+            for _ in 0..3 {
+                let compressed = deflater.deflate(json.as_bytes())?;
+                let (data, size) = inflater.inflate(&compressed, usize::MAX)?;
+
+                assert_eq!(json.len(), size);
+                assert_eq!(json.as_bytes(), &data[..]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deflate_size_reduction() -> std::result::Result<(), String> {
+        let json = controllers_list(10);
+        let compressed = Deflater::new(false).deflate(json.as_bytes())?;
+
+        // About 3.8KB compresses to around 12% of its size
+        assert!(compressed.len() * 4 < json.len(), "{} => {}", json.len(), compressed.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deflate_compressed_frame_parses() -> std::result::Result<(), String> {
+        let json = controllers_list(3);
+        let frame = Deflater::new(false).text_frame(&json)?;
+        assert!(frame.reserved[0]);
+
+        // Split the compressed frame into fragments
+        let fragments: Vec<_> = frame.data.chunks(16).collect();
+        let last = fragments.len() - 1;
+        let mut assembler = MessageAssembler::new(false);
+        let mut received = None;
+
+        for (n, fragment) in fragments.into_iter().enumerate() {
+            let opcode = if n == 0 { Opcode::Text } else { Opcode::Continuation };
+            let mut frame = DataFrame::new(n == last, opcode, fragment.to_vec());
+            frame.reserved[0] = n == 0;
+
+            received = assembler.push(frame, usize::MAX)?;
+            assert_eq!(n == last, received.is_some());
+        }
+
+        match received {
+            Some(OwnedMessage::Text(text)) => {
+                assert_eq!(json, text);
+                match Message::parse_from_json_str(&text)? {
+                    Message::ControllersList { data, .. } => assert_eq!(3, data.len()),
+                    msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+                }
+            }
+            msg => return Err(format!("expected text message, got {:?}", msg)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deflate_message_too_large() -> std::result::Result<(), String> {
+        let json = controllers_list(10);
+        let mut deflater = Deflater::new(false);
+        let mut assembler = MessageAssembler::new(false);
+
+        let frame = deflater.text_frame(&json)?;
+        assert_eq!(
+            Err(Error::MessageTooLarge { size: json.len(), limit: 1000 }),
+            assembler.push(frame, 1000).map(|_| ())
+        );
+
+        // The compression context is still intact for the next message
+        let frame = deflater.text_frame(&json)?;
+        match assembler.push(frame, usize::MAX)? {
+            Some(OwnedMessage::Text(text)) => assert_eq!(json, text),
+            msg => return Err(format!("expected text message, got {:?}", msg)),
+        }
+
+        Ok(())
+    }
+}
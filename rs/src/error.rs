@@ -1,5 +1,10 @@
 use derive_more::*;
 use std::borrow::Cow;
+use std::time::Duration;
+use websocket::WebSocketError;
+
+/// An error from the underlying transport, boxed.
+pub type TransportError = Box<dyn std::error::Error + Send + Sync + 'static>;
 
 /// Result error type.
 ///
@@ -42,6 +47,68 @@ pub enum OpenProtocolError<'a> {
     /// An unexpected system error.
     #[display(fmt = "{}", _0)]
     SystemError(Cow<'a, str>),
+    //
+    /// A message cannot be queued for sending because the outbound queue is full.
+    #[display(fmt = "outbound queue is full")]
+    QueueFull,
+    //
+    /// A message is larger than the maximum allowed size, so it is rejected without parsing.
+    #[display(fmt = "message size of {} bytes exceeds the limit of {} bytes", size, limit)]
+    MessageTooLarge { size: usize, limit: usize },
+    //
+    /// Nothing has arrived from the server within the idle timeout, so the connection is
+    /// assumed to be dead and has been closed.
+    #[display(fmt = "no data received from the server within {:?}", _0)]
+    Timeout(Duration),
+    //
+    /// A message cannot be sent now because it exceeds the rate limit.  It can be sent
+    /// after waiting for the period of time indicated.
+    #[display(fmt = "rate limit exceeded; retry after {:?}", _0)]
+    RateLimited(Duration),
+    //
+    /// The protocol version of the server is outside the range of versions supported
+    /// by the client.
+    #[display(
+        fmt = "server protocol version {} is not supported (supported versions: {} to {})",
+        version,
+        min,
+        max
+    )]
+    IncompatibleVersion { version: Cow<'a, str>, min: Cow<'a, str>, max: Cow<'a, str> },
+    //
+    /// A subscriber to inbound messages has fallen behind, so the number of messages
+    /// indicated were dropped before it could receive them.
+    #[display(fmt = "subscriber lagged behind; {} message(s) dropped", _0)]
+    Lagged(u64),
+    //
+    /// An error in the underlying transport (e.g. the TCP connection or the WebSocket).
+    ///
+    /// `context` describes what was being done, and the original error is available
+    /// via `source()`.
+    #[display(fmt = "{}: {}", context, source)]
+    Transport { context: Cow<'a, str>, source: TransportError },
+}
+
+impl<'a> OpenProtocolError<'a> {
+    /// Create an [`OpenProtocolError::Transport`] wrapping an error from the underlying
+    /// transport.
+    ///
+    /// [`OpenProtocolError::Transport`]: enum.OpenProtocolError.html#variant.Transport
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// use std::error::Error as _;
+    /// use std::io::{Error as IoError, ErrorKind};
+    ///
+    /// let err = OpenProtocolError::transport("cannot connect", IoError::from(ErrorKind::ConnectionRefused));
+    /// assert_eq!("cannot connect: connection refused", err.to_string());
+    /// assert!(err.source().unwrap().is::<IoError>());
+    /// ~~~
+    pub fn transport(context: impl Into<Cow<'a, str>>, source: impl Into<TransportError>) -> Self {
+        Self::Transport { context: context.into(), source: source.into() }
+    }
 }
 
 impl std::error::Error for OpenProtocolError<'_> {
@@ -77,28 +144,63 @@ impl std::error::Error for OpenProtocolError<'_> {
             //
             // Field empty
             Self::EmptyField(_) => "field cannot be empty or all whitespace",
+            //
+            // Queue full
+            Self::QueueFull => "outbound queue is full",
+            //
+            // Message too large
+            Self::MessageTooLarge { .. } => "message exceeds the maximum size",
+            //
+            // Timeout
+            Self::Timeout(_) => "no data received from the server within the idle timeout",
+            //
+            // Rate limited
+            Self::RateLimited(_) => "rate limit exceeded",
+            //
+            // Incompatible version
+            Self::IncompatibleVersion { .. } => "server protocol version is not supported",
+            //
+            // Lagged
+            Self::Lagged(_) => "subscriber lagged behind",
+            //
+            // Transport error
+            Self::Transport { .. } => "transport error",
         }
     }
 
-    fn cause(&self) -> Option<&dyn std::error::Error> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::JsonError(err) => Some(err),
+            Self::Transport { source, .. } => Some(source.as_ref()),
             _ => None,
         }
     }
 }
 
+impl From<std::io::Error> for OpenProtocolError<'_> {
+    fn from(error: std::io::Error) -> Self {
+        Self::transport("I/O error", error)
+    }
+}
+
+impl From<WebSocketError> for OpenProtocolError<'_> {
+    fn from(error: WebSocketError) -> Self {
+        Self::transport("WebSocket error", error)
+    }
+}
+
 impl PartialEq for OpenProtocolError<'_> {
     /// Implement `PartialEq` for `OpenProtocolError`.
     ///
     /// Most variants already implement `PartialEq` and are simply delegated.
     ///
-    /// The only variant that doesn't automatically implement `PartialEq` is [`JsonError`]
+    /// The only variants that don't automatically implement `PartialEq` are [`JsonError`]
     /// which encapsulates a `serde::error::Error` object that does not implement
-    /// `PartialEq`.  In this case, we test for equality simply by comparing the `Debug`
-    /// output of `self` and `other`.
+    /// `PartialEq`, and [`Transport`] which encapsulates any error.  In these cases, we test
+    /// for equality simply by comparing the `Debug` output of `self` and `other`.
     ///
     /// [`JsonError`]: #variant.JsonError
+    /// [`Transport`]: #variant.Transport
     ///
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -117,6 +219,22 @@ impl PartialEq for OpenProtocolError<'_> {
             (Self::InconsistentState(err1), Self::InconsistentState(err2)) => err1 == err2,
             (Self::InconsistentField(err1), Self::InconsistentField(err2)) => err1 == err2,
             (Self::ConstraintViolated(err1), Self::ConstraintViolated(err2)) => err1 == err2,
+            (Self::QueueFull, Self::QueueFull) => true,
+            (
+                Self::MessageTooLarge { size: size1, limit: limit1 },
+                Self::MessageTooLarge { size: size2, limit: limit2 },
+            ) => size1 == size2 && limit1 == limit2,
+            (Self::Timeout(timeout1), Self::Timeout(timeout2)) => timeout1 == timeout2,
+            (Self::RateLimited(wait1), Self::RateLimited(wait2)) => wait1 == wait2,
+            (
+                Self::IncompatibleVersion { version: version1, min: min1, max: max1 },
+                Self::IncompatibleVersion { version: version2, min: min2, max: max2 },
+            ) => version1 == version2 && min1 == min2 && max1 == max2,
+            (Self::Lagged(count1), Self::Lagged(count2)) => count1 == count2,
+            (
+                Self::Transport { context: context1, source: err1 },
+                Self::Transport { context: context2, source: err2 },
+            ) => context1 == context2 && format!("{:?}", err1) == format!("{:?}", err2),
             _ => false,
         }
     }
@@ -129,3 +247,53 @@ impl std::convert::From<OpenProtocolError<'_>> for String {
         error.to_string()
     }
 }
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error as _;
+    use std::io::{Error as IoError, ErrorKind};
+
+    #[test]
+    fn test_error_transport_from_io_error() {
+        let err: OpenProtocolError = IoError::new(ErrorKind::BrokenPipe, "pipe closed").into();
+
+        assert_eq!("I/O error: pipe closed", err.to_string());
+        assert_eq!(
+            OpenProtocolError::transport(
+                "I/O error",
+                IoError::new(ErrorKind::BrokenPipe, "pipe closed")
+            ),
+            err
+        );
+        assert_ne!(
+            OpenProtocolError::transport(
+                "cannot connect",
+                IoError::new(ErrorKind::BrokenPipe, "pipe closed")
+            ),
+            err
+        );
+
+        let source = err.source().expect("source");
+        assert_eq!(ErrorKind::BrokenPipe, source.downcast_ref::<IoError>().unwrap().kind());
+    }
+
+    #[test]
+    fn test_error_transport_from_websocket_error() {
+        let err: OpenProtocolError = WebSocketError::ProtocolError("bad frame").into();
+
+        assert!(
+            matches!(&err, OpenProtocolError::Transport { context, .. } if context == "WebSocket error")
+        );
+        assert_eq!("WebSocket error: WebSocketError: WebSocket protocol error", err.to_string());
+        assert!(err.source().unwrap().is::<WebSocketError>());
+
+        // Other variants have no source, except JSON errors
+        assert!(OpenProtocolError::QueueFull.source().is_none());
+        assert!(OpenProtocolError::JsonError(serde_json::from_str::<u32>("x").unwrap_err())
+            .source()
+            .is_some());
+    }
+}
@@ -11,6 +11,14 @@ bitflags! {
     ///
     /// See [this document] for details.
     ///
+    /// `Filters` is a set of flags.  An empty set (i.e. `None`) subscribes to nothing
+    /// and is rejected in a `JOIN` message.
+    ///
+    /// `All` is a superset of the machine-related filters (`Status`, `Cycle`, `Mold`,
+    /// `Actions`, `Alarms` and `Audit`), so combining `All` with any of them is redundant
+    /// (but not an error).  The MIS/MES filters (`JobCards`, `Operators`) and `OPCUA` are
+    /// _not_ included in `All` and must be specified explicitly.
+    ///
     /// [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/doc/enums.md#filters
     ///
     pub struct Filters: u32 {
@@ -46,6 +54,19 @@ bitflags! {
 
 static ALL: &str = "Status | Cycle | Mold | Actions | Alarms | Audit | All";
 
+static VARIANTS: &[Filters] = &[
+    Filters::Status,
+    Filters::Cycle,
+    Filters::Mold,
+    Filters::Actions,
+    Filters::Alarms,
+    Filters::Audit,
+    Filters::All,
+    Filters::JobCards,
+    Filters::Operators,
+    Filters::OPCUA,
+];
+
 impl Filters {
     /// Is a particular set of filters set?
     ///
@@ -63,6 +84,53 @@ impl Filters {
     pub fn has(self, other: Self) -> bool {
         self.contains(other)
     }
+
+    /// Get all the individual filters that can be subscribed to, in declaration order.
+    ///
+    /// `None` is not included.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let all = Filters::all_variants();
+    /// assert_eq!(Filters::Status, all[0]);
+    /// assert!(all.contains(&Filters::All));
+    /// assert!(all.contains(&Filters::OPCUA));
+    /// assert!(!all.contains(&Filters::None));
+    /// ~~~
+    pub fn all_variants() -> &'static [Self] {
+        VARIANTS
+    }
+
+    /// Get a human-readable description of an individual filter.
+    ///
+    /// Returns `None` for `Filters::None` and for combinations of filters.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Some("Mold settings"), Filters::Mold.description());
+    /// assert_eq!(Some("All message types"), Filters::All.description());
+    /// assert_eq!(None, (Filters::Status + Filters::Cycle).description());
+    /// assert_eq!(None, Filters::None.description());
+    /// ~~~
+    pub fn description(self) -> Option<&'static str> {
+        Some(match self {
+            Filters::Status => "Controller status",
+            Filters::Cycle => "Cycle data",
+            Filters::Mold => "Mold settings",
+            Filters::Actions => "Current action",
+            Filters::Alarms => "Controller alarms",
+            Filters::Audit => "Audit trail of setting changes",
+            Filters::All => "All message types",
+            Filters::JobCards => "Job card-related messages",
+            Filters::Operators => "Operator-related messages",
+            Filters::OPCUA => "OPC UA communications",
+            _ => return None,
+        })
+    }
 }
 
 impl FromStr for Filters {
@@ -209,3 +277,31 @@ impl<'de> Deserialize<'de> for Filters {
         Filters::from_str(s).map_err(serde::de::Error::custom)
     }
 }
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_filters_all_variants() {
+        let variants = Filters::all_variants();
+
+        for (index, filter) in variants.iter().enumerate() {
+            assert!(!variants[..index].contains(filter), "duplicated {:?}", filter);
+
+            let description = filter.description().unwrap();
+            assert!(!description.trim().is_empty(), "{:?}", filter);
+
+            // Each variant round-trips through its name
+            assert_eq!(*filter, Filters::from_str(&filter.to_string()).unwrap());
+        }
+
+        // Every flag is covered
+        assert_eq!(
+            Filters::all(),
+            variants.iter().fold(Filters::None, |all, filter| all + *filter)
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use super::utils::*;
+use super::validate::OpValidate;
 use super::R32;
 use derive_more::*;
 use serde::{Deserialize, Serialize};
@@ -56,6 +57,43 @@ impl GeoLocation {
         self.geo_longitude.into()
     }
 
+    /// Mean radius of the Earth in kilometers, used by [`haversine_distance_km`].
+    ///
+    /// [`haversine_distance_km`]: struct.GeoLocation.html#method.haversine_distance_km
+    ///
+    pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+    /// Get the great-circle distance, in kilometers, to another `GeoLocation`.
+    ///
+    /// The distance is calculated with the haversine formula, assuming a spherical Earth
+    /// with a radius of [`EARTH_RADIUS_KM`], so it may be off by up to about 0.5%.
+    ///
+    /// [`EARTH_RADIUS_KM`]: struct.GeoLocation.html#associatedconstant.EARTH_RADIUS_KM
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let london = GeoLocation::new(51.5074, -0.1278)?;
+    /// let paris = GeoLocation::new(48.8566, 2.3522)?;
+    /// assert_eq!(344, london.haversine_distance_km(&paris).round() as i32);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn haversine_distance_km(&self, other: &GeoLocation) -> f64 {
+        let lat1 = f64::from(self.latitude()).to_radians();
+        let lat2 = f64::from(other.latitude()).to_radians();
+        let d_lat = lat2 - lat1;
+        let d_lng = (f64::from(other.longitude()) - f64::from(self.longitude())).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+
+        // Clamp to guard against rounding errors for antipodal points
+        2.0 * Self::EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+
     /// Create a new `GeoLocation`.
     ///
     /// # Errors
@@ -103,12 +141,207 @@ impl GeoLocation {
 
     // Check if the latitude/longitude pair is with constraints.
     fn check_constraints(latitude: f32, longitude: f32) -> Result<(), String> {
-        if !(-90.0..=90.0).contains(&latitude) {
-            Err(format!("invalid latitude: {} (must be between -90 and 90)", latitude))
-        } else if !(-180.0..=180.0).contains(&longitude) {
-            Err(format!("invalid longitude: {} (must be between -180 and 180)", longitude))
+        Position { latitude, longitude }.check_fields().map_err(|err| err.to_string())
+    }
+}
+
+// A latitude/longitude pair, checked before creating a `GeoLocation`.
+#[derive(OpValidate)]
+struct Position {
+    #[op(range(-90.0, 90.0))]
+    latitude: f32,
+    //
+    #[op(range(-180.0, 180.0))]
+    longitude: f32,
+}
+
+/// A rectangular geographic region bounded by two latitudes and two longitudes.
+///
+/// If `west` is larger than `east`, the region wraps across the antimeridian (i.e. ±180°
+/// longitude).
+///
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct GeoBounds {
+    /// Northern-most latitude
+    north: R32,
+    //
+    /// Southern-most latitude
+    south: R32,
+    //
+    /// Eastern-most longitude
+    east: R32,
+    //
+    /// Western-most longitude
+    west: R32,
+}
+
+impl GeoBounds {
+    /// Get the northern-most latitude.
+    pub fn north(self) -> f32 {
+        self.north.into()
+    }
+
+    /// Get the southern-most latitude.
+    pub fn south(self) -> f32 {
+        self.south.into()
+    }
+
+    /// Get the eastern-most longitude.
+    pub fn east(self) -> f32 {
+        self.east.into()
+    }
+
+    /// Get the western-most longitude.
+    pub fn west(self) -> f32 {
+        self.west.into()
+    }
+
+    /// Returns true if the region wraps across the antimeridian (i.e. `west` > `east`).
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// assert!(GeoBounds::new(10.0, -10.0, -170.0, 170.0)?.wraps_antimeridian());
+    /// assert!(!GeoBounds::new(10.0, -10.0, 170.0, -170.0)?.wraps_antimeridian());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn wraps_antimeridian(self) -> bool {
+        self.west > self.east
+    }
+
+    /// Create a new `GeoBounds`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if any of the coordinates is not a valid floating-point number,
+    /// is out of range, or if `north` is less than `south`.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err("NaN is not a supported value for east".into()),
+    ///     GeoBounds::new(10.0, -10.0, std::f32::NAN, 20.0)
+    /// );
+    ///
+    /// assert_eq!(
+    ///     Err("north (-10) cannot be less than south (10)".into()),
+    ///     GeoBounds::new(-10.0, 10.0, 20.0, -20.0)
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let bounds = GeoBounds::new(23.0, 22.0, 114.5, 113.5)?;
+    /// assert!(bounds.contains(&GeoLocation::new(22.3, 114.2)?));
+    /// assert!(!bounds.contains(&GeoLocation::new(31.2, 121.5)?));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn new(north: f32, south: f32, east: f32, west: f32) -> std::result::Result<Self, String> {
+        check_f32(north).map_err(|e| format!("{} for north", e))?;
+        check_f32(south).map_err(|e| format!("{} for south", e))?;
+        check_f32(east).map_err(|e| format!("{} for east", e))?;
+        check_f32(west).map_err(|e| format!("{} for west", e))?;
+
+        GeoLocation::check_constraints(north, east)?;
+        GeoLocation::check_constraints(south, west)?;
+
+        if north < south {
+            return Err(format!("north ({}) cannot be less than south ({})", north, south));
+        }
+
+        Ok(Self {
+            north: north.try_into().unwrap(),
+            south: south.try_into().unwrap(),
+            east: east.try_into().unwrap(),
+            west: west.try_into().unwrap(),
+        })
+    }
+
+    /// Compute the tightest `GeoBounds` enclosing a set of `GeoLocation`'s.
+    ///
+    /// The longitude range is chosen to be the narrowest possible, which may wrap across
+    /// the antimeridian.
+    ///
+    /// Returns `None` if `points` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let points = [GeoLocation::new(10.0, 175.0)?, GeoLocation::new(-5.0, -170.0)?];
+    /// let bounds = GeoBounds::from_points(&points).unwrap();
+    ///
+    /// assert_eq!(GeoBounds::new(10.0, -5.0, -170.0, 175.0)?, bounds);
+    /// assert!(bounds.wraps_antimeridian());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn from_points(points: &[GeoLocation]) -> Option<Self> {
+        let first = points.first()?;
+
+        let (north, south) =
+            points.iter().fold((first.geo_latitude, first.geo_latitude), |(n, s), p| {
+                (n.max(p.geo_latitude), s.min(p.geo_latitude))
+            });
+
+        let mut lons: Vec<_> = points.iter().map(|p| p.geo_longitude).collect();
+        lons.sort();
+        lons.dedup();
+
+        // The narrowest range is the complement of the largest gap between adjacent longitudes.
+        // Start with the gap across the antimeridian, which gives a non-wrapping range.
+        let (mut west, mut east) = (lons[0], lons[lons.len() - 1]);
+        let mut largest_gap = lons[0] + R32::new(360.0) - lons[lons.len() - 1];
+
+        for pair in lons.windows(2) {
+            let gap = pair[1] - pair[0];
+
+            if gap > largest_gap {
+                largest_gap = gap;
+                west = pair[1];
+                east = pair[0];
+            }
+        }
+
+        Some(Self { north, south, east, west })
+    }
+
+    /// Returns true if a `GeoLocation` lies within (or exactly on an edge of) this region.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let bounds = GeoBounds::new(10.0, -10.0, -170.0, 170.0)?;   // Wraps across ±180°
+    ///
+    /// assert!(bounds.contains(&GeoLocation::new(0.0, 180.0)?));
+    /// assert!(bounds.contains(&GeoLocation::new(0.0, -175.0)?));
+    /// assert!(!bounds.contains(&GeoLocation::new(0.0, 0.0)?));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn contains(&self, loc: &GeoLocation) -> bool {
+        let (lat, lon) = (loc.geo_latitude, loc.geo_longitude);
+
+        if lat < self.south || lat > self.north {
+            return false;
+        }
+
+        if self.wraps_antimeridian() {
+            lon >= self.west || lon <= self.east
         } else {
-            Ok(())
+            lon >= self.west && lon <= self.east
         }
     }
 }
@@ -117,7 +350,9 @@ impl GeoLocation {
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeoWrapper {
+    #[cfg_attr(feature = "legacy", serde(alias = "geo_latitude"))]
     pub geo_latitude: f32,
+    #[cfg_attr(feature = "legacy", serde(alias = "geo_longitude"))]
     pub geo_longitude: f32,
 }
 
@@ -134,3 +369,155 @@ impl From<GeoLocation> for GeoWrapper {
         Self { geo_latitude: value.latitude(), geo_longitude: value.longitude() }
     }
 }
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn loc(latitude: f32, longitude: f32) -> GeoLocation {
+        GeoLocation::new(latitude, longitude).unwrap()
+    }
+
+    #[test]
+    fn test_geo_bounds_contains() -> Result<(), String> {
+        let bounds = GeoBounds::new(40.0, 30.0, 120.0, 100.0)?;
+
+        assert!(bounds.contains(&loc(35.0, 110.0)));
+        assert!(!bounds.contains(&loc(45.0, 110.0)));
+        assert!(!bounds.contains(&loc(25.0, 110.0)));
+        assert!(!bounds.contains(&loc(35.0, 125.0)));
+        assert!(!bounds.contains(&loc(35.0, 95.0)));
+        assert!(!bounds.contains(&loc(35.0, -110.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_bounds_contains_antimeridian() -> Result<(), String> {
+        let bounds = GeoBounds::new(10.0, -10.0, -170.0, 170.0)?;
+        assert!(bounds.wraps_antimeridian());
+
+        assert!(bounds.contains(&loc(0.0, 175.0)));
+        assert!(bounds.contains(&loc(0.0, -175.0)));
+        assert!(bounds.contains(&loc(0.0, 180.0)));
+        assert!(bounds.contains(&loc(0.0, -180.0)));
+        assert!(!bounds.contains(&loc(0.0, 0.0)));
+        assert!(!bounds.contains(&loc(0.0, 160.0)));
+        assert!(!bounds.contains(&loc(0.0, -160.0)));
+        assert!(!bounds.contains(&loc(20.0, 175.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_bounds_contains_edges() -> Result<(), String> {
+        let bounds = GeoBounds::new(40.0, 30.0, 120.0, 100.0)?;
+
+        assert!(bounds.contains(&loc(40.0, 110.0)));
+        assert!(bounds.contains(&loc(30.0, 110.0)));
+        assert!(bounds.contains(&loc(35.0, 120.0)));
+        assert!(bounds.contains(&loc(35.0, 100.0)));
+        assert!(bounds.contains(&loc(40.0, 120.0)));
+
+        let wrapped = GeoBounds::new(10.0, -10.0, -170.0, 170.0)?;
+        assert!(wrapped.contains(&loc(10.0, 170.0)));
+        assert!(wrapped.contains(&loc(-10.0, -170.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_bounds_new_invalid() {
+        assert!(GeoBounds::new(f32::INFINITY, 0.0, 0.0, 0.0).is_err());
+        assert!(GeoBounds::new(91.0, 0.0, 0.0, 0.0).is_err());
+        assert!(GeoBounds::new(0.0, -91.0, 0.0, 0.0).is_err());
+        assert!(GeoBounds::new(0.0, 0.0, 181.0, 0.0).is_err());
+        assert!(GeoBounds::new(0.0, 0.0, 0.0, -181.0).is_err());
+        assert!(GeoBounds::new(-1.0, 1.0, 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_geo_bounds_from_points() -> Result<(), String> {
+        assert_eq!(None, GeoBounds::from_points(&[]));
+
+        assert_eq!(
+            Some(GeoBounds::new(12.0, 12.0, 34.0, 34.0)?),
+            GeoBounds::from_points(&[loc(12.0, 34.0)])
+        );
+
+        let points = [loc(22.3, 114.2), loc(31.2, 121.5), loc(39.9, 116.4)];
+        let bounds = GeoBounds::from_points(&points).unwrap();
+        assert_eq!(GeoBounds::new(39.9, 22.3, 121.5, 114.2)?, bounds);
+        assert!(points.iter().all(|p| bounds.contains(p)));
+
+        let points = [loc(-36.8, 174.8), loc(-18.1, 178.4), loc(-13.8, -171.8)];
+        let bounds = GeoBounds::from_points(&points).unwrap();
+        assert_eq!(GeoBounds::new(-13.8, -36.8, -171.8, 174.8)?, bounds);
+        assert!(bounds.wraps_antimeridian());
+        assert!(points.iter().all(|p| bounds.contains(p)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_location_haversine_distance_km() -> Result<(), String> {
+        fn distance(a: (f32, f32), b: (f32, f32)) -> Result<f64, String> {
+            let a = GeoLocation::new(a.0, a.1)?;
+            let b = GeoLocation::new(b.0, b.1)?;
+            let d = a.haversine_distance_km(&b);
+            assert!((d - b.haversine_distance_km(&a)).abs() < 1e-9);
+            Ok(d)
+        }
+
+        let cases = [
+            // London - Paris
+            ((51.5074, -0.1278), (48.8566, 2.3522), 343.556),
+            // New York - Los Angeles
+            ((40.7128, -74.0060), (34.0522, -118.2437), 3935.752),
+            // Hong Kong - Macau
+            ((22.3193, 114.1694), (22.1987, 113.5439), 65.752),
+            // Across the antimeridian
+            ((-16.5, 179.5), (-16.5, -179.5), 106.616),
+            // Pole to pole
+            ((90.0, 0.0), (-90.0, 0.0), 20015.114),
+            // Same point
+            ((22.3193, 114.1694), (22.3193, 114.1694), 0.0),
+        ];
+
+        for &(a, b, expected) in cases.iter() {
+            let d = distance(a, b)?;
+            assert!(
+                (d - expected).abs() < 0.001,
+                "{:?} - {:?}: expected {}, got {}",
+                a,
+                b,
+                expected,
+                d
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_geo_location_within_radius_and_bounds() -> Result<(), String> {
+        let site = GeoLocation::new(22.3193, 114.1694)?;
+        let machines = [
+            GeoLocation::new(22.3964, 114.1095)?, // ~10km
+            GeoLocation::new(22.5431, 114.0579)?, // ~27km
+            GeoLocation::new(23.1291, 113.2644)?, // ~130km
+        ];
+
+        let nearby: Vec<_> =
+            machines.iter().filter(|m| site.haversine_distance_km(m) <= 50.0).collect();
+        assert_eq!(vec![&machines[0], &machines[1]], nearby);
+
+        let bounds = GeoBounds::new(22.5, 22.0, 114.5, 113.8)?;
+        let inside: Vec<_> = machines.iter().filter(|m| bounds.contains(m)).collect();
+        assert_eq!(vec![&machines[0]], inside);
+
+        Ok(())
+    }
+}
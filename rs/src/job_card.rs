@@ -1,18 +1,36 @@
-use super::TextName;
+use super::{Error, Result, TextName};
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 
 /// A data structure containing information on a production job (i.e. a *job card*).
 ///
+/// In JSON, the fields are named `jobCardId`, `moldId`, `progress` and `total`.
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let jc = JobCard::try_new("J001", "Mold#001", 100, 1000)?;
+///
+/// assert_eq!(
+///     r#"{"jobCardId":"J001","moldId":"Mold#001","progress":100,"total":1000}"#,
+///     serde_json::to_string(&jc).map_err(|x| x.to_string())?
+/// );
+/// # Ok(())
+/// # }
+/// ~~~
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JobCard<'a> {
     /// Unique job ID, which must not be empty or all white-spaces.
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "job_card_id"))]
     job_card_id: TextName<'a>,
     //
     /// ID of the set of mold data to load for this job.
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "mold_id"))]
     mold_id: TextName<'a>,
     //
     /// Current production progress, which must not be larger than `total`.
@@ -39,6 +57,11 @@ impl<'a> JobCard<'a> {
         self.job_card_id.as_ref()
     }
 
+    // Get the job ID as a `TextName`.
+    pub(crate) fn job_card_id_ref(&self) -> &TextName<'a> {
+        &self.job_card_id
+    }
+
     /// Get the mold ID.
     ///
     /// # Examples
@@ -87,6 +110,149 @@ impl<'a> JobCard<'a> {
         self.total
     }
 
+    /// Get the production count remaining (i.e. `total` minus `progress`).
+    ///
+    /// This is zero if `progress` is larger than `total` (which is possible for a `JobCard`
+    /// received from the server).
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let jc = JobCard::try_new("J001", "Mold#001", 100, 1000)?;
+    /// assert_eq!(900, jc.remaining());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn remaining(&self) -> u32 {
+        self.total.saturating_sub(self.progress)
+    }
+
+    /// Get the production progress as a percentage (between 0.0 and 100.0) of `total`.
+    ///
+    /// Returns `None` if `total` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let jc = JobCard::try_new("J001", "Mold#001", 100, 1000)?;
+    /// assert_eq!(Some(10.0), jc.percent_complete());
+    ///
+    /// let jc = JobCard::try_new("J002", "Mold#002", 0, 0)?;
+    /// assert_eq!(None, jc.percent_complete());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn percent_complete(&self) -> Option<f64> {
+        match self.total {
+            0 => None,
+            total => Some((f64::from(self.progress) / f64::from(total) * 100.0).min(100.0)),
+        }
+    }
+
+    /// Get the estimated time needed to finish the job at a production rate (in number
+    /// of items per hour).
+    ///
+    /// Returns `None` if `rate_per_hour` is not a positive number, or if the time is too
+    /// long to be represented.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let jc = JobCard::try_new("J001", "Mold#001", 100, 1000)?;
+    /// assert_eq!(Some(chrono::Duration::minutes(90)), jc.eta(600.0));
+    /// assert_eq!(None, jc.eta(0.0));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn eta(&self, rate_per_hour: f64) -> Option<chrono::Duration> {
+        // Also rejects NaN
+        if rate_per_hour.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+            return None;
+        }
+
+        let millis = (f64::from(self.remaining()) / rate_per_hour * 3_600_000.0).round();
+
+        if millis.is_finite() && millis < i64::MAX as f64 {
+            chrono::Duration::try_milliseconds(millis as i64)
+        } else {
+            None
+        }
+    }
+
+    /// Parse a JSON string into a `JobCard`, and [`check`] it immediately.
+    ///
+    /// [`check`]: struct.JobCard.html#method.check
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::JsonError`]`)` if there is an error during parsing
+    /// (including an empty job card ID or mold ID).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if the `JobCard` fails
+    /// [`check`].
+    ///
+    /// [`OpenProtocolError::JsonError`]: enum.OpenProtocolError.html#variant.JsonError
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let json = r#"{"jobCardId":"J001","moldId":"Mold#001","progress":2000,"total":1000}"#;
+    ///
+    /// assert_eq!(
+    ///     Err(Error::ConstraintViolated("progress cannot be larger than total.".into())),
+    ///     JobCard::parse_from_json_str(json)
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"jobCardId":"J001","moldId":"Mold#001","progress":100,"total":1000}"#;
+    /// let jc = JobCard::parse_from_json_str(json)?;
+    ///
+    /// assert_eq!(JobCard::try_new("J001", "Mold#001", 100, 1000)?, jc);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn parse_from_json_str(json: &'a str) -> Result<'a, Self> {
+        let jc: Self = serde_json::from_str(json).map_err(Error::JsonError)?;
+        jc.check()?;
+        Ok(jc)
+    }
+
+    /// Check the `JobCard` data structure for consistency.
+    ///
+    /// `progress` must not be larger than `total`.
+    ///
+    /// This is not checked when parsing messages, as an iChen® Server may send a `JobCard`
+    /// whose production has gone past the total ordered (see [`remaining`]).
+    ///
+    /// [`remaining`]: struct.JobCard.html#method.remaining
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if `progress` is larger
+    /// than `total`.
+    ///
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    ///
+    pub fn check(&self) -> Result<'static, ()> {
+        if self.progress > self.total {
+            return Err(Error::ConstraintViolated("progress cannot be larger than total.".into()));
+        }
+        Ok(())
+    }
+
     /// Create a new `JobCard` with the specified field values.
     ///
     /// # Errors
@@ -149,3 +315,112 @@ impl<'a> JobCard<'a> {
         })
     }
 }
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+    use std::result::Result;
+
+    #[test]
+    fn test_job_card_zero_total() -> Result<(), String> {
+        let jc = JobCard::try_new("J001", "Mold#001", 0, 0)?;
+
+        assert_eq!(0, jc.remaining());
+        assert_eq!(None, jc.percent_complete());
+        assert_eq!(Some(Duration::zero()), jc.eta(100.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_job_card_completed() -> Result<(), String> {
+        let jc = JobCard::try_new("J001", "Mold#001", 5000, 5000)?;
+
+        assert_eq!(0, jc.remaining());
+        assert_eq!(Some(100.0), jc.percent_complete());
+        assert_eq!(Some(Duration::zero()), jc.eta(100.0));
+
+        // Progress beyond the total (e.g. from the server) is capped
+        let jc: JobCard = serde_json::from_str(
+            r#"{"jobCardId":"J001","moldId":"Mold#001","progress":6000,"total":5000}"#,
+        )
+        .map_err(|x| x.to_string())?;
+
+        assert_eq!(0, jc.remaining());
+        assert_eq!(Some(100.0), jc.percent_complete());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_job_card_in_progress() -> Result<(), String> {
+        let jc = JobCard::try_new("J001", "Mold#001", 2500, 10000)?;
+
+        assert_eq!(7500, jc.remaining());
+        assert_eq!(Some(25.0), jc.percent_complete());
+        assert_eq!(Some(Duration::hours(5)), jc.eta(1500.0));
+        assert_eq!(Some(Duration::milliseconds(3_333)), jc.eta(8_100_000.0));
+
+        // Invalid rates
+        assert_eq!(None, jc.eta(0.0));
+        assert_eq!(None, jc.eta(-10.0));
+        assert_eq!(None, jc.eta(f64::NAN));
+
+        // Too long
+        assert_eq!(None, jc.eta(f64::MIN_POSITIVE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_job_card_json_round_trip() -> Result<(), String> {
+        let jc = JobCard::try_new("J001", "Mold#001", 2500, 10000)?;
+        let json = serde_json::to_string(&jc).map_err(|x| x.to_string())?;
+
+        assert_eq!(
+            r#"{"jobCardId":"J001","moldId":"Mold#001","progress":2500,"total":10000}"#,
+            json
+        );
+        assert_eq!(jc, JobCard::parse_from_json_str(&json)?);
+
+        // Completed job
+        let jc = JobCard::try_new("J002", "Mold#002", 5000, 5000)?;
+        let json = serde_json::to_string(&jc).map_err(|x| x.to_string())?;
+        assert_eq!(jc, JobCard::parse_from_json_str(&json)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_job_card_parse_checked_rejects_invalid() -> Result<(), String> {
+        let json = r#"{"jobCardId":"J001","moldId":"Mold#001","progress":6000,"total":5000}"#;
+
+        // Accepted without checking
+        let jc: JobCard = serde_json::from_str(json).map_err(|x| x.to_string())?;
+        assert_eq!(
+            Err(Error::ConstraintViolated("progress cannot be larger than total.".into())),
+            jc.check()
+        );
+
+        assert_eq!(
+            Err(Error::ConstraintViolated("progress cannot be larger than total.".into())),
+            JobCard::parse_from_json_str(json)
+        );
+
+        for json in &[
+            r#"{"jobCardId":"","moldId":"Mold#001","progress":0,"total":5000}"#,
+            r#"{"jobCardId":"J001","moldId":"   ","progress":0,"total":5000}"#,
+            r#"{"jobCardId":"J001","progress":0,"total":5000}"#,
+        ] {
+            match JobCard::parse_from_json_str(json) {
+                Err(Error::JsonError(_)) => (),
+                result => return Err(format!("expected JsonError for {}, got {:?}", json, result)),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use super::{Clock, Message, SystemClock};
+use std::time::{Duration, Instant};
+
+/// Configuration for a [`Keepalive`].
+///
+/// [`Keepalive`]: struct.Keepalive.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct KeepaliveConfig {
+    /// Interval between `ALIVE` messages sent to the server.
+    pub interval: Duration,
+    //
+    /// Time without any traffic from the server after which the link is considered dead.
+    pub timeout: Duration,
+}
+
+impl KeepaliveConfig {
+    /// Default interval between `ALIVE` messages: 10 seconds.
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+    /// Default link timeout: 30 seconds.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self { interval: Self::DEFAULT_INTERVAL, timeout: Self::DEFAULT_TIMEOUT }
+    }
+}
+
+/// An event raised by a [`Keepalive`].
+///
+/// [`Keepalive`]: struct.Keepalive.html
+///
+#[derive(Debug, Clone)]
+pub enum KeepaliveEvent {
+    /// An `ALIVE` message should be sent to the server.
+    SendAlive(Message<'static>),
+    //
+    /// No traffic has arrived from the server within the timeout.
+    LinkDead,
+}
+
+/// A component that keeps a link to the iChen® Server alive.
+///
+/// `Keepalive` does not perform any I/O itself.  Call [`received`] whenever any message
+/// arrives from the server, and call [`poll`] regularly (for example, after each receive
+/// or read timeout) to find out whether an `ALIVE` message should be sent or whether the
+/// link is dead.
+///
+/// The current time is taken from a [`Clock`] (the [`SystemClock`] by default; see
+/// [`with_clock`]).  Each method also has an `_at` variant taking the current time.
+///
+/// [`received`]: struct.Keepalive.html#method.received
+/// [`poll`]: struct.Keepalive.html#method.poll
+/// [`Clock`]: trait.Clock.html
+/// [`SystemClock`]: struct.SystemClock.html
+/// [`with_clock`]: struct.Keepalive.html#method.with_clock
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use std::time::{Duration, Instant};
+/// let config = KeepaliveConfig { interval: Duration::from_secs(5), timeout: Duration::from_secs(15) };
+/// let start = Instant::now();
+/// let mut keepalive = Keepalive::new_at(config, start);
+///
+/// assert!(keepalive.poll_at(start).is_none());
+///
+/// match keepalive.poll_at(start + Duration::from_secs(5)) {
+///     Some(KeepaliveEvent::SendAlive(msg)) => { /* send `msg` to the server */ }
+///     event => panic!("unexpected event: {:?}", event),
+/// }
+///
+/// match keepalive.poll_at(start + Duration::from_secs(16)) {
+///     Some(KeepaliveEvent::LinkDead) => { /* reconnect */ }
+///     event => panic!("unexpected event: {:?}", event),
+/// }
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct Keepalive<C: Clock = SystemClock> {
+    config: KeepaliveConfig,
+    clock: C,
+    last_sent: Instant,
+    last_received: Instant,
+}
+
+impl Keepalive {
+    /// Create a new `Keepalive` for a link that has just been established.
+    pub fn new(config: KeepaliveConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+
+    /// Create a new `Keepalive` for a link that was established at a particular time.
+    pub fn new_at(config: KeepaliveConfig, now: Instant) -> Self {
+        Self { config, clock: SystemClock, last_sent: now, last_received: now }
+    }
+}
+
+impl<C: Clock> Keepalive<C> {
+    /// Create a new `Keepalive`, taking the current time from a [`Clock`], for a link that
+    /// has just been established.
+    ///
+    /// [`Clock`]: trait.Clock.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::time::{Duration, Instant};
+    /// // A clock that is always one minute ahead
+    /// struct FastClock;
+    ///
+    /// impl Clock for FastClock {
+    ///     fn now(&self) -> Instant {
+    ///         Instant::now() + Duration::from_secs(60)
+    ///     }
+    /// }
+    ///
+    /// let keepalive = Keepalive::with_clock(KeepaliveConfig::default(), FastClock);
+    /// assert!(keepalive.next_deadline() > Instant::now() + Duration::from_secs(60));
+    /// ~~~
+    pub fn with_clock(config: KeepaliveConfig, clock: C) -> Self {
+        let now = clock.now();
+        Self { config, clock, last_sent: now, last_received: now }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> KeepaliveConfig {
+        self.config
+    }
+
+    /// Record that a message has just arrived from the server.
+    pub fn received(&mut self) {
+        self.received_at(self.clock.now())
+    }
+
+    /// Record that a message arrived from the server at a particular time.
+    pub fn received_at(&mut self, now: Instant) {
+        self.last_received = self.last_received.max(now);
+    }
+
+    /// Returns true if no traffic has arrived from the server within the timeout.
+    pub fn is_dead(&self) -> bool {
+        self.is_dead_at(self.clock.now())
+    }
+
+    /// Returns true if no traffic has arrived from the server within the timeout, at a
+    /// particular time.
+    pub fn is_dead_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_received) > self.config.timeout
+    }
+
+    /// Get the time at which [`poll`] should next be called.
+    ///
+    /// [`poll`]: struct.Keepalive.html#method.poll
+    ///
+    pub fn next_deadline(&self) -> Instant {
+        (self.last_sent + self.config.interval).min(self.last_received + self.config.timeout)
+    }
+
+    /// Check whether an `ALIVE` message should be sent or the link is dead.
+    ///
+    /// Returns `None` if there is nothing to do.
+    pub fn poll(&mut self) -> Option<KeepaliveEvent> {
+        self.poll_at(self.clock.now())
+    }
+
+    /// Check, at a particular time, whether an `ALIVE` message should be sent or the link
+    /// is dead.
+    ///
+    /// `LinkDead` is returned on every call until traffic arrives from the server again.
+    ///
+    /// Returns `None` if there is nothing to do.
+    pub fn poll_at(&mut self, now: Instant) -> Option<KeepaliveEvent> {
+        if self.is_dead_at(now) {
+            return Some(KeepaliveEvent::LinkDead);
+        }
+
+        if now.saturating_duration_since(self.last_sent) >= self.config.interval {
+            self.last_sent = now;
+            return Some(KeepaliveEvent::SendAlive(Message::new_alive()));
+        }
+
+        None
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockClock;
+
+    const CONFIG: KeepaliveConfig =
+        KeepaliveConfig { interval: Duration::from_secs(5), timeout: Duration::from_secs(12) };
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_keepalive_sends_alive_on_interval() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new_at(CONFIG, start);
+
+        assert!(keepalive.poll_at(start).is_none());
+        assert!(keepalive.poll_at(start + secs(4)).is_none());
+
+        match keepalive.poll_at(start + secs(5)) {
+            Some(KeepaliveEvent::SendAlive(Message::Alive { .. })) => (),
+            event => panic!("expected SendAlive, got {:?}", event),
+        }
+
+        // Not again until the next interval
+        assert!(keepalive.poll_at(start + secs(6)).is_none());
+        assert_eq!(start + secs(10), keepalive.next_deadline());
+
+        keepalive.received_at(start + secs(9));
+
+        match keepalive.poll_at(start + secs(10)) {
+            Some(KeepaliveEvent::SendAlive(_)) => (),
+            event => panic!("expected SendAlive, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_keepalive_link_dead_on_timeout() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new_at(CONFIG, start);
+
+        keepalive.poll_at(start + secs(5));
+        keepalive.poll_at(start + secs(10));
+        assert_eq!(start + secs(12), keepalive.next_deadline());
+
+        assert!(!keepalive.is_dead_at(start + secs(12)));
+
+        match keepalive.poll_at(start + secs(13)) {
+            Some(KeepaliveEvent::LinkDead) => (),
+            event => panic!("expected LinkDead, got {:?}", event),
+        }
+
+        // Still dead until traffic arrives
+        match keepalive.poll_at(start + secs(14)) {
+            Some(KeepaliveEvent::LinkDead) => (),
+            event => panic!("expected LinkDead, got {:?}", event),
+        }
+
+        keepalive.received_at(start + secs(14));
+        assert!(!keepalive.is_dead_at(start + secs(14)));
+
+        match keepalive.poll_at(start + secs(15)) {
+            Some(KeepaliveEvent::SendAlive(_)) => (),
+            event => panic!("expected SendAlive, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_keepalive_received_keeps_link_alive() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new_at(CONFIG, start);
+
+        for n in 1..=10 {
+            keepalive.received_at(start + secs(n * 10));
+            assert!(!keepalive.is_dead_at(start + secs(n * 10 + 11)));
+        }
+
+        // Out-of-order timestamps do not move the last received time backwards
+        keepalive.received_at(start);
+        assert!(!keepalive.is_dead_at(start + secs(111)));
+    }
+
+    #[test]
+    fn test_keepalive_with_mock_clock() {
+        let clock = MockClock::new();
+        let mut keepalive = Keepalive::with_clock(CONFIG, clock.clone());
+
+        assert!(keepalive.poll().is_none());
+
+        clock.advance(secs(4));
+        assert!(keepalive.poll().is_none());
+
+        clock.advance(secs(1));
+        match keepalive.poll() {
+            Some(KeepaliveEvent::SendAlive(Message::Alive { .. })) => (),
+            event => panic!("expected SendAlive, got {:?}", event),
+        }
+        assert!(keepalive.poll().is_none());
+
+        clock.advance(secs(5));
+        keepalive.received();
+
+        match keepalive.poll() {
+            Some(KeepaliveEvent::SendAlive(_)) => (),
+            event => panic!("expected SendAlive, got {:?}", event),
+        }
+
+        // The server goes silent
+        clock.advance(secs(12));
+        assert!(!keepalive.is_dead());
+
+        clock.advance(secs(1));
+        assert!(keepalive.is_dead());
+
+        match keepalive.poll() {
+            Some(KeepaliveEvent::LinkDead) => (),
+            event => panic!("expected LinkDead, got {:?}", event),
+        }
+    }
+}
@@ -16,13 +16,76 @@
 //! especially when deserializing -- the message struct cannot out-live the original JSON text string as
 //! fields are borrowed extensively from the original JSON string.
 //!
-//! Another implication due to extensive usage of borrowed string slices is that string literals with
-//! escape sequences will cause parsing errors because the actual string cannot be simply borrowed from
-//! the original JSON string.  Luckily this is extremely rare for most fields holding names, ID's etc.
-//! For this reason, only certain user-defined text fields (such as `job_card_id`) may contain
-//! escaped characters (especially the double-quote); those are therefore modeled using `Cow<&str>` instead.
+//! Text fields (including [`TextID`] and [`TextName`]) are modeled using `Cow<str>`.  A JSON string
+//! with escape sequences cannot simply be borrowed from the original JSON string, so its unescaped
+//! text is copied instead.  Luckily this is extremely rare for most fields holding names, ID's etc.
+//! To keep data beyond the lifetime of the JSON text, convert it into an owned copy (e.g. with
+//! [`Controller::into_owned`]).
+//!
+//! In the common case where there are no escape sequences, all text fields borrow from
+//! the original JSON string, so parsing does not copy any text.  Allocations still happen for:
+//!
+//! * text fields whose JSON strings contain escape sequences -- the unescaped text must be built
+//!   in a new `String`,
+//! * maps (e.g. `variables`, `lastCycleData` and the `data` of a `ControllersList`) and boxed fields,
+//! * the internal buffering that `serde` needs to handle the `$type` tag and flattened fields.
+//!
+//! The number of allocations is therefore independent of the length of the text fields.
+//! Run `cargo bench` to measure parsing performance.
+//!
+//! Features
+//! ========
+//!
+//! * `legacy` - Accept legacy `snake_case` field names (e.g. `controller_id`) in addition to
+//!   the standard `camelCase` field names (e.g. `controllerId`) when deserializing messages
+//!   sent by older iChen® Servers.  Messages are always serialized with `camelCase` field names.
+//!
+//! * `lowercase-enums` - Serialize [`OpMode`], [`JobMode`] and [`Language`] values in lowercase
+//!   (e.g. `automatic`, `id02`, `en`) instead of the standard names (e.g. `Automatic`, `ID02`,
+//!   `EN`), for servers that expect them.  The standard names are still accepted when
+//!   deserializing.  This changes the wire format of all messages containing these values,
+//!   so only enable it when the other end expects lowercase.
+//!
+//! * `epoch-millis` - Serialize [`Controller::last_connection_time`] as a number of milliseconds
+//!   since the Unix epoch (e.g. `1457277087144`) instead of an RFC3339 string.  RFC3339 strings
+//!   are still accepted when deserializing.  Time-stamps deserialized from epoch milliseconds
+//!   are in UTC.
+//!
+//! * `binary` - Encode messages into a compact binary format ([MessagePack](https://msgpack.org))
+//!   via [`Message::to_bytes`] and [`Message::from_bytes`], for transporting messages
+//!   internally (e.g. through a message broker).  JSON remains the format of the Open Protocol™.
+//!
+//! * `forward-compat` - Deserialize unrecognized [`OpMode`] and [`JobMode`] values (e.g. new
+//!   modes introduced by a newer iChen® Server) into an `Unrecognized` variant instead of
+//!   failing, so that a single new value does not fail an entire message (such as a
+//!   `ControllersList`).  The original text of the value is not kept, so an `Unrecognized`
+//!   value cannot be serialized, and all unrecognized values compare equal.  Read the
+//!   message's JSON text directly if the original value is needed.
+//!
+//! * `proptest` - Implement [`proptest::arbitrary::Arbitrary`](https://docs.rs/proptest) for
+//!   [`Message`], [`Controller`] and the types they contain, generating values that are
+//!   always valid.
+//!
+//! * `test-util` - Test utilities (e.g. a scripted mock server) in the [`testing`] module.
+//!
+//! * `tracing` - Emit diagnostic events via the [`tracing`](https://docs.rs/tracing) crate:
+//!   a span per [`BlockingConnection`], `DEBUG` events for each inbound and outbound message,
+//!   and `WARN` events for messages that fail to parse.
 //!
 //! [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/cs/doc/messages_reference.md
+//! [`testing`]: testing/index.html
+//! [`BlockingConnection`]: struct.BlockingConnection.html
+//! [`Message`]: enum.Message.html
+//! [`Message::to_bytes`]: enum.Message.html#method.to_bytes
+//! [`Message::from_bytes`]: enum.Message.html#method.from_bytes
+//! [`Controller`]: struct.Controller.html
+//! [`Controller::into_owned`]: struct.Controller.html#method.into_owned
+//! [`TextID`]: type.TextID.html
+//! [`TextName`]: type.TextName.html
+//! [`Controller::last_connection_time`]: struct.Controller.html#structfield.last_connection_time
+//! [`OpMode`]: enum.OpMode.html
+//! [`JobMode`]: enum.JobMode.html
+//! [`Language`]: enum.Language.html
 //!
 
 #![doc(html_logo_url = "https://chenhsong.github.io/iChen/images/ichen_40_logo_small.png")]
@@ -30,18 +93,39 @@
 
 // Modules
 mod address;
+#[cfg(feature = "proptest")]
+mod arbitrary;
+mod broadcast;
+mod clock;
+mod connection;
 mod controller;
+mod deflate;
 mod error;
 mod filters;
 mod geo_location;
 mod job_card;
+mod keepalive;
 mod key_value_pair;
+mod message_kind;
 mod messages;
+mod mis;
+mod mold_data;
 mod operator;
+mod password;
+mod pending;
+mod protocol;
+mod rate_limiter;
 mod state_values;
+mod store;
+mod stream;
 mod text;
+mod timestamped;
 mod types;
 mod utils;
+mod validate;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
 
 /// Result type.
 pub type Result<'a, T> = std::result::Result<T, Error<'a>>;
@@ -52,16 +136,38 @@ pub type Error<'a> = OpenProtocolError<'a>;
 /// 32-bit real floating-point number.
 pub use noisy_float::types::R32;
 
+/// A parsed URL (see [`validate_ws_url`]).
+///
+/// [`validate_ws_url`]: fn.validate_ws_url.html
+pub use websocket::url::Url;
+
 // Re-exports
-pub use address::Address;
-pub use controller::Controller;
-pub use error::OpenProtocolError;
+pub use address::{validate_address, Address};
+pub use broadcast::{LagPolicy, Subscription, SubscriptionConfig};
+pub use clock::{Clock, SystemClock};
+pub use connection::{
+    validate_ws_url, BlockingConnection, ConnectionConfig, ConnectionState, QueueConfig,
+    QueueFullPolicy,
+};
+pub use controller::{detect_login_transition, Controller, LoginTransition, NestedGeoController};
+pub use error::{OpenProtocolError, TransportError};
 pub use filters::Filters;
-pub use geo_location::GeoLocation;
+pub use geo_location::{GeoBounds, GeoLocation};
 pub use job_card::JobCard;
+pub use keepalive::{Keepalive, KeepaliveConfig, KeepaliveEvent};
 pub use key_value_pair::KeyValuePair;
+pub use message_kind::{Direction, MessageKind};
 pub use messages::*;
+pub use mis::{respond_to_mis_request, JobProvider, UserDirectory};
+pub use mold_data::resolve_mold_data_path;
 pub use operator::Operator;
+pub use password::Password;
+pub use pending::{ActionOutcome, Correlator, PendingActions};
+pub use protocol::Protocol;
+pub use rate_limiter::{RateLimitConfig, RateLimiter};
 pub use state_values::StateValues;
+pub use store::ControllerStore;
+pub use stream::{ControllerStream, JsonLines};
 pub use text::{TextID, TextName};
-pub use types::{ActionID, JobMode, Language, OpMode, ID};
+pub use timestamped::{MessageStamp, TimestampedMessage};
+pub use types::{AccessLevel, ActionID, JobMode, JoinResult, Language, OpMode, ID};
@@ -0,0 +1,356 @@
+use derive_more::*;
+
+/// Direction in which a type of message is sent.
+///
+#[derive(Debug, Display, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Direction {
+    /// Sent by the client to the iChen® Server.
+    ClientToServer,
+    /// Sent by the iChen® Server to the client.
+    ServerToClient,
+    /// Sent by both the client and the iChen® Server.
+    Both,
+}
+
+/// The type of an Open Protocol message, without any data.
+///
+/// Each variant matches the variant of the same name in [`Message`].
+/// The `Display` text of each variant is the value of the `$type` field for that message type
+/// on the wire.
+///
+/// [`Message`]: enum.Message.html
+///
+#[derive(Debug, Display, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum MessageKind {
+    /// The `ALIVE` message.
+    Alive,
+    /// The `CNTRLER_ACTION` message.
+    ControllerAction,
+    /// The `CNTRLER_ACTION_RESULT` message.
+    ActionResult,
+    /// The `REQ_CNTRLER_LIST` message.
+    RequestControllersList,
+    /// The `RESP_CNTRLER_LIST` message.
+    ControllersList,
+    /// The `UPD_CNTRLER` message.
+    ControllerStatus,
+    /// The `CYCLE_DATA` message.
+    CycleData,
+    /// The `REQ_JOBCARDS_LIST` message.
+    RequestJobCardsList,
+    /// The `RESP_JOBSLIST` message.
+    JobCardsList,
+    /// The `JOIN` message.
+    Join,
+    /// The `RESP_JOIN` message.
+    JoinResponse,
+    /// The `REQ_MOLD` message.
+    RequestMoldData,
+    /// The `RESP_MOLD` message.
+    MoldData,
+    /// The `READ_MOLD_DATA` message.
+    ReadMoldData,
+    /// The `RESP_MOLD_DATA_VALUE` message.
+    MoldDataValue,
+    /// The `REQ_PWD_LEVEL` message.
+    LoginOperator,
+    /// The `RESP_PWD_LEVEL` message.
+    OperatorInfo,
+}
+
+static VARIANTS: &[MessageKind] = &[
+    MessageKind::Alive,
+    MessageKind::ControllerAction,
+    MessageKind::ActionResult,
+    MessageKind::RequestControllersList,
+    MessageKind::ControllersList,
+    MessageKind::ControllerStatus,
+    MessageKind::CycleData,
+    MessageKind::RequestJobCardsList,
+    MessageKind::JobCardsList,
+    MessageKind::Join,
+    MessageKind::JoinResponse,
+    MessageKind::RequestMoldData,
+    MessageKind::MoldData,
+    MessageKind::ReadMoldData,
+    MessageKind::MoldDataValue,
+    MessageKind::LoginOperator,
+    MessageKind::OperatorInfo,
+];
+
+impl MessageKind {
+    /// Get a list of all message types, in the order of their declaration.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let all = MessageKind::all_variants();
+    /// assert_eq!(MessageKind::Alive, all[0]);
+    /// assert!(all.contains(&MessageKind::JoinResponse));
+    /// ~~~
+    pub fn all_variants() -> &'static [Self] {
+        VARIANTS
+    }
+
+    /// Get the value of the `$type` field for this type of message on the wire.
+    ///
+    /// This is the same as the `Display` text, but without any allocation, so it is suitable
+    /// as a key for per-type metrics.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!("Alive", MessageKind::Alive.as_str());
+    /// assert_eq!("RequestJobCardsList", MessageKind::RequestJobCardsList.as_str());
+    /// assert_eq!(MessageKind::JoinResponse.to_string(), MessageKind::JoinResponse.as_str());
+    /// ~~~
+    pub fn as_str(self) -> &'static str {
+        use MessageKind::*;
+
+        match self {
+            Alive => "Alive",
+            ControllerAction => "ControllerAction",
+            ActionResult => "ActionResult",
+            RequestControllersList => "RequestControllersList",
+            ControllersList => "ControllersList",
+            ControllerStatus => "ControllerStatus",
+            CycleData => "CycleData",
+            RequestJobCardsList => "RequestJobCardsList",
+            JobCardsList => "JobCardsList",
+            Join => "Join",
+            JoinResponse => "JoinResponse",
+            RequestMoldData => "RequestMoldData",
+            MoldData => "MoldData",
+            ReadMoldData => "ReadMoldData",
+            MoldDataValue => "MoldDataValue",
+            LoginOperator => "LoginOperator",
+            OperatorInfo => "OperatorInfo",
+        }
+    }
+
+    /// Get the direction in which this type of message is sent.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Direction::Both, MessageKind::Alive.direction());
+    /// assert_eq!(Direction::ClientToServer, MessageKind::Join.direction());
+    /// assert_eq!(Direction::ServerToClient, MessageKind::JoinResponse.direction());
+    ///
+    /// // The server asks the client to authenticate operators on behalf of controllers
+    /// assert_eq!(Direction::ServerToClient, MessageKind::LoginOperator.direction());
+    /// assert_eq!(Direction::ClientToServer, MessageKind::OperatorInfo.direction());
+    ///
+    /// // Actions are reported by the server, or requested by the client and acknowledged
+    /// assert_eq!(Direction::Both, MessageKind::ControllerAction.direction());
+    /// assert_eq!(Direction::ServerToClient, MessageKind::ActionResult.direction());
+    /// ~~~
+    pub fn direction(self) -> Direction {
+        use MessageKind::*;
+
+        match self {
+            Alive | ControllerAction => Direction::Both,
+            //
+            RequestControllersList
+            | JobCardsList
+            | Join
+            | RequestMoldData
+            | ReadMoldData
+            | OperatorInfo => Direction::ClientToServer,
+            //
+            ActionResult | ControllersList | ControllerStatus | CycleData | RequestJobCardsList
+            | JoinResponse | MoldData | MoldDataValue | LoginOperator => Direction::ServerToClient,
+        }
+    }
+
+    /// Returns true if this type of message is a request that expects a reply.
+    ///
+    /// `Alive` is not considered a request (nor a response) as it is part of the keep-alive
+    /// mechanism.  Notifications such as `ControllerStatus` and `CycleData` are neither
+    /// requests nor responses.
+    ///
+    /// See [`Message::expected_response_kind`] for the type of the reply.
+    ///
+    /// [`Message::expected_response_kind`]: enum.Message.html#method.expected_response_kind
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(MessageKind::RequestJobCardsList.is_request());
+    /// assert!(MessageKind::LoginOperator.is_request());
+    /// assert!(MessageKind::ControllerAction.is_request());
+    /// assert!(!MessageKind::JobCardsList.is_request());
+    /// assert!(!MessageKind::Alive.is_request());
+    /// ~~~
+    pub fn is_request(self) -> bool {
+        use MessageKind::*;
+
+        matches!(
+            self,
+            ControllerAction
+                | Join
+                | RequestControllersList
+                | RequestJobCardsList
+                | RequestMoldData
+                | ReadMoldData
+                | LoginOperator
+        )
+    }
+
+    /// Returns true if this type of message is a reply to a request.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(MessageKind::JobCardsList.is_response());
+    /// assert!(MessageKind::OperatorInfo.is_response());
+    /// assert!(MessageKind::ActionResult.is_response());
+    /// assert!(!MessageKind::RequestJobCardsList.is_response());
+    /// assert!(!MessageKind::CycleData.is_response());
+    /// ~~~
+    pub fn is_response(self) -> bool {
+        use MessageKind::*;
+
+        matches!(
+            self,
+            ActionResult
+                | JoinResponse
+                | ControllersList
+                | JobCardsList
+                | MoldData
+                | MoldDataValue
+                | OperatorInfo
+        )
+    }
+
+    /// Returns true if this type of message is a notification that is neither a request
+    /// nor a response.
+    ///
+    /// Every type of message falls into exactly one of these classes:
+    ///
+    /// | Class        | Message types |
+    /// |:-------------|:--------------|
+    /// | Request      | `ControllerAction`, `Join`, `RequestControllersList`, `RequestJobCardsList`, `RequestMoldData`, `ReadMoldData`, `LoginOperator` |
+    /// | Response     | `ActionResult`, `JoinResponse`, `ControllersList`, `JobCardsList`, `MoldData`, `MoldDataValue`, `OperatorInfo` |
+    /// | Notification | `Alive`, `ControllerStatus`, `CycleData` |
+    ///
+    /// Notifications are fire-and-forget: no reply is expected, and they are not sent in reply
+    /// to any request.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(MessageKind::Alive.is_notification());
+    /// assert!(MessageKind::CycleData.is_notification());
+    /// assert!(!MessageKind::RequestJobCardsList.is_notification());
+    /// assert!(!MessageKind::JobCardsList.is_notification());
+    /// ~~~
+    pub fn is_notification(self) -> bool {
+        !self.is_request() && !self.is_response()
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn test_message_kind_direction() {
+        assert_eq!(Direction::Both, MessageKind::Alive.direction());
+        assert_eq!(Direction::ServerToClient, MessageKind::JoinResponse.direction());
+        assert_eq!(Direction::ClientToServer, MessageKind::Join.direction());
+
+        // Only `Alive` and `ControllerAction` are sent both ways
+        for kind in MessageKind::all_variants() {
+            assert_eq!(
+                matches!(kind, MessageKind::Alive | MessageKind::ControllerAction),
+                kind.direction() == Direction::Both,
+                "{}",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_message_kind_request_response() {
+        let requests: Vec<_> =
+            MessageKind::all_variants().iter().copied().filter(|k| k.is_request()).collect();
+        let responses: Vec<_> =
+            MessageKind::all_variants().iter().copied().filter(|k| k.is_response()).collect();
+
+        assert_eq!(7, requests.len());
+        assert_eq!(7, responses.len());
+
+        for kind in MessageKind::all_variants() {
+            // No message is both a request and a response
+            assert!(!(kind.is_request() && kind.is_response()), "{}", kind);
+        }
+
+        // Requests are sent by the client to the server, except for `RequestJobCardsList`
+        // and `LoginOperator` which are sent by the server to the client, and
+        // `ControllerAction` which is also sent by the server to report actions
+        for kind in requests {
+            let expected = match kind {
+                MessageKind::RequestJobCardsList | MessageKind::LoginOperator => {
+                    Direction::ServerToClient
+                }
+                MessageKind::ControllerAction => Direction::Both,
+                _ => Direction::ClientToServer,
+            };
+            assert_eq!(expected, kind.direction(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_message_kind_as_str() {
+        let names: Vec<_> = MessageKind::all_variants().iter().map(|k| k.as_str()).collect();
+
+        // These are the `$type` tags on the wire and must never change
+        assert_eq!(
+            vec![
+                "Alive",
+                "ControllerAction",
+                "ActionResult",
+                "RequestControllersList",
+                "ControllersList",
+                "ControllerStatus",
+                "CycleData",
+                "RequestJobCardsList",
+                "JobCardsList",
+                "Join",
+                "JoinResponse",
+                "RequestMoldData",
+                "MoldData",
+                "ReadMoldData",
+                "MoldDataValue",
+                "LoginOperator",
+                "OperatorInfo",
+            ],
+            names
+        );
+
+        for kind in MessageKind::all_variants() {
+            assert_eq!(kind.to_string(), kind.as_str());
+        }
+    }
+
+    #[test]
+    fn test_message_kind_display_matches_type_tag() -> Result<(), String> {
+        let json = Message::new_alive().to_json_str()?;
+        let kind = Message::parse_from_json_str(&json)?.kind();
+
+        assert_eq!(MessageKind::Alive, kind);
+        assert!(json.starts_with(&format!(r#"{{"$type":"{}","#, kind)));
+
+        Ok(())
+    }
+}
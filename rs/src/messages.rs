@@ -1,20 +1,26 @@
 use super::filters::Filters;
 use super::utils::*;
 use super::{
-    ActionID, Controller, Error, JobCard, JobMode, KeyValuePair, Language, OpMode, Result,
-    StateValues, TextID, TextName, ID, R32,
+    AccessLevel, ActionID, Controller, ControllerStream, Error, JobCard, JobMode, JoinResult,
+    JsonLines, KeyValuePair, Language, MessageKind, OpMode, Password, Result, StateValues, TextID,
+    TextName, ID, R32,
 };
 use chrono::{DateTime, FixedOffset};
-use indexmap::IndexMap;
+use indexmap::{map::Entry, IndexMap};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::convert::TryInto;
+use std::collections::HashSet;
+use std::convert::{TryFrom, TryInto};
+use std::io::{BufRead, Read, Write};
 use std::sync::atomic::{AtomicU64, Ordering};
 use Message::*;
 
 // Auto-incrementing global counter for message sequence numbers.
 static SEQ: AtomicU64 = AtomicU64::new(1);
 
+// Auto-incrementing global counter for idempotency keys.
+static IDEMPOTENCY_SEQ: AtomicU64 = AtomicU64::new(1);
+
 /// Common options of an Open Protocol message.
 ///
 #[derive(Debug, Hash, Clone, Serialize, Deserialize)]
@@ -24,9 +30,11 @@ pub struct MessageOptions<'a> {
     ///
     /// The iChen Server may tag certain messages with a unique tracking key that can be used to
     /// retrieve the message from persistent storage later.
+    //
+    // Boxed because it is rarely present, to keep `Message` small.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(borrow)]
-    id: Option<TextID<'a>>,
+    id: Option<Box<TextID<'a>>>,
     //
     /// Ever-increasing message sequence number.
     ///
@@ -119,7 +127,7 @@ impl<'a> MessageOptions<'a> {
     /// # }
     /// ~~~
     pub fn set_id(&mut self, id: &'a str) -> std::result::Result<(), String> {
-        self.id = Some(id.try_into()?);
+        self.id = Some(Box::new(id.try_into()?));
         Ok(())
     }
 
@@ -179,6 +187,24 @@ impl<'a> MessageOptions<'a> {
     pub fn new_with_priority(priority: i32) -> Self {
         Self { priority, ..Self::new() }
     }
+
+    /// Create a `MessageOptions` with a particular `sequence` number instead of an
+    /// auto-incremented one, but otherwise default values.
+    ///
+    /// The auto-incrementing `sequence` counter is not affected.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let opt = MessageOptions::new_with_sequence(42);
+    /// assert_eq!(42, opt.sequence());
+    /// assert_eq!(0, opt.priority());
+    /// assert_eq!(None, opt.id());
+    /// ~~~
+    pub fn new_with_sequence(sequence: u64) -> Self {
+        Self { id: None, sequence, priority: 0 }
+    }
 }
 
 impl Default for MessageOptions<'_> {
@@ -221,20 +247,75 @@ pub enum Message<'a> {
     },
     //
     /// The `CNTRLER_ACTION` message, sent by the server whenever the current *action* of a controller changes.
+    ///
+    /// It is also sent to the server to request an action on a controller, which is then
+    /// acknowledged by an [`ActionResult`].
+    ///
+    /// [`ActionResult`]: enum.Message.html#variant.ActionResult
+    ///
     #[serde(rename_all = "camelCase")]
     ControllerAction {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         /// Unique action code.
         ///
         /// See [this document] for details.
         ///
         /// [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/doc/actions.md
+        #[cfg_attr(feature = "legacy", serde(alias = "action_id"))]
         action_id: ActionID,
         //
         /// Time-stamp of the event.
         timestamp: DateTime<FixedOffset>,
         //
+        /// Idempotency key (if any) identifying the logical action, which must not be empty.
+        ///
+        /// A `ControllerAction` may be sent again after a reconnection.  The sender generates
+        /// a unique key for each logical action (see [`new_controller_action`]) and reuses it
+        /// when the action is retried, so the receiver can discard duplicates.
+        ///
+        /// On the wire, this is the optional `idempotencyKey` string field.
+        ///
+        /// [`new_controller_action`]: enum.Message.html#method.new_controller_action
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        #[serde(deserialize_with = "deserialize_optional_cow_str")]
+        #[serde(borrow)]
+        idempotency_key: Option<Box<Cow<'a, str>>>,
+        //
+        /// Message configuration options.
+        #[serde(flatten)]
+        options: MessageOptions<'a>,
+    },
+    //
+    /// The `CNTRLER_ACTION_RESULT` message, sent by the server to acknowledge whether a controller
+    /// accepted or rejected a [`ControllerAction`].
+    ///
+    /// The Open Protocol does not echo the `sequence` number of a message in its reply, so match
+    /// the `controller_id` and `action_id` to find the original `ControllerAction` (and its `sequence`).
+    ///
+    /// [`ControllerAction`]: enum.Message.html#variant.ControllerAction
+    ///
+    #[serde(rename_all = "camelCase")]
+    ActionResult {
+        /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
+        controller_id: ID,
+        //
+        /// Action code of the `ControllerAction` being acknowledged.
+        #[cfg_attr(feature = "legacy", serde(alias = "action_id"))]
+        action_id: ActionID,
+        //
+        /// Did the controller accept the action?
+        success: bool,
+        //
+        /// Reason for the result (if any), which must not be empty if `success` is `false`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        #[serde(deserialize_with = "deserialize_optional_cow_str")]
+        reason: Option<Box<Cow<'a, str>>>,
+        //
         /// Message configuration options.
         #[serde(flatten)]
         options: MessageOptions<'a>,
@@ -255,6 +336,7 @@ pub enum Message<'a> {
         ///
         /// If omitted, all controllers of the user's organization will be returned.
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: Option<ID>,
         //
         /// Message configuration options.
@@ -272,6 +354,7 @@ pub enum Message<'a> {
         /// List of controllers requested by a previous `RequestControllersList` message.
         ///
         /// Each controller data structure contains the last-known values of the controller's state.
+        /// A controller (by `controller_id`) must not appear more than once.
         //
         // Custom deserialization of string into integer key.
         // No need for custom serialization because ID to string is fine.
@@ -290,22 +373,27 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     ControllerStatus {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Human-friendly name for display (or `None` if not relevant).
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "display_name"))]
         display_name: Option<Box<TextName<'a>>>,
         //
         /// If true, the controller has disconnected from the iChen® Server.
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "is_disconnected"))]
         is_disconnected: Option<bool>,
         //
         /// Current operation mode of the controller (or `None` if not relevant).
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "op_mode"))]
         op_mode: Option<OpMode>,
         //
         /// Current job mode of the controller (or `None` if not relevant).
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "job_mode"))]
         job_mode: Option<JobMode>,
         //
         /// State of an alarm (if any) on the controller (or `None` if not relevant).
@@ -331,6 +419,7 @@ pub enum Message<'a> {
         #[serde(deserialize_with = "deserialize_invalid_to_some_none")]
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(default)]
+        #[cfg_attr(feature = "legacy", serde(alias = "operator_id"))]
         operator_id: Option<Option<ID>>,
         //
         /// Name of the current logged-on user, `Some(None)` if the current user has no name
@@ -338,6 +427,7 @@ pub enum Message<'a> {
         #[serde(deserialize_with = "deserialize_null_to_some_none")]
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(default)]
+        #[cfg_attr(feature = "legacy", serde(alias = "operator_name"))]
         operator_name: Option<Option<Box<TextName<'a>>>>,
         //
         /// Unique ID of the current job card loaded, `Some(None)` if no job card is currently loaded
@@ -346,6 +436,7 @@ pub enum Message<'a> {
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(default)]
         #[serde(borrow)]
+        #[cfg_attr(feature = "legacy", serde(alias = "job_card_id"))]
         job_card_id: Option<Option<Box<TextName<'a>>>>,
         //
         /// Unique ID of the current mold data set loaded, `Some(None)` if no mold data set is currently loaded
@@ -354,6 +445,7 @@ pub enum Message<'a> {
         #[serde(skip_serializing_if = "Option::is_none")]
         #[serde(default)]
         #[serde(borrow)]
+        #[cfg_attr(feature = "legacy", serde(alias = "mold_id"))]
         mold_id: Option<Option<Box<TextName<'a>>>>,
         //
         /// Snapshot of the current known states of the controller.
@@ -381,6 +473,7 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     CycleData {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// A data dictionary containing a set of cycle data.
@@ -413,6 +506,7 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     RequestJobCardsList {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Message configuration options.
@@ -426,6 +520,7 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     JobCardsList {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// A data dictionary containing a set of `JobCard` data structures.
@@ -447,6 +542,7 @@ pub enum Message<'a> {
     Join {
         /// Organization ID (if any).
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "org_id"))]
         org_id: Option<TextID<'a>>,
         //
         /// The maximum protocol version supported, in the format `x.x.x.x`.
@@ -455,7 +551,7 @@ pub enum Message<'a> {
         version: TextID<'a>,
         //
         /// Password to log onto the server.
-        password: &'a str,
+        password: Password<'a>,
         //
         /// Language encoding.
         language: Language,
@@ -476,6 +572,10 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     JoinResponse {
         /// Result code, >= 100 indicates success.
+        ///
+        /// Use [`join_result`] to decode it.
+        ///
+        /// [`join_result`]: enum.Message.html#method.join_result
         result: u32,
         #[serde(skip_serializing_if = "Option::is_none")]
         //
@@ -484,9 +584,20 @@ pub enum Message<'a> {
         //
         /// A message (mostly likely an error message in case of failure), if any.
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(deserialize_with = "deserialize_optional_cow_str")]
+        #[serde(default)]
         #[serde(borrow)]
         message: Option<Box<Cow<'a, str>>>,
         //
+        /// The protocol version supported by the server (if any), in the format `x.x.x.x`.
+        ///
+        /// Older servers do not send this field.  Use [`server_version`] to get it.
+        ///
+        /// [`server_version`]: enum.Message.html#method.server_version
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
+        version: Option<TextID<'a>>,
+        //
         /// Message configuration options.
         #[serde(flatten)]
         options: MessageOptions<'a>,
@@ -494,14 +605,19 @@ pub enum Message<'a> {
     //
     /// The `REQ_MOLD` message, sent to the server to request the set of mold settings data of a controller.
     ///
+    /// This always requests the _whole_ set of mold settings.  To read a single mold setting,
+    /// use [`ReadMoldData`] instead.
+    ///
     /// # Response
     ///
     /// The Server should reply with a [`MoldData`] message.
     ///
     /// [`MoldData`]: enum.Message.html#variant.MoldData
+    /// [`ReadMoldData`]: enum.Message.html#variant.ReadMoldData
     #[serde(rename_all = "camelCase")]
     RequestMoldData {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Message configuration options.
@@ -517,6 +633,7 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     MoldData {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// A data dictionary containing a set of mold settings.
@@ -541,6 +658,9 @@ pub enum Message<'a> {
     /// The value returned is based on the server cache.
     /// No command is sent to controller to poll the latest value.
     ///
+    /// This is normally used to read a _single_ mold setting.  Setting `field` to `None`
+    /// reads all mold settings from the server cache, which is similar to [`RequestMoldData`].
+    ///
     /// # Response
     ///
     /// The Server should reply with a [`MoldData`] message if `field` is `None`,
@@ -548,12 +668,19 @@ pub enum Message<'a> {
     ///
     /// [`MoldData`]: enum.Message.html#variant.MoldData
     /// [`MoldDataValue`]: enum.Message.html#variant.MoldDataValue
+    /// [`RequestMoldData`]: enum.Message.html#variant.RequestMoldData
     #[serde(rename_all = "camelCase")]
     ReadMoldData {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Name of the mold setting to read, `None` for all.
+        ///
+        /// Nested settings are addressed with dotted paths (e.g. `temp.zone1`).
+        /// See [`resolve_mold_data_path`] for the resolution rules.
+        ///
+        /// [`resolve_mold_data_path`]: fn.resolve_mold_data_path.html
         field: Option<TextID<'a>>,
         //
         /// Message configuration options.
@@ -568,6 +695,7 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     MoldDataValue {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Name of the mold setting to read.
@@ -592,10 +720,11 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     LoginOperator {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// User password.
-        password: &'a str,
+        password: Password<'a>,
         //
         /// Message configuration options.
         #[serde(flatten)]
@@ -609,24 +738,26 @@ pub enum Message<'a> {
     #[serde(rename_all = "camelCase")]
     OperatorInfo {
         /// Unique ID of the controller.
+        #[cfg_attr(feature = "legacy", serde(alias = "controller_id"))]
         controller_id: ID,
         //
         /// Unique ID of the authenticated user.
         #[serde(skip_serializing_if = "Option::is_none")]
+        #[cfg_attr(feature = "legacy", serde(alias = "operator_id"))]
         operator_id: Option<ID>,
         //
         /// Name of the user.
         name: TextName<'a>,
         //
         /// User password.
-        password: TextName<'a>,
+        password: Password<'a>,
         //
         /// Allowed access level for the user.
         ///
         /// Valid values are from 0 to [`MAX_OPERATOR_LEVEL`] (usually 10).
         ///
         /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
-        level: u8,
+        level: AccessLevel,
         //
         /// Message configuration options.
         #[serde(flatten)]
@@ -644,227 +775,1849 @@ impl<'a> Message<'a> {
     /// Maximum operator level: 10.
     pub const MAX_OPERATOR_LEVEL: u8 = 10;
 
+    /// Default maximum size of a JSON message: 1 MiB.
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
     /// Parse a JSON string into a `Message`.
     ///
     /// # Errors
     ///
     /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during parsing.
     ///
+    /// With the `tracing` feature enabled, parse failures are also logged at `WARN` level.
+    ///
     /// [`OpenProtocolError`]: enum.OpenProtocolError.html
     ///
     pub fn parse_from_json_str(json: &'a str) -> Result<'a, Self> {
-        let m = serde_json::from_str::<Message>(json).map_err(Error::JsonError)?;
-        m.validate()?;
-        Ok(m)
+        let result =
+            serde_json::from_str::<Message>(json).map_err(Error::JsonError).and_then(|m| {
+                m.validate()?;
+                Ok(m)
+            });
+
+        #[cfg(feature = "tracing")]
+        {
+            if let Err(err) = &result {
+                // The JSON text is not logged because it may contain passwords
+                tracing::warn!(error = %err, size = json.len(), "cannot parse message");
+            }
+        }
+
+        result
     }
 
-    /// Validate all the fields in the `Message`, then serialize it into a JSON string.
+    /// Parse a JSON string into a `Message`, rejecting it up-front if it is longer than
+    /// `limit` bytes.
+    ///
+    /// Use this for JSON text received from the network, so that an oversized message is
+    /// never parsed.  A `limit` of `None` disables the check, the same as
+    /// [`parse_from_json_str`].
+    ///
+    /// [`parse_from_json_str`]: enum.Message.html#method.parse_from_json_str
     ///
     /// # Errors
     ///
-    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error.
+    /// Return `Err(`[`OpenProtocolError::MessageTooLarge`]`)` if `json` is longer than
+    /// `limit` bytes.
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during parsing.
     ///
     /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`OpenProtocolError::MessageTooLarge`]: enum.OpenProtocolError.html#variant.MessageTooLarge
     ///
-    /// # Examples
+    /// ## Error Examples
     ///
     /// ~~~
     /// # use ichen_openprotocol::*;
-    /// # fn main() -> std::result::Result<(), String> {
-    /// let msg = Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "MyCompany")?;
+    /// let json = r#"{"$type":"Alive","sequence":42}"#;
+    ///
     /// assert_eq!(
-    ///     r#"{"$type":"Join","orgId":"MyCompany","version":"4.0","password":"MyPassword","language":"EN","filter":"Status, Cycle","sequence":1}"#,
-    ///     msg.to_json_str()?
+    ///     OpenProtocolError::MessageTooLarge { size: 31, limit: 16 },
+    ///     Message::parse_from_json_str_with_limit(json, Some(16)).unwrap_err()
     /// );
-    /// # Ok(())
-    /// # }
     /// ~~~
-    pub fn to_json_str(&self) -> Result<'_, String> {
-        self.validate()?;
-        serde_json::to_string(self).map_err(Error::JsonError)
-    }
-
-    /// Create an `ALIVE` message.
     ///
     /// # Examples
     ///
     /// ~~~
     /// # use ichen_openprotocol::*;
-    /// let msg = Message::new_alive();
-    /// if let Message::Alive { options } = msg {
-    ///     assert_eq!(1, options.sequence());
-    ///     assert_eq!(0, options.priority());
-    ///     assert_eq!(None, options.id());
-    /// } else {
-    ///     panic!();
-    /// }
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"Alive","sequence":42}"#;
+    ///
+    /// let msg = Message::parse_from_json_str_with_limit(json, Some(Message::DEFAULT_MAX_MESSAGE_SIZE))?;
+    /// assert_eq!(42, msg.sequence());
+    ///
+    /// let msg = Message::parse_from_json_str_with_limit(json, None)?;
+    /// assert_eq!(42, msg.sequence());
+    /// # Ok(())
+    /// # }
     /// ~~~
-    pub fn new_alive() -> Self {
-        Alive { options: Default::default() }
+    pub fn parse_from_json_str_with_limit(json: &'a str, limit: Option<usize>) -> Result<'a, Self> {
+        match limit {
+            Some(limit) if json.len() > limit => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(size = json.len(), limit, "message too large");
+
+                Err(Error::MessageTooLarge { size: json.len(), limit })
+            }
+            _ => Self::parse_from_json_str(json),
+        }
     }
 
-    /// Create a `JOIN` message with default language and protocol version.
+    /// Parse a UTF-8 encoded JSON byte slice into a `Message`.
     ///
-    /// The default language is [`DEFAULT_LANGUAGE`] (usually `EN`).
+    /// # Errors
     ///
-    /// The default protocol version is given in [`PROTOCOL_VERSION`].
+    /// Return `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if the bytes are not valid UTF-8.
     ///
-    /// [`DEFAULT_LANGUAGE`]: enum.Message.html#associatedconstant.DEFAULT_LANGUAGE
-    /// [`PROTOCOL_VERSION`]: enum.Message.html#associatedconstant.PROTOCOL_VERSION
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during parsing.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
     ///
     /// # Examples
     ///
     /// ~~~
     /// # use ichen_openprotocol::*;
-    /// let msg = Message::new_join("MyPassword", Filters::Status + Filters::Cycle);
-    /// if let Message::Join { org_id, version, password, language, filter, options } = msg {
-    ///     assert_eq!(None, org_id);
-    ///     assert_eq!(Message::PROTOCOL_VERSION, &version);
-    ///     assert_eq!("MyPassword", password);
-    ///     assert_eq!(Message::DEFAULT_LANGUAGE, language);
-    ///     assert_eq!(Filters::Status + Filters::Cycle, filter);
-    ///     assert_eq!(1, options.sequence());
-    ///     assert_eq!(0, options.priority());
-    ///     assert_eq!(None, options.id());
-    /// } else {
-    ///     panic!();
-    /// }
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_slice(br#"{"$type":"Alive","sequence":42}"#)?;
+    /// assert_eq!(42, msg.sequence());
+    ///
+    /// assert!(Message::parse_from_json_slice(b"{\"$type\":\"Alive\",\xFF}").is_err());
+    /// # Ok(())
+    /// # }
     /// ~~~
-    pub fn new_join(password: &'a str, filter: Filters) -> Self {
-        Join {
-            org_id: None,
-            version: Self::PROTOCOL_VERSION.try_into().unwrap(),
-            password,
-            language: Self::DEFAULT_LANGUAGE,
-            filter,
-            options: Default::default(),
-        }
+    pub fn parse_from_json_slice(json: &'a [u8]) -> Result<'a, Self> {
+        let text = std::str::from_utf8(json)
+            .map_err(|err| Error::ConstraintViolated(format!("invalid UTF-8: {}", err).into()))?;
+
+        Self::parse_from_json_str(text)
     }
 
-    /// Create a `JOIN` message with non-default organization.
+    /// Read the entire JSON text from a reader into `buffer`, then parse it into a `Message`.
+    ///
+    /// Text fields in the `Message` borrow from `buffer`, which can be reused for the next
+    /// message once the `Message` is dropped.
     ///
     /// # Errors
     ///
-    /// Returns `Err(String)` if the organization ID is empty or all-whitespace or contains
-    /// any non-ASCII characters.
+    /// Return `Err(`[`OpenProtocolError::JsonError`]`)` if the text cannot be read
+    /// (e.g. it is not valid UTF-8).
     ///
-    /// ## Error Examples
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during parsing.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`OpenProtocolError::JsonError`]: enum.OpenProtocolError.html#variant.JsonError
+    ///
+    /// # Examples
     ///
     /// ~~~
     /// # use ichen_openprotocol::*;
-    /// match Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "") {
-    ///     Err(e) => assert_eq!("invalid value: a non-empty, non-whitespace, all-ASCII string required", e),
-    ///     _ => ()
-    /// }
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let file = br#"{"$type":"Alive","sequence":42}"#;
+    /// let mut buffer = String::new();
+    ///
+    /// let msg = Message::from_reader(&file[..], &mut buffer)?;
+    /// assert_eq!(42, msg.sequence());
+    /// # Ok(())
+    /// # }
     /// ~~~
+    pub fn from_reader<R: Read>(mut reader: R, buffer: &'a mut String) -> Result<'a, Self> {
+        buffer.clear();
+        reader
+            .read_to_string(buffer)
+            .map_err(|err| Error::JsonError(serde_json::Error::io(err)))?;
+        Self::parse_from_json_str(buffer)
+    }
+
+    /// Read a stream of newline-delimited JSON (NDJSON) text from a reader, one line at a time.
+    ///
+    /// Unlike [`parse_ndjson`], which needs the entire text in memory, only one line is held
+    /// at any time.  Blank lines are skipped.  A malformed line yields an `Err` for that line
+    /// only; subsequent lines are still parsed.
+    ///
+    /// [`parse_ndjson`]: enum.Message.html#method.parse_ndjson
     ///
     /// # Examples
     ///
     /// ~~~
     /// # use ichen_openprotocol::*;
     /// # fn main() -> std::result::Result<(), String> {
-    /// let msg = Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "MyCompany")?;
+    /// let file = "{\"$type\":\"Alive\",\"sequence\":1}\n\n{\"$type\":\"Alive\",\"sequence\":2}\n";
+    /// let mut lines = Message::iter_json_lines(file.as_bytes());
+    /// let mut sequences = Vec::new();
     ///
-    /// if let Message::Join { org_id, version, password, language, filter, options } = msg {
-    ///     assert_eq!(Some("MyCompany"), org_id.as_ref().map(|x| x.get()));
-    ///     assert_eq!(Message::PROTOCOL_VERSION, version.get());
-    ///     assert_eq!("MyPassword", password);
-    ///     assert_eq!(Message::DEFAULT_LANGUAGE, language);
-    ///     assert_eq!(Filters::Status + Filters::Cycle, filter);
-    ///     assert_eq!(1, options.sequence());
-    ///     assert_eq!(0, options.priority());
-    ///     assert_eq!(None, options.id());
-    /// } else {
-    ///     panic!();
+    /// while let Some(msg) = lines.next_message() {
+    ///     sequences.push(msg?.sequence());
     /// }
+    ///
+    /// assert_eq!(vec![1, 2], sequences);
     /// # Ok(())
     /// # }
     /// ~~~
-    pub fn try_new_join_with_org(
-        password: &'a str,
-        filter: Filters,
-        org: &'a str,
-    ) -> std::result::Result<Self, String> {
-        let mut msg = Self::new_join(password, filter);
-
-        if let Join { ref mut org_id, .. } = msg {
-            *org_id = Some(org.try_into()?);
-        }
-
-        Ok(msg)
-    }
-
-    /// Get the optional message ID from the `options` field.
-    pub fn id(&self) -> Option<&str> {
-        match self {
-            Alive { options }
-            | ControllerAction { options, .. }
-            | RequestControllersList { options, .. }
-            | ControllersList { options, .. }
-            | ControllerStatus { options, .. }
-            | CycleData { options, .. }
-            | RequestJobCardsList { options, .. }
-            | JobCardsList { options, .. }
-            | Join { options, .. }
-            | JoinResponse { options, .. }
-            | RequestMoldData { options, .. }
-            | MoldData { options, .. }
-            | ReadMoldData { options, .. }
-            | MoldDataValue { options, .. }
-            | LoginOperator { options, .. }
-            | OperatorInfo { options, .. } => options.id(),
-        }
-    }
-
-    /// Get the message sequence number from the `options` field.
-    pub fn sequence(&self) -> u64 {
-        match self {
-            Alive { options }
-            | ControllerAction { options, .. }
-            | RequestControllersList { options, .. }
-            | ControllersList { options, .. }
-            | ControllerStatus { options, .. }
-            | CycleData { options, .. }
-            | RequestJobCardsList { options, .. }
-            | JobCardsList { options, .. }
-            | Join { options, .. }
-            | JoinResponse { options, .. }
-            | RequestMoldData { options, .. }
-            | MoldData { options, .. }
-            | ReadMoldData { options, .. }
-            | MoldDataValue { options, .. }
-            | LoginOperator { options, .. }
-            | OperatorInfo { options, .. } => options.sequence(),
-        }
-    }
-
-    /// Get the message priority from the `options` field.
-    pub fn priority(&self) -> i32 {
-        match self {
-            Alive { options, .. }
-            | ControllerAction { options, .. }
-            | RequestControllersList { options, .. }
-            | ControllersList { options, .. }
-            | ControllerStatus { options, .. }
-            | CycleData { options, .. }
-            | RequestJobCardsList { options, .. }
-            | JobCardsList { options, .. }
-            | Join { options, .. }
-            | JoinResponse { options, .. }
-            | RequestMoldData { options, .. }
-            | MoldData { options, .. }
-            | ReadMoldData { options, .. }
-            | MoldDataValue { options, .. }
-            | LoginOperator { options, .. }
-            | OperatorInfo { options, .. } => options.priority(),
-        }
+    pub fn iter_json_lines<R: BufRead>(reader: R) -> JsonLines<R> {
+        JsonLines::new(reader)
     }
 
-    /// Validate the `Message` data structure.
+    /// Read the controllers in a `ControllersList` message from a JSON text, one
+    /// controller at a time.
+    ///
+    /// Unlike [`parse_from_json_str`], which needs the entire message in memory, only the
+    /// JSON text of one controller is held at any time.  Use this for a very large
+    /// `ControllersList` (e.g. for thousands of controllers).
+    ///
+    /// Fields of the message other than `$type` and `data` are skipped.  Controllers are not
+    /// checked for duplicated IDs.
+    ///
+    /// [`parse_from_json_str`]: enum.Message.html#method.parse_from_json_str
     ///
     /// # Errors
     ///
-    /// Returns `Err(`[`OpenProtocolError`]`)` if some fields in the `Message` are not valid.
+    /// [`ControllerStream::next_controller`] returns `Err(`[`OpenProtocolError`]`)` if the
+    /// JSON text is not a `ControllersList` message or a controller cannot be parsed.
+    ///
+    /// [`ControllerStream::next_controller`]: struct.ControllerStream.html#method.next_controller
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"ControllersList","data":{
+    ///     "1":{"controllerId":1,"displayName":"Hello"},
+    ///     "2":{"controllerId":2,"displayName":"World"}
+    /// },"sequence":1}"#;
+    ///
+    /// let mut stream = Message::stream_controllers(json.as_bytes());
+    /// let mut names = Vec::new();
+    ///
+    /// while let Some(controller) = stream.next_controller() {
+    ///     names.push(controller?.display_name.to_string());
+    /// }
+    ///
+    /// assert_eq!(vec!["Hello", "World"], names);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn stream_controllers<R: std::io::Read>(reader: R) -> ControllerStream<R> {
+        ControllerStream::new(reader)
+    }
+
+    /// Parse a stream of newline-delimited JSON (NDJSON) text into `Message`s.
+    ///
+    /// Each non-blank line is parsed lazily via [`parse_from_json_str`] when the iterator
+    /// is advanced.  Blank (or all-whitespace) lines are skipped.
+    ///
+    /// A malformed line yields an `Err` for that line only; subsequent lines are still parsed.
+    ///
+    /// [`parse_from_json_str`]: enum.Message.html#method.parse_from_json_str
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let text = "{\"$type\":\"Alive\",\"sequence\":1}\n\n{\"$type\":\"Alive\",\"sequence\":2}\n";
+    /// let messages: Vec<_> = Message::parse_ndjson(text).collect();
+    ///
+    /// assert_eq!(2, messages.len());
+    /// assert_eq!(2, messages[1].as_ref().unwrap().sequence());
+    /// ~~~
+    pub fn parse_ndjson(text: &'a str) -> impl Iterator<Item = Result<'a, Self>> {
+        text.lines().filter(|line| !line.trim().is_empty()).map(Self::parse_from_json_str)
+    }
+
+    /// Parse a batch of messages, sent as a JSON array in a single frame, into `Message`s.
+    ///
+    /// Batching is not part of the Open Protocol™ and must be agreed with the peer beforehand.
+    /// The batch is produced by [`batch_to_json`].  An empty array yields an empty `Vec`.
+    ///
+    /// [`batch_to_json`]: enum.Message.html#method.batch_to_json
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if the text is not a JSON array, or if there is
+    /// an error parsing any message in the batch.
+    ///
+    /// With the `tracing` feature enabled, parse failures are also logged at `WARN` level.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"[{"$type":"Alive","sequence":1},{"$type":"Alive","sequence":2}]"#;
+    /// let messages = Message::parse_batch(json)?;
+    ///
+    /// assert_eq!(2, messages.len());
+    /// assert_eq!(2, messages[1].sequence());
+    ///
+    /// assert!(Message::parse_batch("[]")?.is_empty());
+    /// assert!(Message::parse_batch(r#"{"$type":"Alive","sequence":1}"#).is_err());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn parse_batch(json: &'a str) -> Result<'a, Vec<Self>> {
+        let result = serde_json::from_str::<Vec<Message>>(json).map_err(Error::JsonError).and_then(
+            |messages| {
+                for m in &messages {
+                    m.validate()?;
+                }
+                Ok(messages)
+            },
+        );
+
+        #[cfg(feature = "tracing")]
+        {
+            if let Err(err) = &result {
+                // The JSON text is not logged because it may contain passwords
+                tracing::warn!(error = %err, size = json.len(), "cannot parse message batch");
+            }
+        }
+
+        result
+    }
+
+    /// Validate all the fields in the `Message`, then serialize it into a JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "MyCompany")?;
+    /// let json = msg.to_json_str()?;
+    /// # #[cfg(not(feature = "lowercase-enums"))]
+    /// assert_eq!(
+    ///     r#"{"$type":"Join","orgId":"MyCompany","version":"4.0","password":"MyPassword","language":"EN","filter":"Status, Cycle","sequence":1}"#,
+    ///     json
+    /// );
+    /// # #[cfg(feature = "lowercase-enums")]
+    /// # assert_eq!(r#"{"$type":"Join","orgId":"MyCompany","version":"4.0","password":"MyPassword","language":"en","filter":"Status, Cycle","sequence":1}"#, json);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn to_json_str(&self) -> Result<'_, String> {
+        self.validate()?;
+        serde_json::to_string(self).map_err(Error::JsonError)
+    }
+
+    /// Validate all the fields in the `Message`, then write it as compact JSON (the same
+    /// as [`to_json_str`]) into a writer.
+    ///
+    /// No newline is written after the JSON text.
+    ///
+    /// [`to_json_str`]: enum.Message.html#method.to_json_str
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error, including an I/O error
+    /// from the writer.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::new_alive_with(42);
+    /// let mut file = Vec::new();
+    ///
+    /// msg.to_writer(&mut file)?;
+    /// assert_eq!(br#"{"$type":"Alive","sequence":42}"#, &file[..]);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<'_, ()> {
+        self.validate()?;
+        serde_json::to_writer(writer, self).map_err(Error::JsonError)
+    }
+
+    /// Validate all the fields in the `Message`, then convert it into a JSON
+    /// [`Value`](https://docs.rs/serde_json/1/serde_json/enum.Value.html).
+    ///
+    /// 32-bit floating-point values (e.g. in `lastCycleData`) are widened into 64-bit numbers
+    /// in the `Value`, so `12.33` becomes `12.329999923706055`.  Converting the `Value` back
+    /// via [`from_value`] restores the original values.
+    ///
+    /// Unless the `preserve_order` feature of `serde_json` is enabled, the keys of JSON objects
+    /// in the `Value` are sorted, so maps such as `lastCycleData` lose their original order.
+    ///
+    /// [`from_value`]: enum.Message.html#method.from_value
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::new_join("MyPassword", Filters::Status);
+    /// let value = msg.to_value()?;
+    ///
+    /// assert_eq!("Join", value["$type"]);
+    /// assert_eq!("MyPassword", value["password"]);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn to_value(&self) -> Result<'_, serde_json::Value> {
+        self.validate()?;
+        serde_json::to_value(self).map_err(Error::JsonError)
+    }
+
+    /// Convert a JSON [`Value`](https://docs.rs/serde_json/1/serde_json/enum.Value.html)
+    /// into a `Message`, then validate all its fields.
+    ///
+    /// Text fields in the `Message` borrow from `value`, so the `Value` is taken by
+    /// reference.
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during conversion.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let value = serde_json::json!({ "$type": "Alive", "sequence": 42 });
+    /// let msg = Message::from_value(&value)?;
+    ///
+    /// assert_eq!(MessageKind::Alive, msg.kind());
+    /// assert_eq!(42, msg.sequence());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn from_value(value: &'a serde_json::Value) -> Result<'a, Self> {
+        let msg = Message::deserialize(value).map_err(Error::JsonError)?;
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    /// Validate all the fields in the `Message`, then encode it into a compact binary format
+    /// (requires the `binary` feature).
+    ///
+    /// The binary format is [MessagePack](https://msgpack.org), with the same structure as
+    /// the JSON format.  It is intended for transporting messages internally (e.g. through
+    /// a message broker); JSON remains the format of the Open Protocol™.
+    /// Use [`from_bytes`] to decode the bytes.
+    ///
+    /// [`from_bytes`]: enum.Message.html#method.from_bytes
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::new_join("MyPassword", Filters::Status + Filters::Cycle);
+    /// let bytes = msg.to_bytes()?;
+    ///
+    /// assert!(bytes.len() < msg.to_json_str()?.len());
+    /// assert_eq!(msg.to_json_str()?, Message::from_bytes(&bytes)?.to_json_str()?);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    #[cfg(feature = "binary")]
+    pub fn to_bytes(&self) -> Result<'_, Vec<u8>> {
+        self.validate()?;
+        rmp_serde::to_vec_named(self)
+            .map_err(|err| Error::SystemError(format!("cannot encode message: {}", err).into()))
+    }
+
+    /// Decode a `Message` encoded by [`to_bytes`] (requires the `binary` feature).
+    ///
+    /// Text fields in the `Message` borrow from `bytes` whenever possible.
+    ///
+    /// [`to_bytes`]: enum.Message.html#method.to_bytes
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if the bytes cannot be decoded.
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if the `Message` fails validation.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    ///
+    #[cfg(feature = "binary")]
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<'a, Self> {
+        let msg: Message = rmp_serde::from_slice(bytes).map_err(|err| {
+            Error::ConstraintViolated(format!("invalid binary message: {}", err).into())
+        })?;
+
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    /// Validate all the fields in a batch of `Message`s, then serialize them into a single
+    /// JSON array, to be sent in one frame.
+    ///
+    /// Batching is not part of the Open Protocol™ and must be agreed with the peer beforehand.
+    /// Use [`parse_batch`] to parse the batch.
+    ///
+    /// [`parse_batch`]: enum.Message.html#method.parse_batch
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error in any message.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let alive = Message::new_alive();
+    /// let request = Message::new_request_all_controllers();
+    ///
+    /// assert_eq!(
+    ///     r#"[{"$type":"Alive","sequence":1},{"$type":"RequestControllersList","sequence":2}]"#,
+    ///     Message::batch_to_json(&[alive, request])?
+    /// );
+    /// assert_eq!("[]", Message::batch_to_json(&[])?);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn batch_to_json(messages: &[Self]) -> Result<'_, String> {
+        for m in messages {
+            m.validate()?;
+        }
+        serde_json::to_string(messages).map_err(Error::JsonError)
+    }
+
+    /// Validate all the fields in the `Message`, then serialize it into a canonical JSON string
+    /// suitable for signing or hashing.
+    ///
+    /// The keys of every JSON object (including maps such as `variables` and `lastCycleData`)
+    /// are sorted, so the output depends only on the contents of the `Message`, not on the
+    /// insertion order of its maps.  Field names and values are otherwise identical to
+    /// [`to_json_str`].
+    ///
+    /// This format is for signing only.  Always use [`to_json_str`] for messages sent to
+    /// the iChen® Server.
+    ///
+    /// [`to_json_str`]: enum.Message.html#method.to_json_str
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "MyCompany")?;
+    /// let json = msg.to_canonical_json()?;
+    /// # #[cfg(not(feature = "lowercase-enums"))]
+    /// assert_eq!(
+    ///     r#"{"$type":"Join","filter":"Status, Cycle","language":"EN","orgId":"MyCompany","password":"MyPassword","sequence":1,"version":"4.0"}"#,
+    ///     json
+    /// );
+    /// # #[cfg(feature = "lowercase-enums")]
+    /// # assert_eq!(r#"{"$type":"Join","filter":"Status, Cycle","language":"en","orgId":"MyCompany","password":"MyPassword","sequence":1,"version":"4.0"}"#, json);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn to_canonical_json(&self) -> Result<'_, String> {
+        fn canonicalize(value: serde_json::Value) -> serde_json::Value {
+            use serde_json::Value;
+
+            match value {
+                Value::Object(map) => {
+                    let mut entries: Vec<_> =
+                        map.into_iter().map(|(k, v)| (k, canonicalize(v))).collect();
+                    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                    Value::Object(entries.into_iter().collect())
+                }
+                Value::Array(list) => Value::Array(list.into_iter().map(canonicalize).collect()),
+                value => value,
+            }
+        }
+
+        // Re-parse the wire format so that numbers are formatted exactly as in `to_json_str`
+        let json = self.to_json_str()?;
+        let value = serde_json::from_str(&json).map_err(Error::JsonError)?;
+        serde_json::to_string(&canonicalize(value)).map_err(Error::JsonError)
+    }
+
+    /// Create an `ALIVE` message.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::new_alive();
+    /// if let Message::Alive { options } = msg {
+    ///     assert_eq!(1, options.sequence());
+    ///     assert_eq!(0, options.priority());
+    ///     assert_eq!(None, options.id());
+    /// } else {
+    ///     panic!();
+    /// }
+    /// ~~~
+    pub fn new_alive() -> Self {
+        Alive { options: Default::default() }
+    }
+
+    /// Create an `ALIVE` message with a particular `sequence` number.
+    ///
+    /// A unique `sequence` number identifies the keepalive, for example to match it with
+    /// a reply when measuring the round-trip latency.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::new_alive_with(12345);
+    /// assert_eq!(r#"{"$type":"Alive","sequence":12345}"#, msg.to_json_str()?);
+    ///
+    /// let json = msg.to_json_str()?;
+    /// let reply = Message::parse_from_json_str(&json)?;
+    /// assert!(reply.is_keepalive());
+    /// assert_eq!(12345, reply.sequence());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn new_alive_with(sequence: u64) -> Self {
+        Alive { options: MessageOptions::new_with_sequence(sequence) }
+    }
+
+    /// Create a `REQ_CNTRLER_LIST` message for a single controller, or for all controllers
+    /// if `controller_id` is `None`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::new_request_controllers_list(Some(ID::from_u32(123)));
+    /// if let Message::RequestControllersList { controller_id, .. } = msg {
+    ///     assert_eq!(Some(ID::from_u32(123)), controller_id);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// ~~~
+    pub fn new_request_controllers_list(controller_id: Option<ID>) -> Self {
+        RequestControllersList { controller_id, options: Default::default() }
+    }
+
+    /// Create a `REQ_CNTRLER_LIST` message for all controllers.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::new_request_all_controllers();
+    /// if let Message::RequestControllersList { controller_id, .. } = msg {
+    ///     assert_eq!(None, controller_id);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// ~~~
+    pub fn new_request_all_controllers() -> Self {
+        Self::new_request_controllers_list(None)
+    }
+
+    /// Create a `RESP_JOBSLIST` message from a list of job cards, keyed by their job ID's.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if more than one job card has
+    /// the same job ID.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let jobs = vec![
+    ///     JobCard::try_new("J001", "Mold#001", 0, 1000).unwrap(),
+    ///     JobCard::try_new("J001", "Mold#002", 0, 2000).unwrap(),
+    /// ];
+    ///
+    /// assert_eq!(
+    ///     Err(OpenProtocolError::InvalidField {
+    ///         field: "job_card_id",
+    ///         value: "J001".into(),
+    ///         description: "duplicated job ID".into(),
+    ///     }),
+    ///     Message::job_cards_list(ID::from_u32(123), jobs).map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let jobs = vec![
+    ///     JobCard::try_new("J001", "Mold#001", 0, 1000)?,
+    ///     JobCard::try_new("J002", "Mold#002", 0, 2000)?,
+    /// ];
+    ///
+    /// if let Message::JobCardsList { controller_id, data, .. } = Message::job_cards_list(ID::from_u32(123), jobs)? {
+    ///     assert_eq!(123, controller_id);
+    ///     assert_eq!(2, data.len());
+    ///     assert_eq!("Mold#002", data["J002"].mold_id());
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn job_cards_list<I>(controller_id: ID, job_cards: I) -> Result<'a, Self>
+    where
+        I: IntoIterator<Item = JobCard<'a>>,
+    {
+        let mut data = IndexMap::new();
+
+        for job in job_cards {
+            match data.entry(job.job_card_id_ref().clone()) {
+                Entry::Occupied(entry) => {
+                    return Err(Error::InvalidField {
+                        field: "job_card_id",
+                        value: entry.key().get().to_string().into(),
+                        description: "duplicated job ID".into(),
+                    })
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(job);
+                }
+            }
+        }
+
+        Ok(JobCardsList { controller_id, data, options: Default::default() })
+    }
+
+    /// Create a `RESP_PWD_LEVEL` message granting an access level to an authenticated user,
+    /// in reply to a [`LoginOperator`] message.
+    ///
+    /// By convention, the operator ID is `level + 1` so that it is never zero.
+    ///
+    /// [`LoginOperator`]: enum.Message.html#variant.LoginOperator
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::EmptyField`]`)` if `name` or `password` is empty
+    /// or all white-space.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if `level` is higher than
+    /// [`MAX_OPERATOR_LEVEL`].
+    ///
+    /// [`OpenProtocolError::EmptyField`]: enum.OpenProtocolError.html#variant.EmptyField
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err(OpenProtocolError::ConstraintViolated(
+    ///         "Level 11 is too high - must be between 0 and 10.".into()
+    ///     )),
+    ///     Message::operator_info(ID::from_u32(123), Password::new("pw"), 11, "John").map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::operator_info(ID::from_u32(123), Password::new("pw"), 5, "John")?;
+    ///
+    /// if let Message::OperatorInfo { controller_id, operator_id, name, level, .. } = msg {
+    ///     assert_eq!(123, controller_id);
+    ///     assert_eq!(Some(ID::from_u32(6)), operator_id);
+    ///     assert_eq!("John", &name);
+    ///     assert_eq!(5, level);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn operator_info(
+        controller_id: ID,
+        password: Password<'a>,
+        level: u8,
+        name: impl Into<Cow<'a, str>>,
+    ) -> Result<'a, Self> {
+        // Widened before adding, so it cannot wrap around to zero
+        Self::new_operator_info(controller_id, u32::from(level) + 1, password, level, name)
+    }
+
+    /// Create a `RESP_PWD_LEVEL` message granting an access level to an authenticated user
+    /// with a particular operator ID, in reply to a [`LoginOperator`] message.
+    ///
+    /// [`LoginOperator`]: enum.Message.html#variant.LoginOperator
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `operator_id` is zero
+    /// (e.g. a computed ID that has wrapped around).
+    ///
+    /// Returns `Err(`[`OpenProtocolError::EmptyField`]`)` if `name` or `password` is empty
+    /// or all white-space.
+    ///
+    /// Returns `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if `level` is higher than
+    /// [`MAX_OPERATOR_LEVEL`].
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError::EmptyField`]: enum.OpenProtocolError.html#variant.EmptyField
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err(OpenProtocolError::InvalidField {
+    ///         field: "operator_id",
+    ///         value: "0".into(),
+    ///         description: "operator ID cannot be zero".into(),
+    ///     }),
+    ///     Message::new_operator_info(ID::from_u32(123), 0, Password::new("pw"), 5, "John").map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::new_operator_info(ID::from_u32(123), 42, Password::new("pw"), 5, "John")?;
+    ///
+    /// if let Message::OperatorInfo { operator_id, level, .. } = msg {
+    ///     assert_eq!(Some(ID::from_u32(42)), operator_id);
+    ///     assert_eq!(5, level);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn new_operator_info(
+        controller_id: ID,
+        operator_id: u32,
+        password: Password<'a>,
+        level: u8,
+        name: impl Into<Cow<'a, str>>,
+    ) -> Result<'a, Self> {
+        let level =
+            AccessLevel::try_from(level).map_err(|err| Error::ConstraintViolated(err.into()))?;
+
+        let operator_id = ID::new(operator_id).ok_or_else(|| Error::InvalidField {
+            field: "operator_id",
+            value: operator_id.to_string().into(),
+            description: "operator ID cannot be zero".into(),
+        })?;
+
+        let msg = OperatorInfo {
+            controller_id,
+            operator_id: Some(operator_id),
+            name: TextName::new_from_str(name).ok_or(Error::EmptyField("name"))?,
+            password,
+            level,
+            options: Default::default(),
+        };
+
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    /// Create a `CNTRLER_ACTION_RESULT` message acknowledging a [`ControllerAction`].
+    ///
+    /// [`ControllerAction`]: enum.Message.html#variant.ControllerAction
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::EmptyField`]`)` if `success` is `false` and `reason`
+    /// is `None`, empty or all white-spaces.
+    ///
+    /// [`OpenProtocolError::EmptyField`]: enum.OpenProtocolError.html#variant.EmptyField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err(OpenProtocolError::EmptyField("reason")),
+    ///     Message::try_new_action_result(ID::from_u32(123), ActionID::new(1), false, Some(" ")).map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_new_action_result(ID::from_u32(123), ActionID::new(1), false, Some("Busy"))?;
+    ///
+    /// if let Message::ActionResult { controller_id, success, reason, .. } = &msg {
+    ///     assert_eq!(123, *controller_id);
+    ///     assert!(!success);
+    ///     assert_eq!(Some("Busy"), reason.as_deref().map(AsRef::as_ref));
+    /// } else {
+    ///     panic!();
+    /// }
+    ///
+    /// assert_eq!(
+    ///     r#"{"$type":"ActionResult","controllerId":123,"actionId":1,"success":false,"reason":"Busy","sequence":1}"#,
+    ///     msg.to_json_str()?
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn try_new_action_result(
+        controller_id: ID,
+        action_id: ActionID,
+        success: bool,
+        reason: Option<&'a str>,
+    ) -> Result<'a, Self> {
+        let msg = ActionResult {
+            controller_id,
+            action_id,
+            success,
+            reason: reason.map(|r| Box::new(r.into())),
+            options: Default::default(),
+        };
+
+        msg.validate()?;
+        Ok(msg)
+    }
+
+    /// Create a `CNTRLER_ACTION` message with a newly-generated idempotency key.
+    ///
+    /// Each call generates a different key.  To retry the same logical action (e.g. after a
+    /// reconnection), send the same message (or a clone of it) again, so that it carries the
+    /// same key and the receiver can discard the duplicate.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use chrono::DateTime;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let timestamp = DateTime::parse_from_rfc3339("2019-02-26T02:03:04+08:00").map_err(|x| x.to_string())?;
+    /// let msg = Message::new_controller_action(ID::from_u32(123), ActionID::new(42), timestamp);
+    /// let key = msg.idempotency_key().unwrap();
+    ///
+    /// assert!(msg.to_json_str()?.contains(&format!(r#""idempotencyKey":"{}""#, key)));
+    ///
+    /// // A retry carries the same key
+    /// assert_eq!(Some(key), msg.clone().idempotency_key());
+    ///
+    /// // A new action has a different key
+    /// let msg2 = Message::new_controller_action(ID::from_u32(123), ActionID::new(42), timestamp);
+    /// assert_ne!(Some(key), msg2.idempotency_key());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn new_controller_action(
+        controller_id: ID,
+        action_id: ActionID,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Self {
+        // Unique across processes (by process ID and time) and within a process (by counter)
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_nanos())
+            .unwrap_or_default();
+        let key = format!(
+            "{:x}-{:x}-{:x}",
+            nanos,
+            std::process::id(),
+            IDEMPOTENCY_SEQ.fetch_add(1, Ordering::SeqCst)
+        );
+
+        ControllerAction {
+            controller_id,
+            action_id,
+            timestamp,
+            idempotency_key: Some(Box::new(key.into())),
+            options: Default::default(),
+        }
+    }
+
+    /// Create a `RESP_PWD_LEVEL` message denying access to an unknown user, in reply to a
+    /// [`LoginOperator`] message.
+    ///
+    /// The access level is zero, and there is no operator ID.
+    ///
+    /// [`LoginOperator`]: enum.Message.html#variant.LoginOperator
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::operator_info_denied(ID::from_u32(123), Password::new("pw"));
+    ///
+    /// if let Message::OperatorInfo { operator_id, name, level, .. } = msg {
+    ///     assert_eq!(None, operator_id);
+    ///     assert_eq!("Not Allowed", &name);
+    ///     assert_eq!(0, level);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// ~~~
+    pub fn operator_info_denied(controller_id: ID, password: Password<'a>) -> Self {
+        OperatorInfo {
+            controller_id,
+            operator_id: None,
+            name: TextName::new_from_str("Not Allowed").unwrap(),
+            password,
+            level: AccessLevel::MIN,
+            options: Default::default(),
+        }
+    }
+
+    /// Create a `JOIN` message with default language and protocol version.
+    ///
+    /// The default language is [`DEFAULT_LANGUAGE`] (usually `EN`).
+    ///
+    /// The default protocol version is given in [`PROTOCOL_VERSION`].
+    ///
+    /// `filter` should not be empty (i.e. `Filters::None`), otherwise the `JOIN` message
+    /// will fail validation when serialized.  Use [`try_new_join`] to check this up-front.
+    ///
+    /// [`DEFAULT_LANGUAGE`]: enum.Message.html#associatedconstant.DEFAULT_LANGUAGE
+    /// [`PROTOCOL_VERSION`]: enum.Message.html#associatedconstant.PROTOCOL_VERSION
+    /// [`try_new_join`]: enum.Message.html#method.try_new_join
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::new_join("MyPassword", Filters::Status + Filters::Cycle);
+    /// if let Message::Join { org_id, version, password, language, filter, options } = msg {
+    ///     assert_eq!(None, org_id);
+    ///     assert_eq!(Message::PROTOCOL_VERSION, &version);
+    ///     assert_eq!("MyPassword", password);
+    ///     assert_eq!(Message::DEFAULT_LANGUAGE, language);
+    ///     assert_eq!(Filters::Status + Filters::Cycle, filter);
+    ///     assert_eq!(1, options.sequence());
+    ///     assert_eq!(0, options.priority());
+    ///     assert_eq!(None, options.id());
+    /// } else {
+    ///     panic!();
+    /// }
+    /// ~~~
+    pub fn new_join(password: &'a str, filter: Filters) -> Self {
+        Join {
+            org_id: None,
+            version: Self::PROTOCOL_VERSION.try_into().unwrap(),
+            password: Password::new(password),
+            language: Self::DEFAULT_LANGUAGE,
+            filter,
+            options: Default::default(),
+        }
+    }
+
+    /// Create a `JOIN` message with default language and protocol version, checking that
+    /// the set of filters is not empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if `filter` is empty (i.e. `Filters::None`) because such a `JOIN`
+    /// message subscribes to nothing.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err("filter cannot be empty".into()),
+    ///     Message::try_new_join("MyPassword", Filters::None).map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let filters: Filters = [Filters::All, Filters::JobCards].iter().copied().collect();
+    /// let msg = Message::try_new_join("MyPassword", filters)?;
+    ///
+    /// if let Message::Join { filter, .. } = msg {
+    ///     assert_eq!(Filters::All + Filters::JobCards, filter);
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn try_new_join(password: &'a str, filter: Filters) -> std::result::Result<Self, String> {
+        if filter.is_empty() {
+            return Err("filter cannot be empty".into());
+        }
+
+        Ok(Self::new_join(password, filter))
+    }
+
+    /// Create a `JOIN` message with non-default organization.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the organization ID is empty or all-whitespace or contains
+    /// any non-ASCII characters.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// match Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "") {
+    ///     Err(e) => assert_eq!("invalid value: a non-empty, non-whitespace, all-ASCII string required", e),
+    ///     _ => ()
+    /// }
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_new_join_with_org("MyPassword", Filters::Status + Filters::Cycle, "MyCompany")?;
+    ///
+    /// if let Message::Join { org_id, version, password, language, filter, options } = msg {
+    ///     assert_eq!(Some("MyCompany"), org_id.as_ref().map(|x| x.get()));
+    ///     assert_eq!(Message::PROTOCOL_VERSION, version.get());
+    ///     assert_eq!("MyPassword", password);
+    ///     assert_eq!(Message::DEFAULT_LANGUAGE, language);
+    ///     assert_eq!(Filters::Status + Filters::Cycle, filter);
+    ///     assert_eq!(1, options.sequence());
+    ///     assert_eq!(0, options.priority());
+    ///     assert_eq!(None, options.id());
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn try_new_join_with_org(
+        password: &'a str,
+        filter: Filters,
+        org: &'a str,
+    ) -> std::result::Result<Self, String> {
+        let mut msg = Self::new_join(password, filter);
+
+        if let Join { ref mut org_id, .. } = msg {
+            *org_id = Some(org.try_into()?);
+        }
+
+        Ok(msg)
+    }
+
+    /// Create a `JOIN` message requesting a particular protocol version instead of
+    /// [`PROTOCOL_VERSION`].
+    ///
+    /// [`PROTOCOL_VERSION`]: enum.Message.html#associatedconstant.PROTOCOL_VERSION
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the version is empty or all-whitespace or contains
+    /// any non-ASCII characters.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err("invalid value: a non-empty, non-whitespace, all-ASCII string required".into()),
+    ///     Message::try_new_join_with_version("MyPassword", Filters::All, " ").map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_new_join_with_version("MyPassword", Filters::All, "4.1")?;
+    ///
+    /// if let Message::Join { version, .. } = msg {
+    ///     assert_eq!("4.1", version.get());
+    /// } else {
+    ///     panic!();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn try_new_join_with_version(
+        password: &'a str,
+        filter: Filters,
+        version: &'a str,
+    ) -> std::result::Result<Self, String> {
+        let mut msg = Self::new_join(password, filter);
+
+        if let Join { version: ref mut ver, .. } = msg {
+            *ver = version.try_into()?;
+        }
+
+        Ok(msg)
+    }
+
+    /// Get the optional message ID from the `options` field.
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Alive { options }
+            | ControllerAction { options, .. }
+            | ActionResult { options, .. }
+            | RequestControllersList { options, .. }
+            | ControllersList { options, .. }
+            | ControllerStatus { options, .. }
+            | CycleData { options, .. }
+            | RequestJobCardsList { options, .. }
+            | JobCardsList { options, .. }
+            | Join { options, .. }
+            | JoinResponse { options, .. }
+            | RequestMoldData { options, .. }
+            | MoldData { options, .. }
+            | ReadMoldData { options, .. }
+            | MoldDataValue { options, .. }
+            | LoginOperator { options, .. }
+            | OperatorInfo { options, .. } => options.id(),
+        }
+    }
+
+    /// Get the message sequence number from the `options` field.
+    pub fn sequence(&self) -> u64 {
+        match self {
+            Alive { options }
+            | ControllerAction { options, .. }
+            | ActionResult { options, .. }
+            | RequestControllersList { options, .. }
+            | ControllersList { options, .. }
+            | ControllerStatus { options, .. }
+            | CycleData { options, .. }
+            | RequestJobCardsList { options, .. }
+            | JobCardsList { options, .. }
+            | Join { options, .. }
+            | JoinResponse { options, .. }
+            | RequestMoldData { options, .. }
+            | MoldData { options, .. }
+            | ReadMoldData { options, .. }
+            | MoldDataValue { options, .. }
+            | LoginOperator { options, .. }
+            | OperatorInfo { options, .. } => options.sequence(),
+        }
+    }
+
+    /// Get the message priority from the `options` field.
+    pub fn priority(&self) -> i32 {
+        match self {
+            Alive { options, .. }
+            | ControllerAction { options, .. }
+            | ActionResult { options, .. }
+            | RequestControllersList { options, .. }
+            | ControllersList { options, .. }
+            | ControllerStatus { options, .. }
+            | CycleData { options, .. }
+            | RequestJobCardsList { options, .. }
+            | JobCardsList { options, .. }
+            | Join { options, .. }
+            | JoinResponse { options, .. }
+            | RequestMoldData { options, .. }
+            | MoldData { options, .. }
+            | ReadMoldData { options, .. }
+            | MoldDataValue { options, .. }
+            | LoginOperator { options, .. }
+            | OperatorInfo { options, .. } => options.priority(),
+        }
+    }
+
+    /// Decode the result code of a `JoinResponse` message.
+    ///
+    /// Returns `None` if the message is not a `JoinResponse`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?;
+    /// assert_eq!(Some(JoinResult::Succeeded(100)), msg.join_result());
+    ///
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":99,"sequence":1}"#)?;
+    /// assert_eq!(Some(JoinResult::Failed(99)), msg.join_result());
+    ///
+    /// assert_eq!(None, Message::new_alive().join_result());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn join_result(&self) -> Option<JoinResult> {
+        match self {
+            JoinResponse { result, .. } => Some(JoinResult::from_code(*result)),
+            _ => None,
+        }
+    }
+
+    /// Get the protocol version advertised by the server in a `JoinResponse` message, if any.
+    ///
+    /// Returns `None` for all other messages, and for a `JoinResponse` from an older server
+    /// that does not advertise its version.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"JoinResponse","result":100,"version":"4.1","sequence":1}"#;
+    /// assert_eq!(Some("4.1"), Message::parse_from_json_str(json)?.server_version());
+    ///
+    /// let json = r#"{"$type":"JoinResponse","result":100,"sequence":1}"#;
+    /// assert_eq!(None, Message::parse_from_json_str(json)?.server_version());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn server_version(&self) -> Option<&str> {
+        match self {
+            JoinResponse { version, .. } => version.as_ref().map(|v| v.get()),
+            _ => None,
+        }
+    }
+
+    /// Get the ID of the controller that the message refers to, if any.
+    ///
+    /// Returns `None` for messages that do not refer to a particular controller
+    /// (e.g. `Alive`, `Join`, `ControllersList`), and for a `RequestControllersList`
+    /// requesting all controllers.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let request = Message::RequestMoldData { controller_id: ID::from_u32(123), options: Default::default() };
+    /// assert_eq!(Some(ID::from_u32(123)), request.controller_id());
+    ///
+    /// assert_eq!(None, Message::new_alive().controller_id());
+    /// ~~~
+    pub fn controller_id(&self) -> Option<ID> {
+        match self {
+            Alive { .. } | ControllersList { .. } | Join { .. } | JoinResponse { .. } => None,
+            //
+            RequestControllersList { controller_id, .. } => *controller_id,
+            //
+            ControllerAction { controller_id, .. }
+            | ActionResult { controller_id, .. }
+            | ControllerStatus { controller_id, .. }
+            | CycleData { controller_id, .. }
+            | RequestJobCardsList { controller_id, .. }
+            | JobCardsList { controller_id, .. }
+            | RequestMoldData { controller_id, .. }
+            | MoldData { controller_id, .. }
+            | ReadMoldData { controller_id, .. }
+            | MoldDataValue { controller_id, .. }
+            | LoginOperator { controller_id, .. }
+            | OperatorInfo { controller_id, .. } => Some(*controller_id),
+        }
+    }
+
+    /// Get the idempotency key of a `ControllerAction` message, if any.
+    ///
+    /// Returns `None` for any other message.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"ControllerAction","controllerId":123,"actionId":42,"timestamp":"2019-02-26T02:03:04+08:00","idempotencyKey":"abc-123","sequence":1}"#)?;
+    /// assert_eq!(Some("abc-123"), msg.idempotency_key());
+    ///
+    /// assert_eq!(None, Message::new_alive().idempotency_key());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn idempotency_key(&self) -> Option<&str> {
+        match self {
+            ControllerAction { idempotency_key, .. } => {
+                idempotency_key.as_deref().map(AsRef::as_ref)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the type of the message.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let msg = Message::new_alive();
+    /// assert_eq!(MessageKind::Alive, msg.kind());
+    /// assert_eq!(Direction::Both, msg.kind().direction());
+    /// ~~~
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            Alive { .. } => MessageKind::Alive,
+            ControllerAction { .. } => MessageKind::ControllerAction,
+            ActionResult { .. } => MessageKind::ActionResult,
+            RequestControllersList { .. } => MessageKind::RequestControllersList,
+            ControllersList { .. } => MessageKind::ControllersList,
+            ControllerStatus { .. } => MessageKind::ControllerStatus,
+            CycleData { .. } => MessageKind::CycleData,
+            RequestJobCardsList { .. } => MessageKind::RequestJobCardsList,
+            JobCardsList { .. } => MessageKind::JobCardsList,
+            Join { .. } => MessageKind::Join,
+            JoinResponse { .. } => MessageKind::JoinResponse,
+            RequestMoldData { .. } => MessageKind::RequestMoldData,
+            MoldData { .. } => MessageKind::MoldData,
+            ReadMoldData { .. } => MessageKind::ReadMoldData,
+            MoldDataValue { .. } => MessageKind::MoldDataValue,
+            LoginOperator { .. } => MessageKind::LoginOperator,
+            OperatorInfo { .. } => MessageKind::OperatorInfo,
+        }
+    }
+
+    /// Returns true if the message is a request that expects a reply (see [`MessageKind::is_request`]).
+    ///
+    /// [`MessageKind::is_request`]: enum.MessageKind.html#method.is_request
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(Message::new_request_all_controllers().is_request());
+    /// assert!(!Message::new_alive().is_request());
+    /// ~~~
+    pub fn is_request(&self) -> bool {
+        self.kind().is_request()
+    }
+
+    /// Returns true if the message is a reply to a request (see [`MessageKind::is_response`]).
+    ///
+    /// [`MessageKind::is_response`]: enum.MessageKind.html#method.is_response
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?;
+    /// assert!(msg.is_response());
+    /// assert!(!Message::new_alive().is_response());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn is_response(&self) -> bool {
+        self.kind().is_response()
+    }
+
+    /// Returns true if the message is a notification that is neither a request nor a
+    /// response, such as `Alive` (see [`MessageKind::is_notification`] for the classification
+    /// of each type of message).
+    ///
+    /// [`MessageKind::is_notification`]: enum.MessageKind.html#method.is_notification
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(Message::new_alive().is_notification());
+    /// assert!(!Message::new_request_all_controllers().is_notification());
+    /// ~~~
+    pub fn is_notification(&self) -> bool {
+        self.kind().is_notification()
+    }
+
+    /// Returns true if the message is an `ALIVE` keepalive.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(Message::new_alive().is_keepalive());
+    /// assert!(Message::new_alive_with(42).is_keepalive());
+    /// assert!(!Message::new_request_all_controllers().is_keepalive());
+    /// ~~~
+    pub fn is_keepalive(&self) -> bool {
+        matches!(self, Alive { .. })
+    }
+
+    /// Get an unambiguous one-line description of the message, for display or logging.
+    ///
+    /// The description contains the type of the message, its key fields and its sequence
+    /// number, but not bulk data (only the number of items is shown) or passwords.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"RequestMoldData","controllerId":1,"sequence":3}"#)?;
+    /// assert_eq!("RequestMoldData(controller=1, all settings, sequence=3)", msg.describe());
+    ///
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"ReadMoldData","controllerId":1,"field":"Foo","sequence":4}"#)?;
+    /// assert_eq!("ReadMoldData(controller=1, field=Foo, sequence=4)", msg.describe());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn describe(&self) -> String {
+        let mut items = Vec::new();
+
+        if let Some(id) = self.controller_id() {
+            items.push(format!("controller={}", id));
+        }
+
+        match self {
+            Alive { .. }
+            | ControllerStatus { .. }
+            | RequestJobCardsList { .. }
+            | LoginOperator { .. } => (),
+            ControllerAction { action_id, .. } => items.push(format!("action={}", action_id)),
+            ActionResult { action_id, success, reason, .. } => {
+                items.push(format!("action={}", action_id));
+                items.push(format!("success={}", success));
+                if let Some(reason) = reason {
+                    items.push(format!("reason={:?}", reason));
+                }
+            }
+            RequestControllersList { controller_id: None, .. } => {
+                items.push("all controllers".into())
+            }
+            RequestControllersList { .. } => (),
+            ControllersList { data, .. } => items.push(format!("{} controller(s)", data.len())),
+            CycleData { data, .. } => items.push(format!("{} value(s)", data.len())),
+            JobCardsList { data, .. } => items.push(format!("{} job card(s)", data.len())),
+            Join { version, filter, .. } => {
+                items.push(format!("version={}", version));
+                items.push(format!("filter=[{}]", filter));
+            }
+            JoinResponse { result, .. } => items.push(format!("result={}", result)),
+            RequestMoldData { .. } => items.push("all settings".into()),
+            MoldData { data, .. } => items.push(format!("{} setting(s)", data.len())),
+            ReadMoldData { field: None, .. } => items.push("all cached settings".into()),
+            ReadMoldData { field: Some(field), .. } => items.push(format!("field={}", field)),
+            MoldDataValue { field, value, .. } => {
+                items.push(format!("field={}", field));
+                items.push(format!("value={}", value));
+            }
+            OperatorInfo { operator_id, name, level, .. } => {
+                if let Some(id) = operator_id {
+                    items.push(format!("operator={}", id));
+                }
+                items.push(format!("name={}", name));
+                items.push(format!("level={}", level));
+            }
+        }
+
+        items.push(format!("sequence={}", self.sequence()));
+
+        format!("{}({})", self.kind(), items.join(", "))
+    }
+
+    /// Get the type of the reply expected for a request.
+    ///
+    /// | Request                  | Reply           |
+    /// |:-------------------------|:----------------|
+    /// | `Join`                   | `JoinResponse`  |
+    /// | `RequestControllersList` | `ControllersList` |
+    /// | `RequestJobCardsList`    | `JobCardsList`  |
+    /// | `RequestMoldData`        | `MoldData`      |
+    /// | `ReadMoldData` (all)     | `MoldData`      |
+    /// | `ReadMoldData` (single field) | `MoldDataValue` |
+    /// | `LoginOperator`          | `OperatorInfo`  |
+    /// | `ControllerAction`       | `ActionResult`  |
+    ///
+    /// Returns `None` if the message is not a request.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::parse_from_json_str(r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":1}"#)?;
+    /// assert_eq!(Some(MessageKind::JobCardsList), msg.expected_response_kind());
+    ///
+    /// assert_eq!(None, Message::new_alive().expected_response_kind());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn expected_response_kind(&self) -> Option<MessageKind> {
+        match self {
+            Join { .. } => Some(MessageKind::JoinResponse),
+            RequestControllersList { .. } => Some(MessageKind::ControllersList),
+            RequestJobCardsList { .. } => Some(MessageKind::JobCardsList),
+            RequestMoldData { .. } | ReadMoldData { field: None, .. } => {
+                Some(MessageKind::MoldData)
+            }
+            ReadMoldData { field: Some(_), .. } => Some(MessageKind::MoldDataValue),
+            LoginOperator { .. } => Some(MessageKind::OperatorInfo),
+            ControllerAction { .. } => Some(MessageKind::ActionResult),
+            _ => None,
+        }
+    }
+
+    /// Split a `ControllersList` message into individual `ControllerStatus` messages,
+    /// one for each controller in the list, in order.
+    ///
+    /// Each `ControllerStatus` message carries the full [`Controller`] data structure in its
+    /// `controller` field (as if the controller has just connected), with a `state` matching
+    /// the controller's current states.  All other info fields are set to `None`.
+    ///
+    /// This allows a bulk `ControllersList` to be processed in the same way as
+    /// individual `ControllerStatus` updates.
+    ///
+    /// An empty `ControllersList`, or any message that is not a `ControllersList`,
+    /// yields no messages.
+    ///
+    /// [`Controller`]: struct.Controller.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"ControllersList","data":{"123":{"controllerId":123,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"}},"sequence":1}"#;
+    /// let list = Message::parse_from_json_str(json)?;
+    ///
+    /// for msg in list.into_status_messages() {
+    ///     msg.validate()?;
+    ///
+    ///     match msg {
+    ///         Message::ControllerStatus { controller_id, state, .. } => {
+    ///             assert_eq!(123, controller_id);
+    ///             assert_eq!(OpMode::Automatic, state.op_mode());
+    ///         }
+    ///         _ => unreachable!(),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn into_status_messages(self) -> impl Iterator<Item = Message<'a>> {
+        let data = match self {
+            ControllersList { data, .. } => data,
+            _ => IndexMap::new(),
+        };
+
+        data.into_iter().map(|(controller_id, controller)| ControllerStatus {
+            controller_id,
+            display_name: None,
+            is_disconnected: None,
+            op_mode: None,
+            job_mode: None,
+            alarm: None,
+            audit: None,
+            variable: None,
+            operator_id: None,
+            operator_name: None,
+            job_card_id: None,
+            mold_id: None,
+            state: StateValues::from_controller(&controller),
+            controller: Some(Box::new(controller)),
+            options: Default::default(),
+        })
+    }
+
+    /// Iterate over the `(field, value)` pairs of the mold settings in a `MoldData` message.
+    ///
+    /// Mold settings are yielded in the order they appear in the message, which is stable
+    /// across serialization.
+    ///
+    /// Any message that is not a `MoldData` yields no values.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"MoldData","controllerId":1,"data":{"temp.zone2":215.0,"temp.zone1":210.0},"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#;
+    /// let msg = Message::parse_from_json_str(json)?;
+    ///
+    /// let fields: Vec<_> = msg.mold_data_values().map(|(field, value)| (field.get(), value)).collect();
+    /// assert_eq!(vec![("temp.zone2", R32::new(215.0)), ("temp.zone1", R32::new(210.0))], fields);
+    ///
+    /// assert_eq!(0, Message::new_alive().mold_data_values().count());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn mold_data_values(&self) -> impl Iterator<Item = (&TextID<'a>, R32)> {
+        let data = match self {
+            MoldData { data, .. } => Some(data),
+            _ => None,
+        };
+
+        data.into_iter().flatten().map(|(field, value)| (field, *value))
+    }
+
+    /// Consume a `MoldData` message and iterate over the `(field, value)` pairs of its
+    /// mold settings, in the same order as [`mold_data_values`].
+    ///
+    /// Any message that is not a `MoldData` yields no values.
+    ///
+    /// [`mold_data_values`]: enum.Message.html#method.mold_data_values
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::collections::HashMap;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"MoldData","controllerId":1,"data":{"temp.zone1":210.0,"cycles":100.0},"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#;
+    /// let msg = Message::parse_from_json_str(json)?;
+    ///
+    /// let store: HashMap<TextID, R32> = msg.into_mold_data_values().collect();
+    /// assert_eq!(Some(&R32::new(100.0)), store.get("cycles"));
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn into_mold_data_values(self) -> impl Iterator<Item = (TextID<'a>, R32)> {
+        let data = match self {
+            MoldData { data, .. } => data,
+            _ => IndexMap::new(),
+        };
+
+        data.into_iter()
+    }
+
+    // Get references to the controllers in a `ControllersList` message, in list order.
+    fn controllers(&self) -> Vec<&Controller<'a>> {
+        match self {
+            ControllersList { data, .. } => data.values().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the controllers in a `ControllersList` message, sorted by controller ID.
+    ///
+    /// Returns an empty list for any message that is not a `ControllersList`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"ControllersList","data":{
+    ///     "2":{"controllerId":2,"displayName":"B","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"},
+    ///     "1":{"controllerId":1,"displayName":"A","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Manual","jobMode":"ID01"}
+    /// },"sequence":1}"#;
+    /// let msg = Message::parse_from_json_str(json)?;
+    ///
+    /// let ids: Vec<_> = msg.controllers_sorted_by_id().iter().map(|c| c.controller_id).collect();
+    /// assert_eq!(vec![ID::from_u32(1), ID::from_u32(2)], ids);
+    ///
+    /// assert!(Message::new_alive().controllers_sorted_by_id().is_empty());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn controllers_sorted_by_id(&self) -> Vec<&Controller<'a>> {
+        let mut list = self.controllers();
+        list.sort_by_key(|c| c.controller_id);
+        list
+    }
+
+    /// Get the controllers in a `ControllersList` message, sorted by display name.
+    ///
+    /// Controllers with the same display name are kept in list order.
+    ///
+    /// Returns an empty list for any message that is not a `ControllersList`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"ControllersList","data":{
+    ///     "1":{"controllerId":1,"displayName":"Zeta","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"},
+    ///     "2":{"controllerId":2,"displayName":"Alpha","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Manual","jobMode":"ID01"}
+    /// },"sequence":1}"#;
+    /// let msg = Message::parse_from_json_str(json)?;
+    ///
+    /// let names: Vec<_> = msg.controllers_sorted_by_name().iter().map(|c| c.display_name.get()).collect();
+    /// assert_eq!(vec!["Alpha", "Zeta"], names);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn controllers_sorted_by_name(&self) -> Vec<&Controller<'a>> {
+        let mut list = self.controllers();
+        list.sort_by(|a, b| a.display_name.get().cmp(b.display_name.get()));
+        list
+    }
+
+    /// Get the controllers in a `ControllersList` message, sorted by status.
+    ///
+    /// Controllers are ordered by their [`OpMode`]:
+    ///
+    /// 1. Producing (i.e. `SemiAutomatic` or `Automatic`)
+    /// 2. Online but not producing (e.g. `Manual`)
+    /// 3. `Offline`
+    /// 4. `Unknown`
+    ///
+    /// Controllers with the same status are kept in list order.
+    ///
+    /// Returns an empty list for any message that is not a `ControllersList`.
+    ///
+    /// [`OpMode`]: enum.OpMode.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let json = r#"{"$type":"ControllersList","data":{
+    ///     "1":{"controllerId":1,"displayName":"A","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Offline","jobMode":"Offline"},
+    ///     "2":{"controllerId":2,"displayName":"B","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Manual","jobMode":"ID01"},
+    ///     "3":{"controllerId":3,"displayName":"C","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.6:5678","opMode":"Automatic","jobMode":"ID01"}
+    /// },"sequence":1}"#;
+    /// let msg = Message::parse_from_json_str(json)?;
+    ///
+    /// let modes: Vec<_> = msg.controllers_sorted_by_status().iter().map(|c| c.op_mode).collect();
+    /// assert_eq!(vec![OpMode::Automatic, OpMode::Manual, OpMode::Offline], modes);
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn controllers_sorted_by_status(&self) -> Vec<&Controller<'a>> {
+        // Higher rank sorts first
+        fn rank(op_mode: OpMode) -> u8 {
+            match op_mode {
+                _ if op_mode.is_producing() => 3,
+                _ if op_mode.is_online() => 2,
+                OpMode::Offline => 1,
+                _ => 0,
+            }
+        }
+
+        let mut list = self.controllers();
+        list.sort_by_key(|c| std::cmp::Reverse(rank(c.op_mode)));
+        list
+    }
+
+    /// Validate the `Message` data structure.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if some fields in the `Message` are not valid.
     ///
     /// [`OpenProtocolError`]: enum.OpenProtocolError.html
     ///
@@ -905,12 +2658,11 @@ impl<'a> Message<'a> {
     pub fn validate(&self) -> Result<'a, ()> {
         match self {
             Alive { .. }
-            | ControllerAction { .. }
+            | ControllerAction { idempotency_key: None, .. }
             | RequestControllersList { .. }
             | RequestJobCardsList { .. }
             | JoinResponse { .. }
             | RequestMoldData { .. }
-            | ControllersList { .. }
             | CycleData { .. }
             | ReadMoldData { .. }
             | MoldDataValue { .. }
@@ -918,147 +2670,919 @@ impl<'a> Message<'a> {
             | JobCardsList { .. }
             | MoldData { .. } => (),
 
-            ControllerStatus {
-                display_name,
-                is_disconnected,
-                op_mode,
-                job_mode,
-                alarm,
-                audit,
-                variable,
+            ControllerStatus {
+                display_name,
+                is_disconnected,
+                op_mode,
+                job_mode,
+                alarm,
+                audit,
+                variable,
+                operator_id,
+                operator_name,
+                job_card_id,
+                mold_id,
+                state,
+                controller,
+                ..
+            } => {
+                if let Some(c) = controller {
+                    // If controller is present, some fields must be None
+                    if !is_disconnected.is_none()
+                        || !alarm.is_none()
+                        || !audit.is_none()
+                        || !variable.is_none()
+                    {
+                        return Err(Error::ConstraintViolated(
+                            "All other fields must be set to None if controller is present.".into(),
+                        ));
+                    }
+
+                    // Check controller fields with specified fields
+                    if display_name.is_some()
+                        && display_name.as_ref().unwrap().get() != &c.display_name
+                    {
+                        return Err(Error::InconsistentField("display_name"));
+                    }
+                    if operator_name.is_some()
+                        && operator_name.as_ref().unwrap().as_ref().map(|x| x.get())
+                            != c.operator.as_ref().map(|u| u.name()).flatten()
+                    {
+                        return Err(Error::InconsistentField("operator_name"));
+                    }
+
+                    // Check controller fields with the state
+                    if state.op_mode() != c.op_mode {
+                        return Err(Error::InconsistentState("op_mode"));
+                    }
+                    if state.job_mode() != c.job_mode {
+                        return Err(Error::InconsistentState("job_mode"));
+                    }
+                    if state.operator_id() != c.operator.as_ref().map(|user| user.id()) {
+                        return Err(Error::InconsistentState("operator_id"));
+                    }
+                    if state.job_card_id() != c.job_card_id.as_ref().map(|x| x.as_ref().as_ref()) {
+                        return Err(Error::InconsistentState("job_card_id"));
+                    }
+                    if state.mold_id() != c.mold_id.as_ref().map(|x| x.as_ref().as_ref()) {
+                        return Err(Error::InconsistentField("mold_id"));
+                    }
+                }
+
+                // Check controller fields with the state
+                if op_mode.is_some() && op_mode.unwrap() != state.op_mode() {
+                    return Err(Error::InconsistentState("op_mode"));
+                }
+                if job_mode.is_some() && job_mode.unwrap() != state.job_mode() {
+                    return Err(Error::InconsistentState("job_mode"));
+                }
+                if operator_id.is_some() && operator_id.unwrap() != state.operator_id() {
+                    return Err(Error::InconsistentState("operator_id"));
+                }
+                if job_card_id.is_some()
+                    && state.job_card_id()
+                        != job_card_id.as_ref().unwrap().as_ref().map(|jc| jc.get())
+                {
+                    return Err(Error::InconsistentState("job_card_id"));
+                }
+                if mold_id.is_some()
+                    && state.mold_id() != mold_id.as_ref().unwrap().as_ref().map(|m| m.get())
+                {
+                    return Err(Error::InconsistentState("mold_id"));
+                }
+            }
+
+            ControllersList { data, .. } => {
+                // Each controller must appear only once
+                let mut ids = HashSet::with_capacity(data.len());
+
+                if let Some(c) = data.values().find(|c| !ids.insert(c.controller_id)) {
+                    return Err(Error::InvalidField {
+                        field: "controller_id",
+                        value: c.controller_id.to_string().into(),
+                        description: format!("duplicated controller ID {}", c.controller_id).into(),
+                    });
+                }
+            }
+
+            Join { language, filter, .. } => {
+                // Check for invalid language
+                if *language == Language::Unknown {
+                    return Err(Error::InvalidField {
+                        field: "language",
+                        value: "Unknown".into(),
+                        description: "language cannot be Unknown".into(),
+                    });
+                }
+
+                // Check for empty filter which subscribes to nothing
+                if filter.is_empty() {
+                    return Err(Error::InvalidField {
+                        field: "filter",
+                        value: "None".into(),
+                        description: "filter cannot be empty".into(),
+                    });
+                }
+            }
+
+            OperatorInfo { password, .. } => {
+                if password.is_empty() {
+                    return Err(Error::EmptyField("password"));
+                }
+            }
+
+            ActionResult { success: false, reason, .. } => match reason {
+                // A rejected action must have a reason
+                Some(r) if !r.trim().is_empty() => (),
+                _ => return Err(Error::EmptyField("reason")),
+            },
+
+            ActionResult { success: true, .. } => (),
+
+            ControllerAction { idempotency_key: Some(key), .. } => {
+                if key.trim().is_empty() {
+                    return Err(Error::EmptyField("idempotency_key"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A request sent by the iChen® Server that has a natural response.
+///
+/// Requests are variants of [`Message`] rather than distinct types, so a `Message` is both
+/// the request and its [`Response`].  [`new_response`] returns `None` for a message that
+/// does not require a reply.
+///
+/// [`Message`]: enum.Message.html
+/// [`Response`]: trait.RequestMessage.html#associatedtype.Response
+/// [`new_response`]: trait.RequestMessage.html#tymethod.new_response
+///
+pub trait RequestMessage {
+    /// The type of the response.
+    type Response;
+
+    /// Create the natural reply to a request sent by the Server, pre-populated with the
+    /// matching `controller_id` and fresh message options (i.e. a new `sequence`).
+    ///
+    /// The remaining fields of the response can be filled in before it is sent.
+    ///
+    /// Returns `None` if the message does not require a reply.
+    fn new_response(&self) -> Option<Self::Response>;
+}
+
+impl<'a> RequestMessage for Message<'a> {
+    type Response = Message<'a>;
+
+    /// Create the natural reply to a request sent by the Server.
+    ///
+    /// | Request               | Response                                              |
+    /// |:----------------------|:------------------------------------------------------|
+    /// | `Alive`               | `Alive`                                               |
+    /// | `LoginOperator`       | `OperatorInfo` denying access (see [`operator_info_denied`]) |
+    /// | `RequestJobCardsList` | `JobCardsList` with no job cards                      |
+    ///
+    /// [`operator_info_denied`]: enum.Message.html#method.operator_info_denied
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let request = Message::parse_from_json_str(
+    ///     r#"{"$type":"RequestJobCardsList","controllerId":123,"sequence":42}"#,
+    /// )?;
+    ///
+    /// let mut response = request.new_response().unwrap();
+    ///
+    /// if let Message::JobCardsList { controller_id, ref mut data, .. } = response {
+    ///     assert_eq!(123, controller_id);
+    ///     assert!(data.is_empty());
+    ///
+    ///     let job = JobCard::try_new("J001", "Mold#001", 0, 1000)?;
+    ///     data.insert(TextName::new_from_str("J001").unwrap(), job);
+    /// } else {
+    ///     panic!();
+    /// }
+    ///
+    /// assert_ne!(request.sequence(), response.sequence());
+    /// assert!(Message::new_alive().new_response().is_some());
+    /// assert!(Message::new_request_all_controllers().new_response().is_none());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    fn new_response(&self) -> Option<Self::Response> {
+        match self {
+            Alive { .. } => Some(Self::new_alive()),
+            LoginOperator { controller_id, password, .. } => {
+                Some(Self::operator_info_denied(*controller_id, password.clone()))
+            }
+            RequestJobCardsList { controller_id, .. } => Some(JobCardsList {
+                controller_id: *controller_id,
+                data: IndexMap::new(),
+                options: Default::default(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Message<'a> {
+    type Error = Error<'a>;
+
+    /// Parse a JSON string into a `Message`.  This is the same as [`parse_from_json_str`].
+    ///
+    /// [`parse_from_json_str`]: enum.Message.html#method.parse_from_json_str
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(`[`OpenProtocolError`]`)` if there is an error during parsing.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use std::convert::TryFrom;
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let msg = Message::try_from(r#"{"$type":"Alive","sequence":42}"#)?;
+    /// assert_eq!(42, msg.sequence());
+    ///
+    /// let json = [r#"{"$type":"Alive","sequence":1}"#, r#"{"$type":"Alive","sequence":2}"#];
+    /// let messages = json.iter().copied().map(Message::try_from).collect::<Result<Vec<_>>>()?;
+    /// assert_eq!(2, messages.len());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    fn try_from(json: &'a str) -> Result<'a, Self> {
+        Self::parse_from_json_str(json)
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::result::Result;
+
+    impl<'a> MessageOptions<'a> {
+        /// A private constructor function that creates a `MessageOptions` structure
+        /// with `sequence` always set to 1 (for testing purposes).
+        fn default_new() -> Self {
+            Self { sequence: 1, ..Self::new() }
+        }
+    }
+
+    // Maximum size of a `Message` on 64-bit targets: 160 bytes.
+    //
+    // Messages are moved in and out of the send queue, so the enum is kept small by boxing
+    // large fields that are rarely present (e.g. the `controller` of a `ControllerStatus`).
+    // If a change makes this test fail, box the new fields instead of raising the limit.
+    const MAX_MESSAGE_SIZE_OF: usize = 160;
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_message_size_of() {
+        let size = std::mem::size_of::<Message>();
+        assert!(
+            size <= MAX_MESSAGE_SIZE_OF,
+            "Message is {} bytes (max {})",
+            size,
+            MAX_MESSAGE_SIZE_OF
+        );
+    }
+
+    #[test]
+    fn test_message_clone() -> Result<(), String> {
+        fn assert_clone<T: Clone>() {}
+        assert_clone::<Message>();
+        assert_clone::<JobCard>();
+
+        let job = JobCard::try_new("J001", "Mold#001", 0, 1000)?;
+        let msg = Message::job_cards_list(ID::from_u32(1), vec![job])?;
+        assert_eq!(msg.to_json_str()?, msg.clone().to_json_str()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_alive_with_sequence() -> Result<(), String> {
+        let msg = Message::new_alive_with(u64::MAX);
+        assert!(msg.is_keepalive());
+        assert_eq!(u64::MAX, msg.sequence());
+        assert_eq!(0, msg.priority());
+        assert_eq!(None, msg.id());
+
+        let json = msg.to_json_str()?;
+        assert_eq!(format!(r#"{{"$type":"Alive","sequence":{}}}"#, u64::MAX), json);
+
+        let reply = Message::parse_from_json_str(&json)?;
+        assert!(reply.is_keepalive());
+        assert!(reply.is_notification());
+        assert_eq!(u64::MAX, reply.sequence());
+
+        assert!(!Message::new_join("hello", Filters::All).is_keepalive());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_controller_action_retry_same_idempotency_key() -> Result<(), String> {
+        let timestamp =
+            DateTime::parse_from_rfc3339("2019-02-26T02:03:04+08:00").map_err(|x| x.to_string())?;
+        let msg = Message::new_controller_action(ID::from_u32(123), ActionID::new(42), timestamp);
+        let key = msg.idempotency_key().ok_or("no idempotency key")?.to_string();
+        assert!(!key.trim().is_empty());
+
+        // The action is retried (e.g. after a reconnection) by sending it again
+        let json = msg.to_json_str()?;
+        let retry = msg.clone().to_json_str()?;
+        assert_eq!(json, retry);
+        assert_eq!(
+            format!(
+                r#"{{"$type":"ControllerAction","controllerId":123,"actionId":42,"timestamp":"2019-02-26T02:03:04+08:00","idempotencyKey":"{}","sequence":{}}}"#,
+                key,
+                msg.sequence()
+            ),
+            retry
+        );
+
+        // The receiver sees the same key for both
+        assert_eq!(Some(key.as_str()), Message::parse_from_json_str(&json)?.idempotency_key());
+        assert_eq!(Some(key.as_str()), Message::parse_from_json_str(&retry)?.idempotency_key());
+
+        // Another logical action has a different key
+        let other = Message::new_controller_action(ID::from_u32(123), ActionID::new(42), timestamp);
+        assert_ne!(Some(key.as_str()), other.idempotency_key());
+
+        // The key is optional
+        let json = r#"{"$type":"ControllerAction","controllerId":123,"actionId":42,"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#;
+        let msg = Message::parse_from_json_str(json)?;
+        assert_eq!(None, msg.idempotency_key());
+        assert_eq!(json, msg.to_json_str()?);
+
+        // ... but cannot be empty
+        let json = r#"{"$type":"ControllerAction","controllerId":123,"actionId":42,"timestamp":"2019-02-26T02:03:04+08:00","idempotencyKey":" ","sequence":1}"#;
+        assert_eq!(
+            Err(Error::EmptyField("idempotency_key")),
+            Message::parse_from_json_str(json).map(|_| ())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_new_operator_info_rejects_zero_id() -> Result<(), String> {
+        let zero_id = || Error::InvalidField {
+            field: "operator_id",
+            value: "0".into(),
+            description: "operator ID cannot be zero".into(),
+        };
+        let new = |operator_id: u32, level: u8| {
+            Message::new_operator_info(
+                ID::from_u32(1),
                 operator_id,
-                operator_name,
-                job_card_id,
-                mold_id,
-                state,
-                controller,
-                ..
-            } => {
-                if let Some(c) = controller {
-                    // If controller is present, some fields must be None
-                    if !is_disconnected.is_none()
-                        || !alarm.is_none()
-                        || !audit.is_none()
-                        || !variable.is_none()
-                    {
-                        return Err(Error::ConstraintViolated(
-                            "All other fields must be set to None if controller is present.".into(),
-                        ));
-                    }
+                Password::new("pw"),
+                level,
+                "John",
+            )
+            .map(|msg| match msg {
+                OperatorInfo { operator_id, .. } => operator_id,
+                _ => unreachable!(),
+            })
+        };
 
-                    // Check controller fields with specified fields
-                    if display_name.is_some()
-                        && display_name.as_ref().unwrap().get() != &c.display_name
-                    {
-                        return Err(Error::InconsistentField("display_name"));
-                    }
-                    if operator_name.is_some()
-                        && operator_name.as_ref().unwrap().as_ref().map(|x| x.get())
-                            != c.operator.as_ref().map(|u| u.name()).flatten()
-                    {
-                        return Err(Error::InconsistentField("operator_name"));
-                    }
+        assert_eq!(Err(zero_id()), new(0, 5));
 
-                    // Check controller fields with the state
-                    if state.op_mode() != c.op_mode {
-                        return Err(Error::InconsistentState("op_mode"));
-                    }
-                    if state.job_mode() != c.job_mode {
-                        return Err(Error::InconsistentState("job_mode"));
-                    }
-                    if state.operator_id() != c.operator.as_ref().map(|user| user.id()) {
-                        return Err(Error::InconsistentState("operator_id"));
-                    }
-                    if state.job_card_id() != c.job_card_id.as_ref().map(|x| x.as_ref().as_ref()) {
-                        return Err(Error::InconsistentState("job_card_id"));
-                    }
-                    if state.mold_id() != c.mold_id.as_ref().map(|x| x.as_ref().as_ref()) {
-                        return Err(Error::InconsistentField("mold_id"));
-                    }
-                }
+        // Computed IDs that wrap around to zero
+        assert_eq!(Err(zero_id()), new(u32::MAX.wrapping_add(1), 5));
+        assert_eq!(Err(zero_id()), new(u32::from(u8::MAX.wrapping_add(1)), 5));
 
-                // Check controller fields with the state
-                if op_mode.is_some() && op_mode.unwrap() != state.op_mode() {
-                    return Err(Error::InconsistentState("op_mode"));
-                }
-                if job_mode.is_some() && job_mode.unwrap() != state.job_mode() {
-                    return Err(Error::InconsistentState("job_mode"));
-                }
-                if operator_id.is_some() && operator_id.unwrap() != state.operator_id() {
-                    return Err(Error::InconsistentState("operator_id"));
-                }
-                if job_card_id.is_some()
-                    && state.job_card_id()
-                        != job_card_id.as_ref().unwrap().as_ref().map(|jc| jc.get())
-                {
-                    return Err(Error::InconsistentState("job_card_id"));
-                }
-                if mold_id.is_some()
-                    && state.mold_id() != mold_id.as_ref().unwrap().as_ref().map(|m| m.get())
-                {
-                    return Err(Error::InconsistentState("mold_id"));
+        // The level is checked first
+        assert_eq!(
+            Err(Error::ConstraintViolated(
+                "Level 11 is too high - must be between 0 and 10.".into()
+            )),
+            new(0, 11)
+        );
+
+        assert_eq!(Ok(Some(ID::from_u32(u32::MAX))), new(u32::MAX, 0));
+
+        // `operator_info` derives the ID as `level + 1` without wrapping
+        for level in 0..=Message::MAX_OPERATOR_LEVEL {
+            match Message::operator_info(ID::from_u32(1), Password::new("pw"), level, "John")? {
+                OperatorInfo { operator_id, .. } => {
+                    assert_eq!(Some(ID::from_u32(u32::from(level) + 1)), operator_id)
                 }
+                _ => unreachable!(),
             }
+        }
+        assert!(matches!(
+            Message::operator_info(ID::from_u32(1), Password::new("pw"), u8::MAX, "John"),
+            Err(Error::ConstraintViolated(_))
+        ));
 
-            Join { language, .. } => {
-                // Check for invalid language
-                if *language == Language::Unknown {
-                    return Err(Error::InvalidField {
-                        field: "language",
-                        value: "Unknown".into(),
-                        description: "language cannot be Unknown".into(),
-                    });
-                }
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_alive_to_json() -> Result<(), String> {
+        let mut options = MessageOptions::new_with_priority(20);
+        options.sequence = 999;
+        options.set_id("hello")?;
+
+        let msg = Alive { options };
+
+        let serialized = serde_json::to_string(&msg).map_err(|x| x.to_string())?;
+
+        assert_eq!(r#"{"$type":"Alive","id":"hello","sequence":999,"priority":20}"#, serialized);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_parse_ndjson() -> Result<(), String> {
+        let text = concat!(
+            r#"{"$type":"Alive","sequence":1}"#,
+            "\n",
+            r#"{"$type":"Alive","sequence":2,"#,
+            "\n\n",
+            r#"{"$type":"RequestMoldData","controllerId":123,"sequence":3}"#,
+            "\n"
+        );
+
+        let messages: Vec<_> = Message::parse_ndjson(text).collect();
+
+        assert_eq!(3, messages.len());
+        assert_eq!(1, messages[0].as_ref().map_err(|x| x.to_string())?.sequence());
+        assert!(messages[1].is_err());
+
+        if let RequestMoldData { controller_id, options } =
+            messages[2].as_ref().map_err(|x| x.to_string())?
+        {
+            assert_eq!(123, *controller_id);
+            assert_eq!(3, options.sequence());
+            Ok(())
+        } else {
+            Err(format!("Expected RequestMoldData, got {:#?}", messages[2]))
+        }
+    }
+
+    #[cfg(feature = "legacy")]
+    #[test]
+    fn test_message_legacy_snake_case_from_json() -> Result<(), String> {
+        let json = r#"{"$type":"ControllerStatus","controllerId":123,"displayName":"Testing","opMode":"Automatic","jobMode":"ID05","jobCardId":"XYZ","operatorId":42,"state":{"opMode":"Automatic","jobMode":"ID05","operatorId":42,"jobCardId":"XYZ"},"controller":{"controllerId":123,"displayName":"Testing","controllerType":"Ai02","version":"2.2","model":"JM138Ai","IP":"192.168.1.1:12345","opMode":"Automatic","jobMode":"ID05","jobCardId":"XYZ","operatorId":42,"operatorName":"John","lastCycleData":{"INJ":5}},"sequence":1}"#;
+        let legacy = r#"{"$type":"ControllerStatus","controller_id":123,"display_name":"Testing","op_mode":"Automatic","job_mode":"ID05","job_card_id":"XYZ","operator_id":42,"state":{"op_mode":"Automatic","job_mode":"ID05","operator_id":42,"job_card_id":"XYZ"},"controller":{"controller_id":123,"display_name":"Testing","controller_type":"Ai02","version":"2.2","model":"JM138Ai","IP":"192.168.1.1:12345","op_mode":"Automatic","job_mode":"ID05","job_card_id":"XYZ","operator_id":42,"operator_name":"John","last_cycle_data":{"INJ":5}},"sequence":1}"#;
+
+        let msg = Message::parse_from_json_str(json).map_err(|x| x.to_string())?;
+        let msg2 = Message::parse_from_json_str(legacy).map_err(|x| x.to_string())?;
+
+        assert_eq!(format!("{:?}", msg), format!("{:?}", msg2));
+
+        // Always serialize to camelCase
+        assert_eq!(msg.to_json_str()?, msg2.to_json_str()?);
+        assert!(!msg2.to_json_str()?.contains("controller_id"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_join_filters() -> Result<(), String> {
+        // Empty filter subscribes to nothing
+        let msg = Message::new_join("hello", Filters::None);
+        assert_eq!(
+            Err(Error::InvalidField {
+                field: "filter",
+                value: "None".into(),
+                description: "filter cannot be empty".into()
+            }),
+            msg.validate()
+        );
+        assert!(Message::try_new_join("hello", Filters::None).is_err());
+
+        // All
+        let msg = Message::try_new_join("hello", Filters::All)?;
+        assert!(msg.to_json_str()?.contains(r#""filter":"All""#));
+
+        // All plus machine-related filters is redundant
+        let msg = Message::try_new_join("hello", Filters::All + Filters::Cycle)?;
+        assert!(msg.to_json_str()?.contains(r#""filter":"All""#));
+
+        // All does not include MIS/MES filters
+        let filters: Filters = [Filters::All, Filters::JobCards].iter().copied().collect();
+        let msg = Message::try_new_join("hello", filters)?;
+        assert!(msg.to_json_str()?.contains(r#""filter":"All, JobCards""#));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_action_result_round_trip() -> Result<(), String> {
+        let msg = ActionResult {
+            controller_id: ID::from_u32(123),
+            action_id: ActionID::new(42),
+            success: true,
+            reason: None,
+            options: MessageOptions::default_new(),
+        };
+
+        let json = msg.to_json_str()?;
+        assert_eq!(
+            r#"{"$type":"ActionResult","controllerId":123,"actionId":42,"success":true,"sequence":1}"#,
+            json
+        );
+
+        match Message::parse_from_json_str(&json)? {
+            ActionResult { controller_id, action_id, success, reason, .. } => {
+                assert_eq!(123, controller_id);
+                assert_eq!(ActionID::new(42), action_id);
+                assert!(success);
+                assert_eq!(None, reason);
+            }
+            m => panic!("expected ActionResult, got {:?}", m),
+        }
+
+        let json = r#"{"$type":"ActionResult","controllerId":123,"actionId":42,"success":false,"reason":"Door \"A\" open","sequence":1}"#;
+        let msg = Message::parse_from_json_str(json)?;
+
+        match &msg {
+            ActionResult { success, reason, .. } => {
+                assert!(!success);
+                assert_eq!(Some(r#"Door "A" open"#), reason.as_deref().map(AsRef::as_ref));
             }
+            m => panic!("expected ActionResult, got {:?}", m),
+        }
+        assert_eq!(json, msg.to_json_str()?);
+        assert_eq!(MessageKind::ActionResult, msg.kind());
+        assert_eq!(Some(ID::from_u32(123)), msg.controller_id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_action_result_validate() -> Result<(), String> {
+        let id = ID::from_u32(123);
+        let action_id = ActionID::new(42);
+
+        // A rejected action must have a reason
+        for reason in &[None, Some(""), Some("   ")] {
+            assert_eq!(
+                Err(Error::EmptyField("reason")),
+                Message::try_new_action_result(id, action_id, false, *reason).map(|_| ())
+            );
+        }
+        assert!(Message::parse_from_json_str(
+            r#"{"$type":"ActionResult","controllerId":123,"actionId":42,"success":false,"reason":"","sequence":1}"#
+        )
+        .is_err());
+
+        // An accepted action may have no reason
+        Message::try_new_action_result(id, action_id, true, None)?;
+        Message::try_new_action_result(id, action_id, true, Some(""))?;
+        Message::try_new_action_result(id, action_id, false, Some("Busy"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_request_response_pairs() -> Result<(), String> {
+        for (request, response) in &[
+            (
+                r#"{"$type":"Join","version":"4.0","password":"hello","language":"EN","filter":"All","sequence":1}"#,
+                r#"{"$type":"JoinResponse","result":100,"sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"RequestControllersList","sequence":1}"#,
+                r#"{"$type":"ControllersList","data":{},"sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":1}"#,
+                r#"{"$type":"JobCardsList","controllerId":1,"data":{},"sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"RequestMoldData","controllerId":1,"sequence":1}"#,
+                r#"{"$type":"MoldData","controllerId":1,"data":{},"timestamp":"2019-01-01T00:00:00+08:00","sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"ReadMoldData","controllerId":1,"field":"ClampPos","sequence":1}"#,
+                r#"{"$type":"MoldDataValue","controllerId":1,"field":"ClampPos","value":1.5,"sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"LoginOperator","controllerId":1,"password":"hello","sequence":1}"#,
+                r#"{"$type":"OperatorInfo","controllerId":1,"name":"John","password":"hello","level":1,"sequence":2}"#,
+            ),
+            (
+                r#"{"$type":"ControllerAction","controllerId":1,"actionId":42,"timestamp":"2019-01-01T00:00:00+08:00","sequence":1}"#,
+                r#"{"$type":"ActionResult","controllerId":1,"actionId":42,"success":true,"sequence":2}"#,
+            ),
+        ] {
+            let request = Message::parse_from_json_str(request)?;
+            let response = Message::parse_from_json_str(response)?;
+
+            assert!(request.is_request(), "{:?}", request);
+            assert!(!request.is_response(), "{:?}", request);
+            assert!(response.is_response(), "{:?}", response);
+            assert!(!response.is_request(), "{:?}", response);
+            assert_eq!(Some(response.kind()), request.expected_response_kind());
+            assert_eq!(None, response.expected_response_kind());
+        }
+
+        // Reading all mold data returns `MoldData`
+        let msg = Message::parse_from_json_str(
+            r#"{"$type":"ReadMoldData","controllerId":1,"sequence":1}"#,
+        )?;
+        assert_eq!(Some(MessageKind::MoldData), msg.expected_response_kind());
 
-            OperatorInfo { level, .. } => {
-                if *level > Self::MAX_OPERATOR_LEVEL {
-                    return Err(Error::ConstraintViolated(
-                        format!(
-                            "Level {} is too high - must be between 0 and {}.",
-                            level,
-                            Self::MAX_OPERATOR_LEVEL
-                        )
-                        .into(),
-                    ));
+        // Neither requests nor responses
+        for msg in &[
+            Message::new_alive(),
+            Message::parse_from_json_str(
+                r#"{"$type":"CycleData","controllerId":1,"data":{},"timestamp":"2019-01-01T00:00:00+08:00","sequence":1}"#,
+            )?,
+        ] {
+            assert!(!msg.is_request());
+            assert!(!msg.is_response());
+            assert_eq!(None, msg.expected_response_kind());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_try_from_str() -> Result<(), String> {
+        let msg =
+            Message::try_from(r#"{"$type":"RequestMoldData","controllerId":123,"sequence":1}"#)?;
+        assert_eq!(Some(ID::from_u32(123)), msg.controller_id());
+
+        let json = [
+            r#"{"$type":"Alive","sequence":1}"#,
+            r#"{"$type":"RequestControllersList","sequence":2}"#,
+            r#"{"$type":"LoginOperator","controllerId":1,"password":"hello","sequence":3}"#,
+        ];
+
+        let messages = json
+            .iter()
+            .copied()
+            .map(Message::try_from)
+            .collect::<std::result::Result<Vec<_>, Error>>()?;
+        assert_eq!(
+            vec![
+                MessageKind::Alive,
+                MessageKind::RequestControllersList,
+                MessageKind::LoginOperator
+            ],
+            messages.iter().map(Message::kind).collect::<Vec<_>>()
+        );
+
+        // The first error is returned
+        let json = vec![r#"{"$type":"Alive","sequence":1}"#, r#"{"$type":"Unknown"}"#, "{"];
+        match json.into_iter().map(Message::try_from).collect::<std::result::Result<Vec<_>, _>>() {
+            Err(Error::JsonError(err)) => assert!(err.to_string().contains("Unknown")),
+            r => panic!("expected JsonError, got {:?}", r),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_batch() -> Result<(), String> {
+        // Empty batch
+        assert_eq!("[]", Message::batch_to_json(&[])?);
+        assert!(Message::parse_batch("[]")?.is_empty());
+        assert!(Message::parse_batch(" [ ] ")?.is_empty());
+
+        // Single message
+        let msg = Message::new_join("hello", Filters::All);
+        let json = Message::batch_to_json(std::slice::from_ref(&msg))?;
+        assert_eq!(format!("[{}]", msg.to_json_str()?), json);
+
+        let parsed = Message::parse_batch(&json)?;
+        assert_eq!(1, parsed.len());
+        assert_eq!(msg.to_json_str()?, parsed[0].to_json_str()?);
+
+        // Invalid message in batch
+        let bad = Message::new_join("hello", Filters::None);
+        assert!(Message::batch_to_json(&[Message::new_alive(), bad]).is_err());
+
+        // Not an array, or invalid message
+        assert!(Message::parse_batch(r#"{"$type":"Alive","sequence":1}"#).is_err());
+        assert!(Message::parse_batch(r#"[{"$type":"Alive","sequence":1},{"$type":"Unknown"}]"#)
+            .is_err());
+        assert!(Message::parse_batch(
+            r#"[{"$type":"Join","version":"4.0","password":"x","language":"EN","filter":"None","sequence":1}]"#
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_join_version() -> Result<(), String> {
+        // Default protocol version
+        let msg = Message::new_join("hello", Filters::All);
+        assert!(msg.to_json_str()?.contains(r#""version":"4.0""#));
+
+        // Custom protocol version
+        let msg = Message::try_new_join_with_version("hello", Filters::All, "4.1")?;
+        let json = msg.to_json_str()?;
+        assert!(json.contains(r#""version":"4.1""#));
+
+        match Message::parse_from_json_str(&json)? {
+            Join { version, .. } => assert_eq!("4.1", version.get()),
+            m => panic!("expected Join, got {:?}", m),
+        }
+
+        assert!(Message::try_new_join_with_version("hello", Filters::All, "").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_join_response_server_version() -> Result<(), String> {
+        // Without version
+        let json = r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#;
+        let msg = Message::parse_from_json_str(json)?;
+        assert_eq!(None, msg.server_version());
+        assert!(!msg.to_json_str()?.contains("version"));
+
+        // With version
+        let json =
+            r#"{"$type":"JoinResponse","result":100,"level":10,"version":"4.1","sequence":1}"#;
+        let msg = Message::parse_from_json_str(json)?;
+        assert_eq!(Some("4.1"), msg.server_version());
+        assert!(msg.to_json_str()?.contains(r#""version":"4.1""#));
+
+        // Other messages
+        assert_eq!(None, Message::new_join("hello", Filters::All).server_version());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    #[tracing_test::traced_test]
+    fn test_message_parse_error_logs_warning() {
+        assert!(Message::parse_from_json_str(r#"{"$type":"Unknown","sequence":1}"#).is_err());
+        assert!(logs_contain("WARN"));
+        assert!(logs_contain("cannot parse message"));
+    }
+
+    #[test]
+    fn test_message_into_status_messages() -> Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{"123":{"controllerId":123,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01","operatorId":456,"operatorName":"John","moldId":"ABC"},"789":{"controllerId":789,"displayName":"World","controllerType":"Ai02","version":"2.0","model":"JM138","IP":"5.6.7.8:1234","opMode":"Manual","jobMode":"ID02"}},"sequence":1}"#;
+
+        let messages: Vec<_> = Message::parse_from_json_str(json)?.into_status_messages().collect();
+        assert_eq!(2, messages.len());
+
+        for (msg, id) in messages.iter().zip(&[123, 789]) {
+            msg.validate()?;
+
+            match msg {
+                ControllerStatus { controller_id, controller: Some(c), .. } => {
+                    assert_eq!(*id, *controller_id);
+                    assert_eq!(*id, c.controller_id);
                 }
+                _ => return Err(format!("Expected ControllerStatus, got {:#?}", msg)),
             }
         }
 
+        if let ControllerStatus { state, .. } = &messages[0] {
+            assert_eq!(OpMode::Automatic, state.op_mode());
+            assert_eq!(Some(ID::from_u32(456)), state.operator_id());
+            assert_eq!(Some("ABC"), state.mold_id());
+        }
+
+        let json = r#"{"$type":"ControllersList","data":{},"sequence":1}"#;
+        assert_eq!(0, Message::parse_from_json_str(json)?.into_status_messages().count());
+        assert_eq!(0, Message::new_alive().into_status_messages().count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_controllers_sorted() -> Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{
+            "5":{"controllerId":5,"displayName":"Echo","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Unknown","jobMode":"Unknown"},
+            "3":{"controllerId":3,"displayName":"Charlie","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.3:5678","opMode":"Offline","jobMode":"Offline"},
+            "4":{"controllerId":4,"displayName":"Delta","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Manual","jobMode":"ID01"},
+            "1":{"controllerId":1,"displayName":"Alpha","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.1:5678","opMode":"SemiAutomatic","jobMode":"ID01"},
+            "6":{"controllerId":6,"displayName":"Alpha","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.6:5678","opMode":"Others","jobMode":"ID01"},
+            "2":{"controllerId":2,"displayName":"Bravo","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.2:5678","opMode":"Automatic","jobMode":"ID01"}
+        },"sequence":1}"#;
+
+        let msg = Message::parse_from_json_str(json)?;
+        let ids = |list: Vec<&Controller>| -> Vec<u32> {
+            list.iter().map(|c| u32::from(c.controller_id)).collect()
+        };
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], ids(msg.controllers_sorted_by_id()));
+
+        // Equal names are kept in list order
+        assert_eq!(vec![1, 6, 2, 3, 4, 5], ids(msg.controllers_sorted_by_name()));
+
+        // Producing > Online > Offline > Unknown, equal status kept in list order
+        assert_eq!(vec![1, 2, 4, 6, 3, 5], ids(msg.controllers_sorted_by_status()));
+
+        assert!(Message::new_alive().controllers_sorted_by_id().is_empty());
+        assert!(Message::new_alive().controllers_sorted_by_name().is_empty());
+        assert!(Message::new_alive().controllers_sorted_by_status().is_empty());
+
         Ok(())
     }
-}
 
-// Tests
+    #[test]
+    fn test_message_escaped_strings_round_trip() -> Result<(), String> {
+        let json = r#"{"$type":"OperatorInfo","controllerId":123,"operatorId":1,"name":"J\"o\\hn\n","password":"\u0012pass\"word","level":3,"sequence":1}"#;
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use std::result::Result;
+        let msg = Message::parse_from_json_str(json)?;
 
-    impl<'a> MessageOptions<'a> {
-        /// A private constructor function that creates a `MessageOptions` structure
-        /// with `sequence` always set to 1 (for testing purposes).
-        fn default_new() -> Self {
-            Self { sequence: 1, ..Self::new() }
+        if let OperatorInfo { name, password, .. } = &msg {
+            assert_eq!("J\"o\\hn\n", name.get());
+            assert_eq!("\u{12}pass\"word", password.get());
+        } else {
+            return Err(format!("Expected OperatorInfo, got {:#?}", msg));
+        }
+
+        assert_eq!(json, msg.to_json_str()?);
+
+        // A `TextID` holds escape sequences, as an owned copy
+        let json = r#"{"$type":"ReadMoldData","controllerId":123,"field":"A\"B","sequence":1}"#;
+        let msg = Message::parse_from_json_str(json)?;
+
+        if let ReadMoldData { field: Some(field), .. } = &msg {
+            assert_eq!("A\"B", field.get());
+        } else {
+            return Err(format!("Expected ReadMoldData, got {:#?}", msg));
         }
+
+        assert_eq!(json, msg.to_json_str()?);
+
+        Ok(())
     }
 
     #[test]
-    fn test_message_alive_to_json() -> Result<(), String> {
-        let mut options = MessageOptions::new_with_priority(20);
-        options.sequence = 999;
-        options.set_id("hello")?;
+    fn test_message_request_controllers_list_to_json() -> Result<(), String> {
+        let msg = Message::new_request_controllers_list(Some(ID::from_u32(123)));
+        assert_eq!(
+            format!(
+                r#"{{"$type":"RequestControllersList","controllerId":123,"sequence":{}}}"#,
+                msg.sequence()
+            ),
+            msg.to_json_str()?
+        );
 
-        let msg = Alive { options };
+        let msg = Message::new_request_all_controllers();
+        let json = msg.to_json_str()?;
+        assert!(!json.contains("controllerId"));
+        assert_eq!(
+            format!(r#"{{"$type":"RequestControllersList","sequence":{}}}"#, msg.sequence()),
+            json
+        );
 
-        let serialized = serde_json::to_string(&msg).map_err(|x| x.to_string())?;
+        Ok(())
+    }
 
-        assert_eq!(r#"{"$type":"Alive","id":"hello","sequence":999,"priority":20}"#, serialized);
+    #[test]
+    fn test_message_password_redacted() -> Result<(), String> {
+        let msg = Message::new_join("MySecret", Filters::All);
+        assert!(!format!("{:?}", msg).contains("MySecret"));
+        assert!(!format!("{:#?}", msg).contains("MySecret"));
+        assert!(format!("{:?}", msg).contains(Password::REDACTED));
+        assert!(msg.to_json_str()?.contains(r#""password":"MySecret""#));
+
+        let msg = Message::parse_from_json_str(
+            r#"{"$type":"LoginOperator","controllerId":123,"password":"MySecret","sequence":1}"#,
+        )?;
+        assert!(!format!("{:?}", msg).contains("MySecret"));
+        assert!(!format!("{:#?}", msg).contains("MySecret"));
+        if let LoginOperator { password, .. } = msg {
+            assert_eq!("MySecret", password.get());
+            assert_eq!(Password::REDACTED, password.to_string());
+        } else {
+            return Err(format!("Expected LoginOperator, got {:#?}", msg));
+        }
+
+        let msg = OperatorInfo {
+            controller_id: ID::from_u32(123),
+            operator_id: Some(ID::from_u32(1)),
+            name: "John".try_into()?,
+            password: Password::new("MySecret"),
+            level: AccessLevel::new(5).unwrap(),
+            options: MessageOptions::default_new(),
+        };
+        assert!(!format!("{:?}", msg).contains("MySecret"));
+        assert!(!format!("{:#?}", msg).contains("MySecret"));
+        assert!(msg.to_json_str()?.contains(r#""password":"MySecret""#));
 
         Ok(())
     }
 
+    // Checks the standard names of enum values
+    #[cfg(not(feature = "lowercase-enums"))]
     #[test]
     fn test_message_mold_data_to_json() -> Result<(), String> {
         let mut map: IndexMap<TextID, R32> = IndexMap::new();
@@ -1118,6 +3642,110 @@ mod test {
         }
     }
 
+    #[test]
+    #[cfg(feature = "binary")]
+    fn test_message_binary_round_trip() -> Result<(), String> {
+        for json in &[
+            r#"{"$type":"Alive","sequence":1}"#,
+            r#"{"$type":"Join","orgId":"MyCompany","version":"4.0","password":"MyPassword","language":"EN","filter":"Status, Cycle","sequence":1}"#,
+            r#"{"$type":"ControllersList","data":{"123":{"controllerId":123,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01","lastCycleData":{"Z_QDGODCNT":123,"Z_QDCYCTIM":12.33},"lastConnectionTime":"2016-03-06T23:11:27.1442+08:00"}},"sequence":1}"#,
+            r#"{"$type":"CycleData","controllerId":123,"data":{"Z_QDGODCNT":123,"Z_QDCYCTIM":12.33,"Z_QDINJTIM":3,"Z_QDPLSTIM":4.4,"Z_QDINJENDPOS":30.1,"Z_QDPLSENDPOS":20.3,"Z_QDFLAG":1,"Z_QDPRDCNT":500},"timestamp":"2016-02-26T01:12:23+08:00","jobCardId":"XYZ","moldId":"Mold-123","opMode":"Automatic","jobMode":"ID02","sequence":1,"priority":10}"#,
+        ] {
+            let msg = Message::parse_from_json_str(json)?;
+            let bytes = msg.to_bytes()?;
+
+            assert!(bytes.len() < json.len(), "{} bytes: {}", bytes.len(), json);
+            assert_eq!(msg.to_json_str()?, Message::from_bytes(&bytes)?.to_json_str()?);
+        }
+
+        assert!(Message::from_bytes(&[0xc1, 0x00]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_value_round_trip() -> Result<(), String> {
+        for json in &[
+            r#"{"$type":"Alive","sequence":1}"#,
+            r#"{"$type":"Join","orgId":"MyCompany","version":"4.0","password":"MyPassword","language":"EN","filter":"Status, Cycle","sequence":1}"#,
+            r#"{"$type":"ControllersList","data":{"123":{"controllerId":123,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01","lastCycleData":{"Z_QDGODCNT":123,"Z_QDCYCTIM":12.33},"lastConnectionTime":"2016-03-06T23:11:27.1442+08:00","jobCardId":"ABC\"DEF"}},"sequence":1}"#,
+            r#"{"$type":"CycleData","controllerId":123,"data":{"Z_QDGODCNT":123,"Z_QDCYCTIM":12.33},"timestamp":"2016-02-26T01:12:23+08:00","jobCardId":"XYZ","moldId":"Mold-123","opMode":"Automatic","jobMode":"ID02","sequence":1,"priority":10}"#,
+        ] {
+            let msg = Message::parse_from_json_str(json)?;
+            let value = msg.to_value()?;
+
+            let original: serde_json::Value = serde_json::from_str(json).unwrap();
+            assert_eq!(original["$type"], value["$type"]);
+            assert_eq!(original["sequence"], value["sequence"]);
+            assert_eq!(value, Message::from_value(&value)?.to_value()?);
+        }
+
+        // Invalid messages are rejected
+        let value = serde_json::json!({ "$type": "Alive" });
+        assert!(matches!(Message::from_value(&value), Err(Error::JsonError(_))));
+
+        let json = r#"{"$type":"ControllersList","data":{
+            "1":{"controllerId":1,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"},
+            "2":{"controllerId":1,"displayName":"World","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Manual","jobMode":"ID01"}
+        },"sequence":1}"#;
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        match Message::from_value(&value) {
+            Err(Error::InvalidField { field: "controller_id", .. }) => (),
+            result => return Err(format!("Expected InvalidField, got {:?}", result)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_controllers_list_duplicated_ids() -> Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{
+            "1":{"controllerId":1,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"},
+            "2":{"controllerId":2,"displayName":"World","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"Manual","jobMode":"ID01"}
+        },"sequence":1}"#;
+
+        Message::parse_from_json_str(json)?.validate()?;
+
+        let json = json.replace(r#""controllerId":2"#, r#""controllerId":1"#);
+
+        match Message::parse_from_json_str(&json) {
+            Err(Error::InvalidField { field: "controller_id", value, description }) => {
+                assert_eq!("1", value);
+                assert_eq!("duplicated controller ID 1", description);
+            }
+            result => return Err(format!("Expected InvalidField, got {:?}", result)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_controllers_list_unrecognized_modes() -> Result<(), String> {
+        let json = r#"{"$type":"ControllersList","data":{
+            "1":{"controllerId":1,"displayName":"Hello","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.4:5678","opMode":"Automatic","jobMode":"ID01"},
+            "2":{"controllerId":2,"displayName":"World","controllerType":"Ai01","version":"1.0","model":"JM128","IP":"1.2.3.5:5678","opMode":"TurboMode","jobMode":"ID99"}
+        },"sequence":1}"#;
+
+        let result = Message::parse_from_json_str(json);
+
+        // One unrecognized value fails the entire list unless `forward-compat` is enabled
+        #[cfg(not(feature = "forward-compat"))]
+        assert!(result.is_err());
+
+        #[cfg(feature = "forward-compat")]
+        match result? {
+            ControllersList { data, .. } => {
+                assert_eq!(OpMode::Automatic, data[&ID::from_u32(1)].op_mode);
+                assert_eq!(OpMode::Unrecognized, data[&ID::from_u32(2)].op_mode);
+                assert_eq!(JobMode::Unrecognized, data[&ID::from_u32(2)].job_mode);
+            }
+            msg => return Err(format!("Expected ControllersList, got {:?}", msg)),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_message_cycle_data_from_json() -> Result<(), String> {
         let json = r#"{"$type":"CycleData","timestamp":"2016-02-26T01:12:23+08:00","opMode":"Automatic","jobMode":"ID02","controllerId":123,"data":{"Z_QDGODCNT":123,"Z_QDCYCTIM":12.33,"Z_QDINJTIM":3,"Z_QDPLSTIM":4.4,"Z_QDINJENDPOS":30.1,"Z_QDPLSENDPOS":20.3,"Z_QDFLAG":1,"Z_QDPRDCNT":500,"Z_QDCOLTIM":12.12,"Z_QDMLDOPNTIM":2.1,"Z_QDMLDCLSTIM":1.3,"Z_QDVPPOS":12.11,"Z_QDMLDOPNENDPOS":130.1,"Z_QDMAXINJSPD":213.12,"Z_QDMAXPLSRPM":551,"Z_QDNOZTEMP":256,"Z_QDTEMPZ01":251,"Z_QDTEMPZ02":252,"Z_QDTEMPZ03":253,"Z_QDTEMPZ04":254,"Z_QDTEMPZ05":255,"Z_QDTEMPZ06":256,"Z_QDBCKPRS":54,"Z_QDHLDTIM":2.3,"Z_QDCPT01":231,"Z_QDCPT02":232,"Z_QDCPT03":233,"Z_QDCPT04":234,"Z_QDCPT05":235,"Z_QDCPT06":236,"Z_QDCPT07":237,"Z_QDCPT08":238,"Z_QDCPT09":239,"Z_QDCPT10":240,"Z_QDCPT11":241,"Z_QDCPT12":242,"Z_QDCPT13":243,"Z_QDCPT14":244,"Z_QDCPT15":245,"Z_QDCPT16":246,"Z_QDCPT17":247,"Z_QDCPT18":248,"Z_QDCPT19":249,"Z_QDCPT20":250,"Z_QDCPT21":251,"Z_QDCPT22":252,"Z_QDCPT23":253,"Z_QDCPT24":254,"Z_QDCPT25":255,"Z_QDCPT26":256,"Z_QDCPT27":257,"Z_QDCPT28":258,"Z_QDCPT29":259,"Z_QDCPT30":260,"Z_QDCPT31":261,"Z_QDCPT32":262,"Z_QDCPT33":263,"Z_QDCPT34":264,"Z_QDCPT35":265,"Z_QDCPT36":266,"Z_QDCPT37":267,"Z_QDCPT38":268,"Z_QDCPT39":269,"Z_QDCPT40":270},"sequence":1}"#;
@@ -1183,6 +3811,8 @@ mod test {
         }
     }
 
+    // Checks the standard names of enum values
+    #[cfg(not(feature = "lowercase-enums"))]
     #[test]
     fn test_message_controller_status_to_json() -> Result<(), String> {
         let status: Message = ControllerStatus {
@@ -1217,6 +3847,8 @@ mod test {
         Ok(())
     }
 
+    // Checks the standard names of enum values
+    #[cfg(not(feature = "lowercase-enums"))]
     #[test]
     fn test_message_controller_status_to_json2() -> Result<(), String> {
         let status = ControllerStatus {
@@ -1250,4 +3882,425 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_message_canonical_json_ignores_map_order() -> Result<(), String> {
+        let values = [("Z_QDGODCNT", 8567.0), ("Z_QDCYCTIM", 11.475), ("Z_QDINJTIM", 4.0)];
+
+        let make_msg = |order: &[usize]| {
+            let mut controller =
+                Controller { controller_id: ID::from_u32(123), ..Default::default() };
+
+            for &index in order {
+                let (name, value) = values[index];
+                controller.last_cycle_data.insert(TextID::new(name).unwrap(), R32::new(value));
+                controller.variables.insert(TextID::new(name).unwrap(), R32::new(-value));
+            }
+
+            ControllerStatus {
+                controller_id: ID::from_u32(123),
+                display_name: None,
+                is_disconnected: None,
+                op_mode: None,
+                job_mode: None,
+                job_card_id: None,
+                mold_id: None,
+                operator_id: None,
+                operator_name: None,
+                variable: None,
+                audit: None,
+                alarm: None,
+                state: StateValues::from_controller(&controller),
+                controller: Some(Box::new(controller)),
+                options: MessageOptions::default_new(),
+            }
+        };
+
+        let msg1 = make_msg(&[0, 1, 2]);
+        let msg2 = make_msg(&[2, 0, 1]);
+
+        // The wire format follows insertion order...
+        assert_ne!(msg1.to_json_str()?, msg2.to_json_str()?);
+
+        // ...but the canonical format does not
+        let json = msg1.to_canonical_json()?;
+        assert_eq!(json, msg2.to_canonical_json()?);
+
+        assert!(json.starts_with(r#"{"$type":"ControllerStatus","controller":{"#), "{}", json);
+        assert!(
+            json.contains(
+                r#""lastCycleData":{"Z_QDCYCTIM":11.475,"Z_QDGODCNT":8567.0,"Z_QDINJTIM":4.0}"#
+            ),
+            "{}",
+            json
+        );
+
+        // Field names and values are unchanged from the wire format
+        let canonical: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let wire: serde_json::Value = serde_json::from_str(&msg1.to_json_str()?).unwrap();
+        assert_eq!(wire, canonical);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_job_cards_list() -> Result<(), String> {
+        let jobs = vec![
+            JobCard::try_new("J001", "Mold#001", 0, 1000)?,
+            JobCard::try_new("J003", "Mold#003", 300, 3000)?,
+            JobCard::try_new("J002", "Mold#002", 200, 2000)?,
+        ];
+
+        let msg = Message::job_cards_list(ID::from_u32(123), jobs.clone())?;
+
+        if let JobCardsList { controller_id, data, .. } = &msg {
+            assert_eq!(123, *controller_id);
+            assert_eq!(
+                vec!["J001", "J003", "J002"],
+                data.keys().map(|k| k.get()).collect::<Vec<_>>()
+            );
+            assert_eq!(jobs, data.values().cloned().collect::<Vec<_>>());
+        } else {
+            return Err(format!("Expected JobCardsList, got {:#?}", msg));
+        }
+
+        assert!(msg.to_json_str()?.contains(r#""data":{"J001":{"jobCardId":"J001","#));
+
+        // An empty list is fine
+        match Message::job_cards_list(ID::from_u32(123), vec![])? {
+            JobCardsList { data, .. } => assert!(data.is_empty()),
+            msg => return Err(format!("Expected JobCardsList, got {:#?}", msg)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_job_cards_list_duplicated_id() -> Result<(), String> {
+        let jobs = vec![
+            JobCard::try_new("J001", "Mold#001", 0, 1000)?,
+            JobCard::try_new("J002", "Mold#002", 0, 2000)?,
+            JobCard::try_new("J001", "Mold#003", 0, 3000)?,
+        ];
+
+        match Message::job_cards_list(ID::from_u32(123), jobs) {
+            Err(Error::InvalidField { field, value, .. }) => {
+                assert_eq!("job_card_id", field);
+                assert_eq!("J001", value);
+            }
+            result => return Err(format!("Expected InvalidField, got {:?}", result)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_controller_id() -> Result<(), String> {
+        let id = ID::from_u32(123);
+
+        let request = RequestMoldData { controller_id: id, options: MessageOptions::default_new() };
+        let response = Message::parse_from_json_str(
+            r#"{"$type":"MoldData","controllerId":123,"data":{},"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#,
+        )?;
+        assert_eq!(Some(id), request.controller_id());
+        assert_eq!(request.controller_id(), response.controller_id());
+
+        let wrong = Message::parse_from_json_str(
+            r#"{"$type":"MoldData","controllerId":456,"data":{},"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#,
+        )?;
+        assert_ne!(request.controller_id(), wrong.controller_id());
+
+        assert_eq!(
+            Some(id),
+            ReadMoldData { controller_id: id, field: None, options: MessageOptions::default_new() }
+                .controller_id()
+        );
+        assert_eq!(
+            Some(id),
+            LoginOperator {
+                controller_id: id,
+                password: Password::new("pw"),
+                options: MessageOptions::default_new()
+            }
+            .controller_id()
+        );
+        assert_eq!(Some(id), Message::new_request_controllers_list(Some(id)).controller_id());
+
+        // Messages without a controller ID
+        assert_eq!(None, Message::new_alive().controller_id());
+        assert_eq!(None, Message::new_join("hello", Filters::All).controller_id());
+        assert_eq!(None, Message::new_request_all_controllers().controller_id());
+        assert_eq!(
+            None,
+            Message::parse_from_json_str(r#"{"$type":"ControllersList","data":{},"sequence":1}"#)?
+                .controller_id()
+        );
+        assert_eq!(
+            None,
+            Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?
+                .controller_id()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_parse_with_limit() -> Result<(), String> {
+        let json = r#"{"$type":"Alive","sequence":42}"#;
+
+        // At the limit
+        assert_eq!(42, Message::parse_from_json_str_with_limit(json, Some(json.len()))?.sequence());
+
+        // Just over the limit
+        assert_eq!(
+            Error::MessageTooLarge { size: json.len(), limit: json.len() - 1 },
+            Message::parse_from_json_str_with_limit(json, Some(json.len() - 1)).unwrap_err()
+        );
+
+        // The size is checked before parsing
+        assert_eq!(
+            Error::MessageTooLarge { size: 10, limit: 9 },
+            Message::parse_from_json_str_with_limit("not JSON!!", Some(9)).unwrap_err()
+        );
+
+        // No limit
+        assert_eq!(42, Message::parse_from_json_str_with_limit(json, None)?.sequence());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_operator_info_allowed() -> Result<(), String> {
+        let id = ID::from_u32(123);
+
+        for level in 0..=Message::MAX_OPERATOR_LEVEL {
+            let msg = Message::operator_info(id, Password::new("secret"), level, "MISUser")?;
+
+            match msg {
+                OperatorInfo { controller_id, operator_id, name, password, level: lv, .. } => {
+                    assert_eq!(id, controller_id);
+                    assert_eq!(Some(ID::from_u32(u32::from(level) + 1)), operator_id);
+                    assert_eq!("MISUser", &name);
+                    assert_eq!("secret", password);
+                    assert_eq!(level, lv);
+                }
+                msg => return Err(format!("expected OperatorInfo, got {:?}", msg)),
+            }
+        }
+
+        assert_eq!(
+            Error::EmptyField("name"),
+            Message::operator_info(id, Password::new("secret"), 1, " ").unwrap_err()
+        );
+        assert_eq!(
+            Error::EmptyField("password"),
+            Message::operator_info(id, Password::new(""), 1, "MISUser").unwrap_err()
+        );
+        assert!(Message::operator_info(id, Password::new("secret"), 11, "MISUser").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_operator_info_denied() -> Result<(), String> {
+        let msg = Message::operator_info_denied(ID::from_u32(123), Password::new("secret"));
+        msg.validate()?;
+
+        match msg {
+            OperatorInfo { operator_id, level, .. } => {
+                assert_eq!(None, operator_id);
+                assert_eq!(0, level);
+            }
+            msg => return Err(format!("expected OperatorInfo, got {:?}", msg)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_operator_info_level_range() -> Result<(), String> {
+        for level in &[0, 10] {
+            let json = format!(
+                r#"{{"$type":"OperatorInfo","controllerId":123,"name":"John","password":"pw","level":{},"sequence":1}}"#,
+                level
+            );
+            let msg = Message::parse_from_json_str(&json)?;
+
+            match &msg {
+                OperatorInfo { level: lv, .. } => assert_eq!(*level, *lv),
+                msg => return Err(format!("expected OperatorInfo, got {:?}", msg)),
+            }
+
+            assert_eq!(json, msg.to_json_str()?);
+        }
+
+        let json = r#"{"$type":"OperatorInfo","controllerId":123,"name":"John","password":"pw","level":11,"sequence":1}"#;
+        match Message::parse_from_json_str(json) {
+            Err(Error::JsonError(err)) => assert!(err.to_string().contains("too high"), "{}", err),
+            result => return Err(format!("expected JsonError, got {:?}", result)),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_new_response_login() -> Result<(), String> {
+        let request = Message::parse_from_json_str(
+            r#"{"$type":"LoginOperator","controllerId":123,"password":"secret","sequence":42}"#,
+        )?;
+
+        let mut response = request.new_response().ok_or("expected a response")?;
+        assert_ne!(request.sequence(), response.sequence());
+
+        // Defaults to denying access
+        match &response {
+            OperatorInfo { controller_id, operator_id, password, level, .. } => {
+                assert_eq!(123, *controller_id);
+                assert_eq!(None, *operator_id);
+                assert_eq!("secret", *password);
+                assert_eq!(0, *level);
+            }
+            msg => return Err(format!("expected OperatorInfo, got {:?}", msg)),
+        }
+
+        // Grant access
+        if let OperatorInfo { ref mut operator_id, ref mut name, ref mut level, .. } = response {
+            *operator_id = Some(ID::from_u32(6));
+            *name = "John".try_into()?;
+            *level = AccessLevel::new(5).unwrap();
+        }
+
+        assert_eq!(
+            format!(
+                r#"{{"$type":"OperatorInfo","controllerId":123,"operatorId":6,"name":"John","password":"secret","level":5,"sequence":{}}}"#,
+                response.sequence()
+            ),
+            response.to_json_str()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_new_response_job_cards() -> Result<(), String> {
+        let request = RequestJobCardsList {
+            controller_id: ID::from_u32(123),
+            options: MessageOptions::default_new(),
+        };
+
+        match request.new_response().ok_or("expected a response")? {
+            JobCardsList { controller_id, data, options } => {
+                assert_eq!(123, controller_id);
+                assert!(data.is_empty());
+                assert_ne!(request.sequence(), options.sequence());
+            }
+            msg => return Err(format!("expected JobCardsList, got {:?}", msg)),
+        }
+
+        // Usable through the trait by generic handlers
+        fn respond<R: RequestMessage>(request: &R) -> Option<R::Response> {
+            request.new_response()
+        }
+        assert!(matches!(respond(&request), Some(JobCardsList { .. })));
+
+        // Messages that need no reply
+        assert!(Message::new_request_all_controllers().new_response().is_none());
+        assert!(Message::new_join("hello", Filters::All).new_response().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_describe_mold_data_requests() -> Result<(), String> {
+        let request = RequestMoldData {
+            controller_id: ID::from_u32(1),
+            options: MessageOptions::default_new(),
+        };
+        let read_all = ReadMoldData {
+            controller_id: ID::from_u32(1),
+            field: None,
+            options: MessageOptions::default_new(),
+        };
+        let read_one = ReadMoldData {
+            controller_id: ID::from_u32(1),
+            field: Some(TextID::new("Foo").unwrap()),
+            options: MessageOptions::default_new(),
+        };
+
+        assert_eq!(
+            format!("RequestMoldData(controller=1, all settings, sequence={})", request.sequence()),
+            request.describe()
+        );
+        assert_eq!(
+            format!(
+                "ReadMoldData(controller=1, all cached settings, sequence={})",
+                read_all.sequence()
+            ),
+            read_all.describe()
+        );
+        assert_eq!(
+            format!("ReadMoldData(controller=1, field=Foo, sequence={})", read_one.sequence()),
+            read_one.describe()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_describe_hides_password() -> Result<(), String> {
+        let join = Message::new_join("secret", Filters::Cycle + Filters::Mold);
+        let text = join.describe();
+
+        assert_eq!(
+            format!(
+                "Join(version={}, filter=[Cycle, Mold], sequence={})",
+                Message::PROTOCOL_VERSION,
+                join.sequence()
+            ),
+            text
+        );
+        assert!(!text.contains("secret"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_mold_data_values() -> Result<(), String> {
+        let json = r#"{"$type":"MoldData","controllerId":1,"data":{"temp.zone2":215.0,"inj.stage1.speed":80.0,"temp.zone1":210.0,"cycles":100.0},"timestamp":"2019-02-26T02:03:04+08:00","sequence":1}"#;
+        let expected = vec![
+            ("temp.zone2", R32::new(215.0)),
+            ("inj.stage1.speed", R32::new(80.0)),
+            ("temp.zone1", R32::new(210.0)),
+            ("cycles", R32::new(100.0)),
+        ];
+
+        let msg = Message::parse_from_json_str(json)?;
+
+        let values: Vec<_> =
+            msg.mold_data_values().map(|(field, value)| (field.get(), value)).collect();
+        assert_eq!(expected, values);
+
+        // The order survives a round trip
+        let json = msg.to_json_str()?;
+
+        let values: Vec<_> = Message::parse_from_json_str(&json)?
+            .into_mold_data_values()
+            .map(|(field, value)| (field.get().to_string(), value))
+            .collect();
+        assert_eq!(
+            expected.iter().map(|(field, value)| (field.to_string(), *value)).collect::<Vec<_>>(),
+            values
+        );
+
+        // Other messages have no mold data
+        let read = ReadMoldData {
+            controller_id: ID::from_u32(1),
+            field: None,
+            options: MessageOptions::default_new(),
+        };
+        assert_eq!(0, read.mold_data_values().count());
+        assert_eq!(0, read.into_mold_data_values().count());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,230 @@
+use super::{AccessLevel, JobCard, Message, Result, ID};
+
+/// A source of MIS/MES users, used to authenticate operators who log into controllers.
+///
+/// Implement this trait on top of a real user database to answer [`LoginOperator`]
+/// requests via [`respond_to_mis_request`].
+///
+/// [`LoginOperator`]: enum.Message.html#variant.LoginOperator
+/// [`respond_to_mis_request`]: fn.respond_to_mis_request.html
+///
+pub trait UserDirectory {
+    /// Look up the user with a password.
+    ///
+    /// Returns the user's access level and name, or `None` if there is no user with that password.
+    fn lookup(&self, password: &str) -> Option<(AccessLevel, String)>;
+}
+
+/// A source of MIS/MES production jobs (i.e. *job cards*) for controllers.
+///
+/// Implement this trait on top of a real job scheduling system to answer
+/// [`RequestJobCardsList`] requests via [`respond_to_mis_request`].
+///
+/// [`RequestJobCardsList`]: enum.Message.html#variant.RequestJobCardsList
+/// [`respond_to_mis_request`]: fn.respond_to_mis_request.html
+///
+pub trait JobProvider {
+    /// Get the list of jobs available for a controller.
+    fn jobs_for(&self, controller_id: ID) -> Vec<JobCard<'_>>;
+}
+
+/// Generate the reply (if any) to a MIS/MES request sent by the iChen® Server.
+///
+/// * A [`LoginOperator`] request is answered with an [`OperatorInfo`] message for the user
+///   found in `users`, or a denial if there is no such user.
+///
+/// * A [`RequestJobCardsList`] request is answered with a [`JobCardsList`] message containing
+///   the jobs from `jobs`.
+///
+/// All other messages return `Ok(None)`.
+///
+/// [`LoginOperator`]: enum.Message.html#variant.LoginOperator
+/// [`OperatorInfo`]: enum.Message.html#variant.OperatorInfo
+/// [`RequestJobCardsList`]: enum.Message.html#variant.RequestJobCardsList
+/// [`JobCardsList`]: enum.Message.html#variant.JobCardsList
+///
+/// # Errors
+///
+/// Returns `Err(`[`OpenProtocolError`]`)` if the reply cannot be created (for example,
+/// the user name is empty or `jobs` contains duplicated job ID's).
+///
+/// [`OpenProtocolError`]: enum.OpenProtocolError.html
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// struct Users;
+///
+/// impl UserDirectory for Users {
+///     fn lookup(&self, password: &str) -> Option<(AccessLevel, String)> {
+///         match password {
+///             "123456" => Some((AccessLevel::new(5).unwrap(), "John".to_string())),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// struct Jobs(Vec<JobCard<'static>>);
+///
+/// impl JobProvider for Jobs {
+///     fn jobs_for(&self, _controller_id: ID) -> Vec<JobCard<'_>> {
+///         self.0.clone()
+///     }
+/// }
+///
+/// let jobs = Jobs(vec![JobCard::try_new("J001", "Mold#001", 0, 1000)?]);
+///
+/// let json = r#"{"$type":"LoginOperator","controllerId":1,"password":"123456","sequence":1}"#;
+/// let request = Message::parse_from_json_str(json)?;
+///
+/// match respond_to_mis_request(&request, &Users, &jobs)? {
+///     Some(Message::OperatorInfo { name, level, .. }) => {
+///         assert_eq!("John", name.get());
+///         assert_eq!(5, level.get());
+///     }
+///     _ => panic!(),
+/// }
+///
+/// assert!(respond_to_mis_request(&Message::new_alive(), &Users, &jobs)?.is_none());
+/// # Ok(())
+/// # }
+/// ~~~
+pub fn respond_to_mis_request<'a>(
+    message: &Message<'a>,
+    users: &(impl UserDirectory + ?Sized),
+    jobs: &'a (impl JobProvider + ?Sized),
+) -> Result<'a, Option<Message<'a>>> {
+    match message {
+        Message::LoginOperator { controller_id, password, .. } => {
+            match users.lookup(password.get()) {
+                Some((level, name)) => {
+                    Message::operator_info(*controller_id, password.clone(), level.get(), name)
+                        .map(Some)
+                }
+                None => Ok(Some(Message::operator_info_denied(*controller_id, password.clone()))),
+            }
+        }
+        Message::RequestJobCardsList { controller_id, .. } => {
+            Message::job_cards_list(*controller_id, jobs.jobs_for(*controller_id)).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Database {
+        users: HashMap<&'static str, (AccessLevel, String)>,
+        jobs: HashMap<ID, Vec<JobCard<'static>>>,
+    }
+
+    impl UserDirectory for Database {
+        fn lookup(&self, password: &str) -> Option<(AccessLevel, String)> {
+            self.users.get(password).cloned()
+        }
+    }
+
+    impl JobProvider for Database {
+        fn jobs_for(&self, controller_id: ID) -> Vec<JobCard<'_>> {
+            self.jobs.get(&controller_id).cloned().unwrap_or_default()
+        }
+    }
+
+    fn database() -> std::result::Result<Database, String> {
+        let mut db = Database { users: HashMap::new(), jobs: HashMap::new() };
+
+        db.users.insert("111111", (AccessLevel::new(1).unwrap(), "Operator".to_string()));
+        db.users.insert("999999", (AccessLevel::MAX, "Supervisor".to_string()));
+
+        db.jobs.insert(
+            ID::from_u32(1),
+            vec![
+                JobCard::try_new("JOB_CARD_1", "ABC-123", 0, 8000)?,
+                JobCard::try_new("JOB_CARD_2", "M002", 2000, 10000)?,
+            ],
+        );
+
+        Ok(db)
+    }
+
+    #[test]
+    fn test_mis_login_operator() -> std::result::Result<(), String> {
+        let db = database()?;
+
+        let json = r#"{"$type":"LoginOperator","controllerId":1,"password":"999999","sequence":1}"#;
+        let request = Message::parse_from_json_str(json)?;
+
+        match respond_to_mis_request(&request, &db, &db)? {
+            Some(Message::OperatorInfo {
+                controller_id,
+                operator_id,
+                name,
+                password,
+                level,
+                ..
+            }) => {
+                assert_eq!(1, controller_id);
+                assert!(operator_id.is_some());
+                assert_eq!("Supervisor", name.get());
+                assert_eq!("999999", password.get());
+                assert_eq!(AccessLevel::MAX, level);
+            }
+            m => panic!("expected OperatorInfo, got {:?}", m),
+        }
+
+        let json = r#"{"$type":"LoginOperator","controllerId":1,"password":"000000","sequence":1}"#;
+        let request = Message::parse_from_json_str(json)?;
+
+        match respond_to_mis_request(&request, &db, &db)? {
+            Some(Message::OperatorInfo { operator_id, level, .. }) => {
+                assert_eq!(None, operator_id);
+                assert_eq!(AccessLevel::MIN, level);
+            }
+            m => panic!("expected OperatorInfo, got {:?}", m),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mis_request_job_cards_list() -> std::result::Result<(), String> {
+        let db = database()?;
+
+        let request = Message::parse_from_json_str(
+            r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":1}"#,
+        )?;
+
+        match respond_to_mis_request(&request, &db, &db)? {
+            Some(Message::JobCardsList { controller_id, data, .. }) => {
+                assert_eq!(1, controller_id);
+                assert_eq!(
+                    vec!["JOB_CARD_1", "JOB_CARD_2"],
+                    data.keys().map(|k| k.get()).collect::<Vec<_>>()
+                );
+            }
+            m => panic!("expected JobCardsList, got {:?}", m),
+        }
+
+        // No jobs for an unknown controller
+        let request = Message::parse_from_json_str(
+            r#"{"$type":"RequestJobCardsList","controllerId":2,"sequence":1}"#,
+        )?;
+
+        match respond_to_mis_request(&request, &db, &db)? {
+            Some(Message::JobCardsList { data, .. }) => assert!(data.is_empty()),
+            m => panic!("expected JobCardsList, got {:?}", m),
+        }
+
+        // Other messages are not answered
+        assert!(respond_to_mis_request(&Message::new_alive(), &db, &db)?.is_none());
+
+        Ok(())
+    }
+}
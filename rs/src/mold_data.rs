@@ -0,0 +1,208 @@
+use super::{OpenProtocolError, Result, TextID, R32};
+use indexmap::IndexMap;
+
+// Separator between the segments of a mold data field path.
+const MOLD_DATA_PATH_SEPARATOR: char = '.';
+
+/// Resolve a dotted field path (e.g. `temp.zone1`) against a set of mold data.
+///
+/// Mold data is a flat map of fields; nested settings (such as heating zones or injection
+/// stages) are grouped by dotted field names.  For example, the fields `temp.zone1` and
+/// `temp.zone2` together form the group `temp`.  A path resolves to the value of the field
+/// with exactly the same name.
+///
+/// This can be used to answer a [`ReadMoldData`] request with a `field` path.
+///
+/// [`ReadMoldData`]: enum.Message.html#variant.ReadMoldData
+///
+/// # Errors
+///
+/// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` with `field` set to `"field"` if:
+///
+/// * `path` is empty or contains an empty segment (e.g. `temp..zone1`),
+/// * an intermediate segment does not exist (i.e. no field starts with that group),
+/// * an intermediate segment is a value rather than a group, or
+/// * the group exists but does not contain the final segment.
+///
+/// The error description names the first segment that cannot be resolved.
+///
+/// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+///
+/// ## Error Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use indexmap::IndexMap;
+/// let mut data = IndexMap::new();
+/// data.insert(TextID::new("temp.zone1").unwrap(), R32::new(210.0));
+///
+/// // Absent leaf
+/// assert_eq!(
+///     "value [temp.zone9] is invalid for the field field - group [temp] has no field [zone9]",
+///     resolve_mold_data_path(&data, "temp.zone9").unwrap_err().to_string()
+/// );
+///
+/// // Absent intermediate
+/// assert_eq!(
+///     "value [speed.stage1] is invalid for the field field - no group [speed]",
+///     resolve_mold_data_path(&data, "speed.stage1").unwrap_err().to_string()
+/// );
+/// ~~~
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use indexmap::IndexMap;
+/// let mut data = IndexMap::new();
+/// data.insert(TextID::new("temp.zone1").unwrap(), R32::new(210.0));
+/// data.insert(TextID::new("temp.zone2").unwrap(), R32::new(215.0));
+/// data.insert(TextID::new("cycles").unwrap(), R32::new(100.0));
+///
+/// assert_eq!(R32::new(215.0), resolve_mold_data_path(&data, "temp.zone2").unwrap());
+/// assert_eq!(R32::new(100.0), resolve_mold_data_path(&data, "cycles").unwrap());
+/// ~~~
+pub fn resolve_mold_data_path<'a>(
+    data: &IndexMap<TextID<'_>, R32>,
+    path: &'a str,
+) -> Result<'a, R32> {
+    let error = |description: String| OpenProtocolError::InvalidField {
+        field: "field",
+        value: path.into(),
+        description: description.into(),
+    };
+
+    if path.split(MOLD_DATA_PATH_SEPARATOR).any(str::is_empty) {
+        return Err(error("path cannot contain empty segments".into()));
+    }
+
+    if let Some(value) = data.get(path) {
+        return Ok(*value);
+    }
+
+    // Find the first segment that cannot be resolved
+    let mut group: Option<&str> = None;
+
+    for (index, _) in path.match_indices(MOLD_DATA_PATH_SEPARATOR) {
+        let prefix = &path[..index];
+
+        if data.contains_key(prefix) {
+            return Err(error(format!("[{}] is a value, not a group", prefix)));
+        }
+
+        let is_group = data.keys().any(|key| {
+            key.get().starts_with(prefix)
+                && key.get()[prefix.len()..].starts_with(MOLD_DATA_PATH_SEPARATOR)
+        });
+
+        if !is_group {
+            return Err(error(format!("no group [{}]", prefix)));
+        }
+
+        group = Some(prefix);
+    }
+
+    Err(error(match group {
+        Some(group) => format!("group [{}] has no field [{}]", group, &path[group.len() + 1..]),
+        None => format!("no field [{}]", path),
+    }))
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mold_data() -> IndexMap<TextID<'static>, R32> {
+        let mut data = IndexMap::new();
+        data.insert(TextID::new("temp.zone1").unwrap(), R32::new(210.0));
+        data.insert(TextID::new("temp.zone2").unwrap(), R32::new(215.0));
+        data.insert(TextID::new("inj.stage1.speed").unwrap(), R32::new(80.0));
+        data.insert(TextID::new("inj.stage1.pressure").unwrap(), R32::new(120.0));
+        data.insert(TextID::new("cycles").unwrap(), R32::new(100.0));
+        data
+    }
+
+    fn description(err: OpenProtocolError) -> String {
+        match err {
+            OpenProtocolError::InvalidField { field: "field", description, .. } => {
+                description.into_owned()
+            }
+            err => panic!("expected InvalidField, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_resolve_mold_data_path_present() {
+        let data = mold_data();
+
+        assert_eq!(R32::new(210.0), resolve_mold_data_path(&data, "temp.zone1").unwrap());
+        assert_eq!(R32::new(120.0), resolve_mold_data_path(&data, "inj.stage1.pressure").unwrap());
+        assert_eq!(R32::new(100.0), resolve_mold_data_path(&data, "cycles").unwrap());
+    }
+
+    #[test]
+    fn test_resolve_mold_data_path_absent_leaf() {
+        let data = mold_data();
+
+        assert_eq!(
+            "group [temp] has no field [zone3]",
+            description(resolve_mold_data_path(&data, "temp.zone3").unwrap_err())
+        );
+        assert_eq!(
+            "group [inj.stage1] has no field [time]",
+            description(resolve_mold_data_path(&data, "inj.stage1.time").unwrap_err())
+        );
+        assert_eq!(
+            "no field [speed]",
+            description(resolve_mold_data_path(&data, "speed").unwrap_err())
+        );
+
+        // A group is not a value
+        assert_eq!(
+            "no field [temp]",
+            description(resolve_mold_data_path(&data, "temp").unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_resolve_mold_data_path_absent_intermediate() {
+        let data = mold_data();
+
+        assert_eq!(
+            "no group [inj.stage2]",
+            description(resolve_mold_data_path(&data, "inj.stage2.speed").unwrap_err())
+        );
+        assert_eq!(
+            "no group [clamp]",
+            description(resolve_mold_data_path(&data, "clamp.stage1.speed").unwrap_err())
+        );
+
+        // A prefix of a group name is not a group
+        assert_eq!(
+            "no group [te]",
+            description(resolve_mold_data_path(&data, "te.zone1").unwrap_err())
+        );
+
+        // A value is not a group
+        assert_eq!(
+            "[cycles] is a value, not a group",
+            description(resolve_mold_data_path(&data, "cycles.total").unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_resolve_mold_data_path_invalid() {
+        let data = mold_data();
+
+        for path in &["", ".", "temp.", ".zone1", "temp..zone1"] {
+            assert_eq!(
+                "path cannot contain empty segments",
+                description(resolve_mold_data_path(&data, path).unwrap_err()),
+                "{}",
+                path
+            );
+        }
+    }
+}
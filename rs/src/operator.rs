@@ -8,10 +8,12 @@ use std::convert::TryInto;
 #[serde(rename_all = "camelCase")]
 pub struct Operator<'a> {
     /// Unique user ID, which cannot be zero.
+    #[cfg_attr(feature = "legacy", serde(alias = "operator_id"))]
     operator_id: ID,
     //
     /// Name of the user.
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "operator_name"))]
     operator_name: Option<TextName<'a>>,
 }
 
@@ -92,4 +94,30 @@ impl<'a> Operator<'a> {
             ..Self::new(id)
         })
     }
+
+    // Create a new `Operator` with an already-validated name.
+    pub(crate) fn new_with_name(id: ID, name: Option<TextName<'a>>) -> Self {
+        Self { operator_id: id, operator_name: name }
+    }
+
+    /// Convert into an `Operator` that owns its data, copying the name if borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let name = String::from("John");
+    /// let opr = Operator::try_new_with_name(ID::from_u32(123), &name)?.into_owned();
+    /// drop(name);
+    /// assert_eq!(Some("John"), opr.name());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn into_owned(self) -> Operator<'static> {
+        Operator {
+            operator_id: self.operator_id,
+            operator_name: self.operator_name.map(TextName::into_owned),
+        }
+    }
 }
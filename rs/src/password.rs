@@ -0,0 +1,149 @@
+use super::utils::deserialize_cow_str;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+
+/// A password text string that is redacted when displayed.
+///
+/// Both `Debug` and `Display` print `***` instead of the actual password, so that
+/// passwords do not leak into logs.  The actual password is still serialized into JSON.
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// let pw = Password::new("secret");
+/// assert_eq!("secret", pw.get());
+/// assert_eq!("***", pw.to_string());
+/// assert_eq!(r#""***""#, format!("{:?}", pw));
+/// ~~~
+#[derive(Clone, Eq, PartialEq, Hash, Default)]
+pub struct Password<'a>(Cow<'a, str>);
+
+impl<'a> Password<'a> {
+    /// The text displayed in place of a password.
+    pub const REDACTED: &'static str = "***";
+
+    /// Create a new `Password`.
+    pub fn new<T: Into<Cow<'a, str>>>(password: T) -> Self {
+        Self(password.into())
+    }
+
+    /// Get the actual password.
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns true if the password is empty or all whitespace.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(Password::new("   ").is_empty());
+    /// assert!(!Password::new("secret").is_empty());
+    /// ~~~
+    pub fn is_empty(&self) -> bool {
+        self.0.trim().is_empty()
+    }
+}
+
+impl Debug for Password<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", Self::REDACTED)
+    }
+}
+
+impl Display for Password<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(Self::REDACTED)
+    }
+}
+
+impl<'a> From<&'a str> for Password<'a> {
+    fn from(password: &'a str) -> Self {
+        Self(password.into())
+    }
+}
+
+impl AsRef<str> for Password<'_> {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for Password<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Password<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == **other
+    }
+}
+
+impl PartialEq<Password<'_>> for str {
+    fn eq(&self, other: &Password<'_>) -> bool {
+        self == other.get()
+    }
+}
+
+impl PartialEq<Password<'_>> for &str {
+    fn eq(&self, other: &Password<'_>) -> bool {
+        *self == other.get()
+    }
+}
+
+impl Serialize for Password<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'a, 'de: 'a> Deserialize<'de> for Password<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_cow_str(deserializer).map(Self)
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_password_deserialize_borrowed() -> Result<(), String> {
+        let pw: Password = serde_json::from_str(r#""secret""#).map_err(|x| x.to_string())?;
+
+        assert_eq!("secret", pw.get());
+        assert!(matches!(pw.0, Cow::Borrowed(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_deserialize_escaped() -> Result<(), String> {
+        let pw: Password =
+            serde_json::from_str(r#""se\"cr\\et\u0012""#).map_err(|x| x.to_string())?;
+
+        assert_eq!("se\"cr\\et\u{12}", pw.get());
+        assert!(matches!(pw.0, Cow::Owned(_)));
+        assert_eq!(r#""se\"cr\\et\u0012""#, serde_json::to_string(&pw).map_err(|x| x.to_string())?);
+        assert_eq!("***", pw.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_new_owned() {
+        let text = String::from("secret");
+        let pw: Password<'static> = Password::new(text.clone());
+        drop(text);
+
+        assert_eq!("secret", pw.get());
+        assert_eq!(r#""***""#, format!("{:?}", pw));
+    }
+}
@@ -0,0 +1,616 @@
+use super::{ActionID, Message, ID};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The outcome of a request tracked by [`PendingActions`].
+///
+/// [`PendingActions`]: struct.PendingActions.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ActionOutcome {
+    /// A matching reply arrived within the timeout.
+    Completed {
+        /// Sequence number of the original request.
+        sequence: u64,
+        //
+        /// Time elapsed between sending the request and receiving the reply.
+        elapsed: Duration,
+    },
+    //
+    /// No matching reply arrived within the timeout.
+    TimedOut {
+        /// Sequence number of the original request.
+        sequence: u64,
+    },
+}
+
+// The reply expected for a request.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Reply {
+    JoinResponse,
+    ControllersList,
+    MoldData(ID),
+    MoldDataValue(ID, String),
+    ActionResult(ID, ActionID),
+}
+
+impl Reply {
+    // Get the reply expected for a request message, or `None` if the message
+    // does not expect a reply from the server.
+    fn expected_for(msg: &Message) -> Option<Self> {
+        match msg {
+            Message::Join { .. } => Some(Reply::JoinResponse),
+            Message::RequestControllersList { .. } => Some(Reply::ControllersList),
+            Message::RequestMoldData { controller_id, .. }
+            | Message::ReadMoldData { controller_id, field: None, .. } => {
+                Some(Reply::MoldData(*controller_id))
+            }
+            Message::ReadMoldData { controller_id, field: Some(field), .. } => {
+                Some(Reply::MoldDataValue(*controller_id, field.get().to_string()))
+            }
+            Message::ControllerAction { controller_id, action_id, .. } => {
+                Some(Reply::ActionResult(*controller_id, *action_id))
+            }
+            _ => None,
+        }
+    }
+
+    // Does a message match this expected reply?
+    fn matches(&self, msg: &Message) -> bool {
+        match (self, msg) {
+            (Reply::JoinResponse, Message::JoinResponse { .. }) => true,
+            (Reply::ControllersList, Message::ControllersList { .. }) => true,
+            (Reply::MoldData(id), Message::MoldData { controller_id, .. }) => id == controller_id,
+            (
+                Reply::MoldDataValue(id, name),
+                Message::MoldDataValue { controller_id, field, .. },
+            ) => id == controller_id && name == field.get(),
+            (
+                Reply::ActionResult(id, action),
+                Message::ActionResult { controller_id, action_id, .. },
+            ) => id == controller_id && action == action_id,
+            _ => false,
+        }
+    }
+}
+
+// A request waiting for its reply.
+#[derive(Debug, Clone)]
+struct Pending {
+    reply: Reply,
+    sequence: u64,
+    sent: Instant,
+}
+
+/// A helper that correlates requests sent to the iChen® Server with their replies.
+///
+/// The Open Protocol does not echo the `sequence` number of a request in its reply,
+/// so replies are matched by message type (and controller ID/field where applicable),
+/// with the oldest matching request resolved first:
+///
+/// | Request                                   | Reply                                    |
+/// |:------------------------------------------|:-----------------------------------------|
+/// | `Join`                                    | `JoinResponse`                           |
+/// | `RequestControllersList`                  | `ControllersList`                        |
+/// | `RequestMoldData`, `ReadMoldData` (all)   | `MoldData` (same controller)             |
+/// | `ReadMoldData` (single field)             | `MoldDataValue` (same controller, field) |
+/// | `ControllerAction`                        | `ActionResult` (same controller, action) |
+///
+/// Other messages are not tracked.
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use std::time::Duration;
+/// # fn main() -> std::result::Result<(), String> {
+/// let mut pending = PendingActions::new(Duration::from_secs(10));
+///
+/// let request = Message::new_request_all_controllers();
+/// assert!(pending.register(&request));
+///
+/// // ... send `request` and wait for the reply ...
+/// let reply = Message::parse_from_json_str(r#"{"$type":"ControllersList","data":{},"sequence":1}"#)?;
+///
+/// match pending.resolve(&reply) {
+///     Some(ActionOutcome::Completed { sequence, .. }) => assert_eq!(request.sequence(), sequence),
+///     outcome => panic!("unexpected outcome: {:?}", outcome),
+/// }
+/// # Ok(())
+/// # }
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct PendingActions {
+    timeout: Duration,
+    pending: VecDeque<Pending>,
+}
+
+impl PendingActions {
+    /// Create a new `PendingActions` with a timeout for replies.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, pending: VecDeque::new() }
+    }
+
+    /// Number of requests waiting for replies.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if there are no requests waiting for replies.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Record a request that has just been sent.
+    ///
+    /// Returns `false` (and does nothing) if the message does not expect a reply.
+    pub fn register(&mut self, msg: &Message) -> bool {
+        self.register_at(msg, Instant::now())
+    }
+
+    /// Record a request that was sent at a particular time.
+    ///
+    /// Returns `false` (and does nothing) if the message does not expect a reply.
+    pub fn register_at(&mut self, msg: &Message, now: Instant) -> bool {
+        match Reply::expected_for(msg) {
+            Some(reply) => {
+                self.pending.push_back(Pending { reply, sequence: msg.sequence(), sent: now });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resolve the oldest pending request matching a reply that has just arrived.
+    ///
+    /// Returns `None` if the message does not match any pending request.
+    pub fn resolve(&mut self, msg: &Message) -> Option<ActionOutcome> {
+        self.resolve_at(msg, Instant::now())
+    }
+
+    /// Resolve the oldest pending request matching a reply that arrived at a particular time.
+    ///
+    /// Requests whose timeout has not yet elapsed are matched first, so the reply to a
+    /// request that is sent again (e.g. a retried `ControllerAction`) resolves the new request
+    /// instead of an earlier one that has timed out.  Only if no such request matches does
+    /// the oldest matching request whose timeout has elapsed resolve to
+    /// [`ActionOutcome::TimedOut`].
+    ///
+    /// Returns `None` if the message does not match any pending request.
+    ///
+    /// [`ActionOutcome::TimedOut`]: enum.ActionOutcome.html#variant.TimedOut
+    ///
+    pub fn resolve_at(&mut self, msg: &Message, now: Instant) -> Option<ActionOutcome> {
+        let timeout = self.timeout;
+        let expired = |p: &Pending| now.saturating_duration_since(p.sent) > timeout;
+
+        let index = self
+            .pending
+            .iter()
+            .position(|p| p.reply.matches(msg) && !expired(p))
+            .or_else(|| self.pending.iter().position(|p| p.reply.matches(msg)))?;
+
+        let Pending { sequence, sent, .. } = self.pending.remove(index)?;
+        let elapsed = now.saturating_duration_since(sent);
+
+        Some(if elapsed > self.timeout {
+            ActionOutcome::TimedOut { sequence }
+        } else {
+            ActionOutcome::Completed { sequence, elapsed }
+        })
+    }
+
+    /// Remove all pending requests whose timeout has elapsed.
+    ///
+    /// Returns [`ActionOutcome::TimedOut`] for each request removed, oldest first.
+    ///
+    /// [`ActionOutcome::TimedOut`]: enum.ActionOutcome.html#variant.TimedOut
+    ///
+    pub fn expire(&mut self) -> Vec<ActionOutcome> {
+        self.expire_at(Instant::now())
+    }
+
+    /// Remove all pending requests whose timeout has elapsed at a particular time.
+    ///
+    /// Returns [`ActionOutcome::TimedOut`] for each request removed, oldest first.
+    ///
+    /// [`ActionOutcome::TimedOut`]: enum.ActionOutcome.html#variant.TimedOut
+    ///
+    pub fn expire_at(&mut self, now: Instant) -> Vec<ActionOutcome> {
+        let timeout = self.timeout;
+        let mut expired = Vec::new();
+
+        self.pending.retain(|p| {
+            if now.saturating_duration_since(p.sent) > timeout {
+                expired.push(ActionOutcome::TimedOut { sequence: p.sequence });
+                false
+            } else {
+                true
+            }
+        });
+
+        expired
+    }
+}
+
+// A request waiting for its reply, kept by a `Correlator`.
+#[derive(Debug, Clone)]
+struct Outbound<'a> {
+    reply: Reply,
+    request: Message<'a>,
+    sent: Instant,
+}
+
+/// A helper that matches replies from the iChen® Server to the requests that caused them.
+///
+/// Unlike [`PendingActions`], which only reports the outcome of each request, a `Correlator`
+/// keeps a copy of every request recorded, and returns the original request when its reply
+/// arrives.  Replies are matched in the same way as [`PendingActions`] (by message type,
+/// and controller ID/field where applicable), oldest request first.
+///
+/// Requests whose timeout has elapsed are expired and never matched.
+///
+/// [`PendingActions`]: struct.PendingActions.html
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use std::time::Duration;
+/// # fn main() -> std::result::Result<(), String> {
+/// let mut correlator = Correlator::new(Duration::from_secs(10));
+///
+/// let request = Message::new_join("MyPassword", Filters::All);
+/// assert!(correlator.record(&request));
+///
+/// // ... send `request` and wait for the reply ...
+/// let reply = Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?;
+///
+/// let original = correlator.correlate(&reply).unwrap();
+/// assert_eq!(request.sequence(), original.sequence());
+/// assert!(correlator.is_empty());
+/// # Ok(())
+/// # }
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct Correlator<'a> {
+    timeout: Duration,
+    pending: VecDeque<Outbound<'a>>,
+}
+
+impl<'a> Correlator<'a> {
+    /// Create a new `Correlator` with a timeout for replies.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, pending: VecDeque::new() }
+    }
+
+    /// Number of requests waiting for replies.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if there are no requests waiting for replies.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Record a copy of a request that has just been sent.
+    ///
+    /// Returns `false` (and does nothing) if the message does not expect a reply.
+    pub fn record(&mut self, msg: &Message<'a>) -> bool {
+        self.record_at(msg, Instant::now())
+    }
+
+    /// Record a copy of a request that was sent at a particular time.
+    ///
+    /// Returns `false` (and does nothing) if the message does not expect a reply.
+    pub fn record_at(&mut self, msg: &Message<'a>, now: Instant) -> bool {
+        match Reply::expected_for(msg) {
+            Some(reply) => {
+                self.pending.push_back(Outbound { reply, request: msg.clone(), sent: now });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Find and remove the oldest request matching a reply that has just arrived.
+    ///
+    /// Returns `None` if the message does not match any request that has not yet expired.
+    pub fn correlate(&mut self, msg: &Message) -> Option<Message<'a>> {
+        self.correlate_at(msg, Instant::now())
+    }
+
+    /// Find and remove the oldest request matching a reply that arrived at a particular time.
+    ///
+    /// Expired requests are removed first (see [`sweep_at`]), so a late reply never matches.
+    ///
+    /// Returns `None` if the message does not match any request that has not yet expired.
+    ///
+    /// [`sweep_at`]: struct.Correlator.html#method.sweep_at
+    ///
+    pub fn correlate_at(&mut self, msg: &Message, now: Instant) -> Option<Message<'a>> {
+        self.sweep_at(now);
+
+        let index = self.pending.iter().position(|p| p.reply.matches(msg))?;
+        self.pending.remove(index).map(|p| p.request)
+    }
+
+    /// Remove all requests whose timeout has elapsed.
+    ///
+    /// Returns the requests removed, oldest first.
+    pub fn sweep(&mut self) -> Vec<Message<'a>> {
+        self.sweep_at(Instant::now())
+    }
+
+    /// Remove all requests whose timeout has elapsed at a particular time.
+    ///
+    /// Returns the requests removed, oldest first.
+    pub fn sweep_at(&mut self, now: Instant) -> Vec<Message<'a>> {
+        let timeout = self.timeout;
+        let mut expired = Vec::new();
+
+        self.pending.retain(|p| {
+            if now.saturating_duration_since(p.sent) > timeout {
+                expired.push(p.request.clone());
+                false
+            } else {
+                true
+            }
+        });
+
+        expired
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TextID, R32};
+    use chrono::DateTime;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn read_mold_data(controller_id: u32, field: Option<&str>) -> Message<'_> {
+        Message::ReadMoldData {
+            controller_id: ID::from_u32(controller_id),
+            field: field.map(|f| TextID::new(f).unwrap()),
+            options: Default::default(),
+        }
+    }
+
+    fn controller_action(controller_id: u32, action_id: i32) -> Message<'static> {
+        Message::ControllerAction {
+            controller_id: ID::from_u32(controller_id),
+            action_id: ActionID::new(action_id),
+            timestamp: DateTime::parse_from_rfc3339("2019-01-01T00:00:00Z").unwrap(),
+            idempotency_key: None,
+            options: Default::default(),
+        }
+    }
+
+    fn mold_data_value(controller_id: u32, field: &str) -> Message<'_> {
+        Message::MoldDataValue {
+            controller_id: ID::from_u32(controller_id),
+            field: TextID::new(field).unwrap(),
+            value: R32::new(42.0),
+            options: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_pending_actions_matched() {
+        let mut pending = PendingActions::new(TIMEOUT);
+        let start = Instant::now();
+
+        let req1 = read_mold_data(1, Some("ClampPos"));
+        let req2 = read_mold_data(2, Some("ClampPos"));
+        assert!(pending.register_at(&req1, start));
+        assert!(pending.register_at(&req2, start));
+        assert_eq!(2, pending.len());
+
+        let later = start + Duration::from_secs(2);
+
+        assert_eq!(
+            Some(ActionOutcome::Completed { sequence: req2.sequence(), elapsed: later - start }),
+            pending.resolve_at(&mold_data_value(2, "ClampPos"), later)
+        );
+        assert_eq!(
+            Some(ActionOutcome::Completed { sequence: req1.sequence(), elapsed: later - start }),
+            pending.resolve_at(&mold_data_value(1, "ClampPos"), later)
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_pending_actions_unmatched() {
+        let mut pending = PendingActions::new(TIMEOUT);
+        let start = Instant::now();
+
+        // Messages that do not expect a reply are not tracked
+        assert!(!pending.register_at(&Message::new_alive(), start));
+        assert!(pending.is_empty());
+
+        assert!(pending.register_at(&read_mold_data(1, Some("ClampPos")), start));
+
+        // Wrong controller, wrong field, or wrong type
+        assert_eq!(None, pending.resolve_at(&mold_data_value(2, "ClampPos"), start));
+        assert_eq!(None, pending.resolve_at(&mold_data_value(1, "InjPos"), start));
+        assert_eq!(None, pending.resolve_at(&Message::new_alive(), start));
+        assert_eq!(None, pending.resolve_at(&controller_action(1, 42), start));
+        assert_eq!(1, pending.len());
+    }
+
+    #[test]
+    fn test_pending_actions_controller_action() -> Result<(), String> {
+        let mut pending = PendingActions::new(TIMEOUT);
+        let start = Instant::now();
+
+        let action = controller_action(1, 42);
+        assert!(pending.register_at(&action, start));
+
+        // Wrong controller or wrong action
+        let result = |id, action| {
+            Message::try_new_action_result(ID::from_u32(id), ActionID::new(action), true, None)
+        };
+        assert_eq!(None, pending.resolve_at(&result(2, 42)?, start));
+        assert_eq!(None, pending.resolve_at(&result(1, 99)?, start));
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(
+            Some(ActionOutcome::Completed { sequence: action.sequence(), elapsed: later - start }),
+            pending.resolve_at(&result(1, 42)?, later)
+        );
+        assert!(pending.is_empty());
+
+        // The `Correlator` returns the original action
+        let mut correlator = Correlator::new(TIMEOUT);
+        assert!(correlator.record_at(&action, start));
+        assert!(correlator.correlate_at(&result(1, 99)?, later).is_none());
+
+        match correlator.correlate_at(&result(1, 42)?, later) {
+            Some(Message::ControllerAction { action_id, options, .. }) => {
+                assert_eq!(42, action_id);
+                assert_eq!(action.sequence(), options.sequence());
+            }
+            msg => panic!("expected ControllerAction, got {:?}", msg),
+        }
+        assert!(correlator.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pending_actions_timed_out() {
+        let mut pending = PendingActions::new(TIMEOUT);
+        let start = Instant::now();
+
+        let req1 = read_mold_data(1, Some("ClampPos"));
+        let req2 = read_mold_data(1, None);
+        let req3 = Message::new_join("hello", crate::Filters::All);
+        pending.register_at(&req1, start);
+        pending.register_at(&req2, start + Duration::from_secs(5));
+        pending.register_at(&req3, start + Duration::from_secs(5));
+
+        // A late reply resolves as timed-out
+        let late = start + TIMEOUT + Duration::from_secs(1);
+        assert_eq!(
+            Some(ActionOutcome::TimedOut { sequence: req1.sequence() }),
+            pending.resolve_at(&mold_data_value(1, "ClampPos"), late)
+        );
+
+        // Nothing else has expired yet
+        assert!(pending.expire_at(late).is_empty());
+        assert_eq!(2, pending.len());
+
+        let later = late + Duration::from_secs(5);
+        assert_eq!(
+            vec![
+                ActionOutcome::TimedOut { sequence: req2.sequence() },
+                ActionOutcome::TimedOut { sequence: req3.sequence() },
+            ],
+            pending.expire_at(later)
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_pending_actions_retried_after_timeout() {
+        let mut pending = PendingActions::new(TIMEOUT);
+        let start = Instant::now();
+
+        // The first action times out, and the same action is sent again
+        let first = controller_action(1, 42);
+        let retry = controller_action(1, 42);
+        assert!(pending.register_at(&first, start));
+
+        let later = start + TIMEOUT + Duration::from_secs(1);
+        assert!(pending.register_at(&retry, later));
+
+        // The reply resolves the retry, not the expired first action
+        let reply =
+            Message::try_new_action_result(ID::from_u32(1), ActionID::new(42), true, None).unwrap();
+        let now = later + Duration::from_secs(1);
+
+        assert_eq!(
+            Some(ActionOutcome::Completed { sequence: retry.sequence(), elapsed: now - later }),
+            pending.resolve_at(&reply, now)
+        );
+        assert_eq!(
+            vec![ActionOutcome::TimedOut { sequence: first.sequence() }],
+            pending.expire_at(now)
+        );
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_correlator_matched() {
+        let mut correlator = Correlator::new(TIMEOUT);
+        let start = Instant::now();
+
+        let req1 = read_mold_data(1, Some("ClampPos"));
+        let req2 = read_mold_data(2, None);
+        assert!(correlator.record_at(&req1, start));
+        assert!(correlator.record_at(&req2, start));
+        assert_eq!(2, correlator.len());
+
+        let later = start + Duration::from_secs(2);
+        let reply = Message::parse_from_json_str(
+            r#"{"$type":"MoldData","controllerId":2,"data":{},"timestamp":"2019-01-01T00:00:00Z","sequence":1}"#,
+        )
+        .unwrap();
+
+        match correlator.correlate_at(&reply, later) {
+            Some(Message::ReadMoldData { controller_id, field: None, options }) => {
+                assert_eq!(2, controller_id);
+                assert_eq!(req2.sequence(), options.sequence());
+            }
+            msg => panic!("expected ReadMoldData, got {:?}", msg),
+        }
+
+        let request = correlator.correlate_at(&mold_data_value(1, "ClampPos"), later).unwrap();
+        assert_eq!(req1.sequence(), request.sequence());
+        assert!(correlator.is_empty());
+    }
+
+    #[test]
+    fn test_correlator_unmatched() {
+        let mut correlator = Correlator::new(TIMEOUT);
+        let start = Instant::now();
+
+        assert!(!correlator.record_at(&Message::new_alive(), start));
+        assert!(correlator.record_at(&read_mold_data(1, Some("ClampPos")), start));
+
+        // A reply nobody asked for
+        assert!(correlator.correlate_at(&mold_data_value(1, "InjPos"), start).is_none());
+        assert!(correlator.correlate_at(&mold_data_value(2, "ClampPos"), start).is_none());
+        assert!(correlator.correlate_at(&Message::new_alive(), start).is_none());
+        assert_eq!(1, correlator.len());
+    }
+
+    #[test]
+    fn test_correlator_expired() {
+        let mut correlator = Correlator::new(TIMEOUT);
+        let start = Instant::now();
+
+        let req1 = read_mold_data(1, Some("ClampPos"));
+        let req2 = Message::new_join("hello", crate::Filters::All);
+        correlator.record_at(&req1, start);
+        correlator.record_at(&req2, start + Duration::from_secs(5));
+
+        // Nothing has expired yet
+        let late = start + TIMEOUT + Duration::from_secs(1);
+        assert!(correlator.sweep_at(start + TIMEOUT).is_empty());
+
+        // A late reply does not match the expired request
+        assert!(correlator.correlate_at(&mold_data_value(1, "ClampPos"), late).is_none());
+        assert_eq!(1, correlator.len());
+
+        let expired = correlator.sweep_at(late + Duration::from_secs(5));
+        assert_eq!(1, expired.len());
+        assert_eq!(req2.sequence(), expired[0].sequence());
+        assert!(correlator.is_empty());
+    }
+}
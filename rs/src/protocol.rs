@@ -0,0 +1,550 @@
+use super::{
+    respond_to_mis_request, Error, Filters, JobProvider, JoinResult, Message, Result, TextID,
+    UserDirectory,
+};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+/// The client side of an Open Protocol™ session, as a state machine that performs no I/O.
+///
+/// `Protocol` only deals with [`Message`] values, so it can be driven by any transport
+/// (e.g. a WebSocket client, a raw TCP stream, or a test harness):
+///
+/// * Call [`poll_output`] repeatedly, and send each message returned to the server,
+///   until it returns `None`.  Initially, this is the `JOIN` message.
+///
+/// * Call [`handle_input`] with each message received from the server, and send all the
+///   replies it returns.
+///
+/// `Protocol` handles the following:
+///
+/// * `JOIN` - the session starts with a `Join` message.  A successful `JoinResponse`
+///   is followed by a `RequestControllersList` for all controllers.
+///
+/// * Protocol version - the `Join` message declares the maximum protocol version supported,
+///   and a `JoinResponse` from a server with a protocol version outside the supported range
+///   (see [`with_version_range`]) is rejected.
+///
+/// * `ALIVE` - an `Alive` message from the server is answered with an `Alive` message.
+///
+/// * MIS/MES - `LoginOperator` and `RequestJobCardsList` requests are answered via
+///   [`respond_to_mis_request`], if created with [`new_with_mis`].
+///
+/// [`Message`]: enum.Message.html
+/// [`poll_output`]: struct.Protocol.html#method.poll_output
+/// [`handle_input`]: struct.Protocol.html#method.handle_input
+/// [`new_with_mis`]: struct.Protocol.html#method.new_with_mis
+/// [`with_version_range`]: struct.Protocol.html#method.with_version_range
+/// [`respond_to_mis_request`]: fn.respond_to_mis_request.html
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let mut protocol = Protocol::new("hello", Filters::Status + Filters::Cycle);
+///
+/// // The session starts with a `JOIN`
+/// match protocol.poll_output() {
+///     Some(Message::Join { password, .. }) => assert_eq!("hello", password.get()),
+///     msg => panic!("expected Join, got {:?}", msg),
+/// }
+/// assert!(protocol.poll_output().is_none());
+///
+/// // The server accepts the `JOIN`
+/// let response = Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?;
+/// let replies = protocol.handle_input(&response)?;
+///
+/// assert_eq!(Some(JoinResult::Succeeded(100)), protocol.join_result());
+/// assert_eq!(1, replies.len());
+/// assert_eq!(MessageKind::RequestControllersList, replies[0].kind());
+/// # Ok(())
+/// # }
+/// ~~~
+pub struct Protocol<'a> {
+    join_result: Option<JoinResult>,
+    min_version: &'a str,
+    max_version: &'a str,
+    output: VecDeque<Message<'a>>,
+    users: Option<&'a dyn UserDirectory>,
+    jobs: Option<&'a dyn JobProvider>,
+}
+
+impl<'a> Protocol<'a> {
+    /// Create a new `Protocol` for a session that joins with a password and filters.
+    ///
+    /// MIS/MES requests from the server are not answered.
+    pub fn new(password: &'a str, filters: Filters) -> Self {
+        let mut output = VecDeque::new();
+        output.push_back(Message::new_join(password, filters));
+
+        // All versions with the same major version are compatible
+        let major = Message::PROTOCOL_VERSION.split('.').next().unwrap_or_default();
+
+        Self {
+            join_result: None,
+            min_version: major,
+            max_version: major,
+            output,
+            users: None,
+            jobs: None,
+        }
+    }
+
+    /// Create a new `Protocol` for a session that joins with a password and filters, and
+    /// answers MIS/MES requests from the server with a user directory and a job provider.
+    pub fn new_with_mis(
+        password: &'a str,
+        filters: Filters,
+        users: &'a dyn UserDirectory,
+        jobs: &'a dyn JobProvider,
+    ) -> Self {
+        Self { users: Some(users), jobs: Some(jobs), ..Self::new(password, filters) }
+    }
+
+    /// Set the range of protocol versions (in the format `x.x.x.x`) supported by the client,
+    /// consuming and returning the `Protocol`.
+    ///
+    /// The `Join` message declares `max` as the protocol version.  All versions beginning
+    /// with `max` are also supported (e.g. a `max` of `4.1` includes `4.1.2`).
+    ///
+    /// By default, all versions with the same major version as [`PROTOCOL_VERSION`] are
+    /// supported (i.e. a range of `4` to `4`, which includes `4.1` and `4.0.0.1`).
+    ///
+    /// Older servers do not send their protocol version, and are always accepted.
+    ///
+    /// [`PROTOCOL_VERSION`]: enum.Message.html#associatedconstant.PROTOCOL_VERSION
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `min` or `max` is not a valid
+    /// version, or `Err(`[`OpenProtocolError::ConstraintViolated`]`)` if `min` is larger
+    /// than `max`.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    /// [`OpenProtocolError::ConstraintViolated`]: enum.OpenProtocolError.html#variant.ConstraintViolated
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(
+    ///     Err(Error::ConstraintViolated("minimum version 4.1 is larger than maximum version 4.0.".into())),
+    ///     Protocol::new("hello", Filters::All).with_version_range("4.1", "4.0").map(|_| ())
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # fn main() -> std::result::Result<(), String> {
+    /// let mut protocol = Protocol::new("hello", Filters::All).with_version_range("3.0", "4.1")?;
+    ///
+    /// match protocol.poll_output() {
+    ///     Some(Message::Join { version, .. }) => assert_eq!("4.1", version.get()),
+    ///     msg => panic!("expected Join, got {:?}", msg),
+    /// }
+    ///
+    /// let response = Message::parse_from_json_str(
+    ///     r#"{"$type":"JoinResponse","result":100,"version":"5.0","sequence":1}"#
+    /// )?;
+    ///
+    /// assert_eq!(
+    ///     Err(Error::IncompatibleVersion { version: "5.0".into(), min: "3.0".into(), max: "4.1".into() }),
+    ///     protocol.handle_input(&response).map(|_| ())
+    /// );
+    /// assert!(!protocol.is_joined());
+    /// # Ok(())
+    /// # }
+    /// ~~~
+    pub fn with_version_range(mut self, min: &'a str, max: &'a str) -> Result<'a, Self> {
+        let invalid = |value: &'a str| Error::InvalidField {
+            field: "version",
+            value: value.into(),
+            description: "version must be in the format x.x.x.x".into(),
+        };
+
+        let max_id = TextID::new(max).ok_or_else(|| invalid(max))?;
+
+        match compare_with_max(min, max) {
+            None if parse_version(min).is_none() => return Err(invalid(min)),
+            None => return Err(invalid(max)),
+            Some(Ordering::Greater) => {
+                return Err(Error::ConstraintViolated(
+                    format!("minimum version {} is larger than maximum version {}.", min, max)
+                        .into(),
+                ))
+            }
+            Some(_) => (),
+        }
+
+        for msg in self.output.iter_mut() {
+            if let Message::Join { version, .. } = msg {
+                *version = max_id.clone();
+            }
+        }
+
+        self.min_version = min;
+        self.max_version = max;
+        Ok(self)
+    }
+
+    /// Get the outcome of the `JOIN`, or `None` if no `JoinResponse` has been received yet.
+    pub fn join_result(&self) -> Option<JoinResult> {
+        self.join_result
+    }
+
+    /// Returns true if the `JOIN` has succeeded.
+    pub fn is_joined(&self) -> bool {
+        matches!(self.join_result, Some(r) if r.is_success())
+    }
+
+    /// Get the next message to send to the server, or `None` if there is nothing to send.
+    pub fn poll_output(&mut self) -> Option<Message<'a>> {
+        self.output.pop_front()
+    }
+
+    /// Process a message received from the server.
+    ///
+    /// Returns the replies (if any) to send back to the server, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::IncompatibleVersion`]`)` if a `JoinResponse` comes
+    /// from a server with an unsupported protocol version (see [`with_version_range`]).
+    /// The `JOIN` is then not considered successful.
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if the reply to a MIS/MES request cannot be
+    /// created (see [`respond_to_mis_request`]).
+    ///
+    /// [`OpenProtocolError::IncompatibleVersion`]: enum.OpenProtocolError.html#variant.IncompatibleVersion
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`with_version_range`]: struct.Protocol.html#method.with_version_range
+    /// [`respond_to_mis_request`]: fn.respond_to_mis_request.html
+    ///
+    pub fn handle_input<'m>(&mut self, message: &Message<'m>) -> Result<'m, Vec<Message<'m>>>
+    where
+        'a: 'm,
+    {
+        match message {
+            Message::Alive { .. } => Ok(vec![Message::new_alive()]),
+            //
+            Message::JoinResponse { result, version, .. } => {
+                if let Some(version) = version {
+                    self.check_version(version.get())?;
+                }
+
+                let result = JoinResult::from_code(*result);
+                self.join_result = Some(result);
+
+                if result.is_success() {
+                    Ok(vec![Message::new_request_all_controllers()])
+                } else {
+                    Ok(Vec::new())
+                }
+            }
+            //
+            Message::LoginOperator { .. } | Message::RequestJobCardsList { .. } => {
+                match (self.users, self.jobs) {
+                    (Some(users), Some(jobs)) => {
+                        Ok(respond_to_mis_request(message, users, jobs)?.into_iter().collect())
+                    }
+                    _ => Ok(Vec::new()),
+                }
+            }
+            //
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn check_version(&self, version: &str) -> Result<'a, ()> {
+        // An invalid version is never supported
+        let supported = matches!(
+            compare_versions(version, self.min_version),
+            Some(Ordering::Greater) | Some(Ordering::Equal)
+        ) && matches!(
+            compare_with_max(version, self.max_version),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        );
+
+        if supported {
+            Ok(())
+        } else {
+            Err(Error::IncompatibleVersion {
+                version: version.to_string().into(),
+                min: self.min_version.into(),
+                max: self.max_version.into(),
+            })
+        }
+    }
+}
+
+/// Parse a version in the format `x.x.x.x` into its numeric parts.
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    version.trim().split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Compare two versions, treating missing trailing parts as zero (i.e. `4.0` == `4`).
+///
+/// Returns `None` if either version is invalid.
+fn compare_versions(left: &str, right: &str) -> Option<Ordering> {
+    Some(compare_parts(&parse_version(left)?, &parse_version(right)?))
+}
+
+/// Compare a version with a maximum version, ignoring the parts of the version beyond those
+/// in the maximum (i.e. `4.1.2` is within both `4.1` and `4`, but not within `4.0`).
+///
+/// Returns `None` if either version is invalid.
+fn compare_with_max(version: &str, max: &str) -> Option<Ordering> {
+    let version = parse_version(version)?;
+    let max = parse_version(max)?;
+    Some(compare_parts(&version[..version.len().min(max.len())], &max))
+}
+
+/// Compare the numeric parts of two versions, treating missing trailing parts as zero.
+fn compare_parts(left: &[u32], right: &[u32]) -> Ordering {
+    let len = left.len().max(right.len());
+    let part = |parts: &[u32], index: usize| parts.get(index).copied().unwrap_or(0);
+
+    (0..len)
+        .map(|i| part(left, i).cmp(&part(right, i)))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+impl std::fmt::Debug for Protocol<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Protocol")
+            .field("join_result", &self.join_result)
+            .field("min_version", &self.min_version)
+            .field("max_version", &self.max_version)
+            .field("output", &self.output.len())
+            .field("mis", &self.users.is_some())
+            .finish()
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AccessLevel, JobCard, MessageKind, ID};
+    use std::result::Result;
+
+    struct Database {
+        jobs: Vec<JobCard<'static>>,
+    }
+
+    impl UserDirectory for Database {
+        fn lookup(&self, password: &str) -> Option<(AccessLevel, String)> {
+            match password {
+                "123456" => Some((AccessLevel::new(5).unwrap(), "John".to_string())),
+                _ => None,
+            }
+        }
+    }
+
+    impl JobProvider for Database {
+        fn jobs_for(&self, _controller_id: ID) -> Vec<JobCard<'_>> {
+            self.jobs.clone()
+        }
+    }
+
+    fn kinds(messages: &[Message]) -> Vec<MessageKind> {
+        messages.iter().map(Message::kind).collect()
+    }
+
+    #[test]
+    fn test_protocol_handshake() -> Result<(), String> {
+        let mut protocol = Protocol::new("hello", Filters::All);
+
+        match protocol.poll_output() {
+            Some(Message::Join { password, filter, .. }) => {
+                assert_eq!("hello", password.get());
+                assert_eq!(Filters::All, filter);
+            }
+            msg => panic!("expected Join, got {:?}", msg),
+        }
+        assert!(protocol.poll_output().is_none());
+        assert_eq!(None, protocol.join_result());
+        assert!(!protocol.is_joined());
+
+        let response = Message::parse_from_json_str(
+            r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#,
+        )?;
+        let replies = protocol.handle_input(&response)?;
+
+        assert_eq!(vec![MessageKind::RequestControllersList], kinds(&replies));
+        assert_eq!(Some(JoinResult::Succeeded(100)), protocol.join_result());
+        assert!(protocol.is_joined());
+        assert!(protocol.poll_output().is_none());
+
+        let alive = Message::parse_from_json_str(r#"{"$type":"Alive","sequence":2}"#)?;
+        assert_eq!(vec![MessageKind::Alive], kinds(&protocol.handle_input(&alive)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_join_failed() -> Result<(), String> {
+        let mut protocol = Protocol::new("wrong", Filters::All);
+        assert!(protocol.poll_output().is_some());
+
+        let response =
+            Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":1,"sequence":1}"#)?;
+
+        assert!(protocol.handle_input(&response)?.is_empty());
+        assert_eq!(Some(JoinResult::Failed(1)), protocol.join_result());
+        assert!(!protocol.is_joined());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_mis_requests() -> Result<(), String> {
+        let db = Database { jobs: vec![JobCard::try_new("J001", "Mold#001", 0, 1000)?] };
+
+        let login = Message::parse_from_json_str(
+            r#"{"$type":"LoginOperator","controllerId":1,"password":"123456","sequence":1}"#,
+        )?;
+        let jobs = Message::parse_from_json_str(
+            r#"{"$type":"RequestJobCardsList","controllerId":1,"sequence":2}"#,
+        )?;
+
+        // Without MIS/MES, requests are not answered
+        let mut protocol = Protocol::new("hello", Filters::All);
+        assert!(protocol.handle_input(&login)?.is_empty());
+        assert!(protocol.handle_input(&jobs)?.is_empty());
+
+        let mut protocol = Protocol::new_with_mis("hello", Filters::All, &db, &db);
+
+        match protocol.handle_input(&login)?.as_slice() {
+            [Message::OperatorInfo { name, level, .. }] => {
+                assert_eq!("John", name.get());
+                assert_eq!(5, level.get());
+            }
+            replies => panic!("expected OperatorInfo, got {:?}", replies),
+        }
+
+        match protocol.handle_input(&jobs)?.as_slice() {
+            [Message::JobCardsList { data, .. }] => {
+                assert_eq!(vec!["J001"], data.keys().map(|k| k.get()).collect::<Vec<_>>())
+            }
+            replies => panic!("expected JobCardsList, got {:?}", replies),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_compatible_version() -> Result<(), String> {
+        let mut protocol = Protocol::new("hello", Filters::All).with_version_range("3.5", "4.1")?;
+
+        match protocol.poll_output() {
+            Some(Message::Join { version, .. }) => assert_eq!("4.1", version.get()),
+            msg => panic!("expected Join, got {:?}", msg),
+        }
+
+        let response = Message::parse_from_json_str(
+            r#"{"$type":"JoinResponse","result":100,"version":"4.0.1.123","sequence":1}"#,
+        )?;
+
+        assert_eq!(
+            vec![MessageKind::RequestControllersList],
+            kinds(&protocol.handle_input(&response)?)
+        );
+        assert!(protocol.is_joined());
+
+        // Versions at both ends of the range
+        for version in &["3.5", "3.5.0.0", "4.1", "4.1.2"] {
+            let json = format!(
+                r#"{{"$type":"JoinResponse","result":100,"version":"{}","sequence":1}}"#,
+                version
+            );
+            let response = Message::parse_from_json_str(&json)?;
+            assert!(protocol.handle_input(&response).is_ok(), "version {} rejected", version);
+        }
+
+        let response = Message::parse_from_json_str(
+            r#"{"$type":"JoinResponse","result":100,"version":"4.2","sequence":1}"#,
+        )?;
+        assert!(protocol.handle_input(&response).is_err());
+
+        // Older servers do not send a version
+        let response =
+            Message::parse_from_json_str(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#)?;
+        let mut protocol = Protocol::new("hello", Filters::All);
+        assert!(protocol.handle_input(&response).is_ok());
+        assert!(protocol.is_joined());
+
+        // By default, all versions with the same major version are supported
+        for version in &["4", "4.0", "4.1", "4.0.0.1", "4.99.1"] {
+            let mut protocol = Protocol::new("hello", Filters::All);
+
+            match protocol.poll_output() {
+                Some(Message::Join { version, .. }) => {
+                    assert_eq!(Message::PROTOCOL_VERSION, version.get())
+                }
+                msg => panic!("expected Join, got {:?}", msg),
+            }
+
+            let json = format!(
+                r#"{{"$type":"JoinResponse","result":100,"version":"{}","sequence":1}}"#,
+                version
+            );
+            let response = Message::parse_from_json_str(&json)?;
+            assert!(protocol.handle_input(&response).is_ok(), "version {} rejected", version);
+            assert!(protocol.is_joined());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_incompatible_version() -> Result<(), String> {
+        for version in &["3.0", "3.9.9", "5", "5.0.1", "garbage"] {
+            let mut protocol = Protocol::new("hello", Filters::All);
+            assert!(protocol.poll_output().is_some());
+
+            let json = format!(
+                r#"{{"$type":"JoinResponse","result":100,"version":"{}","sequence":1}}"#,
+                version
+            );
+            let response = Message::parse_from_json_str(&json)?;
+
+            assert_eq!(
+                Err(Error::IncompatibleVersion {
+                    version: version.to_string().into(),
+                    min: "4".into(),
+                    max: "4".into()
+                }),
+                protocol.handle_input(&response).map(|_| ())
+            );
+            assert_eq!(None, protocol.join_result());
+            assert!(!protocol.is_joined());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_invalid_version_range() {
+        let invalid = || Error::InvalidField {
+            field: "version",
+            value: "x".into(),
+            description: "version must be in the format x.x.x.x".into(),
+        };
+
+        assert_eq!(
+            Err(invalid()),
+            Protocol::new("a", Filters::All).with_version_range("x", "4.0").map(|_| ())
+        );
+        assert_eq!(
+            Err(invalid()),
+            Protocol::new("a", Filters::All).with_version_range("4.0", "x").map(|_| ())
+        );
+        assert!(Protocol::new("a", Filters::All).with_version_range("4.0", "4").is_ok());
+    }
+}
@@ -0,0 +1,279 @@
+use super::{Clock, Message, SystemClock};
+use std::time::{Duration, Instant};
+
+/// Configuration for a [`RateLimiter`].
+///
+/// Up to `max_messages` messages can be sent in a burst, and the allowance is refilled
+/// continuously at a rate of `max_messages` messages `per` period of time.
+///
+/// [`RateLimiter`]: struct.RateLimiter.html
+///
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RateLimitConfig {
+    /// Maximum number of messages sent within the period, which must not be zero.
+    pub max_messages: u32,
+    //
+    /// The period of time, which must not be zero.
+    pub per: Duration,
+}
+
+impl RateLimitConfig {
+    /// Default rate limit: 10 messages per second.
+    pub const DEFAULT: Self = Self { max_messages: 10, per: Duration::from_secs(1) };
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A token-bucket rate limiter for messages sent to the iChen® Server.
+///
+/// Sending a rapid stream of messages (especially `ControllerAction` commands) can
+/// overwhelm the firmware of some controllers.  `RateLimiter` allows bursts of up to
+/// [`max_messages`] messages, after which messages are only allowed at the configured rate.
+///
+/// `Alive` messages are never limited.
+///
+/// `RateLimiter` does not perform any I/O itself.  Call [`try_acquire`] before sending each
+/// message; if it returns `Err(wait)`, either wait for that period of time and try again, or
+/// reject the message.
+///
+/// The current time is taken from a [`Clock`] (the [`SystemClock`] by default; see
+/// [`with_clock`]).  Each method also has an `_at` variant taking the current time.
+///
+/// [`Clock`]: trait.Clock.html
+/// [`SystemClock`]: struct.SystemClock.html
+/// [`with_clock`]: struct.RateLimiter.html#method.with_clock
+/// [`max_messages`]: struct.RateLimitConfig.html#structfield.max_messages
+/// [`try_acquire`]: struct.RateLimiter.html#method.try_acquire
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use std::time::{Duration, Instant};
+/// let config = RateLimitConfig { max_messages: 2, per: Duration::from_secs(1) };
+/// let start = Instant::now();
+/// let mut limiter = RateLimiter::new_at(config, start);
+///
+/// let msg = Message::new_request_all_controllers();
+///
+/// assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+/// assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+/// assert_eq!(Err(Duration::from_millis(500)), limiter.try_acquire_at(&msg, start));
+///
+/// // `Alive` is never limited
+/// assert_eq!(Ok(()), limiter.try_acquire_at(&Message::new_alive(), start));
+///
+/// assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start + Duration::from_millis(500)));
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct RateLimiter<C: Clock = SystemClock> {
+    config: RateLimitConfig,
+    clock: C,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Create a new `RateLimiter` with a full allowance.
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+
+    /// Create a new `RateLimiter` with a full allowance at a particular time.
+    pub fn new_at(config: RateLimitConfig, now: Instant) -> Self {
+        Self {
+            config,
+            clock: SystemClock,
+            tokens: f64::from(config.max_messages),
+            last_refill: now,
+        }
+    }
+}
+
+impl<C: Clock> RateLimiter<C> {
+    /// Create a new `RateLimiter` with a full allowance, taking the current time from a
+    /// [`Clock`].
+    ///
+    /// [`Clock`]: trait.Clock.html
+    ///
+    pub fn with_clock(config: RateLimitConfig, clock: C) -> Self {
+        let now = clock.now();
+        Self { config, clock, tokens: f64::from(config.max_messages), last_refill: now }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> RateLimitConfig {
+        self.config
+    }
+
+    /// Check whether a message can be sent now and, if so, count it against the allowance.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Duration)` with the period of time to wait before the message can be
+    /// sent.  The message is not counted.
+    pub fn try_acquire(&mut self, message: &Message) -> std::result::Result<(), Duration> {
+        self.try_acquire_at(message, self.clock.now())
+    }
+
+    /// Check whether a message can be sent at a particular time and, if so, count it against
+    /// the allowance.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Duration)` with the period of time to wait before the message can be
+    /// sent.  The message is not counted.
+    pub fn try_acquire_at(
+        &mut self,
+        message: &Message,
+        now: Instant,
+    ) -> std::result::Result<(), Duration> {
+        if let Message::Alive { .. } = message {
+            return Ok(());
+        }
+
+        let capacity = f64::from(self.config.max_messages);
+        let per = self.config.per.as_secs_f64();
+
+        // A zero period or count means no limit
+        if capacity <= 0.0 || per <= 0.0 {
+            return Ok(());
+        }
+
+        // Out-of-order timestamps do not refill the allowance
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * capacity / per).min(capacity);
+        self.last_refill = self.last_refill.max(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) * per / capacity))
+        }
+    }
+
+    /// Return the allowance counted for a message that was not sent after all (e.g. because
+    /// the outbound queue is full).
+    ///
+    /// The allowance never exceeds the burst size of [`max_messages`].
+    ///
+    /// [`max_messages`]: struct.RateLimitConfig.html#structfield.max_messages
+    ///
+    pub fn release(&mut self, message: &Message) {
+        if let Message::Alive { .. } = message {
+            return;
+        }
+
+        self.tokens = (self.tokens + 1.0).min(f64::from(self.config.max_messages));
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockClock;
+
+    const CONFIG: RateLimitConfig =
+        RateLimitConfig { max_messages: 4, per: Duration::from_secs(2) };
+
+    fn millis(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_rate_limiter_throttles_bursts() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new_at(CONFIG, start);
+        let msg = Message::new_request_all_controllers();
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+        }
+
+        // Beyond the burst, wait for one message's worth of time (2s / 4)
+        assert_eq!(Err(millis(500)), limiter.try_acquire_at(&msg, start));
+        assert_eq!(Err(millis(300)), limiter.try_acquire_at(&msg, start + millis(200)));
+
+        assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start + millis(500)));
+        assert_eq!(Err(millis(500)), limiter.try_acquire_at(&msg, start + millis(500)));
+
+        // The allowance is refilled up to the burst size only
+        let later = start + Duration::from_secs(60);
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&msg, later));
+        }
+        assert!(limiter.try_acquire_at(&msg, later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_exempts_alive() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new_at(CONFIG, start);
+        let msg = Message::new_request_all_controllers();
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+        }
+        assert!(limiter.try_acquire_at(&msg, start).is_err());
+
+        for _ in 0..100 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&Message::new_alive(), start));
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_release() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new_at(CONFIG, start);
+        let msg = Message::new_request_all_controllers();
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+        }
+        assert!(limiter.try_acquire_at(&msg, start).is_err());
+
+        limiter.release(&msg);
+        assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+        assert!(limiter.try_acquire_at(&msg, start).is_err());
+
+        // `Alive` is not counted, so nothing is returned
+        limiter.release(&Message::new_alive());
+        assert!(limiter.try_acquire_at(&msg, start).is_err());
+
+        // The allowance is capped at the burst size
+        let mut limiter = RateLimiter::new_at(CONFIG, start);
+        limiter.release(&msg);
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire_at(&msg, start));
+        }
+        assert!(limiter.try_acquire_at(&msg, start).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_with_mock_clock() {
+        let clock = MockClock::new();
+        let mut limiter = RateLimiter::with_clock(CONFIG, clock.clone());
+        let msg = Message::new_request_all_controllers();
+
+        for _ in 0..4 {
+            assert_eq!(Ok(()), limiter.try_acquire(&msg));
+        }
+        assert_eq!(Err(millis(500)), limiter.try_acquire(&msg));
+
+        clock.advance(millis(200));
+        assert_eq!(Err(millis(300)), limiter.try_acquire(&msg));
+
+        clock.advance(millis(300));
+        assert_eq!(Ok(()), limiter.try_acquire(&msg));
+        assert!(limiter.try_acquire(&msg).is_err());
+    }
+}
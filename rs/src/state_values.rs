@@ -1,4 +1,4 @@
-use super::{JobMode, OpMode, TextName, ID};
+use super::{Controller, JobMode, OpMode, TextName, ID};
 use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 
@@ -10,25 +10,30 @@ pub struct StateValues<'a> {
     /// Current operating mold of the controller.
     #[serde(skip_serializing_if = "OpMode::is_unknown")]
     #[serde(default)]
+    #[cfg_attr(feature = "legacy", serde(alias = "op_mode"))]
     op_mode: OpMode,
     //
     /// Current job mode of the controller.
     #[serde(skip_serializing_if = "JobMode::is_unknown")]
     #[serde(default)]
+    #[cfg_attr(feature = "legacy", serde(alias = "job_mode"))]
     job_mode: JobMode,
     //
     /// Unique ID of the current logged-in user (if any) on the controller.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "legacy", serde(alias = "operator_id"))]
     operator_id: Option<ID>,
     //
     /// Current active job ID (if any) on the controller.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "job_card_id"))]
     job_card_id: Option<Box<TextName<'a>>>,
     //
     /// Unique ID of the set of mold data currently loaded (if any) on the controller.
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(borrow)]
+    #[cfg_attr(feature = "legacy", serde(alias = "mold_id"))]
     mold_id: Option<Box<TextName<'a>>>,
 }
 
@@ -244,6 +249,25 @@ impl<'a> StateValues<'a> {
 
         Ok(Self { operator_id, job_card_id, mold_id, ..Self::new(op, job) })
     }
+
+    // Create a `StateValues` from the current states of a `Controller`.
+    pub(crate) fn from_controller(controller: &Controller<'a>) -> Self {
+        Self {
+            op_mode: controller.op_mode,
+            job_mode: controller.job_mode,
+            operator_id: controller.operator.as_ref().map(|user| user.id()),
+            job_card_id: controller
+                .job_card_id
+                .as_ref()
+                .and_then(|jc| TextName::new(jc.as_ref().clone()))
+                .map(Box::new),
+            mold_id: controller
+                .mold_id
+                .as_ref()
+                .and_then(|m| TextName::new(m.as_ref().clone()))
+                .map(Box::new),
+        }
+    }
 }
 
 impl Default for StateValues<'_> {
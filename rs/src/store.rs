@@ -0,0 +1,284 @@
+use super::{Controller, Message, Operator, ID};
+use indexmap::IndexMap;
+
+/// A cache of the latest state of each controller, keyed by controller ID.
+///
+/// Messages from the server are folded into the cache by [`apply`]:
+///
+/// | Message            | Known controller                        | Unknown controller |
+/// |:-------------------|:----------------------------------------|:-------------------|
+/// | `ControllersList`  | replaced                                | inserted           |
+/// | `ControllerStatus` | merged (see [`Controller::merge`])      | inserted if the message contains the whole `controller`, otherwise ignored |
+/// | `CycleData`        | `last_cycle_data` replaced              | ignored            |
+/// | `OperatorInfo`     | `operator` replaced if the login is allowed | ignored        |
+///
+/// A `ControllersList` contains all the controllers connected to the server, so cached
+/// controllers missing from it are removed.
+///
+/// All other messages (including `LoginOperator`, which is only a request) are ignored.
+/// A controller that has not yet been seen in full is ignored because there is not enough
+/// information to create it; request a `ControllersList` to fill the cache.
+///
+/// The cached controllers own all their data (see [`Controller::into_owned`]), so messages
+/// can be dropped right after they are applied.
+///
+/// [`apply`]: struct.ControllerStore.html#method.apply
+/// [`Controller::merge`]: struct.Controller.html#method.merge
+/// [`Controller::into_owned`]: struct.Controller.html#method.into_owned
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let list = Message::parse_from_json_str(
+///     r#"{"$type":"ControllersList","data":{"1":{"controllerId":1,"displayName":"Hello","controllerType":"Ai12","version":"1.0","model":"JM128-Ai","IP":"192.168.1.1:5000","opMode":"Manual","jobMode":"ID01"}},"sequence":1}"#
+/// )?;
+/// let status = Message::parse_from_json_str(
+///     r#"{"$type":"ControllerStatus","controllerId":1,"opMode":"Automatic","state":{"opMode":"Automatic"},"sequence":2}"#
+/// )?;
+///
+/// let mut store = ControllerStore::new();
+/// assert!(store.apply(&list));
+/// assert!(store.apply(&status));
+///
+/// assert_eq!(1, store.len());
+/// assert_eq!(OpMode::Automatic, store.get(ID::from_u32(1)).unwrap().op_mode);
+/// # Ok(())
+/// # }
+/// ~~~
+#[derive(Debug, Clone, Default)]
+pub struct ControllerStore {
+    controllers: IndexMap<ID, Controller<'static>>,
+}
+
+impl ControllerStore {
+    /// Create an empty `ControllerStore`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Fold a message into the cache.
+    ///
+    /// Returns `true` if any cached controller was inserted, updated or removed, or `false`
+    /// if the message is ignored.
+    pub fn apply(&mut self, msg: &Message<'_>) -> bool {
+        match msg {
+            Message::ControllersList { data, .. } => {
+                let count = self.controllers.len();
+                self.controllers.retain(|id, _| data.contains_key(id));
+                let removed = self.controllers.len() < count;
+
+                for (id, controller) in data {
+                    self.controllers.insert(*id, Controller::clone(controller).into_owned());
+                }
+                removed || !data.is_empty()
+            }
+            Message::ControllerStatus { controller_id, controller, .. } => {
+                match self.controllers.get_mut(controller_id) {
+                    Some(cached) => cached.merge(msg),
+                    None => match controller {
+                        Some(controller) => {
+                            let mut cached = Controller::clone(controller).into_owned();
+                            cached.merge(msg);
+                            self.controllers.insert(*controller_id, cached);
+                            true
+                        }
+                        None => false,
+                    },
+                }
+            }
+            Message::CycleData { controller_id, data, .. } => {
+                match self.controllers.get_mut(controller_id) {
+                    Some(cached) => {
+                        cached.last_cycle_data = data
+                            .iter()
+                            .map(|(key, value)| (key.clone().into_owned(), *value))
+                            .collect();
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Message::OperatorInfo { controller_id, operator_id: Some(id), name, .. } => {
+                match self.controllers.get_mut(controller_id) {
+                    Some(cached) => {
+                        let name = Some(name.clone().into_owned());
+                        cached.operator = Some(Operator::new_with_name(*id, name));
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Get a cached controller by ID.
+    pub fn get(&self, id: ID) -> Option<&Controller<'static>> {
+        self.controllers.get(&id)
+    }
+
+    /// Iterate over all cached controllers, in the order they are first inserted.
+    pub fn iter(&self) -> impl Iterator<Item = (ID, &Controller<'static>)> {
+        self.controllers.iter().map(|(id, controller)| (*id, controller))
+    }
+
+    /// Number of cached controllers.
+    pub fn len(&self) -> usize {
+        self.controllers.len()
+    }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool {
+        self.controllers.is_empty()
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{OpMode, R32};
+    use std::result::Result;
+
+    const LIST: &str = r#"{"$type":"ControllersList","data":{
+        "1":{"controllerId":1,"displayName":"Hello","controllerType":"Ai12","version":"1.0","model":"JM128-Ai","IP":"192.168.1.1:5000","opMode":"Manual","jobMode":"ID01"},
+        "2":{"controllerId":2,"displayName":"World","controllerType":"Ai02","version":"2.0","model":"JM268-Ai","IP":"192.168.1.2:5000","opMode":"Automatic","jobMode":"ID02"}
+    },"sequence":1}"#;
+
+    #[test]
+    fn test_controller_store_apply() -> Result<(), String> {
+        let list = Message::parse_from_json_str(LIST)?;
+        let status = Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":1,"opMode":"SemiAutomatic","operatorId":42,"state":{"opMode":"SemiAutomatic","operatorId":42},"sequence":2}"#,
+        )?;
+        let cycle = Message::parse_from_json_str(
+            r#"{"$type":"CycleData","controllerId":2,"data":{"Z_QDGODCNT":123},"timestamp":"2019-02-26T02:03:04+08:00","sequence":3}"#,
+        )?;
+        let login = Message::parse_from_json_str(
+            r#"{"$type":"OperatorInfo","controllerId":1,"operatorId":42,"name":"John","password":"secret","level":5,"sequence":4}"#,
+        )?;
+
+        let mut store = ControllerStore::new();
+        assert!(store.is_empty());
+
+        for msg in &[&list, &status, &cycle, &login] {
+            assert!(store.apply(msg));
+        }
+
+        assert_eq!(2, store.len());
+        assert_eq!(vec![1, 2], store.iter().map(|(id, _)| u32::from(id)).collect::<Vec<_>>());
+
+        let c1 = store.get(ID::from_u32(1)).ok_or("controller 1 missing")?;
+        assert_eq!(OpMode::SemiAutomatic, c1.op_mode);
+        assert_eq!(Some(ID::from_u32(42)), c1.operator_id());
+        assert_eq!(Some("John"), c1.operator.as_ref().and_then(Operator::name));
+
+        let c2 = store.get(ID::from_u32(2)).ok_or("controller 2 missing")?;
+        assert_eq!(OpMode::Automatic, c2.op_mode);
+        assert_eq!(Some(&R32::new(123.0)), c2.last_cycle_data.get("Z_QDGODCNT"));
+
+        // A new list replaces cached controllers
+        assert!(store.apply(&list));
+        assert_eq!(OpMode::Manual, store.get(ID::from_u32(1)).unwrap().op_mode);
+        assert!(store.get(ID::from_u32(2)).unwrap().last_cycle_data.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_store_list_removes_unlisted() -> Result<(), String> {
+        let mut store = ControllerStore::new();
+        assert!(store.apply(&Message::parse_from_json_str(LIST)?));
+        assert_eq!(2, store.len());
+
+        // Controller 1 has disconnected
+        let list = Message::parse_from_json_str(
+            r#"{"$type":"ControllersList","data":{
+                "2":{"controllerId":2,"displayName":"World","controllerType":"Ai02","version":"2.0","model":"JM268-Ai","IP":"192.168.1.2:5000","opMode":"Automatic","jobMode":"ID02"}
+            },"sequence":2}"#,
+        )?;
+
+        assert!(store.apply(&list));
+        assert_eq!(1, store.len());
+        assert!(store.get(ID::from_u32(1)).is_none());
+        assert!(store.get(ID::from_u32(2)).is_some());
+
+        // No controller is connected
+        let empty =
+            Message::parse_from_json_str(r#"{"$type":"ControllersList","data":{},"sequence":3}"#)?;
+
+        assert!(store.apply(&empty));
+        assert!(store.is_empty());
+        assert!(!store.apply(&empty));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_store_outlives_messages() -> Result<(), String> {
+        let mut store = ControllerStore::new();
+
+        {
+            let list = LIST.to_string();
+            let cycle = String::from(
+                r#"{"$type":"CycleData","controllerId":1,"data":{"Z_QDGODCNT":123},"timestamp":"2019-02-26T02:03:04+08:00","sequence":2}"#,
+            );
+            assert!(store.apply(&Message::parse_from_json_str(&list)?));
+            assert!(store.apply(&Message::parse_from_json_str(&cycle)?));
+        }
+
+        let c1 = store.get(ID::from_u32(1)).ok_or("controller 1 missing")?;
+        assert_eq!("Hello", c1.display_name.get());
+        assert_eq!("JM128-Ai", c1.model.get());
+        assert_eq!(Some(&R32::new(123.0)), c1.last_cycle_data.get("Z_QDGODCNT"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_store_unknown_controller() -> Result<(), String> {
+        let mut store = ControllerStore::new();
+
+        // Partial updates for unknown controllers are ignored
+        let status = Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":3,"opMode":"Automatic","state":{"opMode":"Automatic"},"sequence":1}"#,
+        )?;
+        let cycle = Message::parse_from_json_str(
+            r#"{"$type":"CycleData","controllerId":3,"data":{"Z_QDGODCNT":123},"timestamp":"2019-02-26T02:03:04+08:00","sequence":2}"#,
+        )?;
+        let login = Message::parse_from_json_str(
+            r#"{"$type":"OperatorInfo","controllerId":3,"operatorId":42,"name":"John","password":"secret","level":5,"sequence":3}"#,
+        )?;
+
+        assert!(!store.apply(&status));
+        assert!(!store.apply(&cycle));
+        assert!(!store.apply(&login));
+        assert!(!store.apply(&Message::new_alive()));
+        assert!(store.is_empty());
+
+        // A status with the whole controller is inserted
+        let status = Message::parse_from_json_str(
+            r#"{"$type":"ControllerStatus","controllerId":3,"state":{"opMode":"Automatic","jobMode":"ID01"},"sequence":4,
+                "controller":{"controllerId":3,"displayName":"New","controllerType":"Ai12","version":"1.0","model":"JM128-Ai","IP":"192.168.1.3:5000","opMode":"Automatic","jobMode":"ID01"}}"#,
+        )?;
+
+        assert!(store.apply(&status));
+        assert_eq!(1, store.len());
+
+        let c3 = store.get(ID::from_u32(3)).ok_or("controller 3 missing")?;
+        assert_eq!("New", c3.display_name.get());
+        assert_eq!(OpMode::Automatic, c3.op_mode);
+
+        // A failed login leaves the operator unchanged
+        let denied = Message::parse_from_json_str(
+            r#"{"$type":"OperatorInfo","controllerId":3,"name":"Not Allowed","password":"secret","level":0,"sequence":5}"#,
+        )?;
+        assert!(!store.apply(&denied));
+        assert_eq!(None, store.get(ID::from_u32(3)).unwrap().operator);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,458 @@
+use super::{Controller, Error, Message, Result};
+use serde::de::Error as _;
+use std::io::{BufRead, BufReader, Bytes, Read};
+
+// Where the stream is within the `ControllersList` message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    Start,
+    Fields,
+    Data,
+    Done,
+}
+
+/// A stream of the controllers in a `ControllersList` message, read from a JSON text one
+/// controller at a time.
+///
+/// Only the JSON text of one controller is held in memory at any time, so a very large
+/// `ControllersList` (e.g. for thousands of controllers) can be processed without first
+/// buffering and parsing the entire message.
+///
+/// Use [`Message::stream_controllers`] to create a `ControllerStream`.
+///
+/// [`Message::stream_controllers`]: enum.Message.html#method.stream_controllers
+///
+#[derive(Debug)]
+pub struct ControllerStream<R: Read> {
+    reader: Bytes<BufReader<R>>,
+    peeked: Option<u8>,
+    state: State,
+    //
+    // Whether the next member of the current JSON object is the first.
+    first: bool,
+    //
+    // Whether the `$type` and `data` fields have been read.
+    has_type: bool,
+    has_data: bool,
+    //
+    // The JSON text of the last key or value read, which the last `Controller` borrows from.
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> ControllerStream<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader).bytes(),
+            peeked: None,
+            state: State::Start,
+            first: true,
+            has_type: false,
+            has_data: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Read the next `Controller` in the `ControllersList`, or `None` if there are no
+    /// more controllers.
+    ///
+    /// The `Controller` returned borrows from an internal buffer, so it must be dropped
+    /// before `next_controller` can be called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::JsonError`]`)` if the JSON text cannot be read
+    /// or is not a valid `ControllersList` message, or if a controller cannot be parsed.
+    /// The stream ends after an error, except when a controller cannot be parsed.
+    ///
+    /// [`OpenProtocolError::JsonError`]: enum.OpenProtocolError.html#variant.JsonError
+    ///
+    pub fn next_controller(&mut self) -> Option<Result<'_, Controller<'_>>> {
+        match self.advance() {
+            Ok(true) => Some(serde_json::from_slice(&self.buffer).map_err(Error::JsonError)),
+            Ok(false) => None,
+            Err(err) => {
+                self.state = State::Done;
+                Some(Err(Error::JsonError(err)))
+            }
+        }
+    }
+
+    // Move to the JSON text of the next controller (in `buffer`).
+    // Returns false at the end of the message.
+    fn advance(&mut self) -> serde_json::Result<bool> {
+        loop {
+            match self.state {
+                State::Start => {
+                    self.expect(b'{')?;
+                    self.state = State::Fields;
+                    self.first = true;
+                }
+                State::Fields => {
+                    if !self.next_key()? {
+                        self.state = State::Done;
+
+                        if !self.has_type {
+                            return Err(serde_json::Error::missing_field("$type"));
+                        }
+                        if !self.has_data {
+                            return Err(serde_json::Error::missing_field("data"));
+                        }
+                        if self.peek_token()?.is_some() {
+                            return Err(invalid("trailing characters"));
+                        }
+                        return Ok(false);
+                    }
+
+                    match self.buffer.as_slice() {
+                        b"data" => {
+                            self.expect(b'{')?;
+                            self.state = State::Data;
+                            self.first = true;
+                            self.has_data = true;
+                        }
+                        b"$type" => {
+                            self.read_value()?;
+                            if self.buffer != br#""ControllersList""# {
+                                return Err(invalid(format!(
+                                    "expected a ControllersList message, got [{}]",
+                                    String::from_utf8_lossy(&self.buffer)
+                                )));
+                            }
+                            self.has_type = true;
+                        }
+                        _ => self.read_value()?,
+                    }
+                }
+                State::Data => {
+                    if !self.next_key()? {
+                        self.state = State::Fields;
+                        self.first = false;
+                        continue;
+                    }
+
+                    self.read_value()?;
+                    return Ok(true);
+                }
+                State::Done => return Ok(false),
+            }
+        }
+    }
+
+    fn next_byte(&mut self) -> serde_json::Result<Option<u8>> {
+        match self.peeked.take() {
+            Some(b) => Ok(Some(b)),
+            None => self.reader.next().transpose().map_err(serde_json::Error::io),
+        }
+    }
+
+    // Skip whitespace and peek at the next byte, if any.
+    fn peek_token(&mut self) -> serde_json::Result<Option<u8>> {
+        while let Some(b) = self.next_byte()? {
+            if !b.is_ascii_whitespace() {
+                self.peeked = Some(b);
+                return Ok(Some(b));
+            }
+        }
+        Ok(None)
+    }
+
+    // Skip whitespace and read the next byte.
+    fn token(&mut self) -> serde_json::Result<u8> {
+        self.peek_token()?;
+        self.next_byte()?.ok_or_else(|| invalid("unexpected end of input"))
+    }
+
+    fn expect(&mut self, expected: u8) -> serde_json::Result<()> {
+        match self.token()? {
+            b if b == expected => Ok(()),
+            b => Err(invalid(format!("expected '{}', got '{}'", expected as char, b as char))),
+        }
+    }
+
+    // Read the key (unquoted) of the next member of the current JSON object into `buffer`.
+    // Returns false at the end of the object.
+    fn next_key(&mut self) -> serde_json::Result<bool> {
+        if self.peek_token()? == Some(b'}') {
+            self.peeked = None;
+            return Ok(false);
+        }
+
+        if !self.first {
+            self.expect(b',')?;
+        }
+        self.first = false;
+
+        self.expect(b'"')?;
+        self.buffer.clear();
+        self.read_string()?;
+        self.buffer.pop();
+        self.expect(b':')?;
+        Ok(true)
+    }
+
+    // Read the rest of a JSON string, up to and including the closing quote, into `buffer`.
+    fn read_string(&mut self) -> serde_json::Result<()> {
+        let mut escaped = false;
+
+        loop {
+            let b = self.next_byte()?.ok_or_else(|| invalid("unterminated string"))?;
+            self.buffer.push(b);
+
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => return Ok(()),
+                _ => (),
+            }
+        }
+    }
+
+    // Read the JSON text of the next value into `buffer`.
+    fn read_value(&mut self) -> serde_json::Result<()> {
+        self.buffer.clear();
+        let mut depth = 0_usize;
+
+        loop {
+            let b = match self.next_byte()? {
+                Some(b) if depth == 0 && self.buffer.is_empty() && b.is_ascii_whitespace() => {
+                    continue
+                }
+                Some(b) => b,
+                None if depth == 0 && !self.buffer.is_empty() => return Ok(()),
+                None => return Err(invalid("unexpected end of input")),
+            };
+
+            match b {
+                // End of a number, `true`, `false` or `null`
+                b',' | b'}' | b']' if depth == 0 => {
+                    if self.buffer.is_empty() {
+                        return Err(invalid(format!("expected a value, got '{}'", b as char)));
+                    }
+                    self.peeked = Some(b);
+                    return Ok(());
+                }
+                _ if depth == 0 && !self.buffer.is_empty() && b.is_ascii_whitespace() => {
+                    return Ok(())
+                }
+                _ => self.buffer.push(b),
+            }
+
+            match b {
+                b'"' => self.read_string()?,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth -= 1,
+                _ => (),
+            }
+
+            // End of a string, object or array
+            if depth == 0 && matches!(self.buffer[0], b'"' | b'{' | b'[') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// A stream of messages read from newline-delimited JSON (NDJSON) text, one line at a time.
+///
+/// Use [`Message::iter_json_lines`] to create a `JsonLines`.
+///
+/// [`Message::iter_json_lines`]: enum.Message.html#method.iter_json_lines
+///
+#[derive(Debug)]
+pub struct JsonLines<R: BufRead> {
+    reader: Option<R>,
+    //
+    // The last line read, which the last `Message` borrows from.
+    line: String,
+}
+
+impl<R: BufRead> JsonLines<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self { reader: Some(reader), line: String::new() }
+    }
+
+    /// Read the next `Message`, skipping blank lines, or `None` at the end of the text.
+    ///
+    /// The `Message` returned borrows from an internal buffer, so it must be dropped
+    /// before `next_message` can be called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::JsonError`]`)` if the text cannot be read
+    /// (e.g. it is not valid UTF-8), after which the stream ends.
+    ///
+    /// Returns `Err(`[`OpenProtocolError`]`)` if a line cannot be parsed.  Subsequent lines
+    /// are still parsed.
+    ///
+    /// [`OpenProtocolError`]: enum.OpenProtocolError.html
+    /// [`OpenProtocolError::JsonError`]: enum.OpenProtocolError.html#variant.JsonError
+    ///
+    pub fn next_message(&mut self) -> Option<Result<'_, Message<'_>>> {
+        let reader = self.reader.as_mut()?;
+
+        loop {
+            self.line.clear();
+
+            match reader.read_line(&mut self.line) {
+                Ok(0) => {
+                    self.reader = None;
+                    return None;
+                }
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => return Some(Message::parse_from_json_str(&self.line)),
+                Err(err) => {
+                    self.reader = None;
+                    return Some(Err(Error::JsonError(serde_json::Error::io(err))));
+                }
+            }
+        }
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> serde_json::Error {
+    serde_json::Error::custom(message)
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ID;
+    use std::io::Cursor;
+    use std::result::Result;
+
+    fn controllers_list(count: u32) -> String {
+        let controllers: Vec<_> = (1..=count)
+            .map(|id| {
+                format!(
+                    r#""{0}":{{"controllerId":{0},"displayName":"Machine {0}","controllerType":"Ai12","version":"1.0","model":"JM138Ai","IP":"192.168.1.{1}:123","opMode":"Automatic","jobMode":"ID02","lastCycleData":{{"Z_QDGODCNT":{0},"Z_QDCYCTIM":12.5}},"variables":{{"Z_QDVPPOS":1.5}}}}"#,
+                    id,
+                    id % 250 + 1
+                )
+            })
+            .collect();
+
+        format!(
+            r#"{{"$type":"ControllersList","data":{{{}}},"sequence":42,"priority":10}}"#,
+            controllers.join(",")
+        )
+    }
+
+    fn collect(json: &str) -> Result<Vec<(ID, String)>, String> {
+        let mut stream = Message::stream_controllers(Cursor::new(json));
+        let mut list = Vec::new();
+
+        while let Some(controller) = stream.next_controller() {
+            let controller = controller?;
+            list.push((controller.controller_id, controller.display_name.get().to_string()));
+        }
+
+        Ok(list)
+    }
+
+    #[test]
+    fn test_controller_stream_large_list() -> Result<(), String> {
+        const COUNT: u32 = 10_000;
+
+        let json = controllers_list(COUNT);
+        let mut stream = Message::stream_controllers(Cursor::new(json.as_bytes()));
+        let mut count = 0;
+
+        while let Some(controller) = stream.next_controller() {
+            let controller = controller?;
+            count += 1;
+
+            assert_eq!(count, controller.controller_id);
+            assert_eq!(format!("Machine {}", count), controller.display_name.get());
+            assert_eq!(count as f32, controller.last_cycle_data["Z_QDGODCNT"].raw());
+        }
+
+        assert_eq!(COUNT, count);
+        assert!(stream.next_controller().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_stream_same_as_parse() -> Result<(), String> {
+        let json = controllers_list(20);
+        let msg = Message::parse_from_json_str(&json)?;
+        let expected: Vec<_> = match msg {
+            Message::ControllersList { data, .. } => {
+                data.values().map(|c| (c.controller_id, c.display_name.get().to_string())).collect()
+            }
+            _ => unreachable!(),
+        };
+
+        assert_eq!(20, expected.len());
+        assert_eq!(expected, collect(&json)?);
+
+        // Fields in any order, with whitespace and nested values to skip
+        let json = r#"
+            { "sequence" : 1, "options": { "x": [ "}", { "y": "\"{" } ] }, "flag": true,
+              "data" : { "2" : { "controllerId" : 2, "displayName" : "B\"2" } ,
+                         "1" : { "controllerId" : 1, "displayName" : "A1" } },
+              "$type" : "ControllersList" }
+        "#;
+        assert_eq!(
+            vec![(ID::from_u32(2), r#"B"2"#.to_string()), (ID::from_u32(1), "A1".to_string())],
+            collect(json)?
+        );
+
+        assert_eq!(
+            Vec::<(ID, String)>::new(),
+            collect(r#"{"$type":"ControllersList","data":{}}"#)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_stream_errors() -> Result<(), String> {
+        fn error(json: &str) -> String {
+            collect(json).unwrap_err()
+        }
+
+        assert!(error(r#"{"$type":"Alive","sequence":1}"#).contains("expected a ControllersList"));
+        assert!(error(r#"{"data":{}}"#).contains("missing field `$type`"));
+        assert!(error(r#"{"$type":"ControllersList","data":{"1":{"controllerId":1"#)
+            .contains("unexpected end of input"));
+        assert!(error(r#"{"$type":"ControllersList","data":{"1":}}"#).contains("expected a value"));
+        assert!(error(r#"{"$type":"ControllersList"}"#).contains("missing field `data`"));
+        assert!(error(r#"{"$type":"ControllersList","data":{}} x"#).contains("trailing characters"));
+        assert!(error(r#"["$type"]"#).contains("expected '{'"));
+
+        // A controller that cannot be parsed does not end the stream
+        let json = r#"{"$type":"ControllersList","data":{"1":{"controllerId":0},"2":{"controllerId":2,"displayName":"B"}}}"#;
+        let mut stream = Message::stream_controllers(json.as_bytes());
+        assert!(stream.next_controller().unwrap().is_err());
+        assert_eq!(2, stream.next_controller().unwrap()?.controller_id);
+        assert!(stream.next_controller().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_lines() -> Result<(), String> {
+        let text = "{\"$type\":\"Alive\",\"sequence\":1}\r\n   \n{\"$type\":\"Foo\"}\n\n{\"$type\":\"Alive\",\"sequence\":3}";
+        let mut lines = Message::iter_json_lines(text.as_bytes());
+
+        assert_eq!(1, lines.next_message().unwrap()?.sequence());
+        assert!(lines.next_message().unwrap().is_err());
+        assert_eq!(3, lines.next_message().unwrap()?.sequence());
+        assert!(lines.next_message().is_none());
+        assert!(lines.next_message().is_none());
+
+        // Invalid UTF-8 ends the stream
+        let text =
+            b"{\"$type\":\"Alive\",\"sequence\":1}\n\xFF\n{\"$type\":\"Alive\",\"sequence\":3}\n";
+        let mut lines = Message::iter_json_lines(&text[..]);
+
+        assert_eq!(1, lines.next_message().unwrap()?.sequence());
+        assert!(matches!(lines.next_message(), Some(Err(Error::JsonError(_)))));
+        assert!(lines.next_message().is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,603 @@
+//! Test utilities for integrators (requires the `test-util` feature).
+//!
+//! [`MockServer`] is an in-process WebSocket server that plays back a scripted
+//! conversation, so that handlers can be tested end-to-end without a real iChen® Server.
+//!
+//! [`MockClock`] is a [`Clock`] that only moves when told to, so that timer logic (i.e.
+//! [`Keepalive`] and [`RateLimiter`]) can be tested deterministically.
+//!
+//! [`MockServer`]: struct.MockServer.html
+//! [`MockClock`]: struct.MockClock.html
+//! [`Clock`]: ../trait.Clock.html
+//! [`Keepalive`]: ../struct.Keepalive.html
+//! [`RateLimiter`]: ../struct.RateLimiter.html
+//!
+
+use super::deflate::{DeflateParams, Deflater, MessageAssembler};
+use super::{Clock, Message};
+use std::fmt::{Debug, Formatter};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use websocket::dataframe::{DataFrame, Opcode};
+use websocket::sync::{Client, Server};
+use websocket::OwnedMessage;
+
+/// A single step in a [`Script`].
+///
+/// [`Script`]: struct.Script.html
+///
+enum Step {
+    /// Wait for an inbound message that satisfies a predicate.
+    Expect(String, Box<dyn Fn(&Message) -> bool + Send>),
+    //
+    /// Send a canned JSON text frame to the client.
+    Respond(String),
+    //
+    /// Send a canned JSON text message to the client, fragmented into multiple frames.
+    RespondFragmented(String, usize),
+    //
+    /// Stay silent for a period of time.
+    Pause(Duration),
+}
+
+impl Debug for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Step::Expect(description, _) => write!(f, "Expect({})", description),
+            Step::Respond(json) => write!(f, "Respond({})", json),
+            Step::RespondFragmented(json, size) => {
+                write!(f, "RespondFragmented({}, {})", json, size)
+            }
+            Step::Pause(duration) => write!(f, "Pause({:?})", duration),
+        }
+    }
+}
+
+/// A scripted sequence of expected inbound messages and canned responses for a [`MockServer`].
+///
+/// Steps are played back strictly in order.
+///
+/// [`MockServer`]: struct.MockServer.html
+///
+#[derive(Debug, Default)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    /// Create an empty `Script`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Expect the next inbound message to be of a particular type (e.g. `"Join"`),
+    /// as specified by its `$type` tag.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::testing::*;
+    /// let script = Script::new().expect("Join").expect("RequestControllersList");
+    /// ~~~
+    pub fn expect(self, message_type: &str) -> Self {
+        let tag = message_type.to_string();
+        self.expect_with(message_type, move |msg| type_tag(msg).as_ref() == Some(&tag))
+    }
+
+    /// Expect the next inbound message to satisfy a predicate.
+    ///
+    /// `description` is used in the error message when the expectation is not met.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use ichen_openprotocol::testing::*;
+    /// let script = Script::new().expect_with("JOIN with password", |msg| match msg {
+    ///     Message::Join { password, .. } => *password == "hello",
+    ///     _ => false,
+    /// });
+    /// ~~~
+    pub fn expect_with<F>(mut self, description: &str, predicate: F) -> Self
+    where
+        F: Fn(&Message) -> bool + Send + 'static,
+    {
+        self.steps.push(Step::Expect(description.to_string(), Box::new(predicate)));
+        self
+    }
+
+    /// Send a canned JSON text frame to the client.
+    ///
+    /// The JSON text is sent as-is without validation, so malformed messages can be
+    /// used to test error handling.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::testing::*;
+    /// let script = Script::new()
+    ///     .expect("Join")
+    ///     .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#);
+    /// ~~~
+    pub fn respond_json<S: Into<String>>(mut self, json: S) -> Self {
+        self.steps.push(Step::Respond(json.into()));
+        self
+    }
+
+    /// Send a canned JSON text message to the client, fragmented into multiple WebSocket
+    /// frames of (at most) `fragment_size` bytes each.
+    ///
+    /// The JSON text is split on byte boundaries, so a fragment may end in the middle of a
+    /// multi-byte UTF-8 character.  This is useful for testing that clients correctly
+    /// reassemble fragmented messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fragment_size` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::testing::*;
+    /// let script = Script::new()
+    ///     .expect("Join")
+    ///     .respond_json_fragmented(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#, 16);
+    /// ~~~
+    pub fn respond_json_fragmented<S: Into<String>>(
+        mut self,
+        json: S,
+        fragment_size: usize,
+    ) -> Self {
+        assert!(fragment_size > 0, "fragment size cannot be zero");
+        self.steps.push(Step::RespondFragmented(json.into(), fragment_size));
+        self
+    }
+
+    /// Stay silent for a period of time, neither sending nor expecting anything.
+    ///
+    /// This is useful for simulating a server that has silently gone away.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::testing::*;
+    /// # use std::time::Duration;
+    /// let script = Script::new().expect("Join").pause(Duration::from_secs(1));
+    /// ~~~
+    pub fn pause(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Pause(duration));
+        self
+    }
+
+    /// Send a `Message` to the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Message` fails validation.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use ichen_openprotocol::testing::*;
+    /// let script = Script::new().expect("Alive").respond(&Message::new_alive());
+    /// ~~~
+    pub fn respond(self, message: &Message) -> Self {
+        let json = message.to_json_str().expect("invalid message in script");
+        self.respond_json(json)
+    }
+}
+
+/// An in-process WebSocket server that plays back a [`Script`].
+///
+/// The server accepts a single client connection on a random local port, then plays back
+/// each step of the script in order.  Any inbound message that does not match the current
+/// expectation fails the script.  Use [`start_sequence`] to accept multiple connections.
+///
+/// Call [`finish`] at the end of a test to wait for the script to complete and retrieve
+/// the outcome.
+///
+/// [`Script`]: struct.Script.html
+/// [`start_sequence`]: struct.MockServer.html#method.start_sequence
+/// [`finish`]: struct.MockServer.html#method.finish
+///
+/// # Examples
+///
+/// ~~~no_run
+/// # use ichen_openprotocol::testing::*;
+/// # fn main() -> std::result::Result<(), String> {
+/// let server = MockServer::start(
+///     Script::new()
+///         .expect("Join")
+///         .respond_json(r#"{"$type":"JoinResponse","result":100,"sequence":1}"#),
+/// )
+/// .map_err(|x| x.to_string())?;
+///
+/// // Connect to `server.url()` and exercise the handler...
+///
+/// server.finish()?;
+/// # Ok(())
+/// # }
+/// ~~~
+#[derive(Debug)]
+pub struct MockServer {
+    address: SocketAddr,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+impl MockServer {
+    /// Default time to wait for the client to connect or to send the next message.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Start a `MockServer` playing back a `Script`, with the default timeout.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the server cannot bind to a local port.
+    ///
+    pub fn start(script: Script) -> std::io::Result<Self> {
+        Self::start_with_timeout(script, Self::DEFAULT_TIMEOUT)
+    }
+
+    /// Start a `MockServer` playing back a `Script`.
+    ///
+    /// The script fails if the client does not send an expected message within `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the server cannot bind to a local port.
+    ///
+    pub fn start_with_timeout(script: Script, timeout: Duration) -> std::io::Result<Self> {
+        Self::start_with_options(vec![script], timeout, false)
+    }
+
+    /// Start a `MockServer` playing back a `Script`, with the default timeout, that accepts
+    /// the `permessage-deflate` WebSocket extension.
+    ///
+    /// If the client requests compression, all messages sent by the server are compressed,
+    /// and compressed messages from the client are decompressed before checking against
+    /// the script.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the server cannot bind to a local port.
+    ///
+    pub fn start_with_compression(script: Script) -> std::io::Result<Self> {
+        Self::start_with_options(vec![script], Self::DEFAULT_TIMEOUT, true)
+    }
+
+    /// Start a `MockServer`, with the default timeout, that accepts one client connection
+    /// for each `Script`, in order.
+    ///
+    /// Each script is played back over its own connection after the previous script
+    /// completes (e.g. to test a client that reconnects).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(std::io::Error)` if the server cannot bind to a local port.
+    ///
+    pub fn start_sequence(scripts: Vec<Script>) -> std::io::Result<Self> {
+        Self::start_with_options(scripts, Self::DEFAULT_TIMEOUT, false)
+    }
+
+    fn start_with_options(
+        scripts: Vec<Script>,
+        timeout: Duration,
+        compression: bool,
+    ) -> std::io::Result<Self> {
+        let mut server = Server::bind("127.0.0.1:0")?;
+        let address = server.local_addr()?;
+
+        let handle = std::thread::spawn(move || {
+            for (index, script) in scripts.into_iter().enumerate() {
+                let mut request = server
+                    .accept()
+                    .map_err(|x| format!("connection {}: cannot accept: {}", index, x.error))?;
+                request.tcp_stream().set_read_timeout(Some(timeout)).map_err(|x| x.to_string())?;
+
+                let extension = DeflateParams::accept(request.extensions()).filter(|_| compression);
+                let compressed = extension.is_some();
+
+                if let Some(extension) = extension {
+                    request = request.use_extension(extension);
+                }
+
+                let client = request
+                    .accept()
+                    .map_err(|(_, x)| format!("connection {}: cannot accept: {}", index, x))?;
+
+                play(client, script, compressed)?;
+            }
+
+            Ok(())
+        });
+
+        Ok(Self { address, handle })
+    }
+
+    /// Get the WebSocket URL of this server (e.g. `ws://127.0.0.1:12345`).
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Wait for the script to complete.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` describing the first failed step, if any.
+    ///
+    pub fn finish(self) -> Result<(), String> {
+        self.handle.join().map_err(|_| "mock server panicked".to_string())?
+    }
+}
+
+// Play back a script over a connected client, optionally compressing all messages.
+fn play(mut client: Client<TcpStream>, script: Script, compressed: bool) -> Result<(), String> {
+    let mut deflater = if compressed { Some(Deflater::new(false)) } else { None };
+    let mut assembler = if compressed { Some(MessageAssembler::new(false)) } else { None };
+
+    for (index, step) in script.steps.into_iter().enumerate() {
+        match step {
+            Step::Respond(json) => match deflater.as_mut() {
+                Some(deflater) => client.send_dataframe(&deflater.text_frame(&json)?),
+                None => client.send_message(&OwnedMessage::Text(json)),
+            }
+            .map_err(|x| format!("step {}: cannot send: {}", index, x))?,
+            //
+            Step::RespondFragmented(json, size) => {
+                let payload = match deflater.as_mut() {
+                    Some(deflater) => deflater.deflate(json.as_bytes())?,
+                    None => json.into_bytes(),
+                };
+                let mut fragments: Vec<_> = payload.chunks(size).collect();
+
+                // An empty text is still sent, as a single empty frame
+                if fragments.is_empty() {
+                    fragments.push(&[]);
+                }
+
+                let last = fragments.len() - 1;
+
+                for (n, fragment) in fragments.into_iter().enumerate() {
+                    let opcode = if n == 0 { Opcode::Text } else { Opcode::Continuation };
+                    let mut frame = DataFrame::new(n == last, opcode, fragment.to_vec());
+                    frame.reserved[0] = compressed && n == 0;
+
+                    client
+                        .send_dataframe(&frame)
+                        .map_err(|x| format!("step {}: cannot send: {}", index, x))?;
+                }
+            }
+            //
+            Step::Pause(duration) => std::thread::sleep(duration),
+            //
+            Step::Expect(description, predicate) => loop {
+                let json = match recv(&mut client, assembler.as_mut()) {
+                    Ok(OwnedMessage::Text(json)) => json,
+                    Ok(OwnedMessage::Ping(data)) => {
+                        client
+                            .send_message(&OwnedMessage::Pong(data))
+                            .map_err(|x| format!("step {}: cannot send: {}", index, x))?;
+                        continue;
+                    }
+                    Ok(OwnedMessage::Close(_)) => {
+                        return Err(format!(
+                            "step {}: connection closed while expecting {}",
+                            index, description
+                        ))
+                    }
+                    Ok(_) => continue,
+                    Err(err) => {
+                        return Err(format!(
+                            "step {}: error while expecting {}: {}",
+                            index, description, err
+                        ))
+                    }
+                };
+
+                let msg = Message::parse_from_json_str(&json)
+                    .map_err(|x| format!("step {}: invalid message [{}]: {}", index, json, x))?;
+
+                if !predicate(&msg) {
+                    return Err(format!(
+                        "step {}: expected {}, got [{}]",
+                        index, description, json
+                    ));
+                }
+
+                break;
+            },
+        }
+    }
+
+    let _ = client.send_message(&OwnedMessage::Close(None));
+    Ok(())
+}
+
+// Receive the next message from the client, decompressing it if necessary.
+fn recv(
+    client: &mut Client<TcpStream>,
+    assembler: Option<&mut MessageAssembler>,
+) -> Result<OwnedMessage, String> {
+    let assembler = match assembler {
+        Some(assembler) => assembler,
+        None => return client.recv_message().map_err(|x| x.to_string()),
+    };
+
+    loop {
+        let frame = client.recv_dataframe().map_err(|x| x.to_string())?;
+
+        if let Some(msg) = assembler.push(frame, usize::MAX).map_err(|x| x.to_string())? {
+            return Ok(msg);
+        }
+    }
+}
+
+// Get the `$type` tag of a message.
+fn type_tag(msg: &Message) -> Option<String> {
+    match serde_json::to_value(msg).ok()?.get("$type")? {
+        serde_json::Value::String(tag) => Some(tag.clone()),
+        _ => None,
+    }
+}
+
+/// A [`Clock`] whose time only changes when it is advanced.
+///
+/// Clones of a `MockClock` share the same time, so a test can keep a clone to advance the
+/// clock after handing it to the component under test.
+///
+/// [`Clock`]: ../trait.Clock.html
+///
+/// # Examples
+///
+/// ~~~
+/// # use ichen_openprotocol::*;
+/// # use ichen_openprotocol::testing::MockClock;
+/// # use std::time::Duration;
+/// let clock = MockClock::new();
+/// let start = clock.now();
+///
+/// let shared = clock.clone();
+/// shared.advance(Duration::from_secs(5));
+///
+/// assert_eq!(start + Duration::from_secs(5), clock.now());
+/// ~~~
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Create a new `MockClock` starting at the current time of the system.
+    pub fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+
+    /// Create a new `MockClock` starting at a particular time.
+    pub fn new_at(start: Instant) -> Self {
+        Self { now: Arc::new(Mutex::new(start)) }
+    }
+
+    /// Move the clock forward by a period of time.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner()) += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Filters;
+    use websocket::ClientBuilder;
+
+    #[test]
+    fn test_mock_server_join_exchange() -> Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .expect("Join")
+                .respond_json(r#"{"$type":"JoinResponse","result":100,"level":10,"sequence":1}"#)
+                .expect_with("request for all controllers", |msg| match msg {
+                    Message::RequestControllersList { controller_id, .. } => {
+                        controller_id.is_none()
+                    }
+                    _ => false,
+                })
+                .respond_json(r#"{"$type":"ControllersList","data":{},"sequence":2}"#),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut client = ClientBuilder::new(&server.url())
+            .map_err(|x| x.to_string())?
+            .connect_insecure()
+            .map_err(|x| x.to_string())?;
+
+        let send = |client: &mut Client<TcpStream>, msg: Message| {
+            let json = msg.to_json_str().map_err(|x| x.to_string())?;
+            client.send_message(&OwnedMessage::Text(json)).map_err(|x| x.to_string())
+        };
+
+        let recv = |client: &mut Client<TcpStream>| match client.recv_message() {
+            Ok(OwnedMessage::Text(json)) => Ok(json),
+            other => Err(format!("expected text, got {:?}", other)),
+        };
+
+        send(&mut client, Message::new_join("hello", Filters::All))?;
+
+        let json = recv(&mut client)?;
+        match Message::parse_from_json_str(&json).map_err(|x| x.to_string())? {
+            Message::JoinResponse { result, .. } => assert_eq!(100, result),
+            msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+        }
+
+        send(
+            &mut client,
+            Message::RequestControllersList { controller_id: None, options: Default::default() },
+        )?;
+
+        let json = recv(&mut client)?;
+        match Message::parse_from_json_str(&json).map_err(|x| x.to_string())? {
+            Message::ControllersList { data, .. } => assert!(data.is_empty()),
+            msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+        }
+
+        server.finish()
+    }
+
+    #[test]
+    fn test_mock_server_fragmented_empty_text() -> Result<(), String> {
+        let server = MockServer::start(
+            Script::new()
+                .respond_json_fragmented("", 4)
+                .respond_json_fragmented(r#"{"$type":"Alive","sequence":1}"#, 4),
+        )
+        .map_err(|x| x.to_string())?;
+
+        let mut client = ClientBuilder::new(&server.url())
+            .map_err(|x| x.to_string())?
+            .connect_insecure()
+            .map_err(|x| x.to_string())?;
+
+        for expected in &["", r#"{"$type":"Alive","sequence":1}"#] {
+            match client.recv_message() {
+                Ok(OwnedMessage::Text(json)) => assert_eq!(*expected, json),
+                other => return Err(format!("expected text, got {:?}", other)),
+            }
+        }
+
+        server.finish()
+    }
+
+    #[test]
+    fn test_mock_server_unexpected_message() -> Result<(), String> {
+        let server = MockServer::start(Script::new().expect("Join")).map_err(|x| x.to_string())?;
+
+        let mut client = ClientBuilder::new(&server.url())
+            .map_err(|x| x.to_string())?
+            .connect_insecure()
+            .map_err(|x| x.to_string())?;
+
+        let json = Message::new_alive().to_json_str().map_err(|x| x.to_string())?;
+        client.send_message(&OwnedMessage::Text(json)).map_err(|x| x.to_string())?;
+
+        let err = server.finish().unwrap_err();
+        assert!(err.starts_with("step 0: expected Join, got"), "{}", err);
+
+        Ok(())
+    }
+}
@@ -1,18 +1,19 @@
-use derive_more::*;
+use super::utils::deserialize_cow_str;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::borrow::{Borrow, Cow};
 use std::cmp::{Ordering, PartialEq, PartialOrd};
 use std::convert::TryFrom;
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::ops::Deref;
 
-/// A text string ID that cannot be empty or all-whitespace, and must be all-ASCII.
+/// A `Cow<str>` for a text string ID that cannot be empty or all-whitespace, and must be
+/// all-ASCII.
 ///
 /// This type is usually used for specifying a unique ID.
 ///
 /// It `Deref`s to `&str`.
 ///
-pub type TextID<'a> = ConstrainedText<&'a str, NonEmptyAllASCII>;
+pub type TextID<'a> = ConstrainedText<Cow<'a, str>, NonEmptyAllASCII>;
 
 /// A `Cow<str>` for a name that cannot be empty or all-whitespace.
 ///
@@ -71,8 +72,7 @@ impl TextConstraint for NonEmptyAllASCII {
 /// A data structure that wraps a text string (or anything that dereferences into a text string)
 /// while guaranteeing that the specified text constraint is upheld.
 ///
-#[derive(Display, Clone, Ord, Eq, Hash)]
-#[display(fmt = "_0")]
+#[derive(Clone, Ord, Eq, Hash)]
 pub struct ConstrainedText<T: AsRef<str>, C: TextConstraint>(T, C);
 
 impl<T: AsRef<str>, C: TextConstraint> Debug for ConstrainedText<T, C> {
@@ -81,6 +81,12 @@ impl<T: AsRef<str>, C: TextConstraint> Debug for ConstrainedText<T, C> {
     }
 }
 
+impl<T: AsRef<str>, C: TextConstraint> Display for ConstrainedText<T, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.get())
+    }
+}
+
 impl<T: AsRef<str>, C: TextConstraint> ConstrainedText<T, C> {
     /// Create a new `ConstrainedText` from a text string and a constraint.
     ///
@@ -105,7 +111,9 @@ impl<T: AsRef<str>, C: TextConstraint> ConstrainedText<T, C> {
     /// let id = TextName::new_from_str("你好吗？").unwrap();
     /// assert_eq!("你好吗？", &id);
     /// ~~~
-    pub fn new(text: T) -> Option<Self> {
+    pub fn new(text: impl Into<T>) -> Option<Self> {
+        let text = text.into();
+
         if !C::check(text.as_ref()) {
             None
         } else {
@@ -135,7 +143,7 @@ where
     type Error = String;
 
     fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        Self::new(value.into()).ok_or_else(|| format!("invalid value: {} required", C::required()))
+        Self::new(value).ok_or_else(|| format!("invalid value: {} required", C::required()))
     }
 }
 
@@ -203,21 +211,76 @@ impl<T: AsRef<str>, C: TextConstraint> Serialize for ConstrainedText<T, C> {
 
 impl<'a, 'de: 'a, T, C> Deserialize<'de> for ConstrainedText<T, C>
 where
-    T: AsRef<str> + From<&'a str>,
+    T: AsRef<str> + FromCowStr<'a>,
     C: TextConstraint,
 {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let string_val: &str = Deserialize::deserialize(deserializer)?;
-        let value = string_val.into();
+        let string_val = deserialize_cow_str(deserializer)?;
+
+        if !C::check(&string_val) {
+            return Err(serde::de::Error::custom(format!(
+                "expected {}, got [{}]",
+                C::required(),
+                string_val
+            )));
+        }
 
-        ConstrainedText::new(value).ok_or_else(|| {
-            serde::de::Error::custom(format!("expected {}, got [{}]", C::required(), string_val))
+        T::from_cow_str(string_val).map(|value| ConstrainedText(value, C::new())).map_err(|text| {
+            serde::de::Error::custom(format!(
+                "expected {} without escape sequences, got [{}]",
+                C::required(),
+                text
+            ))
         })
     }
 }
 
+/// A text string type that can be created from a deserialized `Cow<str>`.
+///
+pub trait FromCowStr<'a>: Sized {
+    /// Create a value from a `Cow<str>`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` with the original text if it cannot be held by this type
+    /// (e.g. an owned string for a borrowed `&str`).
+    fn from_cow_str(text: Cow<'a, str>) -> Result<Self, Cow<'a, str>>;
+}
+
+impl<'a> FromCowStr<'a> for &'a str {
+    fn from_cow_str(text: Cow<'a, str>) -> Result<Self, Cow<'a, str>> {
+        match text {
+            Cow::Borrowed(text) => Ok(text),
+            text => Err(text),
+        }
+    }
+}
+
+impl<'a> FromCowStr<'a> for Cow<'a, str> {
+    fn from_cow_str(text: Cow<'a, str>) -> Result<Self, Cow<'a, str>> {
+        Ok(text)
+    }
+}
+
 impl<'a> TextName<'a> {
     pub fn new_from_str<T: Into<Cow<'a, str>>>(text: T) -> Option<Self> {
         Self::new(text.into())
     }
 }
+
+impl<C: TextConstraint> ConstrainedText<Cow<'_, str>, C> {
+    /// Convert into a `ConstrainedText` that owns its text string, copying it if borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// let text = String::from("hello");
+    /// let id: TextID<'static> = TextID::new(text.as_str()).unwrap().into_owned();
+    /// drop(text);
+    /// assert_eq!("hello", &id);
+    /// ~~~
+    pub fn into_owned(self) -> ConstrainedText<Cow<'static, str>, C> {
+        ConstrainedText(Cow::Owned(self.0.into_owned()), self.1)
+    }
+}
@@ -0,0 +1,97 @@
+use super::Message;
+use std::time::{Duration, Instant};
+
+/// A [`Message`] together with the time it was received.
+///
+/// Returned by [`BlockingConnection::recv_timestamped`] for latency analysis.
+///
+/// [`Message`]: enum.Message.html
+/// [`BlockingConnection::recv_timestamped`]: struct.BlockingConnection.html#method.recv_timestamped
+///
+#[derive(Debug, Clone)]
+pub struct TimestampedMessage<'a> {
+    /// The time the message arrived from the server (before it is queued for delivery).
+    pub received_at: Instant,
+    //
+    /// The message.
+    pub message: Message<'a>,
+}
+
+impl TimestampedMessage<'_> {
+    /// Get the [`MessageStamp`] of this message.
+    ///
+    /// The stamp does not borrow the message, so it can be kept after the message is dropped
+    /// (e.g. to compare with the next message received).
+    ///
+    /// [`MessageStamp`]: struct.MessageStamp.html
+    pub fn stamp(&self) -> MessageStamp {
+        MessageStamp { received_at: self.received_at, sequence: self.message.sequence() }
+    }
+}
+
+/// The time a message was received, together with its `sequence` number.
+///
+/// Get it from [`TimestampedMessage::stamp`].
+///
+/// [`TimestampedMessage::stamp`]: struct.TimestampedMessage.html#method.stamp
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageStamp {
+    /// The time the message arrived from the server (before it is queued for delivery).
+    pub received_at: Instant,
+    //
+    /// The `sequence` number of the message.
+    pub sequence: u64,
+}
+
+impl MessageStamp {
+    /// Get the time elapsed between receiving an `earlier` message and this message.
+    ///
+    /// Returns zero if `earlier` was actually received after this message.
+    pub fn elapsed_since(self, earlier: MessageStamp) -> Duration {
+        self.received_at.saturating_duration_since(earlier.received_at)
+    }
+
+    /// Get the difference in `sequence` numbers between an `earlier` message and this
+    /// message.
+    ///
+    /// This is 1 for consecutive messages, and larger if messages in between are missing.
+    /// Returns zero if `earlier` does not have a smaller sequence number.
+    pub fn sequence_gap(self, earlier: MessageStamp) -> u64 {
+        self.sequence.saturating_sub(earlier.sequence)
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MessageKind;
+
+    #[test]
+    fn test_timestamped_message_gaps() {
+        let start = Instant::now();
+
+        let first = {
+            let msg =
+                TimestampedMessage { received_at: start, message: Message::new_alive_with(5) };
+            msg.stamp()
+        };
+        let second = TimestampedMessage {
+            received_at: start + Duration::from_millis(250),
+            message: Message::new_alive_with(8),
+        };
+
+        assert_eq!(MessageKind::Alive, second.message.kind());
+        assert_eq!(8, second.stamp().sequence);
+
+        let second = second.stamp();
+        assert_eq!(Duration::from_millis(250), second.elapsed_since(first));
+        assert_eq!(3, second.sequence_gap(first));
+
+        // Out of order
+        assert_eq!(Duration::from_secs(0), first.elapsed_since(second));
+        assert_eq!(0, first.sequence_gap(second));
+    }
+}
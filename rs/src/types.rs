@@ -1,3 +1,4 @@
+use super::{Error, Message};
 use derive_more::*;
 use serde::{Deserialize, Serialize};
 use std::cmp::{Ordering, PartialEq, PartialOrd};
@@ -12,39 +13,53 @@ use std::{borrow::Borrow, ops::Deref};
 ///
 /// [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/doc/enums.md#languages
 ///
+/// With the `lowercase-enums` feature, values are serialized in lowercase (e.g. `en`),
+/// and both lowercase and the standard names (e.g. `EN`) are accepted when deserializing.
+///
 #[derive(
     Debug, Display, Ord, PartialOrd, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone,
 )]
+#[cfg_attr(feature = "lowercase-enums", serde(rename_all = "lowercase"))]
 pub enum Language {
     /// Unknown language.
     #[display(fmt = "Unknown")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Unknown"))]
     Unknown,
     /// English (en)
     #[display(fmt = "English")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "EN"))]
     EN,
     /// Traditional Chinese (zh-tw)
     #[display(fmt = "䌓體中文")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "B5"))]
     B5,
     /// Simplified Chinese (zh-cn)
     #[display(fmt = "简体中文")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "GB"))]
     GB,
     /// French (fr)
     #[display(fmt = "Français")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "FR"))]
     FR,
     /// German (de)
     #[display(fmt = "Deutsch")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "DE"))]
     DE,
     /// Italian (it)
     #[display(fmt = "Italiano")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "IT"))]
     IT,
     /// Spanish (es)
     #[display(fmt = "Español")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ES"))]
     ES,
     /// Portuguese (pt)
     #[display(fmt = "Português")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "PT"))]
     PT,
     /// Japanese (ja)
     #[display(fmt = "日本語")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "JA"))]
     JA,
 }
 
@@ -71,26 +86,90 @@ impl Default for Language {
     }
 }
 
+impl std::str::FromStr for Language {
+    type Err = String;
+
+    /// Parse a `Language` from either the name of the variant (e.g. `B5`) or the ISO code
+    /// of the language (e.g. `zh-tw`).
+    ///
+    /// Matching is case-insensitive and ignores surrounding white-space.
+    /// ISO codes may use either `-` or `_` as the separator (e.g. `zh_TW`).
+    ///
+    /// `"Unknown"` parses to `Language::Unknown`.  Any other text is an error rather than
+    /// `Language::Unknown`, so that typos in configuration are not silently ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(String)` if the text is not a known language.
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::str::FromStr;
+    /// assert_eq!(Err("unknown language: [klingon]".into()), Language::from_str("klingon"));
+    /// assert_eq!(Err("unknown language: []".into()), Language::from_str(""));
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::str::FromStr;
+    /// assert_eq!(Ok(Language::EN), Language::from_str("EN"));
+    /// assert_eq!(Ok(Language::EN), Language::from_str("en"));
+    /// assert_eq!(Ok(Language::B5), Language::from_str("zh-tw"));
+    /// assert_eq!(Ok(Language::B5), Language::from_str("B5"));
+    /// assert_eq!(Ok(Language::Unknown), Language::from_str("Unknown"));
+    /// ~~~
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let text = text.trim();
+
+        Ok(match text.to_ascii_lowercase().replace('_', "-").as_str() {
+            "unknown" => Language::Unknown,
+            "en" => Language::EN,
+            "b5" | "zh-tw" => Language::B5,
+            "gb" | "zh-cn" => Language::GB,
+            "fr" => Language::FR,
+            "de" => Language::DE,
+            "it" => Language::IT,
+            "es" => Language::ES,
+            "pt" => Language::PT,
+            "ja" => Language::JA,
+            _ => return Err(format!("unknown language: [{}]", text)),
+        })
+    }
+}
+
 /// Operating modes of the controller.
 ///
 /// See [this document] for details.
 ///
 /// [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/doc/enums.md#opmodes
 ///
+/// With the `lowercase-enums` feature, values are serialized in lowercase (e.g. `automatic`),
+/// and both lowercase and the standard names (e.g. `Automatic`) are accepted when deserializing.
+///
 #[derive(
     Debug, Display, Ord, PartialOrd, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone,
 )]
+#[cfg_attr(feature = "lowercase-enums", serde(rename_all = "lowercase"))]
 pub enum OpMode {
     /// Unknown operation mode.
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Unknown"))]
     Unknown,
     /// Manual mode.
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Manual"))]
     Manual,
     /// Semi-Automatic mode.
     #[display(fmt = "Semi-Automatic")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "SemiAutomatic"))]
     SemiAutomatic,
     /// Automatic mode.
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Automatic"))]
     Automatic,
     /// Other unspecified operation mode.
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Others"))]
     Others,
     /// The controller is off-line.
     ///
@@ -99,7 +178,20 @@ pub enum OpMode {
     /// [job mode]: enum.JobMode.html
     ///
     #[display(fmt = "Off-Line")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Offline"))]
     Offline,
+    /// An operating mode not recognized by this version of the library (e.g. a new mode
+    /// introduced by a newer iChen® Server).
+    ///
+    /// Only available with the `forward-compat` feature; without it, an unrecognized
+    /// operating mode fails to deserialize.
+    ///
+    /// The original text is not kept, so serializing this variant fails instead of sending
+    /// a different value back to the server.
+    ///
+    #[cfg(feature = "forward-compat")]
+    #[serde(other, skip_serializing)]
+    Unrecognized,
 }
 
 impl OpMode {
@@ -168,6 +260,39 @@ impl OpMode {
             _ => false,
         }
     }
+
+    /// Parse an `OpMode` from a loosely-formatted string.
+    ///
+    /// Matching is case-insensitive and ignores spaces, hyphens, underscores and dots.
+    /// Common abbreviations (e.g. `auto`, `semi-auto`) are also accepted.
+    ///
+    /// This is intended for data from other sources; JSON messages are always
+    /// deserialized strictly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `None` if `text` is not recognized.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Some(OpMode::Automatic), OpMode::from_loose_str("auto"));
+    /// assert_eq!(Some(OpMode::SemiAutomatic), OpMode::from_loose_str("Semi-Auto"));
+    /// assert_eq!(Some(OpMode::Offline), OpMode::from_loose_str("OFF_LINE"));
+    /// assert_eq!(None, OpMode::from_loose_str("fast"));
+    /// ~~~
+    pub fn from_loose_str(text: &str) -> Option<Self> {
+        match normalize_loose_str(text).as_str() {
+            "unknown" => Some(OpMode::Unknown),
+            "manual" | "man" => Some(OpMode::Manual),
+            "semiautomatic" | "semiauto" | "semi" => Some(OpMode::SemiAutomatic),
+            "automatic" | "auto" => Some(OpMode::Automatic),
+            "others" | "other" => Some(OpMode::Others),
+            "offline" => Some(OpMode::Offline),
+            _ => None,
+        }
+    }
 }
 
 impl Default for OpMode {
@@ -177,6 +302,58 @@ impl Default for OpMode {
     }
 }
 
+impl<'a> TryFrom<&'a str> for OpMode {
+    type Error = Error<'a>;
+
+    /// Parse an `OpMode` strictly from its serialized name (e.g. `SemiAutomatic`), exactly
+    /// as when deserializing JSON.
+    ///
+    /// Unrecognized names are always rejected, even with the `forward-compat` feature.
+    ///
+    /// Use [`from_loose_str`] for loosely-formatted text.
+    ///
+    /// [`from_loose_str`]: enum.OpMode.html#method.from_loose_str
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `text` is not recognized.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// assert_eq!(
+    ///     Err(Error::InvalidField {
+    ///         field: "op_mode",
+    ///         value: "Fast".into(),
+    ///         description: "unrecognized operating mode".into()
+    ///     }),
+    ///     OpMode::try_from("Fast")
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// assert_eq!(Ok(OpMode::SemiAutomatic), OpMode::try_from("SemiAutomatic"));
+    /// assert_eq!(Ok(OpMode::Unknown), OpMode::try_from("Unknown"));
+    /// ~~~
+    fn try_from(text: &'a str) -> Result<Self, Self::Error> {
+        const DESCRIPTION: &str = "unrecognized operating mode";
+
+        match parse_strict(text, "op_mode", DESCRIPTION)? {
+            #[cfg(feature = "forward-compat")]
+            OpMode::Unrecognized => Err(unrecognized(text, "op_mode", DESCRIPTION)),
+            mode => Ok(mode),
+        }
+    }
+}
+
 /// Job modes of the controller.
 ///
 /// On some controller models, job modes 1-15 (`ID01` - `ID15`) can be user-defined.
@@ -185,26 +362,46 @@ impl Default for OpMode {
 ///
 /// [this document]: https://github.com/chenhsong/OpenProtocol/blob/master/doc/enums.md#jobmodes
 ///
+/// With the `lowercase-enums` feature, values are serialized in lowercase (e.g. `id01`),
+/// and both lowercase and the standard names (e.g. `ID01`) are accepted when deserializing.
+///
 #[derive(
     Debug, Display, Ord, PartialOrd, PartialEq, Eq, Hash, Serialize, Deserialize, Copy, Clone,
 )]
+#[cfg_attr(feature = "lowercase-enums", serde(rename_all = "lowercase"))]
 pub enum JobMode {
     /// Unknown job mode.
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Unknown"))]
     Unknown,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID01"))]
     ID01,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID02"))]
     ID02,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID03"))]
     ID03,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID04"))]
     ID04,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID05"))]
     ID05,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID06"))]
     ID06,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID07"))]
     ID07,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID08"))]
     ID08,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID09"))]
     ID09,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID10"))]
     ID10,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID11"))]
     ID11,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID12"))]
     ID12,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID13"))]
     ID13,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID14"))]
     ID14,
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "ID15"))]
     ID15,
     /// The controller is off-line.
     ///
@@ -213,7 +410,20 @@ pub enum JobMode {
     /// [operating mode]: enum.OpMode.html
     ///
     #[display(fmt = "Off-Line")]
+    #[cfg_attr(feature = "lowercase-enums", serde(alias = "Offline"))]
     Offline,
+    /// A job mode not recognized by this version of the library (e.g. a new mode
+    /// introduced by a newer iChen® Server).
+    ///
+    /// Only available with the `forward-compat` feature; without it, an unrecognized
+    /// job mode fails to deserialize.
+    ///
+    /// The original text is not kept, so serializing this variant fails instead of sending
+    /// a different value back to the server.
+    ///
+    #[cfg(feature = "forward-compat")]
+    #[serde(other, skip_serializing)]
+    Unrecognized,
 }
 
 impl JobMode {
@@ -263,6 +473,63 @@ impl JobMode {
             _ => true,
         }
     }
+
+    /// Parse a `JobMode` from a loosely-formatted string.
+    ///
+    /// Matching is case-insensitive and ignores spaces, hyphens, underscores and dots.
+    /// Job modes `ID01` - `ID15` can be written with or without the `ID` prefix and
+    /// leading zeros (e.g. `id2`, `ID-02`, `2`).
+    ///
+    /// This is intended for data from other sources; JSON messages are always
+    /// deserialized strictly.
+    ///
+    /// # Errors
+    ///
+    /// Returns `None` if `text` is not recognized.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Some(JobMode::ID02), JobMode::from_loose_str("id2"));
+    /// assert_eq!(Some(JobMode::ID15), JobMode::from_loose_str("ID-15"));
+    /// assert_eq!(Some(JobMode::Offline), JobMode::from_loose_str("Off-Line"));
+    /// assert_eq!(None, JobMode::from_loose_str("ID16"));
+    /// ~~~
+    pub fn from_loose_str(text: &str) -> Option<Self> {
+        let text = normalize_loose_str(text);
+
+        match text.as_str() {
+            "unknown" => return Some(JobMode::Unknown),
+            "offline" => return Some(JobMode::Offline),
+            _ => (),
+        }
+
+        let digits = text.strip_prefix("id").unwrap_or(&text);
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        match digits.trim_start_matches('0').parse::<u8>().ok()? {
+            1 => Some(JobMode::ID01),
+            2 => Some(JobMode::ID02),
+            3 => Some(JobMode::ID03),
+            4 => Some(JobMode::ID04),
+            5 => Some(JobMode::ID05),
+            6 => Some(JobMode::ID06),
+            7 => Some(JobMode::ID07),
+            8 => Some(JobMode::ID08),
+            9 => Some(JobMode::ID09),
+            10 => Some(JobMode::ID10),
+            11 => Some(JobMode::ID11),
+            12 => Some(JobMode::ID12),
+            13 => Some(JobMode::ID13),
+            14 => Some(JobMode::ID14),
+            15 => Some(JobMode::ID15),
+            _ => None,
+        }
+    }
 }
 
 impl Default for JobMode {
@@ -272,6 +539,83 @@ impl Default for JobMode {
     }
 }
 
+impl<'a> TryFrom<&'a str> for JobMode {
+    type Error = Error<'a>;
+
+    /// Parse a `JobMode` strictly from its serialized name (e.g. `ID02`), exactly as when
+    /// deserializing JSON.
+    ///
+    /// Unrecognized names are always rejected, even with the `forward-compat` feature.
+    ///
+    /// Use [`from_loose_str`] for loosely-formatted text.
+    ///
+    /// [`from_loose_str`]: enum.JobMode.html#method.from_loose_str
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(`[`OpenProtocolError::InvalidField`]`)` if `text` is not recognized.
+    ///
+    /// [`OpenProtocolError::InvalidField`]: enum.OpenProtocolError.html#variant.InvalidField
+    ///
+    /// ## Error Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// assert_eq!(
+    ///     Err(Error::InvalidField {
+    ///         field: "job_mode",
+    ///         value: "ID16".into(),
+    ///         description: "unrecognized job mode".into()
+    ///     }),
+    ///     JobMode::try_from("ID16")
+    /// );
+    /// ~~~
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// # use std::convert::TryFrom;
+    /// assert_eq!(Ok(JobMode::ID02), JobMode::try_from("ID02"));
+    /// assert_eq!(Ok(JobMode::Offline), JobMode::try_from("Offline"));
+    /// ~~~
+    fn try_from(text: &'a str) -> Result<Self, Self::Error> {
+        const DESCRIPTION: &str = "unrecognized job mode";
+
+        match parse_strict(text, "job_mode", DESCRIPTION)? {
+            #[cfg(feature = "forward-compat")]
+            JobMode::Unrecognized => Err(unrecognized(text, "job_mode", DESCRIPTION)),
+            mode => Ok(mode),
+        }
+    }
+}
+
+// Parse an enum value strictly from its serialized name, exactly as when deserializing JSON.
+fn parse_strict<'a, T: Deserialize<'a>>(
+    text: &'a str,
+    field: &'static str,
+    description: &'static str,
+) -> Result<T, Error<'a>> {
+    use serde::de::{value::Error as ValueError, IntoDeserializer};
+
+    T::deserialize(IntoDeserializer::<ValueError>::into_deserializer(text))
+        .map_err(|_| unrecognized(text, field, description))
+}
+
+// Error for an unrecognized enum value.
+fn unrecognized<'a>(text: &'a str, field: &'static str, description: &'static str) -> Error<'a> {
+    Error::InvalidField { field, value: text.into(), description: description.into() }
+}
+
+// Lower-case a string and strip all separators (spaces, hyphens, underscores and dots).
+fn normalize_loose_str(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '_' | '.'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
 /// A 32-bit numeric ID that cannot be zero or negative.
 ///
 /// This type is usually used for specifying a unique identification number.
@@ -355,6 +699,42 @@ impl ID {
     pub fn get(self) -> u32 {
         self.0.get()
     }
+
+    /// Get the next `ID` (i.e. this `ID` plus one).
+    ///
+    /// # Errors
+    ///
+    /// Returns `None` if this `ID` is already `u32::MAX`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Some(ID::from_u32(43)), ID::from_u32(42).checked_next());
+    /// assert_eq!(None, ID::from_u32(u32::MAX).checked_next());
+    /// ~~~
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn checked_next(&self) -> Option<Self> {
+        self.get().checked_add(1).and_then(Self::new)
+    }
+
+    /// Get the previous `ID` (i.e. this `ID` minus one).
+    ///
+    /// # Errors
+    ///
+    /// Returns `None` if this `ID` is `1` (since an `ID` cannot be zero).
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(Some(ID::from_u32(41)), ID::from_u32(42).checked_prev());
+    /// assert_eq!(None, ID::from_u32(1).checked_prev());
+    /// ~~~
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn checked_prev(&self) -> Option<Self> {
+        self.get().checked_sub(1).and_then(Self::new)
+    }
 }
 
 impl Debug for ID {
@@ -484,3 +864,499 @@ impl PartialOrd<ActionID> for i32 {
         self.partial_cmp(&other.0)
     }
 }
+
+/// The access level of an operator, from 0 (lowest) to [`MAX_OPERATOR_LEVEL`] (usually 10).
+///
+/// It serializes as a bare integer, and values out of range are rejected when
+/// deserializing.
+///
+/// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+///
+#[derive(
+    Display, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct AccessLevel(u8);
+
+impl AccessLevel {
+    /// The lowest access level: 0.
+    pub const MIN: Self = Self(0);
+
+    /// The highest access level: [`MAX_OPERATOR_LEVEL`] (usually 10).
+    ///
+    /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+    pub const MAX: Self = Self(Message::MAX_OPERATOR_LEVEL);
+
+    /// Create a new `AccessLevel` from a `u8` value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `None` if `value` is higher than [`MAX_OPERATOR_LEVEL`].
+    ///
+    /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(5, AccessLevel::new(5).unwrap().get());
+    /// assert_eq!(None, AccessLevel::new(11));
+    /// ~~~
+    pub fn new(value: u8) -> Option<Self> {
+        Self::try_from(value).ok()
+    }
+
+    /// Convert an `AccessLevel` into a `u8` value.
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Debug for AccessLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", &self.0)
+    }
+}
+
+impl TryFrom<u8> for AccessLevel {
+    type Error = String;
+
+    /// Create a new `AccessLevel` from an integer value.
+    ///
+    /// # Errors
+    ///
+    /// Return `Err(String)` if `value` is higher than [`MAX_OPERATOR_LEVEL`].
+    ///
+    /// [`MAX_OPERATOR_LEVEL`]: enum.Message.html#associatedconstant.MAX_OPERATOR_LEVEL
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use std::convert::TryFrom;
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(10, u8::from(AccessLevel::try_from(10).unwrap()));
+    /// assert_eq!(
+    ///     Err("Level 11 is too high - must be between 0 and 10.".to_string()),
+    ///     AccessLevel::try_from(11)
+    /// );
+    /// ~~~
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value > Self::MAX.0 {
+            Err(format!("Level {} is too high - must be between 0 and {}.", value, Self::MAX.0))
+        } else {
+            Ok(Self(value))
+        }
+    }
+}
+
+impl From<AccessLevel> for u8 {
+    fn from(level: AccessLevel) -> Self {
+        level.0
+    }
+}
+
+impl PartialEq<u8> for AccessLevel {
+    fn eq(&self, other: &u8) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<AccessLevel> for u8 {
+    fn eq(&self, other: &AccessLevel) -> bool {
+        *self == other.0
+    }
+}
+
+/// The outcome of a `JOIN`, decoded from the `result` code of a [`JoinResponse`] message.
+///
+/// The protocol only defines a threshold: result codes >= 100 indicate success, while
+/// result codes < 100 indicate failure (e.g. the password is not authenticated or not
+/// authorized to access the server).  The original code is preserved in both cases.
+///
+/// [`JoinResponse`]: enum.Message.html#variant.JoinResponse
+///
+#[derive(Debug, Display, Ord, PartialOrd, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum JoinResult {
+    /// The `JOIN` succeeded with the specified result code (>= 100).
+    #[display(fmt = "Succeeded ({})", _0)]
+    Succeeded(u32),
+    /// The `JOIN` failed with the specified result code (< 100).
+    #[display(fmt = "Failed ({})", _0)]
+    Failed(u32),
+}
+
+impl JoinResult {
+    /// Lowest result code that indicates success.
+    pub const MIN_SUCCESS_CODE: u32 = 100;
+
+    /// Decode a `JOIN` result code.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(JoinResult::Failed(99), JoinResult::from_code(99));
+    /// assert_eq!(JoinResult::Succeeded(100), JoinResult::from_code(100));
+    /// ~~~
+    pub fn from_code(code: u32) -> Self {
+        if code >= Self::MIN_SUCCESS_CODE {
+            JoinResult::Succeeded(code)
+        } else {
+            JoinResult::Failed(code)
+        }
+    }
+
+    /// Get the original result code.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert_eq!(42, JoinResult::from_code(42).code());
+    /// ~~~
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn code(&self) -> u32 {
+        match self {
+            JoinResult::Succeeded(code) | JoinResult::Failed(code) => *code,
+        }
+    }
+
+    /// Returns true if `Succeeded`.
+    ///
+    /// # Examples
+    ///
+    /// ~~~
+    /// # use ichen_openprotocol::*;
+    /// assert!(JoinResult::from_code(100).is_success());
+    /// assert!(!JoinResult::from_code(99).is_success());
+    /// ~~~
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn is_success(&self) -> bool {
+        match self {
+            JoinResult::Succeeded(_) => true,
+            JoinResult::Failed(_) => false,
+        }
+    }
+}
+
+impl From<u32> for JoinResult {
+    fn from(code: u32) -> Self {
+        Self::from_code(code)
+    }
+}
+
+impl From<JoinResult> for u32 {
+    fn from(result: JoinResult) -> Self {
+        result.code()
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_id_serializes_as_bare_integer() {
+        let id = ID::from_u32(42);
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!("42", json);
+        assert_eq!(serde_json::to_string(&NonZeroU32::new(42).unwrap()).unwrap(), json);
+        assert_eq!(id, serde_json::from_str::<ID>(&json).unwrap());
+
+        assert_eq!("4294967295", serde_json::to_string(&ID::from_u32(u32::MAX)).unwrap());
+        assert_eq!(ID::from_u32(u32::MAX), serde_json::from_str::<ID>("4294967295").unwrap());
+    }
+
+    #[test]
+    fn test_id_deserialize_rejects_invalid() {
+        assert!(serde_json::from_str::<ID>("0").is_err());
+        assert!(serde_json::from_str::<ID>("-1").is_err());
+        assert!(serde_json::from_str::<ID>("\"42\"").is_err());
+        assert!(serde_json::from_str::<ID>("4294967296").is_err());
+    }
+
+    #[test]
+    fn test_id_in_messages_round_trip() {
+        use crate::Message;
+
+        let json = r#"{"$type":"OperatorInfo","controllerId":123,"operatorId":456,"name":"John","password":"pw","level":3,"sequence":1}"#;
+        let msg = Message::parse_from_json_str(json).unwrap();
+
+        match &msg {
+            Message::OperatorInfo { controller_id, operator_id, .. } => {
+                assert_eq!(123, *controller_id);
+                assert_eq!(Some(ID::from_u32(456)), *operator_id);
+            }
+            _ => panic!("expected OperatorInfo, got {:?}", msg),
+        }
+
+        let json2 = msg.to_json_str().unwrap();
+        assert!(json2.contains(r#""controllerId":123"#), "{}", json2);
+        assert!(json2.contains(r#""operatorId":456"#), "{}", json2);
+    }
+
+    #[test]
+    fn test_id_checked_next() {
+        assert_eq!(Some(ID::from_u32(2)), ID::from_u32(1).checked_next());
+        assert_eq!(u32::MAX, ID::from_u32(u32::MAX - 1).checked_next().unwrap());
+        assert_eq!(None, ID::from_u32(u32::MAX).checked_next());
+    }
+
+    #[test]
+    fn test_id_checked_prev() {
+        assert_eq!(None, ID::from_u32(1).checked_prev());
+        assert_eq!(Some(ID::from_u32(1)), ID::from_u32(2).checked_prev());
+        assert_eq!(u32::MAX - 1, ID::from_u32(u32::MAX).checked_prev().unwrap());
+    }
+
+    #[test]
+    fn test_op_mode_from_loose_str() {
+        for (text, expected) in &[
+            ("Automatic", OpMode::Automatic),
+            ("automatic", OpMode::Automatic),
+            ("AUTO", OpMode::Automatic),
+            (" auto ", OpMode::Automatic),
+            ("SemiAutomatic", OpMode::SemiAutomatic),
+            ("semi-automatic", OpMode::SemiAutomatic),
+            ("Semi_Auto", OpMode::SemiAutomatic),
+            ("semi auto", OpMode::SemiAutomatic),
+            ("Semi", OpMode::SemiAutomatic),
+            ("manual", OpMode::Manual),
+            ("MAN", OpMode::Manual),
+            ("other", OpMode::Others),
+            ("Others", OpMode::Others),
+            ("Off-Line", OpMode::Offline),
+            ("off line", OpMode::Offline),
+            ("unknown", OpMode::Unknown),
+        ] {
+            assert_eq!(Some(*expected), OpMode::from_loose_str(text), "{}", text);
+        }
+
+        for text in &["", "-", "automatically", "semi-manual", "on-line", "1"] {
+            assert_eq!(None, OpMode::from_loose_str(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_job_mode_from_loose_str() {
+        for (text, expected) in &[
+            ("ID02", JobMode::ID02),
+            ("id2", JobMode::ID02),
+            ("Id-02", JobMode::ID02),
+            ("id_002", JobMode::ID02),
+            ("2", JobMode::ID02),
+            ("ID01", JobMode::ID01),
+            ("id 10", JobMode::ID10),
+            ("15", JobMode::ID15),
+            ("OFFLINE", JobMode::Offline),
+            ("Off.Line", JobMode::Offline),
+            ("Unknown", JobMode::Unknown),
+        ] {
+            assert_eq!(Some(*expected), JobMode::from_loose_str(text), "{}", text);
+        }
+
+        for text in &["", "id", "ID00", "0", "ID16", "id-2a", "id+2", "idx2", "99999999999"] {
+            assert_eq!(None, JobMode::from_loose_str(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_modes_strict_deserialize_unchanged() {
+        assert_eq!(OpMode::SemiAutomatic, serde_json::from_str("\"SemiAutomatic\"").unwrap());
+        #[cfg(not(feature = "forward-compat"))]
+        assert!(serde_json::from_str::<OpMode>("\"semi-auto\"").is_err());
+        #[cfg(feature = "forward-compat")]
+        assert_eq!(OpMode::Unrecognized, serde_json::from_str("\"semi-auto\"").unwrap());
+        assert_eq!(JobMode::ID02, serde_json::from_str("\"ID02\"").unwrap());
+        #[cfg(not(feature = "forward-compat"))]
+        assert!(serde_json::from_str::<JobMode>("\"id2\"").is_err());
+        #[cfg(feature = "forward-compat")]
+        assert_eq!(JobMode::Unrecognized, serde_json::from_str("\"id2\"").unwrap());
+    }
+
+    #[test]
+    fn test_modes_try_from_str() {
+        assert_eq!(Ok(OpMode::Automatic), OpMode::try_from("Automatic"));
+        assert_eq!(Ok(OpMode::Offline), OpMode::try_from("Offline"));
+        assert_eq!(Ok(JobMode::ID15), JobMode::try_from("ID15"));
+        assert_eq!(Ok(JobMode::Unknown), JobMode::try_from("Unknown"));
+
+        for text in &["", "garbage", "Semi-Automatic", " Automatic", "auto"] {
+            match OpMode::try_from(*text) {
+                Err(Error::InvalidField { field: "op_mode", value, .. }) => {
+                    assert_eq!(*text, value)
+                }
+                result => panic!("expected InvalidField for [{}], got {:?}", text, result),
+            }
+        }
+
+        for text in &["", "garbage", "ID16", "id2", "2"] {
+            match JobMode::try_from(*text) {
+                Err(Error::InvalidField { field: "job_mode", value, .. }) => {
+                    assert_eq!(*text, value)
+                }
+                result => panic!("expected InvalidField for [{}], got {:?}", text, result),
+            }
+        }
+    }
+
+    #[cfg(feature = "forward-compat")]
+    #[test]
+    fn test_modes_forward_compat() {
+        let op: OpMode = serde_json::from_str(r#""TurboMode""#).unwrap();
+        assert_eq!(OpMode::Unrecognized, op);
+        assert!(!op.is_unknown());
+        assert!(!op.is_offline());
+        assert!(!op.is_producing());
+
+        let job: JobMode = serde_json::from_str(r#""ID99""#).unwrap();
+        assert_eq!(JobMode::Unrecognized, job);
+        assert!(!job.is_unknown());
+        assert!(!job.is_offline());
+
+        // Known values are unaffected
+        assert_eq!(OpMode::Automatic, serde_json::from_str(r#""Automatic""#).unwrap());
+        assert_eq!(JobMode::ID15, serde_json::from_str(r#""ID15""#).unwrap());
+
+        // Strict parsing still rejects unrecognized values
+        assert!(OpMode::try_from("TurboMode").is_err());
+        assert!(JobMode::try_from("ID99").is_err());
+
+        // The original text is not kept, so unrecognized values cannot be serialized
+        assert!(serde_json::to_string(&op).is_err());
+        assert!(serde_json::to_string(&job).is_err());
+    }
+
+    #[cfg(not(feature = "lowercase-enums"))]
+    #[test]
+    fn test_enums_serialize_standard_names() {
+        assert_eq!(r#""SemiAutomatic""#, serde_json::to_string(&OpMode::SemiAutomatic).unwrap());
+        assert_eq!(r#""ID02""#, serde_json::to_string(&JobMode::ID02).unwrap());
+        assert_eq!(r#""Offline""#, serde_json::to_string(&JobMode::Offline).unwrap());
+        assert_eq!(r#""EN""#, serde_json::to_string(&Language::EN).unwrap());
+
+        #[cfg(not(feature = "forward-compat"))]
+        assert!(serde_json::from_str::<OpMode>(r#""automatic""#).is_err());
+        #[cfg(feature = "forward-compat")]
+        assert_eq!(OpMode::Unrecognized, serde_json::from_str(r#""automatic""#).unwrap());
+        assert!(serde_json::from_str::<Language>(r#""en""#).is_err());
+    }
+
+    #[cfg(feature = "lowercase-enums")]
+    #[test]
+    fn test_enums_serialize_lowercase_names() {
+        assert_eq!(r#""semiautomatic""#, serde_json::to_string(&OpMode::SemiAutomatic).unwrap());
+        assert_eq!(r#""id02""#, serde_json::to_string(&JobMode::ID02).unwrap());
+        assert_eq!(r#""offline""#, serde_json::to_string(&JobMode::Offline).unwrap());
+        assert_eq!(r#""en""#, serde_json::to_string(&Language::EN).unwrap());
+
+        // Lowercase names round-trip
+        assert_eq!(OpMode::Automatic, serde_json::from_str(r#""automatic""#).unwrap());
+        assert_eq!(JobMode::ID15, serde_json::from_str(r#""id15""#).unwrap());
+        assert_eq!(Language::B5, serde_json::from_str(r#""b5""#).unwrap());
+    }
+
+    #[test]
+    fn test_enums_deserialize_standard_names() {
+        for (json, expected) in &[
+            (r#""Unknown""#, OpMode::Unknown),
+            (r#""Manual""#, OpMode::Manual),
+            (r#""SemiAutomatic""#, OpMode::SemiAutomatic),
+            (r#""Automatic""#, OpMode::Automatic),
+            (r#""Others""#, OpMode::Others),
+            (r#""Offline""#, OpMode::Offline),
+        ] {
+            assert_eq!(*expected, serde_json::from_str::<OpMode>(json).unwrap(), "{}", json);
+        }
+
+        assert_eq!(JobMode::ID01, serde_json::from_str(r#""ID01""#).unwrap());
+        assert_eq!(JobMode::Offline, serde_json::from_str(r#""Offline""#).unwrap());
+        assert_eq!(Language::JA, serde_json::from_str(r#""JA""#).unwrap());
+
+        let msg = crate::Message::parse_from_json_str(
+            r#"{"$type":"Join","version":"4.0","password":"hello","language":"FR","filter":"All","sequence":1}"#,
+        )
+        .unwrap();
+        assert!(matches!(msg, crate::Message::Join { language: Language::FR, .. }));
+    }
+
+    #[test]
+    fn test_join_result_boundary() {
+        assert_eq!(JoinResult::Failed(0), JoinResult::from_code(0));
+        assert_eq!(JoinResult::Failed(99), JoinResult::from_code(99));
+        assert_eq!(JoinResult::Succeeded(100), JoinResult::from_code(100));
+        assert_eq!(JoinResult::Succeeded(u32::MAX), JoinResult::from_code(u32::MAX));
+
+        assert!(!JoinResult::from_code(99).is_success());
+        assert!(JoinResult::from_code(100).is_success());
+
+        for code in &[0, 99, 100, 101, u32::MAX] {
+            assert_eq!(*code, u32::from(JoinResult::from(*code)));
+        }
+
+        assert_eq!("Failed (99)", JoinResult::from_code(99).to_string());
+        assert_eq!("Succeeded (100)", JoinResult::from_code(100).to_string());
+    }
+
+    #[test]
+    fn test_access_level_range() {
+        assert_eq!(AccessLevel::MIN, AccessLevel::try_from(0).unwrap());
+        assert_eq!(AccessLevel::MAX, AccessLevel::try_from(10).unwrap());
+        assert_eq!(
+            Err("Level 11 is too high - must be between 0 and 10.".to_string()),
+            AccessLevel::try_from(11)
+        );
+        assert_eq!(None, AccessLevel::new(u8::MAX));
+    }
+
+    #[test]
+    fn test_access_level_serializes_as_bare_integer() {
+        for level in &[0, 10] {
+            let json = serde_json::to_string(&AccessLevel::new(*level).unwrap()).unwrap();
+            assert_eq!(level.to_string(), json);
+            assert_eq!(*level, serde_json::from_str::<AccessLevel>(&json).unwrap());
+        }
+
+        let err = serde_json::from_str::<AccessLevel>("11").unwrap_err();
+        assert!(err.to_string().contains("too high"), "{}", err);
+        assert!(serde_json::from_str::<AccessLevel>("-1").is_err());
+        assert!(serde_json::from_str::<AccessLevel>("\"5\"").is_err());
+    }
+
+    #[test]
+    fn test_language_from_str() {
+        use std::str::FromStr;
+
+        let cases = [
+            (Language::Unknown, &["Unknown", "unknown", "UNKNOWN"][..]),
+            (Language::EN, &["EN", "en", "En", " en "]),
+            (Language::B5, &["B5", "b5", "zh-tw", "zh-TW", "ZH_TW"]),
+            (Language::GB, &["GB", "gb", "zh-cn", "zh-CN", "zh_cn"]),
+            (Language::FR, &["FR", "fr"]),
+            (Language::DE, &["DE", "de"]),
+            (Language::IT, &["IT", "it"]),
+            (Language::ES, &["ES", "es"]),
+            (Language::PT, &["PT", "pt"]),
+            (Language::JA, &["JA", "ja"]),
+        ];
+
+        for (language, spellings) in &cases {
+            for text in spellings.iter() {
+                assert_eq!(Ok(*language), Language::from_str(text), "{}", text);
+            }
+
+            // The variant name round-trips through serde
+            let json = serde_json::to_string(language).unwrap();
+            assert_eq!(Ok(*language), Language::from_str(json.trim_matches('"')));
+        }
+
+        for text in &["", "  ", "english", "zh", "zh-hk", "e n", "B 5"] {
+            assert_eq!(
+                Err(format!("unknown language: [{}]", text.trim())),
+                Language::from_str(text)
+            );
+        }
+    }
+}
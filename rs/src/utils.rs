@@ -1,8 +1,10 @@
 use super::ID;
 use indexmap::IndexMap;
+use serde::de::Visitor;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
 use std::convert::TryInto;
-use std::fmt::Display;
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
 use std::num::NonZeroU32;
 use std::str::FromStr;
@@ -76,6 +78,49 @@ pub fn check_f32(value: f32) -> std::result::Result<(), &'static str> {
     }
 }
 
+/// Serialize/deserialize an optional time-stamp as Unix epoch milliseconds
+/// (for the `epoch-millis` feature).
+///
+/// RFC3339 strings are also accepted when deserializing.
+#[cfg(feature = "epoch-millis")]
+pub mod epoch_millis_option {
+    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum EpochOrRfc3339 {
+        Millis(i64),
+        Rfc3339(DateTime<FixedOffset>),
+    }
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn serialize<S: Serializer>(
+        value: &Option<DateTime<FixedOffset>>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(time) => s.serialize_some(&time.timestamp_millis()),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+        match Option::<EpochOrRfc3339>::deserialize(d)? {
+            Some(EpochOrRfc3339::Millis(millis)) => match Utc.timestamp_millis_opt(millis).single()
+            {
+                Some(time) => Ok(Some(time.into())),
+                None => Err(D::Error::custom(format!("invalid epoch milliseconds: {}", millis))),
+            },
+            Some(EpochOrRfc3339::Rfc3339(time)) => Ok(Some(time)),
+            None => Ok(None),
+        }
+    }
+}
+
 /// Deserialize a JSON `null` value as `Some(None)` instead of `None`.
 #[allow(clippy::option_option)]
 pub fn deserialize_null_to_some_none<'de, D, T>(d: D) -> Result<Option<Option<T>>, D::Error>
@@ -128,6 +173,9 @@ where
 /// Serialization is usually not a problem because `serde_json` automatically calls
 /// `to_string()` (for key types that implement `Display`) when serializing.
 ///
+/// Integer keys are also accepted (as their decimal text), for formats other than JSON
+/// that keep integer keys as integers.
+///
 pub fn deserialize_indexmap<'de, D, K, T>(d: D) -> Result<IndexMap<K, T>, D::Error>
 where
     D: Deserializer<'de>,
@@ -135,14 +183,36 @@ where
     K::Err: Display,
     T: Deserialize<'de>,
 {
+    struct KeyVisitor<S>(std::marker::PhantomData<S>);
+
+    impl<'de, S> Visitor<'de> for KeyVisitor<S>
+    where
+        S: FromStr,
+        S::Err: Display,
+    {
+        type Value = S;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            f.write_str("a borrowed string or an integer")
+        }
+
+        fn visit_borrowed_str<E: serde::de::Error>(self, s: &'de str) -> Result<S, E> {
+            S::from_str(s).map_err(|err| E::custom(format!("{}: {}", err, s)))
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, n: u64) -> Result<S, E> {
+            let s = n.to_string();
+            S::from_str(&s).map_err(|err| E::custom(format!("{}: {}", err, s)))
+        }
+    }
+
     fn deserialize_string_key<'de, D, S>(d: D) -> Result<S, D::Error>
     where
         D: Deserializer<'de>,
         S: FromStr,
         S::Err: Display,
     {
-        let s = Deserialize::deserialize(d).map_err(serde::de::Error::custom)?;
-        S::from_str(s).map_err(|err| serde::de::Error::custom(format!("{}: {}", err, s)))
+        d.deserialize_any(KeyVisitor(std::marker::PhantomData))
     }
 
     #[derive(Deserialize, Hash, Eq, PartialEq)]
@@ -154,3 +224,64 @@ where
     let dict: IndexMap<Wrapper<K>, T> = Deserialize::deserialize(d)?;
     Ok(dict.into_iter().map(|(Wrapper(k), v)| (k, v)).collect())
 }
+
+/// Deserialize a text string into a `Cow<str>`, borrowing from the input whenever possible.
+///
+/// Text strings that cannot be borrowed (e.g. JSON strings containing escape sequences)
+/// are returned as owned values.
+///
+pub fn deserialize_cow_str<'de, D: Deserializer<'de>>(d: D) -> Result<Cow<'de, str>, D::Error> {
+    struct CowStrVisitor;
+
+    impl<'de> Visitor<'de> for CowStrVisitor {
+        type Value = Cow<'de, str>;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.write_str("a string")
+        }
+
+        fn visit_borrowed_str<E>(self, value: &'de str) -> Result<Self::Value, E> {
+            Ok(Cow::Borrowed(value))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value.to_string()))
+        }
+
+        fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+            Ok(Cow::Owned(value))
+        }
+    }
+
+    d.deserialize_str(CowStrVisitor)
+}
+
+/// Deserialize an optional text string into a `Box<Cow<str>>`, borrowing from the input
+/// whenever possible.
+///
+/// `serde` only borrows a `Cow<str>` field marked with `#[serde(borrow)]` when it is not
+/// wrapped in another type, so an `Option<Box<Cow<str>>>` field always allocates without
+/// this function.  Use together with `#[serde(default)]`.
+///
+pub fn deserialize_optional_cow_str<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<Box<Cow<'de, str>>>, D::Error> {
+    let text: Option<CowStr> = Deserialize::deserialize(d)?;
+    Ok(text.map(|CowStr(text)| Box::new(text)))
+}
+
+/// Deserialize an optional list of text strings into a `Vec<Cow<str>>`, borrowing each
+/// from the input whenever possible (see [`deserialize_optional_cow_str`]).
+///
+/// [`deserialize_optional_cow_str`]: fn.deserialize_optional_cow_str.html
+///
+pub fn deserialize_optional_cow_str_list<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<Vec<Cow<'de, str>>>, D::Error> {
+    let list: Option<Vec<CowStr>> = Deserialize::deserialize(d)?;
+    Ok(list.map(|list| list.into_iter().map(|CowStr(text)| text).collect()))
+}
+
+// A `Cow<str>` that is borrowed from the input whenever possible.
+#[derive(Deserialize)]
+struct CowStr<'a>(#[serde(borrow, deserialize_with = "deserialize_cow_str")] Cow<'a, str>);
@@ -0,0 +1,176 @@
+//! Field-level validation rules, generated by `#[derive(OpValidate)]`.
+//!
+//! Mark a field with `#[op(non_empty)]` or `#[op(range(min, max))]`, and the derived
+//! `check_fields` method checks it (see the `ichen-openprotocol-derive` crate).
+//! Rules involving more than one field are still written by hand.
+
+use super::{Result, R32};
+use std::borrow::Cow;
+
+pub(crate) use ichen_openprotocol_derive::OpValidate;
+
+/// Checks the fields of a data structure marked with `op` attributes.
+pub(crate) trait OpValidate {
+    /// Check all fields marked with `op` attributes, in the order they are declared,
+    /// returning the first error.
+    fn check_fields(&self) -> Result<'static, ()>;
+}
+
+/// A value that is checked by `#[op(non_empty)]`.
+///
+/// A text string must not be empty or all whitespace.  For an `Option`, `None` is valid.
+/// For a `Vec`, every item must be valid (so an empty `Vec` is valid).
+pub(crate) trait NonEmpty {
+    fn is_non_empty(&self) -> bool;
+}
+
+impl NonEmpty for str {
+    fn is_non_empty(&self) -> bool {
+        !self.trim().is_empty()
+    }
+}
+
+impl NonEmpty for String {
+    fn is_non_empty(&self) -> bool {
+        self.as_str().is_non_empty()
+    }
+}
+
+impl NonEmpty for Cow<'_, str> {
+    fn is_non_empty(&self) -> bool {
+        self.as_ref().is_non_empty()
+    }
+}
+
+impl<T: NonEmpty + ?Sized> NonEmpty for &T {
+    fn is_non_empty(&self) -> bool {
+        (**self).is_non_empty()
+    }
+}
+
+impl<T: NonEmpty + ?Sized> NonEmpty for Box<T> {
+    fn is_non_empty(&self) -> bool {
+        (**self).is_non_empty()
+    }
+}
+
+impl<T: NonEmpty> NonEmpty for Option<T> {
+    fn is_non_empty(&self) -> bool {
+        self.iter().all(NonEmpty::is_non_empty)
+    }
+}
+
+impl<T: NonEmpty> NonEmpty for Vec<T> {
+    fn is_non_empty(&self) -> bool {
+        self.iter().all(NonEmpty::is_non_empty)
+    }
+}
+
+/// A value that is checked by `#[op(range(min, max))]`.
+///
+/// For an `Option`, `None` is valid.
+pub(crate) trait InRange {
+    /// Get the value, formatted for display, if it is outside the range between
+    /// `min` and `max` (inclusive).
+    fn out_of_range(&self, min: f64, max: f64) -> Option<String>;
+}
+
+impl InRange for f64 {
+    fn out_of_range(&self, min: f64, max: f64) -> Option<String> {
+        if (min..=max).contains(self) {
+            None
+        } else {
+            Some(self.to_string())
+        }
+    }
+}
+
+impl InRange for f32 {
+    fn out_of_range(&self, min: f64, max: f64) -> Option<String> {
+        // Format the `f32` itself, as widening it into `f64` adds digits
+        f64::from(*self).out_of_range(min, max).map(|_| self.to_string())
+    }
+}
+
+impl InRange for R32 {
+    fn out_of_range(&self, min: f64, max: f64) -> Option<String> {
+        self.raw().out_of_range(min, max)
+    }
+}
+
+impl<T: InRange> InRange for Option<T> {
+    fn out_of_range(&self, min: f64, max: f64) -> Option<String> {
+        self.as_ref().and_then(|value| value.out_of_range(min, max))
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+
+    #[derive(OpValidate)]
+    struct Sample<'a> {
+        #[op(non_empty)]
+        name: &'a str,
+        #[op(non_empty)]
+        tags: Option<Vec<Cow<'a, str>>>,
+        #[op(range(-90.0, 90.0))]
+        latitude: f32,
+        #[op(range(0.0, 100.0))]
+        percent: Option<R32>,
+        unchecked: &'a str,
+    }
+
+    fn sample() -> Sample<'static> {
+        Sample {
+            name: "Hello",
+            tags: Some(vec!["a".into(), "b".into()]),
+            latitude: 12.5,
+            percent: Some(R32::new(100.0)),
+            unchecked: "  ",
+        }
+    }
+
+    #[test]
+    fn test_op_validate_non_empty() {
+        // Fields without `op` attributes are not checked
+        assert!(sample().unchecked.trim().is_empty());
+        assert_eq!(Ok(()), sample().check_fields());
+        assert_eq!(Ok(()), Sample { tags: None, ..sample() }.check_fields());
+        assert_eq!(Ok(()), Sample { tags: Some(vec![]), ..sample() }.check_fields());
+
+        assert_eq!(Err(Error::EmptyField("name")), Sample { name: "", ..sample() }.check_fields());
+        assert_eq!(
+            Err(Error::EmptyField("tags")),
+            Sample { tags: Some(vec!["a".into(), " \t".into()]), ..sample() }.check_fields()
+        );
+
+        // Fields are checked in order
+        assert_eq!(
+            Err(Error::EmptyField("name")),
+            Sample { name: " ", tags: Some(vec!["".into()]), ..sample() }.check_fields()
+        );
+    }
+
+    #[test]
+    fn test_op_validate_range() {
+        assert_eq!(Ok(()), Sample { latitude: -90.0, ..sample() }.check_fields());
+        assert_eq!(Ok(()), Sample { percent: None, ..sample() }.check_fields());
+
+        assert_eq!(
+            Err(Error::ConstraintViolated(
+                "invalid latitude: 123.456 (must be between -90 and 90)".into()
+            )),
+            Sample { latitude: 123.456, ..sample() }.check_fields()
+        );
+        assert_eq!(
+            Err(Error::ConstraintViolated(
+                "invalid percent: -1 (must be between 0 and 100)".into()
+            )),
+            Sample { percent: Some(R32::new(-1.0)), ..sample() }.check_fields()
+        );
+    }
+}
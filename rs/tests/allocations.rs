@@ -0,0 +1,138 @@
+// Checks that parsing borrows text from the input instead of copying it.
+//
+// Allocations are counted per thread by a custom global allocator, so tests running
+// in parallel do not interfere with each other.
+
+use ichen_openprotocol::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::result::Result;
+
+struct CountingAllocator;
+
+thread_local! {
+    // Number of allocations, and total bytes allocated, on this thread.
+    static ALLOCATED: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|a| {
+            let (count, bytes) = a.get();
+            a.set((count + 1, bytes + layout.size()));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Run a closure, returning its result together with the number of allocations and
+// total bytes allocated by it.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize, usize) {
+    ALLOCATED.with(|a| a.set((0, 0)));
+    let result = f();
+    let (count, bytes) = ALLOCATED.with(|a| a.get());
+    (result, count, bytes)
+}
+
+fn controllers_list(name: &str) -> String {
+    format!(
+        r#"{{"$type":"ControllersList","data":{{"12345":{{"controllerId":12345,"displayName":"{name}","controllerType":"Ai12","version":"1.0.0","model":"JM128-MK6","IP":"192.168.1.1:12345","opMode":"Automatic","jobMode":"ID02","jobCardId":"{name}","lastCycleData":{{"Z_QDGODCNT":8567,"Z_QDCYCTIM":979}},"variables":{{"Z_QDGODCNT":8567}},"lastConnectionTime":"2016-03-06T23:11:27.1442177+08:00","operatorId":123,"operatorName":"{name}","moldId":"{name}"}}}},"sequence":68568}}"#,
+        name = name
+    )
+}
+
+fn borrows_from(text: &str, input: &str) -> bool {
+    let range = input.as_ptr() as usize..input.as_ptr() as usize + input.len();
+    range.contains(&(text.as_ptr() as usize))
+}
+
+fn is_borrowed(text: Option<&Cow<str>>) -> bool {
+    matches!(text, Some(Cow::Borrowed(_)))
+}
+
+#[test]
+fn integration_test_parse_controllers_list_borrows_text() -> Result<(), String> {
+    // Warm up any lazily-initialized statics
+    Message::parse_from_json_str(&controllers_list("Warm-up"))?;
+
+    let json = controllers_list("Hello");
+    let msg = Message::parse_from_json_str(&json)?;
+
+    let controller = match &msg {
+        Message::ControllersList { data, .. } => &data[&ID::from_u32(12345)],
+        msg => return Err(format!("expected ControllersList, got {:?}", msg)),
+    };
+
+    for text in &[
+        controller.display_name.get(),
+        controller.controller_type.get(),
+        controller.version.get(),
+        controller.model.get(),
+        controller.job_card_id.as_deref().unwrap(),
+        controller.mold_id.as_deref().unwrap(),
+        controller.operator.as_ref().unwrap().name().unwrap(),
+        controller.variables.keys().next().unwrap().get(),
+    ] {
+        assert!(borrows_from(text, &json), "{} is not borrowed", text);
+    }
+
+    assert!(is_borrowed(controller.job_card_id.as_deref()));
+    assert!(is_borrowed(controller.mold_id.as_deref()));
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_parse_controllers_list_allocations_independent_of_text_length(
+) -> Result<(), String> {
+    // Warm up any lazily-initialized statics
+    Message::parse_from_json_str(&controllers_list("Warm-up"))?;
+
+    let short = controllers_list("Hello");
+    let long = controllers_list(&"Hello".repeat(1000));
+
+    let (_msg, short_count, short_bytes) =
+        count_allocations(|| Message::parse_from_json_str(&short).unwrap());
+    let (_msg, long_count, long_bytes) =
+        count_allocations(|| Message::parse_from_json_str(&long).unwrap());
+
+    // Maps and boxes still allocate, but no text is copied
+    assert!(short_count > 0);
+    assert_eq!(short_count, long_count);
+    assert_eq!(short_bytes, long_bytes);
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_parse_escaped_text_allocates() -> Result<(), String> {
+    let json =
+        r#"{"$type":"JoinResponse","result":100,"message":"Hello, \"World\"!","sequence":1}"#;
+
+    match Message::parse_from_json_str(json)? {
+        Message::JoinResponse { message: Some(message), .. } => {
+            assert_eq!(r#"Hello, "World"!"#, message.as_ref());
+            assert!(!is_borrowed(Some(&message)));
+        }
+        msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+    }
+
+    let json = r#"{"$type":"JoinResponse","result":100,"message":"Hello, World!","sequence":1}"#;
+
+    match Message::parse_from_json_str(json)? {
+        Message::JoinResponse { message: Some(message), .. } => {
+            assert!(is_borrowed(Some(&message)))
+        }
+        msg => return Err(format!("expected JoinResponse, got {:?}", msg)),
+    }
+
+    Ok(())
+}
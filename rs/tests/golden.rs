@@ -0,0 +1,130 @@
+// Golden files lock the wire format of messages.
+//
+// Each canonical message below must serialize byte-for-byte to `tests/golden/<Variant>.json`,
+// and the golden file must parse back into the same JSON.  A renamed field or a changed
+// default therefore fails this test even though it compiles fine.
+//
+// After an intentional change to the wire format, regenerate the golden files with:
+//
+//     UPDATE_GOLDEN=1 cargo test --test golden
+//
+// then review the changes to the files before committing them.
+
+use chrono::DateTime;
+use ichen_openprotocol::*;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::path::PathBuf;
+use Message::*;
+
+// Fixed options, because the default `sequence` auto-increments.
+fn options(sequence: u64) -> MessageOptions<'static> {
+    MessageOptions::deserialize(serde_json::json!({ "sequence": sequence })).unwrap()
+}
+
+// The canonical instance of each message variant with a golden file.
+fn canonical_messages() -> Vec<Message<'static>> {
+    let id = ID::from_u32(123);
+
+    let mut jobs = IndexMap::new();
+    for (job, mold, progress, total) in
+        &[("J001", "Mold#001", 0, 10000), ("J002", "Mold#002", 42, 1000)]
+    {
+        jobs.insert(
+            TextName::new_from_str(*job).unwrap(),
+            JobCard::try_new(job, mold, *progress, *total).unwrap(),
+        );
+    }
+
+    vec![
+        Alive { options: options(1) },
+        Join {
+            org_id: Some(TextID::new("MyCompany").unwrap()),
+            version: TextID::new(Message::PROTOCOL_VERSION).unwrap(),
+            password: Password::new("MyPassword"),
+            language: Language::EN,
+            filter: Filters::All + Filters::JobCards + Filters::Operators,
+            options: options(2),
+        },
+        JoinResponse {
+            result: 100,
+            level: Some(10),
+            message: Some(Box::new("Welcome".into())),
+            version: Some(TextID::new("4.0").unwrap()),
+            options: options(3),
+        },
+        OperatorInfo {
+            controller_id: id,
+            operator_id: Some(ID::from_u32(456)),
+            name: TextName::new_from_str("John").unwrap(),
+            password: Password::new("hello"),
+            level: AccessLevel::new(3).unwrap(),
+            options: options(4),
+        },
+        JobCardsList { controller_id: id, data: jobs, options: options(5) },
+        ControllerAction {
+            controller_id: id,
+            action_id: ActionID::new(42),
+            timestamp: DateTime::parse_from_rfc3339("2019-02-26T02:03:04+08:00").unwrap(),
+            idempotency_key: None,
+            options: options(6),
+        },
+    ]
+}
+
+// Golden files use the standard names of enum values (e.g. `"EN"` for `Language::EN`), so
+// messages containing them are not checked with the `lowercase-enums` feature.
+fn checked_messages() -> Vec<Message<'static>> {
+    const WITH_ENUMS: &[MessageKind] = &[MessageKind::Join];
+
+    canonical_messages()
+        .into_iter()
+        .filter(|msg| !cfg!(feature = "lowercase-enums") || !WITH_ENUMS.contains(&msg.kind()))
+        .collect()
+}
+
+fn golden_path(msg: &Message) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", &format!("{}.json", msg.kind())]
+        .iter()
+        .collect()
+}
+
+#[test]
+fn integration_test_golden_files() -> std::result::Result<(), String> {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    for msg in checked_messages() {
+        let path = golden_path(&msg);
+        let json = msg.to_json_str()?;
+
+        if update {
+            std::fs::write(&path, format!("{}\n", json)).map_err(|x| x.to_string())?;
+            continue;
+        }
+
+        let golden = std::fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        let golden = golden.strip_suffix('\n').unwrap_or(&golden);
+
+        assert_eq!(golden, json, "{} does not match {}", msg.kind(), path.display());
+
+        let parsed = Message::parse_from_json_str(golden)?;
+        assert_eq!(golden, parsed.to_json_str()?, "{} does not round-trip", path.display());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_golden_files_have_messages() -> std::result::Result<(), String> {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden"].iter().collect();
+    let expected: Vec<_> = canonical_messages().iter().map(golden_path).collect();
+
+    // A golden file without a canonical message is never checked
+    for entry in std::fs::read_dir(&dir).map_err(|x| x.to_string())? {
+        let path = entry.map_err(|x| x.to_string())?.path();
+        assert!(expected.contains(&path), "no canonical message for {}", path.display());
+    }
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use ichen_openprotocol::{Filters, Message};
 use Message::*;
 
+// Checks the standard names of enum values
+#[cfg(not(feature = "lowercase-enums"))]
 #[test]
 fn integration_test_serialize_to_json() -> Result<(), String> {
     let msg = Message::new_join(
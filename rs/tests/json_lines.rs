@@ -0,0 +1,75 @@
+// Archived messages are stored as newline-delimited JSON (NDJSON) text, one message per line.
+
+use ichen_openprotocol::{Message, MessageKind};
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+fn fixture() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "archive.ndjson"].iter().collect()
+}
+
+#[test]
+fn integration_test_read_json_lines() -> Result<(), String> {
+    let file = File::open(fixture()).map_err(|x| x.to_string())?;
+    let mut lines = Message::iter_json_lines(BufReader::new(file));
+    let mut messages = Vec::new();
+
+    while let Some(msg) = lines.next_message() {
+        let msg = msg?;
+        messages.push((msg.kind(), msg.sequence()));
+    }
+
+    assert_eq!(
+        vec![
+            (MessageKind::Alive, 1),
+            (MessageKind::JoinResponse, 2),
+            (MessageKind::RequestControllersList, 3),
+            (MessageKind::CycleData, 4),
+            (MessageKind::ControllerAction, 5),
+            (MessageKind::RequestJobCardsList, 6),
+        ],
+        messages
+    );
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_write_json_lines() -> Result<(), String> {
+    let text = std::fs::read_to_string(fixture()).map_err(|x| x.to_string())?;
+    let file = File::open(fixture()).map_err(|x| x.to_string())?;
+    let mut lines = Message::iter_json_lines(BufReader::new(file));
+    let mut output: Vec<u8> = Vec::new();
+
+    while let Some(msg) = lines.next_message() {
+        msg?.to_writer(&mut output)?;
+        output.write_all(b"\n").map_err(|x| x.to_string())?;
+    }
+
+    // Blank lines are skipped
+    let expected: Vec<_> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+    let output = String::from_utf8(output).map_err(|x| x.to_string())?;
+
+    assert_eq!(expected, output.lines().collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_from_reader() -> Result<(), String> {
+    let file = File::open(fixture()).map_err(|x| x.to_string())?;
+    let mut buffer = String::new();
+
+    // The whole text must be a single JSON value
+    assert!(Message::from_reader(file, &mut buffer).is_err());
+
+    let mut output: Vec<u8> = Vec::new();
+    Message::new_alive_with(42).to_writer(&mut output)?;
+
+    let msg = Message::from_reader(&output[..], &mut buffer)?;
+    assert!(msg.is_keepalive());
+    assert_eq!(42, msg.sequence());
+
+    Ok(())
+}
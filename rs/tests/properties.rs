@@ -0,0 +1,43 @@
+// Property tests for the serde layer.
+//
+// Run with `cargo test --features proptest`.
+
+#![cfg(feature = "proptest")]
+
+use ichen_openprotocol::*;
+use proptest::prelude::*;
+
+proptest! {
+    #[test]
+    fn property_test_controller_round_trip(controller in any::<Controller<'static>>()) {
+        let json = serde_json::to_string(&controller).unwrap();
+        let parsed: Controller = serde_json::from_str(&json)
+            .map_err(|err| TestCaseError::fail(format!("cannot parse {}: {}", json, err)))?;
+
+        prop_assert_eq!(format!("{:?}", controller), format!("{:?}", parsed), "{}", json);
+    }
+
+    #[test]
+    fn property_test_message_round_trip(msg in any::<Message<'static>>()) {
+        let json = msg
+            .to_json_str()
+            .map_err(|err| TestCaseError::fail(format!("invalid message: {}", err)))?;
+        let parsed = Message::parse_from_json_str(&json)
+            .map_err(|err| TestCaseError::fail(format!("cannot parse {}: {}", json, err)))?;
+
+        prop_assert_eq!(format!("{:?}", msg), format!("{:?}", parsed), "{}", json);
+        prop_assert_eq!(&json, &parsed.to_json_str().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "binary")]
+    fn property_test_message_binary_round_trip(msg in any::<Message<'static>>()) {
+        let bytes = msg
+            .to_bytes()
+            .map_err(|err| TestCaseError::fail(format!("invalid message: {}", err)))?;
+        let parsed = Message::from_bytes(&bytes)
+            .map_err(|err| TestCaseError::fail(format!("cannot decode {:?}: {}", msg, err)))?;
+
+        prop_assert_eq!(format!("{:?}", msg), format!("{:?}", parsed));
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::DateTime;
+use ichen_openprotocol::*;
+use indexmap::IndexMap;
+use std::collections::HashSet;
+use Message::*;
+
+// List every `Message` variant exactly once.
+//
+// This generates a `match` with no `_` arm, so adding a new variant to `Message`
+// without listing it here fails to compile.  Each listed variant must then have
+// a sample in `samples()` below, or `integration_test_all_variants_round_trip` fails.
+macro_rules! message_variants {
+    ($($variant:ident),* $(,)?) => {
+        const ALL_VARIANTS: &[&str] = &[$(stringify!($variant)),*];
+
+        fn variant_name(msg: &Message) -> &'static str {
+            match msg {
+                $($variant { .. } => stringify!($variant),)*
+            }
+        }
+    };
+}
+
+message_variants!(
+    Alive,
+    ControllerAction,
+    ActionResult,
+    RequestControllersList,
+    ControllersList,
+    ControllerStatus,
+    CycleData,
+    RequestJobCardsList,
+    JobCardsList,
+    Join,
+    JoinResponse,
+    RequestMoldData,
+    MoldData,
+    ReadMoldData,
+    MoldDataValue,
+    LoginOperator,
+    OperatorInfo,
+);
+
+// One sample instance of every `Message` variant.
+fn samples() -> Vec<Message<'static>> {
+    let id = ID::from_u32(123);
+    let timestamp = DateTime::parse_from_rfc3339("2019-02-26T02:03:04+08:00").unwrap();
+    let state = StateValues::new(OpMode::Automatic, JobMode::ID02);
+
+    let mut data = IndexMap::new();
+    data.insert(TextID::new("Z_QDGODCNT").unwrap(), R32::new(8567.0));
+
+    let mut controllers = IndexMap::new();
+    controllers.insert(id, Controller { controller_id: id, ..Default::default() });
+
+    let mut jobs = IndexMap::new();
+    jobs.insert(
+        TextName::new_from_str("J001").unwrap(),
+        JobCard::try_new("J001", "Mold#001", 0, 10000).unwrap(),
+    );
+
+    vec![
+        Message::new_alive(),
+        ControllerAction {
+            controller_id: id,
+            action_id: ActionID::new(42),
+            timestamp,
+            idempotency_key: Some(Box::new("abc-123".into())),
+            options: Default::default(),
+        },
+        ActionResult {
+            controller_id: id,
+            action_id: ActionID::new(42),
+            success: false,
+            reason: Some(Box::new("Machine is busy".into())),
+            options: Default::default(),
+        },
+        RequestControllersList { controller_id: Some(id), options: Default::default() },
+        ControllersList { data: controllers, options: Default::default() },
+        ControllerStatus {
+            controller_id: id,
+            display_name: None,
+            is_disconnected: Some(false),
+            op_mode: Some(OpMode::Automatic),
+            job_mode: None,
+            alarm: None,
+            audit: None,
+            variable: None,
+            operator_id: Some(None),
+            operator_name: None,
+            job_card_id: None,
+            mold_id: None,
+            state: state.clone(),
+            controller: None,
+            options: Default::default(),
+        },
+        CycleData {
+            controller_id: id,
+            data: data.clone(),
+            timestamp,
+            state: state.clone(),
+            options: Default::default(),
+        },
+        RequestJobCardsList { controller_id: id, options: Default::default() },
+        JobCardsList { controller_id: id, data: jobs, options: Default::default() },
+        Message::new_join("hello", Filters::All + Filters::Operators),
+        JoinResponse {
+            result: 100,
+            level: Some(10),
+            message: Some(Box::new("OK".into())),
+            version: Some(TextID::new("4.0").unwrap()),
+            options: Default::default(),
+        },
+        RequestMoldData { controller_id: id, options: Default::default() },
+        MoldData { controller_id: id, data, timestamp, state, options: Default::default() },
+        ReadMoldData {
+            controller_id: id,
+            field: Some(TextID::new("Z_QDGODCNT").unwrap()),
+            options: Default::default(),
+        },
+        MoldDataValue {
+            controller_id: id,
+            field: TextID::new("Z_QDGODCNT").unwrap(),
+            value: R32::new(42.0),
+            options: Default::default(),
+        },
+        LoginOperator {
+            controller_id: id,
+            password: Password::new("hello"),
+            options: Default::default(),
+        },
+        OperatorInfo {
+            controller_id: id,
+            operator_id: Some(ID::from_u32(456)),
+            name: TextName::new_from_str("John").unwrap(),
+            password: Password::new("hello"),
+            level: AccessLevel::new(3).unwrap(),
+            options: Default::default(),
+        },
+    ]
+}
+
+#[test]
+fn integration_test_all_variants_round_trip() -> std::result::Result<(), String> {
+    let mut seen = HashSet::new();
+
+    for msg in samples() {
+        let name = variant_name(&msg);
+        assert!(seen.insert(name), "duplicated sample for {}", name);
+        assert_eq!(name, msg.kind().to_string());
+
+        let json = msg.to_json_str().map_err(|err| format!("{}: {}", name, err))?;
+        assert!(json.starts_with(&format!(r#"{{"$type":"{}","#, name)), "{}: {}", name, json);
+
+        let parsed =
+            Message::parse_from_json_str(&json).map_err(|err| format!("{}: {}", name, err))?;
+        assert_eq!(name, variant_name(&parsed));
+        assert_eq!(json, parsed.to_json_str()?, "{} does not round-trip", name);
+    }
+
+    for name in ALL_VARIANTS {
+        assert!(seen.contains(name), "missing sample for {}", name);
+    }
+
+    let kinds: Vec<_> = MessageKind::all_variants().iter().map(|k| k.to_string()).collect();
+    assert_eq!(ALL_VARIANTS, &kinds[..]);
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_all_variants_distinct_kinds() {
+    let mut kinds = HashSet::new();
+
+    for msg in samples() {
+        let kind = msg.kind();
+        assert_eq!(variant_name(&msg), kind.as_str());
+        assert!(kinds.insert(kind), "{} is not distinct", kind);
+    }
+
+    assert_eq!(MessageKind::all_variants().len(), kinds.len());
+}
+
+#[test]
+fn integration_test_all_variants_batch_round_trip() -> std::result::Result<(), String> {
+    let messages = samples();
+
+    let json = Message::batch_to_json(&messages)?;
+    let parsed = Message::parse_batch(&json)?;
+
+    assert_eq!(messages.len(), parsed.len());
+
+    for (msg, parsed) in messages.iter().zip(&parsed) {
+        assert_eq!(variant_name(msg), variant_name(parsed));
+        assert_eq!(msg.to_json_str()?, parsed.to_json_str()?);
+    }
+
+    assert_eq!(json, Message::batch_to_json(&parsed)?);
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_all_variants_classification() {
+    for msg in samples() {
+        let name = variant_name(&msg);
+
+        let expected = match name {
+            "ControllerAction"
+            | "Join"
+            | "RequestControllersList"
+            | "RequestJobCardsList"
+            | "RequestMoldData"
+            | "ReadMoldData"
+            | "LoginOperator" => (true, false, false),
+            "ActionResult" | "JoinResponse" | "ControllersList" | "JobCardsList" | "MoldData"
+            | "MoldDataValue" | "OperatorInfo" => (false, true, false),
+            "Alive" | "ControllerStatus" | "CycleData" => (false, false, true),
+            _ => panic!("no classification for {}", name),
+        };
+
+        assert_eq!(
+            expected,
+            (msg.is_request(), msg.is_response(), msg.is_notification()),
+            "{}",
+            name
+        );
+        assert_eq!(msg.is_request(), msg.expected_response_kind().is_some(), "{}", name);
+    }
+}
+
+#[test]
+fn integration_test_all_variants_describe() {
+    let mut descriptions = HashSet::new();
+
+    for msg in samples() {
+        let name = variant_name(&msg);
+        let text = msg.describe();
+
+        assert!(text.starts_with(&format!("{}(", name)), "{}: {}", name, text);
+        assert!(text.ends_with(&format!("sequence={})", msg.sequence())), "{}: {}", name, text);
+        assert!(!text.contains('\n'), "{}: {}", name, text);
+        assert!(descriptions.insert(text), "{} is not distinct", name);
+    }
+}